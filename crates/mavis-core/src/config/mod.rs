@@ -8,7 +8,11 @@ pub use watcher::ConfigWatcher;
 
 use crate::error::CoreError;
 use serde::{Deserialize, Serialize};
-use std::{path::Path, sync::Arc};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
 /// Main configuration struct that holds all MAVIS settings
 #[derive(Clone, Debug, Deserialize, Serialize)]
@@ -70,8 +74,20 @@ pub struct UiConfig {
 /// Terminal settings
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct TerminalConfig {
-    /// Default shell executable
+    /// Default shell executable, optionally followed by arguments
     pub shell_executable: String,
+
+    /// Extra arguments passed to the shell
+    #[serde(default)]
+    pub shell_args: Vec<String>,
+
+    /// Working directory for new shells (defaults to the MAVIS working directory)
+    #[serde(default)]
+    pub working_directory: Option<String>,
+
+    /// Environment overrides for new shells; a `null` value removes the variable
+    #[serde(default)]
+    pub environment: BTreeMap<String, Option<String>>,
     
     /// Terminal dimensions
     pub columns: u32,
@@ -85,6 +101,96 @@ pub struct TerminalConfig {
     
     /// Terminal font size
     pub font_size: f32,
+
+    /// Named profiles that can be opened as terminal tabs
+    #[serde(default)]
+    pub profiles: Vec<TerminalProfile>,
+
+    /// Profile opened at startup (defaults to the shell settings above)
+    #[serde(default)]
+    pub default_profile: Option<String>,
+
+    /// What to do with a tab when its shell exits
+    #[serde(default)]
+    pub on_exit: ExitPolicy,
+
+    /// Delay before the first automatic restart; doubles on each retry
+    #[serde(default = "default_restart_delay_ms")]
+    pub restart_delay_ms: u32,
+
+    /// Upper bound for the restart delay. A shell that ran at least this
+    /// long before exiting starts over from `restart_delay_ms`.
+    #[serde(default = "default_restart_max_delay_ms")]
+    pub restart_max_delay_ms: u32,
+
+    /// Start new tabs in the directory the active tab's shell reports
+    /// (OSC 7) unless the profile sets a working directory
+    #[serde(default = "default_inherit_working_directory")]
+    pub inherit_working_directory: bool,
+}
+
+/// What happens to a terminal tab when its shell exits
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExitPolicy {
+    /// Close the tab
+    Close,
+    /// Keep the tab open with an exit banner
+    #[default]
+    Keep,
+    /// Start the shell again, backing off if it keeps exiting
+    Restart,
+}
+
+fn default_restart_delay_ms() -> u32 {
+    1000
+}
+
+fn default_inherit_working_directory() -> bool {
+    true
+}
+
+fn default_restart_max_delay_ms() -> u32 {
+    30_000
+}
+
+/// A named terminal profile. Unset fields fall back to the terminal settings.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct TerminalProfile {
+    /// Name shown in the View menu and used by `MAVIS.terminal.open`
+    pub name: String,
+
+    /// Shell executable, optionally followed by arguments
+    #[serde(default)]
+    pub command: Option<String>,
+
+    /// Extra arguments passed to the shell
+    #[serde(default)]
+    pub args: Vec<String>,
+
+    /// Working directory
+    #[serde(default)]
+    pub working_directory: Option<String>,
+
+    /// Environment overrides, applied on top of the terminal settings
+    #[serde(default)]
+    pub environment: BTreeMap<String, Option<String>>,
+
+    /// Tab title (defaults to the profile name)
+    #[serde(default)]
+    pub title: Option<String>,
+
+    /// Font family override
+    #[serde(default)]
+    pub font_family: Option<String>,
+
+    /// Font size override
+    #[serde(default)]
+    pub font_size: Option<f32>,
+
+    /// Exit policy override
+    #[serde(default)]
+    pub on_exit: Option<ExitPolicy>,
 }
 
 /// Shell replacement settings
@@ -135,6 +241,88 @@ pub struct MonitoringConfig {
     
     /// Alert threshold for disk space (percentage free)
     pub disk_space_alert_threshold: u32,
+
+    /// Seconds a usage threshold must be exceeded before its alert fires
+    #[serde(default = "default_alert_sustain_secs")]
+    pub alert_sustain_secs: u32,
+
+    /// Percentage points a value must recover past its threshold before
+    /// its alert clears
+    #[serde(default = "default_alert_hysteresis")]
+    pub alert_hysteresis: u32,
+
+    /// Network interfaces to monitor, by name or glob pattern such as
+    /// "eth*". Empty means all interfaces.
+    #[serde(default)]
+    pub network_include: Vec<String>,
+
+    /// Network interfaces to leave out, by name or glob pattern, e.g.
+    /// virtual adapters like "vEthernet*"
+    #[serde(default = "default_network_exclude")]
+    pub network_exclude: Vec<String>,
+
+    /// Enable the per-process table
+    #[serde(default = "default_monitor_processes")]
+    pub monitor_processes: bool,
+
+    /// Whether processes may be ended or reprioritized from MAVIS
+    #[serde(default = "default_allow_process_control")]
+    pub allow_process_control: bool,
+
+    /// Seconds of history kept at full resolution
+    #[serde(default = "default_history_retention_secs")]
+    pub history_retention_secs: u32,
+
+    /// Seconds of history kept as per-bucket summaries
+    #[serde(default = "default_history_downsampled_secs")]
+    pub history_downsampled_secs: u32,
+
+    /// Seconds covered by one summary bucket
+    #[serde(default = "default_history_bucket_secs")]
+    pub history_bucket_secs: u32,
+
+    /// Port on 127.0.0.1 to serve metrics on in the Prometheus text format;
+    /// needs the "prometheus" feature
+    #[serde(default)]
+    pub exporter_port: Option<u16>,
+
+    /// File to write metrics to in the Prometheus text format after every
+    /// sample, for node_exporter's textfile collector; needs the
+    /// "prometheus" feature
+    #[serde(default)]
+    pub exporter_textfile: Option<PathBuf>,
+}
+
+fn default_network_exclude() -> Vec<String> {
+    vec!["lo".to_string()]
+}
+
+fn default_alert_sustain_secs() -> u32 {
+    60
+}
+
+fn default_alert_hysteresis() -> u32 {
+    5
+}
+
+fn default_monitor_processes() -> bool {
+    true
+}
+
+fn default_allow_process_control() -> bool {
+    true
+}
+
+fn default_history_retention_secs() -> u32 {
+    600 // 10 minutes
+}
+
+fn default_history_downsampled_secs() -> u32 {
+    3600 // 1 hour
+}
+
+fn default_history_bucket_secs() -> u32 {
+    10
 }
 
 /// Security settings for Lua scripting
@@ -168,12 +356,21 @@ impl Config {
                 use_direct2d: true,
             },
             terminal: TerminalConfig {
-                shell_executable: "cmd.exe".to_string(),
+                shell_executable: if cfg!(windows) { "cmd.exe" } else { "/bin/sh" }.to_string(),
+                shell_args: Vec::new(),
+                working_directory: None,
+                environment: BTreeMap::new(),
                 columns: 80,
                 rows: 24,
                 scrollback_lines: 10000,
                 font_family: "Cascadia Code".to_string(),
                 font_size: 12.0,
+                profiles: Vec::new(),
+                default_profile: None,
+                on_exit: ExitPolicy::Keep,
+                restart_delay_ms: default_restart_delay_ms(),
+                restart_max_delay_ms: default_restart_max_delay_ms(),
+                inherit_working_directory: default_inherit_working_directory(),
             },
             shell: ShellConfig {
                 replacement_method: "registry".to_string(),
@@ -192,6 +389,17 @@ impl Config {
                 ram_alert_threshold: 90,
                 disk_alert_threshold: 95,
                 disk_space_alert_threshold: 10,
+                alert_sustain_secs: default_alert_sustain_secs(),
+                alert_hysteresis: default_alert_hysteresis(),
+                network_include: Vec::new(),
+                network_exclude: default_network_exclude(),
+                monitor_processes: default_monitor_processes(),
+                allow_process_control: default_allow_process_control(),
+                history_retention_secs: default_history_retention_secs(),
+                history_downsampled_secs: default_history_downsampled_secs(),
+                history_bucket_secs: default_history_bucket_secs(),
+                exporter_port: None,
+                exporter_textfile: None,
             },
             security: SecurityConfig {
                 enable_sandboxing: true,
@@ -1,13 +1,17 @@
 //! Manages Windows Pseudo Console (ConPTY) sessions.
 
 use crate::error::{CoreResult, CoreError};
+use crate::pty::{PtyCommand, PtyControl, PtyHalves, PtySession};
 use log::{debug, error, info};
 use std::{
     io::{Read, Write},
     mem::zeroed,
-    os::windows::prelude::{AsRawHandle, FromRawHandle, OwnedHandle, IntoRawHandle},
+    os::windows::ffi::OsStrExt,
+    os::windows::prelude::{AsRawHandle, BorrowedHandle, FromRawHandle, OwnedHandle},
     ffi::c_void, 
     fs::File,
+    sync::{Arc, Mutex},
+    thread,
 };
 use windows::{
     core::{PCWSTR, PWSTR},
@@ -15,13 +19,14 @@ use windows::{
         Foundation::{CloseHandle, HANDLE, INVALID_HANDLE_VALUE},
         System::{
             Console::{
-                CreatePseudoConsole, ResizePseudoConsole, HPCON, COORD,
+                ClosePseudoConsole, CreatePseudoConsole, ResizePseudoConsole, HPCON, COORD,
             },
             Pipes::{CreatePipe, PeekNamedPipe},
             Threading::{
                 CreateProcessW, DeleteProcThreadAttributeList, GetExitCodeProcess, InitializeProcThreadAttributeList,
-                TerminateProcess, UpdateProcThreadAttribute, EXTENDED_STARTUPINFO_PRESENT,
-                LPPROC_THREAD_ATTRIBUTE_LIST, PROCESS_INFORMATION, STARTUPINFOEXW,
+                TerminateProcess, UpdateProcThreadAttribute, WaitForSingleObject, CREATE_UNICODE_ENVIRONMENT,
+                EXTENDED_STARTUPINFO_PRESENT, INFINITE, LPPROC_THREAD_ATTRIBUTE_LIST, PROCESS_INFORMATION,
+                STARTUPINFOEXW,
             },
         },
     },
@@ -36,12 +41,19 @@ const PROC_THREAD_ATTRIBUTE_PSEUDOCONSOLE: usize = 0x00020016;
 /// Represents an active ConPTY session.
 #[derive(Debug)]
 pub struct ConPtySession {
-    pty_handle: HPCON,
+    control: Arc<ConPtyControl>,
+    input_writer: File, // Write end of the stdin pipe
+    output_reader: File, // Read end of the stdout pipe
+}
+
+/// The pseudo console and child process of a session. Dropping the last
+/// reference terminates the child.
+#[derive(Debug)]
+pub struct ConPtyControl {
+    // `None` once the pseudo console has been closed; shared with the exit
+    // watcher thread
+    pty_handle: Arc<Mutex<Option<HPCON>>>,
     process_info: PROCESS_INFORMATION,
-    input_writer: OwnedHandle, // Write handle for stdin pipe
-    output_reader: OwnedHandle, // Read handle for stdout pipe
-    // Optional: Thread handle for reading output asynchronously
-    // output_thread: Option<JoinHandle<()>>,
 }
 
 impl ConPtySession {
@@ -53,7 +65,14 @@ impl ConPtySession {
     /// * `cols` - Initial number of columns for the pseudo console.
     /// * `rows` - Initial number of rows for the pseudo console.
     pub fn new(command: &str, cols: i16, rows: i16) -> CoreResult<Self> {
-        info!("Creating ConPTY session for command: '{}'", command);
+        Self::with_command(&PtyCommand::parse(command), cols, rows)
+    }
+
+    /// Creates a new ConPTY session for a `PtyCommand`, applying its working
+    /// directory, environment overrides and initial title.
+    pub fn with_command(command: &PtyCommand, cols: i16, rows: i16) -> CoreResult<Self> {
+        let command_line = command.to_command_line();
+        info!("Creating ConPTY session for command: '{}'", command_line);
 
         // 1. Create pipes for stdin and stdout
         let (stdin_reader, stdin_writer) = create_pipe()?;
@@ -85,10 +104,18 @@ impl ConPtySession {
 
         // 3. Prepare startup info for the child process
         let mut si_startup_info = prepare_startup_info(pty_handle)?;
+        let mut title_wide: Option<Vec<u16>> = command.get_title().map(to_wide);
+        if let Some(title) = title_wide.as_mut() {
+            si_startup_info.StartupInfo.lpTitle = PWSTR(title.as_mut_ptr());
+        }
 
         // 4. Create the child process
         let mut process_info: PROCESS_INFORMATION = unsafe { zeroed() };
-        let mut command_wide: Vec<u16> = command.encode_utf16().chain(Some(0)).collect(); // Null-terminated wide string
+        let mut command_wide: Vec<u16> = to_wide(&command_line); // Null-terminated wide string
+        let cwd_wide: Option<Vec<u16>> = command
+            .get_cwd()
+            .map(|dir| dir.as_os_str().encode_wide().chain(Some(0)).collect());
+        let env_block: Option<Vec<u16>> = command.environment().map(environment_block);
 
         unsafe {
             CreateProcessW(
@@ -97,9 +124,9 @@ impl ConPtySession {
                 None, // Process attributes
                 None, // Thread attributes
                 false, // Inherit handles
-                EXTENDED_STARTUPINFO_PRESENT, // Creation flags
-                None, // Environment
-                PCWSTR::null(), // Current directory
+                EXTENDED_STARTUPINFO_PRESENT | CREATE_UNICODE_ENVIRONMENT, // Creation flags
+                env_block.as_ref().map(|block| block.as_ptr() as *const c_void), // Environment
+                cwd_wide.as_ref().map_or(PCWSTR::null(), |dir| PCWSTR(dir.as_ptr())), // Current directory
                 &mut si_startup_info.StartupInfo, // Startup info
                 &mut process_info, // Process information
             )
@@ -118,54 +145,35 @@ impl ConPtySession {
              }
         }
 
-        Ok(Self {
-            pty_handle,
+        let control = Arc::new(ConPtyControl {
+            pty_handle: Arc::new(Mutex::new(Some(pty_handle))),
             process_info,
-            input_writer: stdin_writer,
-            output_reader: stdout_reader,
+        });
+        spawn_exit_watcher(&control)?;
+
+        Ok(Self {
+            control,
+            input_writer: File::from(stdin_writer),
+            output_reader: File::from(stdout_reader),
         })
     }
 
     /// Resizes the pseudo console.
     pub fn resize(&self, cols: i16, rows: i16) -> CoreResult<()> {
-        debug!("Resizing ConPTY to {}x{}", cols, rows);
-        let size = COORD { X: cols, Y: rows };
-        unsafe { ResizePseudoConsole(self.pty_handle, size) }
-            .map_err(CoreError::from)?;
-        Ok(())
+        self.control.resize_console(cols, rows)
     }
 
     /// Writes data to the pseudo console's input (stdin).
     pub fn write(&mut self, data: &[u8]) -> CoreResult<usize> {
-        // Convert OwnedHandle to File to use Write trait
-        let handle_raw = self.input_writer.as_raw_handle();
-        let mut file = unsafe { File::from_raw_handle(handle_raw) };
-        
-        // Write data
-        let result = file.write(data).map_err(CoreError::IoError);
-        
-        // Prevent file from closing the handle when dropped
-        let _ = file.into_raw_handle();
-        
-        let bytes_written = result?;
+        let bytes_written = self.input_writer.write(data)?;
         debug!("Wrote {} bytes to ConPTY input", bytes_written);
         Ok(bytes_written)
     }
 
     /// Reads data from the pseudo console's output (stdout).
-    /// This is a blocking read. Consider using asynchronous reads or threads.
+    /// This is a blocking read; `split` lets another thread do it.
     pub fn read(&mut self, buf: &mut [u8]) -> CoreResult<usize> {
-        // Convert OwnedHandle to File to use Read trait
-        let handle_raw = self.output_reader.as_raw_handle();
-        let mut file = unsafe { File::from_raw_handle(handle_raw) };
-        
-        // Read data
-        let result = file.read(buf).map_err(CoreError::IoError);
-        
-        // Prevent file from closing the handle when dropped
-        let _ = file.into_raw_handle();
-        
-        let bytes_read = result?;
+        let bytes_read = self.output_reader.read(buf)?;
         debug!("Read {} bytes from ConPTY output", bytes_read);
         Ok(bytes_read)
     }
@@ -190,6 +198,28 @@ impl ConPtySession {
 
     /// Gets the exit code of the child process, if it has exited.
     pub fn get_exit_code(&self) -> CoreResult<Option<u32>> {
+        self.control.exit_code()
+    }
+
+    /// Terminates the child process and closes the pseudo console.
+    pub fn terminate(&self) -> CoreResult<()> {
+        self.control.terminate_process()
+    }
+}
+
+impl ConPtyControl {
+    fn resize_console(&self, cols: i16, rows: i16) -> CoreResult<()> {
+        debug!("Resizing ConPTY to {}x{}", cols, rows);
+        let size = COORD { X: cols, Y: rows };
+        let pty_handle = self.pty_handle.lock().map_err(|_| poisoned())?;
+        if let Some(handle) = *pty_handle {
+            unsafe { ResizePseudoConsole(handle, size) }
+                .map_err(CoreError::from)?;
+        }
+        Ok(())
+    }
+
+    fn exit_code(&self) -> CoreResult<Option<u32>> {
         let mut exit_code: u32 = 0;
         unsafe { GetExitCodeProcess(self.process_info.hProcess, &mut exit_code) }
             .map_err(CoreError::from)?;
@@ -202,42 +232,153 @@ impl ConPtySession {
         }
     }
 
-    /// Terminates the child process and closes the pseudo console.
-    pub fn terminate(&self) -> CoreResult<()> {
+    fn terminate_process(&self) -> CoreResult<()> {
+        if self.exit_code()?.is_some() {
+            return Ok(());
+        }
         info!("Terminating ConPTY session (PID: {})", self.process_info.dwProcessId);
-        unsafe {
-            // Terminate the child process
-            if let Err(e) = TerminateProcess(self.process_info.hProcess, 1) {
-                error!("Failed to terminate child process (PID: {}): {:?}", self.process_info.dwProcessId, e);
-                // Continue cleanup even if termination fails
-            }
+        // The exit watcher closes the pseudo console once the process is gone
+        if let Err(e) = unsafe { TerminateProcess(self.process_info.hProcess, 1) } {
+            error!("Failed to terminate child process (PID: {}): {:?}", self.process_info.dwProcessId, e);
+        }
+        Ok(())
+    }
+}
+
+impl PtyControl for ConPtyControl {
+    fn resize(&self, cols: u16, rows: u16) -> CoreResult<()> {
+        self.resize_console(to_coord(cols), to_coord(rows))
+    }
 
-            // Close process and thread handles
+    fn exit_status(&self) -> CoreResult<Option<u32>> {
+        self.exit_code()
+    }
+
+    fn terminate(&self) -> CoreResult<()> {
+        self.terminate_process()
+    }
+}
+
+impl Drop for ConPtyControl {
+    fn drop(&mut self) {
+        if let Err(e) = self.terminate_process() {
+            error!("Error during ConPtyControl drop: {}", e);
+        }
+        close_console(&self.pty_handle);
+        unsafe {
             if self.process_info.hProcess != INVALID_HANDLE_VALUE {
                 // Ignore errors on close since we're cleaning up anyway
                 let _ = CloseHandle(self.process_info.hProcess);
             }
             if self.process_info.hThread != INVALID_HANDLE_VALUE {
-                // Ignore errors on close since we're cleaning up anyway
                 let _ = CloseHandle(self.process_info.hThread);
             }
-
-            // Close the PTY handle - we don't check against INVALID_HANDLE_VALUE here
-            // Just convert HPCON to HANDLE for CloseHandle
-            let handle = HANDLE(self.pty_handle.0);
-            let _ = CloseHandle(handle);
         }
-        // Input/Output handles are OwnedHandle, they will be closed on drop.
-        Ok(())
     }
 }
 
-impl Drop for ConPtySession {
-    fn drop(&mut self) {
-        if let Err(e) = self.terminate() {
-            error!("Error during ConPtySession drop: {}", e);
-        }
+// Close the pseudo console. ConPTY keeps the output pipe open after the
+// child exits; closing the console flushes it and ends pending reads.
+fn close_console(pty_handle: &Mutex<Option<HPCON>>) {
+    let handle = match pty_handle.lock() {
+        Ok(mut pty_handle) => pty_handle.take(),
+        Err(_) => None,
+    };
+    if let Some(handle) = handle {
+        debug!("Closing pseudo console: {:?}", handle);
+        unsafe { ClosePseudoConsole(handle) };
+    }
+}
+
+// Wait for the child to exit, then close the pseudo console so readers see
+// the end of the output. The thread waits on its own duplicate of the
+// process handle, so it does not keep the control alive.
+fn spawn_exit_watcher(control: &ConPtyControl) -> CoreResult<()> {
+    let pid = control.process_info.dwProcessId;
+    let process = unsafe { BorrowedHandle::borrow_raw(control.process_info.hProcess.0 as *mut c_void) }
+        .try_clone_to_owned()?;
+    let pty_handle = control.pty_handle.clone();
+
+    thread::Builder::new()
+        .name(format!("conpty-exit-{}", pid))
+        .spawn(move || {
+            unsafe { WaitForSingleObject(HANDLE(process.as_raw_handle() as isize), INFINITE) };
+            debug!("ConPTY child (PID: {}) exited", pid);
+            close_console(&pty_handle);
+        })?;
+    Ok(())
+}
+
+fn poisoned() -> CoreError {
+    CoreError::InitializationError("ConPTY handle mutex poisoned".to_string())
+}
+
+impl PtySession for ConPtySession {
+    fn spawn(command: &PtyCommand, cols: u16, rows: u16) -> CoreResult<Self> {
+        ConPtySession::with_command(command, to_coord(cols), to_coord(rows))
+    }
+
+    fn read(&mut self, buf: &mut [u8]) -> CoreResult<usize> {
+        ConPtySession::read(self, buf)
+    }
+
+    fn write(&mut self, data: &[u8]) -> CoreResult<usize> {
+        ConPtySession::write(self, data)
+    }
+
+    fn split(self) -> CoreResult<PtyHalves> {
+        // Reads report 0 once the pipe is broken, i.e. the console closed
+        let reader = self.output_reader.try_clone()?;
+        let writer = self.input_writer.try_clone()?;
+        Ok(PtyHalves {
+            reader: Box::new(reader),
+            writer: Box::new(writer),
+            control: self.control.clone(),
+        })
+    }
+}
+
+impl PtyControl for ConPtySession {
+    fn resize(&self, cols: u16, rows: u16) -> CoreResult<()> {
+        self.control.resize(cols, rows)
+    }
+
+    fn exit_status(&self) -> CoreResult<Option<u32>> {
+        self.get_exit_code()
+    }
+
+    fn terminate(&self) -> CoreResult<()> {
+        ConPtySession::terminate(self)
+    }
+}
+
+// COORD fields are i16; clamp larger sizes instead of wrapping
+fn to_coord(value: u16) -> i16 {
+    i16::try_from(value).unwrap_or(i16::MAX)
+}
+
+// Null-terminated UTF-16 string
+fn to_wide(text: &str) -> Vec<u16> {
+    text.encode_utf16().chain(Some(0)).collect()
+}
+
+// Build a CREATE_UNICODE_ENVIRONMENT block: NAME=value entries sorted by name
+// (case-insensitively, as Windows expects), each null-terminated, followed by
+// a final null.
+fn environment_block(mut vars: Vec<(std::ffi::OsString, std::ffi::OsString)>) -> Vec<u16> {
+    vars.sort_by_key(|(key, _)| key.to_string_lossy().to_uppercase());
+    let mut block = Vec::new();
+    for (key, value) in vars {
+        block.extend(key.encode_wide());
+        block.push('=' as u16);
+        block.extend(value.encode_wide());
+        block.push(0);
+    }
+    if block.is_empty() {
+        block.push(0);
     }
+    block.push(0);
+    block
 }
 
 // Helper function to create pipes
@@ -24,6 +24,7 @@ pub enum CoreError {
     #[error("PDH error: {0}")]
     PdhError(String), // Added for Performance Data Helper errors
 
+    #[cfg(windows)]
     #[error("Windows API error: {0:?}")]
     WindowsError(#[from] windows::core::Error),
     
@@ -32,6 +33,12 @@ pub enum CoreError {
 
     #[error("Initialization error: {0}")]
     InitializationError(String),
+
+    #[error("Recording error: {0}")]
+    RecordingError(String),
+
+    #[error("Permission denied: {0}")]
+    PermissionDenied(String),
 }
 
 /// A specialized `Result` type for MAVIS core operations.
@@ -1,4 +1,4 @@
-// MAVIS Core - Core functionality for MAVIS shell environment (Config, Lua, Monitor, ConPTY, etc.)
+// MAVIS Core - Core functionality for MAVIS shell environment (Config, Lua, Monitor, PTY, etc.)
 
 // Top-level modules
 pub mod config;
@@ -7,13 +7,19 @@ pub mod lua;
 pub mod monitor;
 // pub mod theme; // Removed: Theme is part of lua::api, not top-level
 pub mod utils;
+#[cfg(windows)]
 pub mod conpty; // Added ConPTY module
+pub mod pty; // PtySession trait with ConPTY and openpty backends
+pub mod terminal; // VT parser and cell grid (no GUI dependencies)
 
 // Re-exports for easier use by other crates
 pub use config::Config;
 pub use error::{CoreError, CoreResult};
 pub use lua::ScriptEngine;
+#[cfg(windows)]
 pub use conpty::ConPtySession; // Re-export ConPtySession
+pub use pty::{NativePty, PtyCommand, PtyControl, PtySession, SharedPtyControl};
+pub use terminal::Terminal;
 
 use log::{info, warn};
 use std::sync::Once;
@@ -6,6 +6,8 @@ pub mod system;
 pub mod keybindings; // Added keybindings module
 pub mod theme; // Added theme module
 pub mod widgets; // Added widgets module
+pub mod terminal; // Terminal tabs and profiles
+pub mod monitor; // Metrics history
 
 use crate::error::CoreError;
 use mlua::{Lua, Table};
@@ -16,6 +18,8 @@ pub use system::register_system_functions;
 pub use keybindings::register_keybinding_functions; // Added keybindings re-export
 pub use theme::register_theme_functions; // Added theme re-export
 pub use widgets::register_widget_functions; // Added widgets re-export
+pub use terminal::register_terminal_functions;
+pub use monitor::register_monitor_functions;
 
 // Helper function to create tables with proper error handling
 pub(crate) fn create_nested_table<'a>(lua: &'a Lua, parent: &'a Table, name: &str) -> Result<Table<'a>, CoreError> {
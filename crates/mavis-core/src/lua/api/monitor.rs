@@ -0,0 +1,560 @@
+// Monitor API functions for Lua scripts
+// These functions query the recorded metrics history, so widgets can draw
+// graphs and show minimum, maximum and average usage, manage the alert
+// rules checked against every sample, receive new samples as they come and
+// change what the monitor samples while it runs
+
+use crate::config::MonitoringConfig;
+use crate::error::CoreError;
+use crate::monitor::alerts::{AlertKind, AlertSource, Comparison, Severity};
+use crate::monitor::history::Series;
+use crate::monitor::{
+    AlertEngine, AlertEvent, AlertRule, Metric, MetricsHistory, MonitorControl, ResourceUsage, SharedAlerts,
+    SharedHistory,
+};
+use mlua::{Function, Lua, Table};
+use std::sync::MutexGuard;
+use std::time::{Duration, Instant, UNIX_EPOCH};
+
+/// Register metrics history, alert and monitor settings functions in the
+/// provided table
+pub fn register_monitor_functions(
+    lua: &Lua,
+    table: &Table,
+    history: SharedHistory,
+    alerts: SharedAlerts,
+    control: MonitorControl,
+) -> Result<(), CoreError> {
+    // Names of the recorded metrics: MAVIS.monitor.metrics() -> { "cpu_usage", ... }
+    let metrics_fn = lua.create_function(|_, ()| Ok(Metric::ALL.iter().map(Metric::name).collect::<Vec<_>>()))
+        .map_err(|e| CoreError::LuaError(mlua::Error::external(format!("Failed to create metrics function: {}", e))))?;
+
+    table.set("metrics", metrics_fn)
+        .map_err(|e| CoreError::LuaError(mlua::Error::external(format!("Failed to set metrics function: {}", e))))?;
+
+    // Most recent value, or nil: MAVIS.monitor.latest(metric)
+    let shared = history.clone();
+    let latest_fn = lua.create_function(move |_, name: String| {
+        let history = lock(&shared)?;
+        Ok(series(&history, &name)?.latest())
+    })
+    .map_err(|e| CoreError::LuaError(mlua::Error::external(format!("Failed to create latest function: {}", e))))?;
+
+    table.set("latest", latest_fn)
+        .map_err(|e| CoreError::LuaError(mlua::Error::external(format!("Failed to set latest function: {}", e))))?;
+
+    // Values over the last `seconds`, oldest first; windows longer than the
+    // full-resolution retention give one average per bucket:
+    // MAVIS.monitor.history(metric, [seconds]) -> { value, ... }
+    let shared = history.clone();
+    let history_fn = lua.create_function(move |_, (name, seconds): (String, Option<f64>)| {
+        let history = lock(&shared)?;
+        Ok(series(&history, &name)?.values(window(&history, seconds)?))
+    })
+    .map_err(|e| CoreError::LuaError(mlua::Error::external(format!("Failed to create history function: {}", e))))?;
+
+    table.set("history", history_fn)
+        .map_err(|e| CoreError::LuaError(mlua::Error::external(format!("Failed to set history function: {}", e))))?;
+
+    // Summary over the last `seconds`, or nil if nothing was recorded:
+    // MAVIS.monitor.stats(metric, [seconds]) -> { min, max, avg, samples }
+    let shared = history.clone();
+    let stats_fn = lua.create_function(move |lua_ctx, (name, seconds): (String, Option<f64>)| {
+        let history = lock(&shared)?;
+        let Some(summary) = series(&history, &name)?.summary(window(&history, seconds)?) else {
+            return Ok(None);
+        };
+        let stats = lua_ctx.create_table()?;
+        stats.set("min", summary.min)?;
+        stats.set("max", summary.max)?;
+        stats.set("avg", summary.avg)?;
+        stats.set("samples", summary.samples)?;
+        Ok(Some(stats))
+    })
+    .map_err(|e| CoreError::LuaError(mlua::Error::external(format!("Failed to create stats function: {}", e))))?;
+
+    table.set("stats", stats_fn)
+        .map_err(|e| CoreError::LuaError(mlua::Error::external(format!("Failed to set stats function: {}", e))))?;
+
+    // Percentile (0-100) over the last `seconds`, or nil:
+    // MAVIS.monitor.percentile(metric, percentile, [seconds])
+    let shared = history;
+    let percentile_fn = lua.create_function(move |_, (name, percentile, seconds): (String, f64, Option<f64>)| {
+        let history = lock(&shared)?;
+        Ok(series(&history, &name)?.percentile(window(&history, seconds)?, percentile))
+    })
+    .map_err(|e| CoreError::LuaError(mlua::Error::external(format!("Failed to create percentile function: {}", e))))?;
+
+    table.set("percentile", percentile_fn)
+        .map_err(|e| CoreError::LuaError(mlua::Error::external(format!("Failed to set percentile function: {}", e))))?;
+
+    register_alert_functions(lua, table, alerts)?;
+
+    // Called with each new sample, at most once every `seconds`:
+    // MAVIS.monitor.on_update(function(usage) ... end, [seconds]), or nil to
+    // remove. The handler gets {cpu_usage, memory_usage, ..., used_memory,
+    // total_memory, disk_free}, keyed by metric name
+    let on_update_fn = lua.create_function(|lua_ctx, (handler, seconds): (Option<Function>, Option<f64>)| {
+        let min_interval = match seconds {
+            None => Duration::ZERO,
+            Some(seconds) => Duration::try_from_secs_f64(seconds)
+                .map_err(|_| mlua::Error::RuntimeError(format!("Invalid interval: {} seconds", seconds)))?,
+        };
+        lua_ctx.set_app_data(UpdateThrottle {
+            min_interval,
+            last_called: None,
+        });
+        lua_ctx.set_named_registry_value(UPDATE_HANDLER_KEY, handler)
+    })
+    .map_err(|e| CoreError::LuaError(mlua::Error::external(format!("Failed to create on_update function: {}", e))))?;
+
+    table.set("on_update", on_update_fn)
+        .map_err(|e| CoreError::LuaError(mlua::Error::external(format!("Failed to set on_update function: {}", e))))?;
+
+    register_settings_functions(lua, table, control)
+}
+
+/// Settings scripts can change while the monitor runs
+const MONITOR_FLAGS: [&str; 5] = ["monitor_cpu", "monitor_ram", "monitor_network", "monitor_disk", "monitor_processes"];
+
+fn register_settings_functions(lua: &Lua, table: &Table, control: MonitorControl) -> Result<(), CoreError> {
+    // Current settings: MAVIS.monitor.settings() -> { update_interval_ms, monitor_cpu, ... }
+    let shared = control.clone();
+    let settings_fn = lua.create_function(move |lua_ctx, ()| {
+        let mut config = shared.settings();
+        let settings = lua_ctx.create_table()?;
+        settings.set("update_interval_ms", config.update_interval_ms)?;
+        for flag in MONITOR_FLAGS {
+            settings.set(flag, *monitor_flag(&mut config, flag))?;
+        }
+        Ok(settings)
+    })
+    .map_err(|e| CoreError::LuaError(mlua::Error::external(format!("Failed to create settings function: {}", e))))?;
+
+    table.set("settings", settings_fn)
+        .map_err(|e| CoreError::LuaError(mlua::Error::external(format!("Failed to set settings function: {}", e))))?;
+
+    // Change some settings; the monitor applies them before its next sample,
+    // starting or stopping the sources of enabled or disabled families:
+    // MAVIS.monitor.configure{ update_interval_ms = 1000, monitor_disk = false }
+    let shared = control.clone();
+    let configure_fn = lua.create_function(move |_, changes: Table| {
+        let mut config = shared.settings();
+        for pair in changes.pairs::<String, mlua::Value>() {
+            let (key, value) = pair?;
+            match (key.as_str(), value) {
+                ("update_interval_ms", mlua::Value::Integer(ms)) if ms > 0 => {
+                    config.update_interval_ms = u32::try_from(ms)
+                        .map_err(|_| mlua::Error::RuntimeError(format!("Invalid update interval: {} ms", ms)))?;
+                }
+                ("update_interval_ms", value) => {
+                    return Err(mlua::Error::RuntimeError(format!("Invalid update interval: {:?}", value)));
+                }
+                (flag, mlua::Value::Boolean(enabled)) if MONITOR_FLAGS.contains(&flag) => {
+                    *monitor_flag(&mut config, flag) = enabled;
+                }
+                (key, _) => return Err(mlua::Error::RuntimeError(format!("Unknown or invalid monitor setting: {}", key))),
+            }
+        }
+        shared.reconfigure(config);
+        Ok(())
+    })
+    .map_err(|e| CoreError::LuaError(mlua::Error::external(format!("Failed to create configure function: {}", e))))?;
+
+    table.set("configure", configure_fn)
+        .map_err(|e| CoreError::LuaError(mlua::Error::external(format!("Failed to set configure function: {}", e))))?;
+
+    // Health of each family's source as of the latest sample:
+    // MAVIS.monitor.sources() -> { {family, backend, state, error}, ... }
+    // where state is "ok", "disabled", "init_failed" or "failing"
+    let shared = control;
+    let sources_fn = lua.create_function(move |lua_ctx, ()| {
+        let sources = lua_ctx.create_table()?;
+        for (i, status) in shared.health().into_iter().enumerate() {
+            let entry = lua_ctx.create_table()?;
+            entry.set("family", status.family)?;
+            entry.set("backend", status.backend)?;
+            entry.set("state", status.health.name())?;
+            entry.set("error", status.health.error())?;
+            sources.set(i + 1, entry)?;
+        }
+        Ok(sources)
+    })
+    .map_err(|e| CoreError::LuaError(mlua::Error::external(format!("Failed to create sources function: {}", e))))?;
+
+    table.set("sources", sources_fn)
+        .map_err(|e| CoreError::LuaError(mlua::Error::external(format!("Failed to set sources function: {}", e))))?;
+
+    Ok(())
+}
+
+/// The `monitor_*` setting named `flag`, one of `MONITOR_FLAGS`.
+fn monitor_flag<'a>(config: &'a mut MonitoringConfig, flag: &str) -> &'a mut bool {
+    match flag {
+        "monitor_cpu" => &mut config.monitor_cpu,
+        "monitor_ram" => &mut config.monitor_ram,
+        "monitor_network" => &mut config.monitor_network,
+        "monitor_disk" => &mut config.monitor_disk,
+        _ => &mut config.monitor_processes,
+    }
+}
+
+/// Registry key of the function set with `MAVIS.monitor.on_update`
+const UPDATE_HANDLER_KEY: &str = "mavis.monitor.on_update";
+
+/// How often the `on_update` handler wants samples, kept as app data
+struct UpdateThrottle {
+    min_interval: Duration,
+    last_called: Option<Instant>,
+}
+
+/// Pass a new sample to the handler set with `MAVIS.monitor.on_update`,
+/// unless it was called less than its interval ago.
+pub fn call_update_handler(lua: &Lua, usage: &ResourceUsage) -> mlua::Result<()> {
+    let Some(handler) = lua.named_registry_value::<Option<Function>>(UPDATE_HANDLER_KEY)? else {
+        return Ok(());
+    };
+    if let Some(mut throttle) = lua.app_data_mut::<UpdateThrottle>() {
+        let now = Instant::now();
+        if throttle
+            .last_called
+            .is_some_and(|last| now.saturating_duration_since(last) < throttle.min_interval)
+        {
+            return Ok(());
+        }
+        throttle.last_called = Some(now);
+    }
+
+    let entry = lua.create_table()?;
+    for metric in Metric::ALL {
+        entry.set(metric.name(), metric.value(usage))?;
+    }
+    entry.set("used_memory", usage.used_memory)?;
+    entry.set("total_memory", usage.total_memory)?;
+    entry.set("disk_free", usage.disk_free_bytes)?;
+    handler.call(entry)
+}
+
+/// Registry key of the function set with `MAVIS.monitor.on_alert`
+const ALERT_HANDLER_KEY: &str = "mavis.monitor.on_alert";
+
+/// Registry key of the table of per-rule callbacks, by rule name
+const ALERT_CALLBACKS_KEY: &str = "mavis.monitor.alert_callbacks";
+
+fn register_alert_functions(lua: &Lua, table: &Table, alerts: SharedAlerts) -> Result<(), CoreError> {
+    lua.set_named_registry_value(ALERT_CALLBACKS_KEY, lua.create_table()?)
+        .map_err(|e| CoreError::LuaError(mlua::Error::external(format!("Failed to create alert callbacks: {}", e))))?;
+
+    // Add or replace a rule:
+    // MAVIS.monitor.add_alert{name, metric, above | below, [clear], [seconds],
+    // [severity], [callback]}. `metric` is a metric name or "volume_free";
+    // `callback` gets every event of this rule
+    let shared = alerts.clone();
+    let add_alert_fn = lua.create_function(move |lua_ctx, spec: Table| {
+        let name: String = spec.get("name")?;
+        let metric: String = spec.get("metric")?;
+        let source = AlertSource::from_name(&metric)
+            .ok_or_else(|| mlua::Error::RuntimeError(format!("Unknown metric: {}", metric)))?;
+        let mut rule = match (spec.get::<_, Option<f64>>("above")?, spec.get::<_, Option<f64>>("below")?) {
+            (Some(threshold), None) => AlertRule::above(&name, source, threshold),
+            (None, Some(threshold)) => AlertRule::below(&name, source, threshold),
+            _ => return Err(mlua::Error::RuntimeError("Alert needs exactly one of above or below".to_string())),
+        };
+        if let Some(clear) = spec.get::<_, Option<f64>>("clear")? {
+            let recovers = match rule.comparison {
+                Comparison::Above => clear <= rule.threshold,
+                Comparison::Below => clear >= rule.threshold,
+            };
+            if !recovers {
+                return Err(mlua::Error::RuntimeError(format!("Alert clear value {} is past its threshold", clear)));
+            }
+            rule.clear_at = clear;
+        }
+        if let Some(seconds) = spec.get::<_, Option<f64>>("seconds")? {
+            rule.sustain = Duration::try_from_secs_f64(seconds)
+                .map_err(|_| mlua::Error::RuntimeError(format!("Invalid duration: {} seconds", seconds)))?;
+        }
+        if let Some(severity) = spec.get::<_, Option<String>>("severity")? {
+            rule.severity = Severity::from_name(&severity)
+                .ok_or_else(|| mlua::Error::RuntimeError(format!("Unknown severity: {}", severity)))?;
+        }
+
+        let callbacks: Table = lua_ctx.named_registry_value(ALERT_CALLBACKS_KEY)?;
+        callbacks.set(name.as_str(), spec.get::<_, Option<Function>>("callback")?)?;
+        lock_alerts(&shared)?.add_rule(rule);
+        Ok(())
+    })
+    .map_err(|e| CoreError::LuaError(mlua::Error::external(format!("Failed to create add_alert function: {}", e))))?;
+
+    table.set("add_alert", add_alert_fn)
+        .map_err(|e| CoreError::LuaError(mlua::Error::external(format!("Failed to set add_alert function: {}", e))))?;
+
+    // Remove a rule, clearing its alerts: MAVIS.monitor.remove_alert(name) -> bool
+    let shared = alerts.clone();
+    let remove_alert_fn = lua.create_function(move |lua_ctx, name: String| {
+        let callbacks: Table = lua_ctx.named_registry_value(ALERT_CALLBACKS_KEY)?;
+        callbacks.set(name.as_str(), mlua::Value::Nil)?;
+        Ok(lock_alerts(&shared)?.remove_rule(&name))
+    })
+    .map_err(|e| CoreError::LuaError(mlua::Error::external(format!("Failed to create remove_alert function: {}", e))))?;
+
+    table.set("remove_alert", remove_alert_fn)
+        .map_err(|e| CoreError::LuaError(mlua::Error::external(format!("Failed to set remove_alert function: {}", e))))?;
+
+    // Current rules:
+    // MAVIS.monitor.alert_rules() -> { {name, metric, above | below, clear, seconds, severity}, ... }
+    let shared = alerts.clone();
+    let alert_rules_fn = lua.create_function(move |lua_ctx, ()| {
+        let engine = lock_alerts(&shared)?;
+        let rules = lua_ctx.create_table()?;
+        for (i, rule) in engine.rules().iter().enumerate() {
+            let entry = lua_ctx.create_table()?;
+            entry.set("name", rule.name.as_str())?;
+            entry.set("metric", rule.source.name())?;
+            let side = match rule.comparison {
+                Comparison::Above => "above",
+                Comparison::Below => "below",
+            };
+            entry.set(side, rule.threshold)?;
+            entry.set("clear", rule.clear_at)?;
+            entry.set("seconds", rule.sustain.as_secs_f64())?;
+            entry.set("severity", rule.severity.name())?;
+            rules.set(i + 1, entry)?;
+        }
+        Ok(rules)
+    })
+    .map_err(|e| CoreError::LuaError(mlua::Error::external(format!("Failed to create alert_rules function: {}", e))))?;
+
+    table.set("alert_rules", alert_rules_fn)
+        .map_err(|e| CoreError::LuaError(mlua::Error::external(format!("Failed to set alert_rules function: {}", e))))?;
+
+    // Alerts firing now: MAVIS.monitor.active_alerts() -> { alert, ... }
+    let shared = alerts;
+    let active_alerts_fn = lua.create_function(move |lua_ctx, ()| {
+        let engine = lock_alerts(&shared)?;
+        let active = lua_ctx.create_table()?;
+        for (i, event) in engine.active().into_iter().enumerate() {
+            active.set(i + 1, alert_table(lua_ctx, event)?)?;
+        }
+        Ok(active)
+    })
+    .map_err(|e| CoreError::LuaError(mlua::Error::external(format!("Failed to create active_alerts function: {}", e))))?;
+
+    table.set("active_alerts", active_alerts_fn)
+        .map_err(|e| CoreError::LuaError(mlua::Error::external(format!("Failed to set active_alerts function: {}", e))))?;
+
+    // Called for every alert that fires or clears:
+    // MAVIS.monitor.on_alert(function(alert) ... end), or nil to remove. The
+    // handler gets {rule, subject, kind, severity, value, threshold, message, time}
+    let on_alert_fn = lua.create_function(|lua_ctx, handler: Option<Function>| {
+        lua_ctx.set_named_registry_value(ALERT_HANDLER_KEY, handler)
+    })
+    .map_err(|e| CoreError::LuaError(mlua::Error::external(format!("Failed to create on_alert function: {}", e))))?;
+
+    table.set("on_alert", on_alert_fn)
+        .map_err(|e| CoreError::LuaError(mlua::Error::external(format!("Failed to set on_alert function: {}", e))))?;
+
+    Ok(())
+}
+
+/// Pass an alert event to the callback of its rule and to the handler set
+/// with `MAVIS.monitor.on_alert`, whichever are set.
+pub fn call_alert_handlers(lua: &Lua, event: &AlertEvent) -> mlua::Result<()> {
+    let callbacks: Option<Table> = lua.named_registry_value(ALERT_CALLBACKS_KEY)?;
+    let callback = match callbacks {
+        Some(callbacks) => callbacks.get::<_, Option<Function>>(event.rule.as_str())?,
+        None => None,
+    };
+    let handler = lua.named_registry_value::<Option<Function>>(ALERT_HANDLER_KEY)?;
+    for function in callback.into_iter().chain(handler) {
+        function.call::<_, ()>(alert_table(lua, event)?)?;
+    }
+    Ok(())
+}
+
+fn alert_table<'a>(lua: &'a Lua, event: &AlertEvent) -> mlua::Result<Table<'a>> {
+    let entry = lua.create_table()?;
+    entry.set("rule", event.rule.as_str())?;
+    entry.set("subject", event.subject.as_deref())?;
+    let kind = match event.kind {
+        AlertKind::Fired => "fired",
+        AlertKind::Cleared => "cleared",
+    };
+    entry.set("kind", kind)?;
+    entry.set("severity", event.severity.name())?;
+    entry.set("value", event.value)?;
+    entry.set("threshold", event.threshold)?;
+    entry.set("message", event.message.as_str())?;
+    entry.set("time", event.at.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs_f64())?;
+    Ok(entry)
+}
+
+fn lock(history: &SharedHistory) -> mlua::Result<MutexGuard<'_, MetricsHistory>> {
+    history
+        .lock()
+        .map_err(|_| mlua::Error::RuntimeError("Metrics history mutex poisoned".to_string()))
+}
+
+fn lock_alerts(alerts: &SharedAlerts) -> mlua::Result<MutexGuard<'_, AlertEngine>> {
+    alerts
+        .lock()
+        .map_err(|_| mlua::Error::RuntimeError("Alert engine mutex poisoned".to_string()))
+}
+
+fn series<'a>(history: &'a MetricsHistory, name: &str) -> mlua::Result<&'a Series> {
+    let metric = Metric::from_name(name).ok_or_else(|| mlua::Error::RuntimeError(format!("Unknown metric: {}", name)))?;
+    Ok(history.series(metric))
+}
+
+/// The window to query: `seconds` if given, else the full-resolution
+/// retention.
+fn window(history: &MetricsHistory, seconds: Option<f64>) -> mlua::Result<Duration> {
+    match seconds {
+        None => Ok(history.retention().full),
+        Some(seconds) => Duration::try_from_secs_f64(seconds)
+            .map_err(|_| mlua::Error::RuntimeError(format!("Invalid window: {} seconds", seconds))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::monitor::ResourceMonitor;
+    use crate::monitor::history::Retention;
+    use std::sync::{Arc, Mutex};
+    use std::time::Instant;
+
+    fn alerts() -> SharedAlerts {
+        Arc::new(Mutex::new(AlertEngine::new(Vec::new())))
+    }
+
+    fn control() -> MonitorControl {
+        ResourceMonitor::new(&Config::default()).unwrap().control()
+    }
+
+    #[test]
+    fn test_register_monitor_functions() {
+        let history = Arc::new(Mutex::new(MetricsHistory::new(Retention {
+            full: Duration::from_secs(60),
+            downsampled: Duration::from_secs(600),
+            bucket: Duration::from_secs(10),
+        })));
+        let start = Instant::now();
+        for (second, cpu_usage) in [10.0, 30.0, 20.0].into_iter().enumerate() {
+            history.lock().unwrap().record(&ResourceUsage {
+                cpu_usage,
+                timestamp: start + Duration::from_secs(second as u64),
+                ..Default::default()
+            });
+        }
+
+        let lua = Lua::new();
+        let monitor_table = lua.create_table().unwrap();
+        register_monitor_functions(&lua, &monitor_table, history, alerts(), control()).unwrap();
+        lua.globals().set("monitor", monitor_table).unwrap();
+
+        let metrics: Vec<String> = lua.load("return monitor.metrics()").eval().unwrap();
+        assert_eq!(metrics.len(), Metric::ALL.len());
+        assert_eq!(lua.load("return monitor.latest('cpu_usage')").eval::<f64>().unwrap(), 20.0);
+        let values: Vec<f64> = lua.load("return monitor.history('cpu_usage')").eval().unwrap();
+        assert_eq!(values, [10.0, 30.0, 20.0]);
+        let values: Vec<f64> = lua.load("return monitor.history('cpu_usage', 1)").eval().unwrap();
+        assert_eq!(values, [30.0, 20.0]);
+        let (max, avg): (f64, f64) = lua.load("local s = monitor.stats('cpu_usage') return s.max, s.avg").eval().unwrap();
+        assert_eq!((max, avg), (30.0, 20.0));
+        assert_eq!(lua.load("return monitor.percentile('cpu_usage', 50)").eval::<f64>().unwrap(), 20.0);
+        assert!(lua.load("return monitor.history('gpu')").exec().is_err());
+        assert!(lua.load("return monitor.history('cpu_usage', -1)").exec().is_err());
+    }
+
+    #[test]
+    fn test_update_handler_is_throttled() {
+        let lua = Lua::new();
+        let monitor_table = lua.create_table().unwrap();
+        register_monitor_functions(&lua, &monitor_table, SharedHistory::default(), alerts(), control()).unwrap();
+        lua.globals().set("monitor", monitor_table).unwrap();
+        let usage = ResourceUsage {
+            cpu_usage: 25.0,
+            used_memory: 1024,
+            ..Default::default()
+        };
+
+        // Nothing to call yet
+        call_update_handler(&lua, &usage).unwrap();
+        lua.load("seen = {} monitor.on_update(function(usage) table.insert(seen, usage.cpu_usage) end, 3600)")
+            .exec()
+            .unwrap();
+        call_update_handler(&lua, &usage).unwrap();
+        call_update_handler(&lua, &usage).unwrap();
+        let seen: Vec<f64> = lua.load("return seen").eval().unwrap();
+        assert_eq!(seen, [25.0]);
+
+        lua.load("monitor.on_update(function(usage) table.insert(seen, usage.used_memory) end)").exec().unwrap();
+        call_update_handler(&lua, &usage).unwrap();
+        call_update_handler(&lua, &usage).unwrap();
+        let seen: Vec<f64> = lua.load("return seen").eval().unwrap();
+        assert_eq!(seen, [25.0, 1024.0, 1024.0]);
+        assert!(lua.load("monitor.on_update(nil, -1)").exec().is_err());
+    }
+
+    #[test]
+    fn test_alert_functions() {
+        let alerts = alerts();
+        let lua = Lua::new();
+        let monitor_table = lua.create_table().unwrap();
+        register_monitor_functions(&lua, &monitor_table, SharedHistory::default(), alerts.clone(), control()).unwrap();
+        lua.globals().set("monitor", monitor_table).unwrap();
+
+        lua.load(
+            r#"
+            seen = {}
+            monitor.add_alert{ name = "busy", metric = "cpu_usage", above = 80, clear = 70, severity = "critical",
+                callback = function(alert) table.insert(seen, "rule " .. alert.kind) end }
+            monitor.on_alert(function(alert) table.insert(seen, "all " .. alert.message) end)
+            "#,
+        )
+        .exec()
+        .unwrap();
+        let (threshold, clear, severity): (f64, f64, String) = lua
+            .load("local r = monitor.alert_rules()[1] return r.above, r.clear, r.severity")
+            .eval()
+            .unwrap();
+        assert_eq!((threshold, clear, severity.as_str()), (80.0, 70.0, "critical"));
+        assert!(lua.load("monitor.add_alert{ name = 'x', metric = 'gpu', above = 1 }").exec().is_err());
+        assert!(lua.load("monitor.add_alert{ name = 'x', metric = 'cpu_usage', above = 1, clear = 2 }").exec().is_err());
+
+        let events = alerts.lock().unwrap().evaluate(&crate::monitor::ResourceUsage {
+            cpu_usage: 90.0,
+            ..Default::default()
+        });
+        assert_eq!(events.len(), 1);
+        assert_eq!(lua.load("return #monitor.active_alerts()").eval::<usize>().unwrap(), 1);
+        call_alert_handlers(&lua, &events[0]).unwrap();
+        let seen: Vec<String> = lua.load("return seen").eval().unwrap();
+        assert_eq!(seen, ["rule fired".to_string(), format!("all {}", events[0].message)]);
+
+        assert!(lua.load("return monitor.remove_alert('busy')").eval::<bool>().unwrap());
+        assert!(alerts.lock().unwrap().active().is_empty());
+    }
+
+    #[test]
+    fn test_settings_functions() {
+        let monitor = ResourceMonitor::new(&Config::default()).unwrap();
+        let lua = Lua::new();
+        let monitor_table = lua.create_table().unwrap();
+        register_monitor_functions(&lua, &monitor_table, monitor.history(), monitor.alerts(), monitor.control()).unwrap();
+        lua.globals().set("monitor", monitor_table).unwrap();
+
+        assert_eq!(lua.load("return monitor.settings().update_interval_ms").eval::<u32>().unwrap(), 500);
+        lua.load("monitor.configure{ update_interval_ms = 2000, monitor_disk = false }").exec().unwrap();
+        let settings = monitor.settings();
+        assert_eq!((settings.update_interval_ms, settings.monitor_disk, settings.monitor_cpu), (2000, false, true));
+        assert!(!lua.load("return monitor.settings().monitor_disk").eval::<bool>().unwrap());
+        assert!(lua.load("monitor.configure{ update_interval_ms = 0 }").exec().is_err());
+        assert!(lua.load("monitor.configure{ monitor_gpu = true }").exec().is_err());
+        assert_eq!(monitor.settings().update_interval_ms, 2000);
+        // Nothing sampled yet
+        assert_eq!(lua.load("return #monitor.sources()").eval::<usize>().unwrap(), 0);
+    }
+}
@@ -0,0 +1,428 @@
+// Terminal API functions for Lua scripts
+// These functions open, close and focus terminal tabs, search their output,
+// record and replay sessions, and configure how links in the output are
+// found and opened
+
+use crate::error::CoreError;
+use crate::terminal::{Link, LinkDetector, LinkKind, LinkRule, LinkTarget, SearchOptions, SessionManager, SharedSessionManager, search};
+use mlua::{Function, Lua, Table};
+use std::sync::MutexGuard;
+
+/// Register terminal session functions in the provided table
+pub fn register_terminal_functions(lua: &Lua, table: &Table, sessions: SharedSessionManager) -> Result<(), CoreError> {
+    // Open a tab for a named profile, or the default shell: MAVIS.terminal.open([profile]) -> id
+    let manager = sessions.clone();
+    let open_fn = lua.create_function(move |_, profile: Option<String>| {
+        let mut manager = lock(&manager)?;
+        let result = match profile {
+            Some(name) => manager.open_profile(&name),
+            None => manager.open_default(),
+        };
+        result.map_err(|e| mlua::Error::RuntimeError(format!("Failed to open terminal: {}", e)))
+    })
+    .map_err(|e| CoreError::LuaError(mlua::Error::external(format!("Failed to create open function: {}", e))))?;
+
+    table.set("open", open_fn)
+        .map_err(|e| CoreError::LuaError(mlua::Error::external(format!("Failed to set open function: {}", e))))?;
+
+    // Close a tab: MAVIS.terminal.close(id) -> bool
+    let manager = sessions.clone();
+    let close_fn = lua.create_function(move |_, id: u32| Ok(lock(&manager)?.close(id)))
+        .map_err(|e| CoreError::LuaError(mlua::Error::external(format!("Failed to create close function: {}", e))))?;
+
+    table.set("close", close_fn)
+        .map_err(|e| CoreError::LuaError(mlua::Error::external(format!("Failed to set close function: {}", e))))?;
+
+    // Switch to a tab: MAVIS.terminal.focus(id) -> bool
+    let manager = sessions.clone();
+    let focus_fn = lua.create_function(move |_, id: u32| Ok(lock(&manager)?.set_active(id)))
+        .map_err(|e| CoreError::LuaError(mlua::Error::external(format!("Failed to create focus function: {}", e))))?;
+
+    table.set("focus", focus_fn)
+        .map_err(|e| CoreError::LuaError(mlua::Error::external(format!("Failed to set focus function: {}", e))))?;
+
+    // Start a tab's shell again: MAVIS.terminal.restart(id) -> bool
+    let manager = sessions.clone();
+    let restart_fn = lua.create_function(move |_, id: u32| {
+        lock(&manager)?
+            .restart(id)
+            .map_err(|e| mlua::Error::RuntimeError(format!("Failed to restart terminal: {}", e)))
+    })
+    .map_err(|e| CoreError::LuaError(mlua::Error::external(format!("Failed to create restart function: {}", e))))?;
+
+    table.set("restart", restart_fn)
+        .map_err(|e| CoreError::LuaError(mlua::Error::external(format!("Failed to set restart function: {}", e))))?;
+
+    // Id of the active tab, or nil: MAVIS.terminal.active()
+    let manager = sessions.clone();
+    let active_fn = lua.create_function(move |_, ()| Ok(lock(&manager)?.active_id()))
+        .map_err(|e| CoreError::LuaError(mlua::Error::external(format!("Failed to create active function: {}", e))))?;
+
+    table.set("active", active_fn)
+        .map_err(|e| CoreError::LuaError(mlua::Error::external(format!("Failed to set active function: {}", e))))?;
+
+    // Open tabs in order:
+    // MAVIS.terminal.list() -> { {id, title, profile, exited, exit_code, recording, replay}, ... }
+    let manager = sessions.clone();
+    let list_fn = lua.create_function(move |lua_ctx, ()| {
+        let manager = lock(&manager)?;
+        let list = lua_ctx.create_table()?;
+        for (index, session) in manager.sessions().enumerate() {
+            let entry = lua_ctx.create_table()?;
+            entry.set("id", session.id())?;
+            entry.set("title", session.title())?;
+            entry.set("profile", session.profile())?;
+            entry.set("exited", session.has_exited())?;
+            entry.set("exit_code", session.exit().and_then(|exit| exit.code))?;
+            entry.set("recording", session.is_recording())?;
+            entry.set("replay", session.is_replay())?;
+            list.set(index + 1, entry)?;
+        }
+        Ok(list)
+    })
+    .map_err(|e| CoreError::LuaError(mlua::Error::external(format!("Failed to create list function: {}", e))))?;
+
+    table.set("list", list_fn)
+        .map_err(|e| CoreError::LuaError(mlua::Error::external(format!("Failed to set list function: {}", e))))?;
+
+    // Find text in a tab's scrollback and screen:
+    // MAVIS.terminal.search(id, query, [{regex, case_sensitive}])
+    //   -> { {line, col, end_line, end_col, text}, ... }, oldest first, 1-based
+    let manager = sessions.clone();
+    let search_fn = lua.create_function(move |lua_ctx, (id, query, options): (u32, String, Option<Table>)| {
+        let mut search_options = SearchOptions::default();
+        if let Some(options) = options {
+            search_options.regex = options.get::<_, Option<bool>>("regex")?.unwrap_or(false);
+            search_options.case_sensitive = options.get::<_, Option<bool>>("case_sensitive")?.unwrap_or(false);
+        }
+
+        let manager = lock(&manager)?;
+        let session = manager
+            .get(id)
+            .ok_or_else(|| mlua::Error::RuntimeError(format!("No terminal session with id {}", id)))?;
+        let screen = session.terminal().screen();
+        let matches = search::find(screen, &query, search_options)
+            .map_err(|e| mlua::Error::RuntimeError(format!("Invalid search pattern: {}", e)))?;
+
+        let list = lua_ctx.create_table()?;
+        for (index, found) in matches.iter().enumerate() {
+            let entry = lua_ctx.create_table()?;
+            entry.set("line", found.start.line + 1)?;
+            entry.set("col", found.start.col + 1)?;
+            entry.set("end_line", found.end.line + 1)?;
+            entry.set("end_col", found.end.col + 1)?;
+            entry.set("text", found.text(screen))?;
+            list.set(index + 1, entry)?;
+        }
+        Ok(list)
+    })
+    .map_err(|e| CoreError::LuaError(mlua::Error::external(format!("Failed to create search function: {}", e))))?;
+
+    table.set("search", search_fn)
+        .map_err(|e| CoreError::LuaError(mlua::Error::external(format!("Failed to set search function: {}", e))))?;
+
+    // Commands run in a tab, from shell integration marks:
+    // MAVIS.terminal.commands(id)
+    //   -> { {command, exit_code, cwd, line, output_line, running}, ... }, oldest first, 1-based
+    let manager = sessions.clone();
+    let commands_fn = lua.create_function(move |lua_ctx, id: u32| {
+        let manager = lock(&manager)?;
+        let session = manager
+            .get(id)
+            .ok_or_else(|| mlua::Error::RuntimeError(format!("No terminal session with id {}", id)))?;
+
+        let list = lua_ctx.create_table()?;
+        for (index, command) in session.terminal().commands().iter().enumerate() {
+            let entry = lua_ctx.create_table()?;
+            entry.set("command", command.text.as_deref())?;
+            entry.set("exit_code", command.exit_code)?;
+            entry.set("cwd", command.cwd.as_deref())?;
+            entry.set("line", command.prompt.line + 1)?;
+            entry.set("output_line", command.output.map(|point| point.line + 1))?;
+            entry.set("running", command.is_running())?;
+            list.set(index + 1, entry)?;
+        }
+        Ok(list)
+    })
+    .map_err(|e| CoreError::LuaError(mlua::Error::external(format!("Failed to create commands function: {}", e))))?;
+
+    table.set("commands", commands_fn)
+        .map_err(|e| CoreError::LuaError(mlua::Error::external(format!("Failed to set commands function: {}", e))))?;
+
+    // Directory the tab's shell last reported: MAVIS.terminal.cwd(id) -> string or nil
+    let manager = sessions.clone();
+    let cwd_fn = lua.create_function(move |_, id: u32| {
+        let manager = lock(&manager)?;
+        Ok(manager.get(id).and_then(|session| session.terminal().working_directory().map(str::to_string)))
+    })
+    .map_err(|e| CoreError::LuaError(mlua::Error::external(format!("Failed to create cwd function: {}", e))))?;
+
+    table.set("cwd", cwd_fn)
+        .map_err(|e| CoreError::LuaError(mlua::Error::external(format!("Failed to set cwd function: {}", e))))?;
+
+    // Output of the last finished command: MAVIS.terminal.last_output(id) -> string or nil
+    let manager = sessions.clone();
+    let last_output_fn = lua.create_function(move |_, id: u32| {
+        let manager = lock(&manager)?;
+        Ok(manager.get(id).and_then(|session| session.terminal().last_output_text()))
+    })
+    .map_err(|e| CoreError::LuaError(mlua::Error::external(format!("Failed to create last_output function: {}", e))))?;
+
+    table.set("last_output", last_output_fn)
+        .map_err(|e| CoreError::LuaError(mlua::Error::external(format!("Failed to set last_output function: {}", e))))?;
+
+    // Record a tab's output to an asciicast v2 file: MAVIS.terminal.record(id, path) -> bool
+    let manager = sessions.clone();
+    let record_fn = lua.create_function(move |_, (id, path): (u32, String)| {
+        let mut manager = lock(&manager)?;
+        let Some(session) = manager.get_mut(id) else {
+            return Ok(false);
+        };
+        session
+            .start_recording(&path)
+            .map(|()| true)
+            .map_err(|e| mlua::Error::RuntimeError(format!("Failed to start recording: {}", e)))
+    })
+    .map_err(|e| CoreError::LuaError(mlua::Error::external(format!("Failed to create record function: {}", e))))?;
+
+    table.set("record", record_fn)
+        .map_err(|e| CoreError::LuaError(mlua::Error::external(format!("Failed to set record function: {}", e))))?;
+
+    // Finish a tab's recording: MAVIS.terminal.stop_recording(id) -> bool
+    let manager = sessions.clone();
+    let stop_recording_fn = lua.create_function(move |_, id: u32| {
+        let mut manager = lock(&manager)?;
+        match manager.get_mut(id) {
+            Some(session) => session
+                .stop_recording()
+                .map_err(|e| mlua::Error::RuntimeError(format!("Failed to stop recording: {}", e))),
+            None => Ok(false),
+        }
+    })
+    .map_err(|e| CoreError::LuaError(mlua::Error::external(format!("Failed to create stop_recording function: {}", e))))?;
+
+    table.set("stop_recording", stop_recording_fn)
+        .map_err(|e| CoreError::LuaError(mlua::Error::external(format!("Failed to set stop_recording function: {}", e))))?;
+
+    // Replay an asciicast in a new tab: MAVIS.terminal.replay(path, [speed]) -> id
+    let manager = sessions.clone();
+    let replay_fn = lua.create_function(move |_, (path, speed): (String, Option<f64>)| {
+        lock(&manager)?
+            .open_replay(&path, speed.unwrap_or(1.0))
+            .map_err(|e| mlua::Error::RuntimeError(format!("Failed to replay recording: {}", e)))
+    })
+    .map_err(|e| CoreError::LuaError(mlua::Error::external(format!("Failed to create replay function: {}", e))))?;
+
+    table.set("replay", replay_fn)
+        .map_err(|e| CoreError::LuaError(mlua::Error::external(format!("Failed to set replay function: {}", e))))?;
+
+    // Change a replay's speed: MAVIS.terminal.set_replay_speed(id, speed) -> bool
+    let manager = sessions.clone();
+    let set_replay_speed_fn = lua.create_function(move |_, (id, speed): (u32, f64)| {
+        let manager = lock(&manager)?;
+        Ok(manager.get(id).is_some_and(|session| session.set_replay_speed(speed)))
+    })
+    .map_err(|e| CoreError::LuaError(mlua::Error::external(format!("Failed to create set_replay_speed function: {}", e))))?;
+
+    table.set("set_replay_speed", set_replay_speed_fn)
+        .map_err(|e| CoreError::LuaError(mlua::Error::external(format!("Failed to set set_replay_speed function: {}", e))))?;
+
+    // Handle activated links instead of the default action:
+    // MAVIS.terminal.on_link(function(link) ... end), or nil to remove.
+    // The handler gets {kind, url | path, line, col, text} and returns true
+    // when it opened the link itself
+    let on_link_fn = lua.create_function(|lua_ctx, handler: Option<Function>| {
+        lua_ctx.set_named_registry_value(LINK_HANDLER_KEY, handler)
+    })
+    .map_err(|e| CoreError::LuaError(mlua::Error::external(format!("Failed to create on_link function: {}", e))))?;
+
+    table.set("on_link", on_link_fn)
+        .map_err(|e| CoreError::LuaError(mlua::Error::external(format!("Failed to set on_link function: {}", e))))?;
+
+    register_link_rule_functions(lua, table, sessions.clone())?;
+
+    // Configured profile names: MAVIS.terminal.profiles() -> { "name", ... }
+    let manager = sessions;
+    let profiles_fn = lua.create_function(move |_, ()| {
+        let manager = lock(&manager)?;
+        Ok(manager.profiles().iter().map(|profile| profile.name.clone()).collect::<Vec<_>>())
+    })
+    .map_err(|e| CoreError::LuaError(mlua::Error::external(format!("Failed to create profiles function: {}", e))))?;
+
+    table.set("profiles", profiles_fn)
+        .map_err(|e| CoreError::LuaError(mlua::Error::external(format!("Failed to set profiles function: {}", e))))?;
+
+    Ok(())
+}
+
+/// Register `MAVIS.terminal.links`, the rules that find links in output
+fn register_link_rule_functions(lua: &Lua, table: &Table, sessions: SharedSessionManager) -> Result<(), CoreError> {
+    let links_table = super::create_nested_table(lua, table, "links")?;
+
+    // Add or replace a rule: MAVIS.terminal.links.add_rule(name, "url" | "file", pattern)
+    // File rules may name `path`, `line` and `col` capture groups
+    let manager = sessions.clone();
+    let add_rule_fn = lua.create_function(move |_, (name, kind, pattern): (String, String, String)| {
+        let kind = LinkKind::parse(&kind)
+            .ok_or_else(|| mlua::Error::RuntimeError(format!("Unknown link kind '{}', expected 'url' or 'file'", kind)))?;
+        let rule = LinkRule::new(&name, kind, &pattern)
+            .map_err(|e| mlua::Error::RuntimeError(format!("Invalid link pattern: {}", e)))?;
+        lock(&manager)?.link_detector_mut().add_rule(rule);
+        Ok(())
+    })
+    .map_err(|e| CoreError::LuaError(mlua::Error::external(format!("Failed to create add_rule function: {}", e))))?;
+
+    links_table.set("add_rule", add_rule_fn)
+        .map_err(|e| CoreError::LuaError(mlua::Error::external(format!("Failed to set add_rule function: {}", e))))?;
+
+    // Remove a rule by name: MAVIS.terminal.links.remove_rule(name) -> bool
+    let manager = sessions.clone();
+    let remove_rule_fn = lua.create_function(move |_, name: String| Ok(lock(&manager)?.link_detector_mut().remove_rule(&name)))
+        .map_err(|e| CoreError::LuaError(mlua::Error::external(format!("Failed to create remove_rule function: {}", e))))?;
+
+    links_table.set("remove_rule", remove_rule_fn)
+        .map_err(|e| CoreError::LuaError(mlua::Error::external(format!("Failed to set remove_rule function: {}", e))))?;
+
+    // Rules in the order they are tried: MAVIS.terminal.links.rules() -> { {name, kind, pattern}, ... }
+    let manager = sessions.clone();
+    let rules_fn = lua.create_function(move |lua_ctx, ()| {
+        let manager = lock(&manager)?;
+        let list = lua_ctx.create_table()?;
+        for (index, rule) in manager.link_detector().rules().iter().enumerate() {
+            let entry = lua_ctx.create_table()?;
+            entry.set("name", rule.name())?;
+            entry.set("kind", rule.kind().as_str())?;
+            entry.set("pattern", rule.pattern())?;
+            list.set(index + 1, entry)?;
+        }
+        Ok(list)
+    })
+    .map_err(|e| CoreError::LuaError(mlua::Error::external(format!("Failed to create rules function: {}", e))))?;
+
+    links_table.set("rules", rules_fn)
+        .map_err(|e| CoreError::LuaError(mlua::Error::external(format!("Failed to set rules function: {}", e))))?;
+
+    // Go back to the built-in URL and file location rules: MAVIS.terminal.links.reset()
+    let manager = sessions;
+    let reset_fn = lua.create_function(move |_, ()| {
+        *lock(&manager)?.link_detector_mut() = LinkDetector::default();
+        Ok(())
+    })
+    .map_err(|e| CoreError::LuaError(mlua::Error::external(format!("Failed to create reset function: {}", e))))?;
+
+    links_table.set("reset", reset_fn)
+        .map_err(|e| CoreError::LuaError(mlua::Error::external(format!("Failed to set reset function: {}", e))))?;
+
+    Ok(())
+}
+
+/// Registry key of the function set with `MAVIS.terminal.on_link`
+const LINK_HANDLER_KEY: &str = "mavis.terminal.on_link";
+
+/// Pass an activated link to the script handler, if one is set. Returns
+/// true when the handler took care of it.
+pub fn call_link_handler(lua: &Lua, link: &Link) -> mlua::Result<bool> {
+    let Some(handler) = lua.named_registry_value::<Option<Function>>(LINK_HANDLER_KEY)? else {
+        return Ok(false);
+    };
+
+    let entry = lua.create_table()?;
+    entry.set("text", link.text.as_str())?;
+    match &link.target {
+        LinkTarget::Url(url) => {
+            entry.set("kind", "url")?;
+            entry.set("url", url.as_str())?;
+        }
+        LinkTarget::File { path, line, column } => {
+            entry.set("kind", "file")?;
+            entry.set("path", path.as_str())?;
+            entry.set("line", *line)?;
+            entry.set("col", *column)?;
+        }
+    }
+    let handled: Option<bool> = handler.call(entry)?;
+    Ok(handled.unwrap_or(false))
+}
+
+fn lock(manager: &SharedSessionManager) -> mlua::Result<MutexGuard<'_, SessionManager>> {
+    manager
+        .lock()
+        .map_err(|_| mlua::Error::RuntimeError("Terminal session manager mutex poisoned".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Config, TerminalProfile};
+
+    #[test]
+    fn test_register_terminal_functions() {
+        let mut config = Config::default().terminal;
+        config.profiles.push(TerminalProfile {
+            name: "pwsh".to_string(),
+            command: Some("pwsh.exe -NoLogo".to_string()),
+            ..Default::default()
+        });
+        let sessions = SessionManager::shared(config);
+
+        let lua = Lua::new();
+        let terminal_table = lua.create_table().unwrap();
+        register_terminal_functions(&lua, &terminal_table, sessions).unwrap();
+        lua.globals().set("terminal", terminal_table).unwrap();
+
+        let profiles: Vec<String> = lua.load("return terminal.profiles()").eval().unwrap();
+        assert_eq!(profiles, vec!["pwsh"]);
+        let count: usize = lua.load("return #terminal.list()").eval().unwrap();
+        assert_eq!(count, 0);
+        assert!(lua.load("return terminal.active()").eval::<Option<u32>>().unwrap().is_none());
+        assert!(lua.load("terminal.open('missing')").exec().is_err());
+        assert!(!lua.load("return terminal.focus(42)").eval::<bool>().unwrap());
+        assert!(!lua.load("return terminal.restart(42)").eval::<bool>().unwrap());
+        assert!(lua.load("terminal.search(42, 'x')").exec().is_err());
+        assert!(lua.load("terminal.commands(42)").exec().is_err());
+        assert!(lua.load("return terminal.cwd(42)").eval::<Option<String>>().unwrap().is_none());
+        assert!(lua.load("return terminal.last_output(42)").eval::<Option<String>>().unwrap().is_none());
+        assert!(!lua.load("return terminal.record(42, 'x.cast')").eval::<bool>().unwrap());
+        assert!(!lua.load("return terminal.stop_recording(42)").eval::<bool>().unwrap());
+        assert!(!lua.load("return terminal.set_replay_speed(42, 2)").eval::<bool>().unwrap());
+        assert!(lua.load("terminal.replay('missing.cast')").exec().is_err());
+    }
+
+    #[test]
+    fn test_link_rules_and_handler() {
+        let sessions = SessionManager::shared(Config::default().terminal);
+        let lua = Lua::new();
+        let terminal_table = lua.create_table().unwrap();
+        register_terminal_functions(&lua, &terminal_table, sessions.clone()).unwrap();
+        lua.globals().set("terminal", terminal_table).unwrap();
+
+        lua.load("terminal.links.add_rule('ticket', 'url', [[JIRA-\\d+]])").exec().unwrap();
+        assert_eq!(sessions.lock().unwrap().link_detector().rules().len(), 3);
+        assert!(lua.load("terminal.links.add_rule('bad', 'url', '(')").exec().is_err());
+        assert!(lua.load("terminal.links.add_rule('bad', 'mail', 'x')").exec().is_err());
+        let kind: String = lua.load("return terminal.links.rules()[3].kind").eval().unwrap();
+        assert_eq!(kind, "url");
+        assert!(lua.load("return terminal.links.remove_rule('url')").eval::<bool>().unwrap());
+        lua.load("terminal.links.reset()").exec().unwrap();
+        assert_eq!(sessions.lock().unwrap().link_detector().rules().len(), 2);
+
+        let link = Link {
+            start: crate::terminal::Point::new(0, 0),
+            end: crate::terminal::Point::new(0, 9),
+            text: "src/a.rs:3".to_string(),
+            target: LinkTarget::File {
+                path: "src/a.rs".to_string(),
+                line: Some(3),
+                column: None,
+            },
+        };
+        assert!(!call_link_handler(&lua, &link).unwrap());
+        lua.load("terminal.on_link(function(link) opened = link.path .. ':' .. link.line; return true end)")
+            .exec()
+            .unwrap();
+        assert!(call_link_handler(&lua, &link).unwrap());
+        assert_eq!(lua.globals().get::<_, String>("opened").unwrap(), "src/a.rs:3");
+        lua.load("terminal.on_link(nil)").exec().unwrap();
+        assert!(!call_link_handler(&lua, &link).unwrap());
+    }
+}
@@ -5,6 +5,8 @@ pub mod sandbox;
 
 use crate::config::Config;
 use crate::error::CoreError;
+use crate::terminal::{Link, SharedSessionManager};
+use crate::monitor::{AlertEvent, ResourceMonitor, ResourceUsage};
 use log::{debug, info, warn};
 use mlua::{Function, Lua, LuaOptions, StdLib, Table};
 use std::fs;
@@ -164,6 +166,51 @@ impl ScriptEngine {
         Ok(())
     }
     
+    /// Register the terminal API under `MAVIS.terminal`, giving scripts
+    /// access to the shared terminal sessions
+    pub fn register_terminal_api(&self, sessions: SharedSessionManager) -> Result<(), CoreError> {
+        let lua = self.lua.lock().unwrap();
+
+        let mavis_table: Table = lua.globals().get("MAVIS")
+            .map_err(|e| CoreError::LuaError(mlua::Error::external(format!("Failed to get MAVIS global: {}", e))))?;
+        let terminal_table = api::create_nested_table(&lua, &mavis_table, "terminal")?;
+        api::register_terminal_functions(&lua, &terminal_table, sessions)
+    }
+    
+    /// Register the monitor API under `MAVIS.monitor`, giving scripts
+    /// access to the recorded metrics history, the alert rules and the
+    /// monitor's settings
+    pub fn register_monitor_api(&self, monitor: &ResourceMonitor) -> Result<(), CoreError> {
+        let lua = self.lua.lock().unwrap();
+
+        let mavis_table: Table = lua.globals().get("MAVIS")
+            .map_err(|e| CoreError::LuaError(mlua::Error::external(format!("Failed to get MAVIS global: {}", e))))?;
+        let monitor_table = api::create_nested_table(&lua, &mavis_table, "monitor")?;
+        api::register_monitor_functions(&lua, &monitor_table, monitor.history(), monitor.alerts(), monitor.control())
+    }
+    
+    /// Pass an alert event to the script callbacks registered for it
+    pub fn handle_alert(&self, event: &AlertEvent) -> Result<(), CoreError> {
+        let lua = self.lua.lock().unwrap();
+        api::monitor::call_alert_handlers(&lua, event)
+            .map_err(|e| CoreError::LuaError(mlua::Error::external(format!("Alert handler failed: {}", e))))
+    }
+    
+    /// Pass a new sample to the handler set with `MAVIS.monitor.on_update`
+    pub fn handle_usage(&self, usage: &ResourceUsage) -> Result<(), CoreError> {
+        let lua = self.lua.lock().unwrap();
+        api::monitor::call_update_handler(&lua, usage)
+            .map_err(|e| CoreError::LuaError(mlua::Error::external(format!("Update handler failed: {}", e))))
+    }
+    
+    /// Offer an activated terminal link to the handler set with
+    /// `MAVIS.terminal.on_link`. Returns true if the script handled it
+    pub fn handle_terminal_link(&self, link: &Link) -> Result<bool, CoreError> {
+        let lua = self.lua.lock().unwrap();
+        api::terminal::call_link_handler(&lua, link)
+            .map_err(|e| CoreError::LuaError(mlua::Error::external(format!("Link handler failed: {}", e))))
+    }
+    
     /// Load and execute a Lua script from the specified path
     pub fn load_script(&self, path: impl AsRef<Path>) -> Result<(), CoreError> {
         let path = path.as_ref();
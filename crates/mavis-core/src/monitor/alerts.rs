@@ -0,0 +1,508 @@
+// Threshold alerts
+//
+// The `AlertEngine` checks every published sample against its rules. A rule
+// fires once its condition has held for `sustain`, and clears only once the
+// value is back past `clear_at`, so a value hovering around the threshold
+// does not fire over and over. While it fires, a rule raises no further
+// events for the same subject. Events go out on a broadcast channel; the
+// default rules come from the `*_alert_threshold` settings.
+
+use super::ResourceUsage;
+use super::history::Metric;
+use crate::config::MonitoringConfig;
+use log::{info, warn};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+use tokio::sync::broadcast;
+
+/// Events kept for subscribers that fall behind.
+const EVENT_CAPACITY: usize = 64;
+
+/// How urgent an alert is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Info,
+    Warning,
+    Critical,
+}
+
+impl Severity {
+    pub const ALL: [Severity; 3] = [Self::Info, Self::Warning, Self::Critical];
+
+    /// Name used in Lua, e.g. "warning".
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Info => "info",
+            Self::Warning => "warning",
+            Self::Critical => "critical",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|severity| severity.name() == name)
+    }
+}
+
+/// What a rule watches.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlertSource {
+    /// A machine-wide metric
+    Metric(Metric),
+    /// Free space of each volume, as a percentage (0-100); the mount point
+    /// is the alert's subject
+    VolumeFreeSpace,
+}
+
+impl AlertSource {
+    /// Name used in Lua: a metric name or "volume_free".
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Metric(metric) => metric.name(),
+            Self::VolumeFreeSpace => "volume_free",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "volume_free" => Some(Self::VolumeFreeSpace),
+            name => Metric::from_name(name).map(Self::Metric),
+        }
+    }
+
+    /// The current readings, one per subject.
+    fn readings(&self, usage: &ResourceUsage) -> Vec<(Option<String>, f64)> {
+        match self {
+            Self::Metric(metric) => vec![(None, metric.value(usage))],
+            Self::VolumeFreeSpace => usage
+                .disk
+                .volumes
+                .iter()
+                .filter(|volume| volume.total_bytes > 0)
+                .map(|volume| (Some(volume.mount_point.clone()), volume.free_percent() as f64))
+                .collect(),
+        }
+    }
+
+    fn describe(&self, subject: Option<&str>) -> String {
+        match (self, subject) {
+            (Self::Metric(metric), _) => metric.label().to_string(),
+            (Self::VolumeFreeSpace, Some(mount_point)) => format!("Free space on {}", mount_point),
+            (Self::VolumeFreeSpace, None) => "Free space".to_string(),
+        }
+    }
+
+    fn format(&self, value: f64) -> String {
+        match self {
+            Self::Metric(metric) if !metric.is_percentage() => format!("{:.1} KB/s", value / 1024.0),
+            _ => format!("{:.1}%", value),
+        }
+    }
+}
+
+/// Which side of the threshold is a problem.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Above,
+    Below,
+}
+
+/// A condition that raises an alert.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AlertRule {
+    /// Unique name; adding a rule with the same name replaces it
+    pub name: String,
+    pub source: AlertSource,
+    pub comparison: Comparison,
+    pub threshold: f64,
+    /// Value the reading must get back past before the alert clears
+    pub clear_at: f64,
+    /// How long the threshold must be crossed before the alert fires
+    pub sustain: Duration,
+    pub severity: Severity,
+}
+
+impl AlertRule {
+    /// A warning when `source` goes above `threshold`, firing at once and
+    /// clearing as soon as it is back at or below it.
+    pub fn above(name: &str, source: AlertSource, threshold: f64) -> Self {
+        Self {
+            name: name.to_string(),
+            source,
+            comparison: Comparison::Above,
+            threshold,
+            clear_at: threshold,
+            sustain: Duration::ZERO,
+            severity: Severity::Warning,
+        }
+    }
+
+    /// A warning when `source` goes below `threshold`.
+    pub fn below(name: &str, source: AlertSource, threshold: f64) -> Self {
+        Self {
+            comparison: Comparison::Below,
+            ..Self::above(name, source, threshold)
+        }
+    }
+
+    /// The rules for the thresholds in `config`.
+    pub fn defaults(config: &MonitoringConfig) -> Vec<Self> {
+        let sustain = Duration::from_secs(config.alert_sustain_secs as u64);
+        let hysteresis = config.alert_hysteresis as f64;
+        let above = |name, metric, threshold: u32| Self {
+            clear_at: threshold as f64 - hysteresis,
+            sustain,
+            ..Self::above(name, AlertSource::Metric(metric), threshold as f64)
+        };
+        vec![
+            above("cpu", Metric::CpuUsage, config.cpu_alert_threshold),
+            above("memory", Metric::MemoryUsage, config.ram_alert_threshold),
+            above("disk", Metric::DiskBusy, config.disk_alert_threshold),
+            // Space does not come and go, so there is nothing to wait out
+            Self {
+                clear_at: (config.disk_space_alert_threshold as f64 + hysteresis).min(100.0),
+                severity: Severity::Critical,
+                ..Self::below("disk_space", AlertSource::VolumeFreeSpace, config.disk_space_alert_threshold as f64)
+            },
+        ]
+    }
+
+    fn is_crossed(&self, value: f64) -> bool {
+        match self.comparison {
+            Comparison::Above => value > self.threshold,
+            Comparison::Below => value < self.threshold,
+        }
+    }
+
+    fn is_recovered(&self, value: f64) -> bool {
+        match self.comparison {
+            Comparison::Above => value <= self.clear_at,
+            Comparison::Below => value >= self.clear_at,
+        }
+    }
+
+    fn event(&self, kind: AlertKind, subject: Option<String>, value: f64) -> AlertEvent {
+        let what = self.source.describe(subject.as_deref());
+        let message = match (kind, self.comparison) {
+            (AlertKind::Fired, Comparison::Above) => {
+                format!("{} is above {} ({})", what, self.source.format(self.threshold), self.source.format(value))
+            }
+            (AlertKind::Fired, Comparison::Below) => {
+                format!("{} is below {} ({})", what, self.source.format(self.threshold), self.source.format(value))
+            }
+            (AlertKind::Cleared, _) => format!("{} is back to {}", what, self.source.format(value)),
+        };
+        AlertEvent {
+            rule: self.name.clone(),
+            subject,
+            kind,
+            severity: self.severity,
+            value,
+            threshold: self.threshold,
+            message,
+            at: SystemTime::now(),
+        }
+    }
+}
+
+/// Whether an alert started or ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlertKind {
+    Fired,
+    Cleared,
+}
+
+/// An alert firing or clearing.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AlertEvent {
+    /// Name of the rule
+    pub rule: String,
+    /// What the rule fired for, e.g. a mount point; None for machine-wide
+    /// metrics
+    pub subject: Option<String>,
+    pub kind: AlertKind,
+    pub severity: Severity,
+    /// Reading that fired or cleared the alert
+    pub value: f64,
+    pub threshold: f64,
+    /// Human-readable description, e.g. "CPU usage is above 90.0% (97.2%)"
+    pub message: String,
+    pub at: SystemTime,
+}
+
+/// Alert events as they happen.
+#[derive(Debug)]
+pub struct AlertSubscription {
+    receiver: broadcast::Receiver<AlertEvent>,
+}
+
+impl AlertSubscription {
+    /// The next event that has happened, if any, without waiting. Events
+    /// missed by falling too far behind are skipped.
+    pub fn try_next(&mut self) -> Option<AlertEvent> {
+        loop {
+            match self.receiver.try_recv() {
+                Ok(event) => return Some(event),
+                Err(broadcast::error::TryRecvError::Lagged(missed)) => {
+                    warn!("Alert subscriber fell behind; {} events skipped", missed)
+                }
+                Err(_) => return None,
+            }
+        }
+    }
+}
+
+/// Where a rule stands for one subject.
+#[derive(Debug, Clone)]
+enum AlertState {
+    /// Crossed since, but not for long enough yet
+    Pending(Instant),
+    Firing(AlertEvent),
+}
+
+/// Evaluates alert rules against samples.
+#[derive(Debug)]
+pub struct AlertEngine {
+    rules: Vec<AlertRule>,
+    states: HashMap<(String, Option<String>), AlertState>,
+    events: broadcast::Sender<AlertEvent>,
+}
+
+/// An engine shared between the monitor thread, scripts and the GUI.
+pub type SharedAlerts = Arc<Mutex<AlertEngine>>;
+
+impl AlertEngine {
+    pub fn new(rules: Vec<AlertRule>) -> Self {
+        let (events, _) = broadcast::channel(EVENT_CAPACITY);
+        Self {
+            rules,
+            states: HashMap::new(),
+            events,
+        }
+    }
+
+    /// An engine with the default rules for `config`.
+    pub fn from_config(config: &MonitoringConfig) -> Self {
+        Self::new(AlertRule::defaults(config))
+    }
+
+    /// Bring the default rules in line with new settings. Only rules whose
+    /// thresholds changed are replaced, clearing their alerts, so rules that
+    /// scripts added under the same names stay until those settings change.
+    pub fn reconfigure(&mut self, previous: &MonitoringConfig, config: &MonitoringConfig) {
+        for (old, new) in AlertRule::defaults(previous).into_iter().zip(AlertRule::defaults(config)) {
+            if old != new {
+                self.add_rule(new);
+            }
+        }
+    }
+
+    /// Receive every event from now on.
+    pub fn subscribe(&self) -> AlertSubscription {
+        AlertSubscription {
+            receiver: self.events.subscribe(),
+        }
+    }
+
+    pub fn rules(&self) -> &[AlertRule] {
+        &self.rules
+    }
+
+    /// Add a rule, replacing any rule of the same name.
+    pub fn add_rule(&mut self, rule: AlertRule) {
+        let name = rule.name.clone();
+        self.remove_rule(&name);
+        self.rules.push(rule);
+    }
+
+    /// Remove a rule, clearing its alerts. Returns false if there was no
+    /// rule of that name.
+    pub fn remove_rule(&mut self, name: &str) -> bool {
+        let Some(index) = self.rules.iter().position(|rule| rule.name == name) else {
+            return false;
+        };
+        let rule = self.rules.remove(index);
+        let keys: Vec<_> = self.states.keys().filter(|(rule, _)| rule == name).cloned().collect();
+        for key in keys {
+            if let Some(AlertState::Firing(fired)) = self.states.remove(&key) {
+                self.publish(rule.event(AlertKind::Cleared, key.1, fired.value));
+            }
+        }
+        true
+    }
+
+    /// The alerts firing now, as the events that fired them.
+    pub fn active(&self) -> Vec<&AlertEvent> {
+        let mut active: Vec<&AlertEvent> = self
+            .states
+            .values()
+            .filter_map(|state| match state {
+                AlertState::Firing(event) => Some(event),
+                AlertState::Pending(_) => None,
+            })
+            .collect();
+        active.sort_by(|a, b| (&a.rule, &a.subject).cmp(&(&b.rule, &b.subject)));
+        active
+    }
+
+    /// Check `usage` against every rule and publish what fired or cleared.
+    pub fn evaluate(&mut self, usage: &ResourceUsage) -> Vec<AlertEvent> {
+        let now = usage.timestamp;
+        let mut events = Vec::new();
+        let mut seen = HashSet::new();
+
+        for rule in &self.rules {
+            for (subject, value) in rule.source.readings(usage) {
+                let key = (rule.name.clone(), subject.clone());
+                seen.insert(key.clone());
+                match self.states.get(&key) {
+                    Some(AlertState::Firing(_)) => {
+                        if rule.is_recovered(value) {
+                            self.states.remove(&key);
+                            events.push(rule.event(AlertKind::Cleared, subject, value));
+                        }
+                    }
+                    state if rule.is_crossed(value) => {
+                        let since = match state {
+                            Some(AlertState::Pending(since)) => *since,
+                            _ => now,
+                        };
+                        if now.saturating_duration_since(since) >= rule.sustain {
+                            let event = rule.event(AlertKind::Fired, subject, value);
+                            self.states.insert(key, AlertState::Firing(event.clone()));
+                            events.push(event);
+                        } else {
+                            self.states.insert(key, AlertState::Pending(since));
+                        }
+                    }
+                    _ => {
+                        self.states.remove(&key);
+                    }
+                }
+            }
+        }
+
+        // Subjects that went away, such as unmounted volumes, clear
+        let gone: Vec<_> = self.states.keys().filter(|key| !seen.contains(*key)).cloned().collect();
+        for key in gone {
+            if let Some(AlertState::Firing(fired)) = self.states.remove(&key)
+                && let Some(rule) = self.rules.iter().find(|rule| rule.name == key.0)
+            {
+                events.push(rule.event(AlertKind::Cleared, key.1, fired.value));
+            }
+        }
+
+        for event in &events {
+            self.publish(event.clone());
+        }
+        events
+    }
+
+    fn publish(&self, event: AlertEvent) {
+        match event.kind {
+            AlertKind::Fired => warn!("Alert {}: {}", event.rule, event.message),
+            AlertKind::Cleared => info!("Alert {} cleared: {}", event.rule, event.message),
+        }
+        // Nobody listening is not an error
+        let _ = self.events.send(event);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use crate::monitor::disk::{DiskSnapshot, VolumeStats};
+
+    fn usage(at: Instant, cpu_usage: f32) -> ResourceUsage {
+        ResourceUsage {
+            cpu_usage,
+            timestamp: at,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_rule_fires_after_sustain_and_clears_with_hysteresis() {
+        let rule = AlertRule {
+            clear_at: 80.0,
+            sustain: Duration::from_secs(10),
+            ..AlertRule::above("cpu", AlertSource::Metric(Metric::CpuUsage), 90.0)
+        };
+        let mut engine = AlertEngine::new(vec![rule]);
+        let mut subscription = engine.subscribe();
+        let start = Instant::now();
+        let at = |seconds| start + Duration::from_secs(seconds);
+
+        // A short spike does not fire
+        assert!(engine.evaluate(&usage(at(0), 95.0)).is_empty());
+        assert!(engine.evaluate(&usage(at(5), 50.0)).is_empty());
+        // Ten seconds over the threshold does, once
+        assert!(engine.evaluate(&usage(at(10), 95.0)).is_empty());
+        let events = engine.evaluate(&usage(at(20), 97.0));
+        assert_eq!(events.len(), 1);
+        assert_eq!((events[0].kind, events[0].value), (AlertKind::Fired, 97.0));
+        assert_eq!(events[0].message, "CPU usage is above 90.0% (97.0%)");
+        assert!(engine.evaluate(&usage(at(21), 99.0)).is_empty());
+        assert_eq!(engine.active().len(), 1);
+
+        // Dipping under the threshold is not enough to clear
+        assert!(engine.evaluate(&usage(at(22), 85.0)).is_empty());
+        let events = engine.evaluate(&usage(at(23), 75.0));
+        assert_eq!(events[0].kind, AlertKind::Cleared);
+        assert!(engine.active().is_empty());
+
+        let received: Vec<AlertKind> = std::iter::from_fn(|| subscription.try_next()).map(|event| event.kind).collect();
+        assert_eq!(received, [AlertKind::Fired, AlertKind::Cleared]);
+    }
+
+    #[test]
+    fn test_default_rules_watch_each_volume() {
+        let mut config = Config::default().monitoring;
+        config.disk_space_alert_threshold = 10;
+        config.alert_hysteresis = 5;
+        let mut engine = AlertEngine::from_config(&config);
+        let volume = |mount_point: &str, free_bytes| VolumeStats {
+            mount_point: mount_point.to_string(),
+            total_bytes: 100,
+            free_bytes,
+            ..Default::default()
+        };
+        let disks = |volumes| ResourceUsage {
+            disk: DiskSnapshot::new(Vec::new(), volumes, 10),
+            ..Default::default()
+        };
+
+        let events = engine.evaluate(&disks(vec![volume("/", 50), volume("/home", 5)]));
+        assert_eq!(events.len(), 1);
+        assert_eq!((events[0].rule.as_str(), events[0].subject.as_deref()), ("disk_space", Some("/home")));
+        assert_eq!(events[0].severity, Severity::Critical);
+        assert!(engine.evaluate(&disks(vec![volume("/", 50), volume("/home", 12)])).is_empty());
+
+        // Unmounting the volume clears its alert
+        let events = engine.evaluate(&disks(vec![volume("/", 50)]));
+        assert_eq!((events[0].kind, events[0].subject.as_deref()), (AlertKind::Cleared, Some("/home")));
+
+        engine.evaluate(&disks(vec![volume("/", 1)]));
+        assert!(engine.remove_rule("disk_space"));
+        assert!(engine.active().is_empty());
+        assert!(!engine.remove_rule("disk_space"));
+        assert_eq!(AlertSource::from_name("volume_free"), Some(AlertSource::VolumeFreeSpace));
+    }
+
+    #[test]
+    fn test_reconfigure_replaces_changed_defaults() {
+        let config = Config::default().monitoring;
+        let mut engine = AlertEngine::from_config(&config);
+        engine.add_rule(AlertRule::above("memory", AlertSource::Metric(Metric::MemoryUsage), 50.0));
+
+        let mut changed = config.clone();
+        changed.cpu_alert_threshold = 70;
+        engine.reconfigure(&config, &changed);
+        let threshold = |name: &str| engine.rules().iter().find(|rule| rule.name == name).unwrap().threshold;
+        assert_eq!(threshold("cpu"), 70.0);
+        // The script's rule is kept while its setting stays the same
+        assert_eq!(threshold("memory"), 50.0);
+        assert_eq!(engine.rules().len(), AlertRule::defaults(&config).len());
+    }
+}
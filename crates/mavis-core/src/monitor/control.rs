@@ -0,0 +1,57 @@
+// Monitor control
+//
+// The monitor thread watches its settings on a `watch` channel. A change to
+// the sampling interval takes effect at once, and metric families that were
+// enabled or disabled have their sources started or dropped. Alert
+// thresholds and history retention follow as well, all without restarting
+// the monitor. `MonitorControl` is a handle for changing the
+// settings from elsewhere, such as scripts, and for reading the health of
+// each source from the latest sample.
+
+use super::ResourceUsage;
+use super::process::ProcessController;
+use super::source::SourceStatus;
+use crate::config::MonitoringConfig;
+use log::info;
+use tokio::sync::watch;
+
+/// Changes the settings of a monitor and reports on its sources.
+#[derive(Debug, Clone)]
+pub struct MonitorControl {
+    settings: watch::Sender<MonitoringConfig>,
+    usage: watch::Receiver<ResourceUsage>,
+}
+
+impl MonitorControl {
+    pub(super) fn new(settings: watch::Sender<MonitoringConfig>, usage: watch::Receiver<ResourceUsage>) -> Self {
+        Self { settings, usage }
+    }
+
+    /// The settings the monitor runs with.
+    pub fn settings(&self) -> MonitoringConfig {
+        self.settings.borrow().clone()
+    }
+
+    /// Replace the settings; a running monitor applies them before its
+    /// next sample.
+    pub fn reconfigure(&self, config: MonitoringConfig) {
+        self.update(|settings| *settings = config);
+    }
+
+    /// A controller for processes, allowed or not by the current settings.
+    pub fn process_controller(&self) -> ProcessController {
+        ProcessController::new(&self.settings.borrow())
+    }
+
+    /// Change some of the settings.
+    pub fn update(&self, change: impl FnOnce(&mut MonitoringConfig)) {
+        self.settings.send_modify(change);
+        info!("Monitor settings changed");
+    }
+
+    /// The health of each metric family's source as of the latest sample;
+    /// empty until the monitor has taken one.
+    pub fn health(&self) -> Vec<SourceStatus> {
+        self.usage.borrow().sources.clone()
+    }
+}
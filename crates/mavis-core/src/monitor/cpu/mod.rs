@@ -1,143 +1,94 @@
-// CPU monitoring for Windows systems
+// CPU monitoring
+//
+// PDH counters on Windows, /proc/stat on Linux. Besides overall usage a
+// snapshot breaks the time down by state and by logical core.
 
-use crate::error::CoreError;
-use log::{debug, error, warn};
-use once_cell::sync::Lazy;
-use std::sync::Mutex;
-use windows::Win32::System::Performance::{
-    PdhAddEnglishCounterW, PdhCollectQueryData, PdhGetFormattedCounterValue,
-    PdhOpenQueryW, PDH_FMT_DOUBLE, PDH_FMT_COUNTERVALUE,
-};
+#[cfg(windows)]
+mod pdh;
+#[cfg(target_os = "linux")]
+mod procfs;
 
-// Global PDH query handle
-static PDH_QUERY: Lazy<Mutex<Option<PDHQueryWrapper>>> = Lazy::new(|| Mutex::new(None));
+#[cfg(windows)]
+pub use pdh::CpuMonitor;
+#[cfg(target_os = "linux")]
+pub use procfs::ProcCpu;
 
-/// Wrapper around PDH query handle for cleanup
-struct PDHQueryWrapper {
-    query: isize,
-    counter: isize,
+use super::source::DynSource;
+use crate::error::CoreResult;
+
+/// Share of CPU time spent in each state, as percentages (0-100) that add
+/// up to 100. Windows does not report I/O wait, which stays at 0 there.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CpuBreakdown {
+    /// User code, including niced processes
+    pub user: f32,
+    /// Kernel code and interrupt handling
+    pub system: f32,
+    /// Idle while waiting for I/O
+    pub iowait: f32,
+    /// Idle
+    pub idle: f32,
 }
 
-impl Drop for PDHQueryWrapper {
-    fn drop(&mut self) {
-        // Clean up PDH resources
-        unsafe {
-            windows::Win32::System::Performance::PdhCloseQuery(self.query);
-        }
+impl CpuBreakdown {
+    /// Time spent busy, as a percentage (0-100).
+    pub fn busy(&self) -> f32 {
+        (self.user + self.system).clamp(0.0, 100.0)
     }
 }
 
-/// CPU monitoring functionality
-pub struct CpuMonitor {
-    last_usage: f32,
+/// Load of one logical core over the sampling interval.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CoreUsage {
+    /// Time the core was busy, as a percentage (0-100)
+    pub usage: f32,
+    pub breakdown: CpuBreakdown,
+    /// Current clock speed, where the platform reports it
+    pub frequency_mhz: Option<u32>,
 }
 
-impl CpuMonitor {
-    /// Create a new CPU monitor
-    pub fn new() -> Result<Self, CoreError> {
-        // Initialize PDH query once
-        let mut pdh_guard = PDH_QUERY.lock().unwrap();
-        
-        if pdh_guard.is_none() {
-            let mut query_handle = 0;
-            let mut counter_handle = 0;
-            
-            // Create PDH query
-            let query_result = unsafe {
-                PdhOpenQueryW(None, 0, &mut query_handle)
-            };
-            
-            if query_result != 0 {
-                return Err(CoreError::PdhError(format!(
-                    "Failed to open PDH query: error code {}",
-                    query_result
-                )));
-            }
-            
-            // Add CPU counter
-            let counter_path = windows::core::HSTRING::from("\\Processor(_Total)\\% Processor Time");
-            let counter_result = unsafe {
-                PdhAddEnglishCounterW(
-                    query_handle,
-                    &counter_path,
-                    0,
-                    &mut counter_handle,
-                )
-            };
-            
-            if counter_result != 0 {
-                unsafe {
-                    windows::Win32::System::Performance::PdhCloseQuery(query_handle);
-                }
-                
-                return Err(CoreError::PdhError(format!(
-                    "Failed to add CPU counter: error code {}",
-                    counter_result
-                )));
-            }
-            
-            // Store the query wrapper
-            *pdh_guard = Some(PDHQueryWrapper {
-                query: query_handle,
-                counter: counter_handle,
-            });
-            
-            // Initial data collection to establish baseline
-            // First call often returns 0, so we ignore the result
-            unsafe {
-                PdhCollectQueryData(query_handle);
-            }
-            
-            // Small sleep to allow for first measurement
-            std::thread::sleep(std::time::Duration::from_millis(100));
-            
-            unsafe {
-                PdhCollectQueryData(query_handle);
-            }
-        }
-        
-        Ok(Self { last_usage: 0.0 })
-    }
-    
-    /// Get current CPU usage as a percentage (0-100)
-    pub fn get_usage(&mut self) -> Result<f32, CoreError> {
-        let pdh_guard = PDH_QUERY.lock().unwrap();
-        
-        if let Some(pdh) = &*pdh_guard {
-            unsafe {
-                // Collect new data
-                let collect_result = PdhCollectQueryData(pdh.query);
-                
-                if collect_result != 0 {
-                    warn!("Failed to collect PDH data: error code {}", collect_result);
-                    return Ok(self.last_usage); // Return last known value
-                }
-                
-                // Get formatted counter value
-                let mut counter_value = PDH_FMT_COUNTERVALUE::default();
-                let format_result = PdhGetFormattedCounterValue(
-                    pdh.counter,
-                    PDH_FMT_DOUBLE,
-                    None,
-                    &mut counter_value,
-                );
-                
-                if format_result != 0 {
-                    warn!("Failed to format counter value: error code {}", format_result);
-                    return Ok(self.last_usage); // Return last known value
-                }
-                
-                // Extract and store CPU usage
-                let usage = counter_value.Anonymous.doubleValue as f32;
-                self.last_usage = usage.clamp(0.0, 100.0);
-                
-                debug!("Current CPU usage: {:.1}%", self.last_usage);
-                
-                Ok(self.last_usage)
-            }
-        } else {
-            error!("CPU monitor not properly initialized");
-            Ok(0.0)
-        }
+/// CPU load over the sampling interval.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CpuSnapshot {
+    /// Time all processors were busy, as a percentage (0-100)
+    pub usage: f32,
+    pub breakdown: CpuBreakdown,
+    /// One entry per logical core, in processor order
+    pub cores: Vec<CoreUsage>,
+    /// Average clock speed of the cores that report one
+    pub frequency_mhz: Option<u32>,
+    /// Number of processes
+    pub processes: u32,
+    /// Number of threads across all processes
+    pub threads: u32,
+    /// 1, 5 and 15 minute load averages, where the platform has them
+    pub load_average: Option<[f32; 3]>,
+}
+
+/// Average of the frequencies the cores report.
+#[cfg(any(windows, target_os = "linux"))]
+fn average_frequency(cores: &[CoreUsage]) -> Option<u32> {
+    let frequencies: Vec<u32> = cores.iter().filter_map(|core| core.frequency_mhz).collect();
+    if frequencies.is_empty() {
+        return None;
     }
-}
\ No newline at end of file
+    Some((frequencies.iter().map(|&mhz| mhz as u64).sum::<u64>() / frequencies.len() as u64) as u32)
+}
+
+/// The CPU source for the current platform.
+#[cfg(windows)]
+pub fn native() -> CoreResult<DynSource<CpuSnapshot>> {
+    Ok(Box::new(CpuMonitor::new()?))
+}
+
+/// The CPU source for the current platform.
+#[cfg(target_os = "linux")]
+pub fn native() -> CoreResult<DynSource<CpuSnapshot>> {
+    Ok(Box::new(ProcCpu::new()?))
+}
+
+/// The CPU source for the current platform.
+#[cfg(not(any(windows, target_os = "linux")))]
+pub fn native() -> CoreResult<DynSource<CpuSnapshot>> {
+    Err(super::source::unsupported("CPU"))
+}
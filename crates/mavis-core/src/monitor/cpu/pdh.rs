@@ -0,0 +1,149 @@
+// CPU monitoring for Windows systems
+//
+// The `Processor` object has one instance per logical core plus "_Total";
+// clock speeds come from `Processor Information`, whose instances are named
+// "<group>,<core>".
+
+use super::{CoreUsage, CpuBreakdown, CpuSnapshot, average_frequency};
+use crate::error::{CoreError, CoreResult};
+use crate::monitor::pdh::PdhQuery;
+use crate::monitor::source::MetricsSource;
+use log::{debug, warn};
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// Instance name of the aggregate over all processors.
+const TOTAL: &str = "_Total";
+
+/// CPU monitoring functionality
+pub struct CpuMonitor {
+    query: PdhQuery,
+    processor_time: isize,
+    user_time: isize,
+    privileged_time: isize,
+    idle_time: isize,
+    frequency: isize,
+    performance: isize,
+    processes: isize,
+    threads: isize,
+    last_snapshot: CpuSnapshot,
+}
+
+impl CpuMonitor {
+    /// Create a new CPU monitor
+    pub fn new() -> Result<Self, CoreError> {
+        let query = PdhQuery::open()?;
+        let monitor = Self {
+            processor_time: query.add("\\Processor(*)\\% Processor Time")?,
+            user_time: query.add("\\Processor(*)\\% User Time")?,
+            privileged_time: query.add("\\Processor(*)\\% Privileged Time")?,
+            idle_time: query.add("\\Processor(*)\\% Idle Time")?,
+            frequency: query.add("\\Processor Information(*)\\Processor Frequency")?,
+            performance: query.add("\\Processor Information(*)\\% Processor Performance")?,
+            processes: query.add("\\System\\Processes")?,
+            threads: query.add("\\System\\Threads")?,
+            query,
+            last_snapshot: CpuSnapshot::default(),
+        };
+
+        // Initial data collection to establish baseline
+        // First call often returns 0, so we ignore the result
+        let _ = monitor.query.collect();
+
+        // Small sleep to allow for first measurement
+        std::thread::sleep(Duration::from_millis(100));
+
+        let _ = monitor.query.collect();
+
+        Ok(monitor)
+    }
+
+    /// Get current CPU usage as a percentage (0-100)
+    pub fn get_usage(&mut self) -> Result<f32, CoreError> {
+        Ok(self.snapshot()?.usage)
+    }
+
+    /// Take a full snapshot of the CPU counters
+    pub fn snapshot(&mut self) -> Result<CpuSnapshot, CoreError> {
+        if let Err(e) = self.query.collect() {
+            warn!("{}", e);
+            return Ok(self.last_snapshot.clone()); // Return last known value
+        }
+
+        let processor_time = self.query.values(self.processor_time)?;
+        let user_time = self.query.values(self.user_time)?;
+        let privileged_time = self.query.values(self.privileged_time)?;
+        let idle_time = self.query.values(self.idle_time)?;
+        let frequencies = self.core_frequencies();
+
+        let breakdown = |instance: &str| {
+            let value = |values: &HashMap<String, f64>| values.get(instance).copied().unwrap_or(0.0).clamp(0.0, 100.0) as f32;
+            CpuBreakdown {
+                user: value(&user_time),
+                system: value(&privileged_time),
+                iowait: 0.0,
+                idle: value(&idle_time),
+            }
+        };
+        let usage = |instance: &str| processor_time.get(instance).copied().unwrap_or(0.0).clamp(0.0, 100.0) as f32;
+
+        let mut core_indices: Vec<usize> = processor_time.keys().filter_map(|name| name.parse().ok()).collect();
+        core_indices.sort_unstable();
+        let cores: Vec<CoreUsage> = core_indices
+            .iter()
+            .map(|index| {
+                let instance = index.to_string();
+                CoreUsage {
+                    usage: usage(&instance),
+                    breakdown: breakdown(&instance),
+                    frequency_mhz: frequencies.get(*index).copied(),
+                }
+            })
+            .collect();
+
+        let snapshot = CpuSnapshot {
+            usage: usage(TOTAL),
+            breakdown: breakdown(TOTAL),
+            frequency_mhz: average_frequency(&cores),
+            cores,
+            processes: self.query.value(self.processes).unwrap_or(0.0) as u32,
+            threads: self.query.value(self.threads).unwrap_or(0.0) as u32,
+            load_average: None,
+        };
+
+        debug!("Current CPU usage: {:.1}% over {} cores", snapshot.usage, snapshot.cores.len());
+        self.last_snapshot = snapshot.clone();
+        Ok(snapshot)
+    }
+
+    /// Effective clock speed of each logical core in processor order: the
+    /// nominal frequency scaled by the current performance percentage.
+    fn core_frequencies(&self) -> Vec<u32> {
+        let (Ok(frequency), Ok(performance)) = (self.query.values(self.frequency), self.query.values(self.performance)) else {
+            return Vec::new();
+        };
+        let mut cores: Vec<((u32, u32), u32)> = frequency
+            .iter()
+            .filter_map(|(name, mhz)| {
+                let (group, core) = name.split_once(',')?;
+                let position = (group.parse().ok()?, core.parse().ok()?);
+                let scale = performance.get(name).copied().unwrap_or(100.0) / 100.0;
+                Some((position, (mhz * scale).round() as u32))
+            })
+            .collect();
+        cores.sort_unstable();
+        cores.into_iter().map(|(_, mhz)| mhz).collect()
+    }
+}
+
+impl MetricsSource for CpuMonitor {
+    type Sample = CpuSnapshot;
+
+    fn backend(&self) -> &'static str {
+        "pdh"
+    }
+
+    fn sample(&mut self) -> CoreResult<CpuSnapshot> {
+        self.snapshot()
+    }
+}
@@ -0,0 +1,253 @@
+// CPU monitoring from /proc/stat on Linux
+//
+// Per-state times come from the "cpu" lines of /proc/stat, clock speeds from
+// /proc/cpuinfo, and process, thread and load figures from /proc/loadavg.
+
+use super::{CoreUsage, CpuBreakdown, CpuSnapshot, average_frequency};
+use crate::error::{CoreError, CoreResult};
+use crate::monitor::procfs::{ProcFs, counters};
+use crate::monitor::source::MetricsSource;
+use log::debug;
+use std::path::PathBuf;
+
+/// Jiffies spent in each state, from one "cpu" line of /proc/stat.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct CpuTimes {
+    user: u64,
+    system: u64,
+    iowait: u64,
+    idle: u64,
+}
+
+impl CpuTimes {
+    /// Parse "cpu user nice system idle iowait irq softirq steal ...".
+    /// Guest time is already counted in user time.
+    fn parse(line: &str) -> Option<Self> {
+        let mut fields = line.split_whitespace();
+        fields.next().filter(|label| label.starts_with("cpu"))?;
+        let mut values = counters(fields.take(8));
+        if values.len() < 4 {
+            return None;
+        }
+        values.resize(8, 0);
+        Some(Self {
+            user: values[0] + values[1],
+            system: values[2] + values[5] + values[6] + values[7],
+            iowait: values[4],
+            idle: values[3],
+        })
+    }
+
+    fn total(&self) -> u64 {
+        self.user + self.system + self.iowait + self.idle
+    }
+
+    /// Share of each state in the time since `previous`.
+    fn breakdown_since(&self, previous: &Self) -> CpuBreakdown {
+        let total = self.total().saturating_sub(previous.total());
+        if total == 0 {
+            return CpuBreakdown::default();
+        }
+        let share = |current: u64, previous: u64| {
+            (current.saturating_sub(previous) as f64 / total as f64 * 100.0).clamp(0.0, 100.0) as f32
+        };
+        CpuBreakdown {
+            user: share(self.user, previous.user),
+            system: share(self.system, previous.system),
+            iowait: share(self.iowait, previous.iowait),
+            idle: share(self.idle, previous.idle),
+        }
+    }
+}
+
+/// The aggregate line and one line per core, in processor order.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct StatTimes {
+    all: CpuTimes,
+    cores: Vec<CpuTimes>,
+}
+
+/// CPU usage from /proc/stat.
+#[derive(Debug)]
+pub struct ProcCpu {
+    proc_fs: ProcFs,
+    previous: StatTimes,
+}
+
+impl ProcCpu {
+    pub fn new() -> CoreResult<Self> {
+        Self::with_root("/proc")
+    }
+
+    /// Read from `root` instead of /proc.
+    pub fn with_root(root: impl Into<PathBuf>) -> CoreResult<Self> {
+        let proc_fs = ProcFs::new(root);
+        // The first sample measures from here
+        let previous = read_times(&proc_fs)?;
+        Ok(Self { proc_fs, previous })
+    }
+
+    /// Processes, threads and load averages. Missing files are not an
+    /// error; the figures are left out instead.
+    fn load(&self, snapshot: &mut CpuSnapshot) {
+        if let Ok(pids) = self.proc_fs.pids() {
+            snapshot.processes = pids.len() as u32;
+        }
+        if let Ok(loadavg) = self.proc_fs.read("loadavg")
+            && let Some((load_average, threads)) = parse_loadavg(&loadavg)
+        {
+            snapshot.load_average = Some(load_average);
+            snapshot.threads = threads;
+        }
+    }
+}
+
+impl MetricsSource for ProcCpu {
+    type Sample = CpuSnapshot;
+
+    fn backend(&self) -> &'static str {
+        "procfs"
+    }
+
+    fn sample(&mut self) -> CoreResult<CpuSnapshot> {
+        let current = read_times(&self.proc_fs)?;
+        let frequencies = self.proc_fs.read("cpuinfo").map(|cpuinfo| parse_frequencies(&cpuinfo)).unwrap_or_default();
+
+        let cores: Vec<CoreUsage> = current
+            .cores
+            .iter()
+            .enumerate()
+            .map(|(index, times)| {
+                // A core that just came online is measured since boot
+                let previous = self.previous.cores.get(index).copied().unwrap_or_default();
+                let breakdown = times.breakdown_since(&previous);
+                CoreUsage {
+                    usage: breakdown.busy(),
+                    breakdown,
+                    frequency_mhz: frequencies.get(index).copied().flatten(),
+                }
+            })
+            .collect();
+
+        let breakdown = current.all.breakdown_since(&self.previous.all);
+        let mut snapshot = CpuSnapshot {
+            usage: breakdown.busy(),
+            breakdown,
+            frequency_mhz: average_frequency(&cores),
+            cores,
+            ..Default::default()
+        };
+        self.load(&mut snapshot);
+        self.previous = current;
+
+        debug!("Current CPU usage: {:.1}% over {} cores", snapshot.usage, snapshot.cores.len());
+        Ok(snapshot)
+    }
+}
+
+fn read_times(proc_fs: &ProcFs) -> CoreResult<StatTimes> {
+    let stat = proc_fs.read("stat")?;
+    let mut all = None;
+    let mut cores = Vec::new();
+    for line in stat.lines() {
+        if line.starts_with("cpu ") {
+            all = CpuTimes::parse(line);
+        } else if line.starts_with("cpu") {
+            cores.extend(CpuTimes::parse(line));
+        }
+    }
+    let all = all.ok_or_else(|| CoreError::InitializationError("No cpu line in /proc/stat".to_string()))?;
+    Ok(StatTimes { all, cores })
+}
+
+/// The "cpu MHz" of each processor in /proc/cpuinfo, indexed by processor
+/// number. Architectures that do not report it get an empty list.
+fn parse_frequencies(cpuinfo: &str) -> Vec<Option<u32>> {
+    let mut frequencies = Vec::new();
+    let mut processor = None;
+    for line in cpuinfo.lines() {
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        match key.trim() {
+            "processor" => processor = value.trim().parse::<usize>().ok(),
+            "cpu MHz" => {
+                if let (Some(index), Ok(mhz)) = (processor, value.trim().parse::<f64>()) {
+                    if frequencies.len() <= index {
+                        frequencies.resize(index + 1, None);
+                    }
+                    frequencies[index] = Some(mhz.round() as u32);
+                }
+            }
+            _ => {}
+        }
+    }
+    frequencies
+}
+
+/// Parse "0.52 0.58 0.59 2/1157 12345" into the load averages and the
+/// number of scheduling entities (threads).
+fn parse_loadavg(loadavg: &str) -> Option<([f32; 3], u32)> {
+    let fields: Vec<&str> = loadavg.split_whitespace().collect();
+    if fields.len() < 4 {
+        return None;
+    }
+    let load = |field: &str| field.parse::<f32>().ok();
+    let threads = fields[3].split_once('/')?.1.parse().ok()?;
+    Some(([load(fields[0])?, load(fields[1])?, load(fields[2])?], threads))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::monitor::procfs::fixture;
+
+    #[test]
+    fn test_usage_from_stat_deltas() {
+        let root = fixture(
+            "cpu",
+            &[
+                ("stat", "cpu  100 0 100 700 100 0 0 0 0 0\ncpu0 50 0 50 350 50 0 0 0 0 0\ncpu1 50 0 50 350 50 0 0 0 0 0\n"),
+                ("cpuinfo", "processor\t: 0\ncpu MHz\t\t: 2400.4\n\nprocessor\t: 1\ncpu MHz\t\t: 3599.6\n"),
+                ("loadavg", "0.50 0.25 0.10 3/412 9999\n"),
+                ("1/stat", ""),
+                ("42/stat", ""),
+                ("self/stat", ""),
+            ],
+        );
+        let mut cpu = ProcCpu::with_root(&root).unwrap();
+
+        // 300 more jiffies: 100 user, 50 system, 120 idle and 30 waiting on
+        // I/O. Core 0 did all the work, core 1 sat idle.
+        std::fs::write(
+            root.join("stat"),
+            "cpu  200 0 150 820 130 0 0 0 0 0\ncpu0 150 0 100 350 50 0 0 0 0 0\ncpu1 50 0 50 470 80 0 0 0 0 0\n",
+        )
+        .unwrap();
+        let snapshot = cpu.sample().unwrap();
+        assert_eq!(snapshot.usage, 50.0);
+        assert!((snapshot.breakdown.user - 33.33).abs() < 0.01);
+        assert!((snapshot.breakdown.system - 16.67).abs() < 0.01);
+        assert_eq!((snapshot.breakdown.iowait, snapshot.breakdown.idle), (10.0, 40.0));
+        assert_eq!(snapshot.cores.len(), 2);
+        assert_eq!((snapshot.cores[0].usage, snapshot.cores[1].usage), (100.0, 0.0));
+        assert_eq!(snapshot.cores[1].breakdown.iowait, 20.0);
+        assert_eq!(snapshot.cores[0].frequency_mhz, Some(2400));
+        assert_eq!(snapshot.frequency_mhz, Some(3000));
+        assert_eq!((snapshot.processes, snapshot.threads), (2, 412));
+        assert_eq!(snapshot.load_average, Some([0.5, 0.25, 0.1]));
+
+        // No time passed
+        assert_eq!(cpu.sample().unwrap().usage, 0.0);
+
+        std::fs::write(root.join("stat"), "intr 1 2 3\n").unwrap();
+        assert!(cpu.sample().is_err());
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_parse_without_frequencies() {
+        assert!(parse_frequencies("processor\t: 0\nBogoMIPS\t: 48.00\n").is_empty());
+        assert_eq!(parse_loadavg("0.1 0.2"), None);
+    }
+}
@@ -0,0 +1,168 @@
+// Disk monitoring for Windows systems
+//
+// The `PhysicalDisk` object has one instance per disk, named after its
+// number and drive letters ("0 C: D:"), plus "_Total". Volumes are the
+// drive letters, sized with GetDiskFreeSpaceExW.
+
+use super::{DiskSnapshot, PhysicalDiskStats, VolumeStats};
+use crate::error::{CoreError, CoreResult};
+use crate::monitor::pdh::PdhQuery;
+use crate::monitor::source::MetricsSource;
+use log::{debug, warn};
+use std::collections::HashMap;
+use windows::Win32::Storage::FileSystem::{GetDiskFreeSpaceExW, GetLogicalDrives, GetVolumeInformationW};
+use windows::core::HSTRING;
+
+/// Instance name of the aggregate over all disks.
+const TOTAL: &str = "_Total";
+
+/// Disk monitoring functionality
+pub struct DiskMonitor {
+    query: PdhQuery,
+    disk_time: isize,
+    read_bytes: isize,
+    write_bytes: isize,
+    reads: isize,
+    writes: isize,
+    queue_length: isize,
+    space_alert_threshold: u32,
+    last_snapshot: DiskSnapshot,
+}
+
+impl DiskMonitor {
+    /// Create a new disk monitor, flagging volumes with less than
+    /// `space_alert_threshold` percent free
+    pub fn new(space_alert_threshold: u32) -> Result<Self, CoreError> {
+        let query = PdhQuery::open()?;
+        let counter = |name: &str| query.add(&format!("\\PhysicalDisk(*)\\{}", name));
+        let monitor = Self {
+            disk_time: counter("% Disk Time")?,
+            read_bytes: counter("Disk Read Bytes/sec")?,
+            write_bytes: counter("Disk Write Bytes/sec")?,
+            reads: counter("Disk Reads/sec")?,
+            writes: counter("Disk Writes/sec")?,
+            queue_length: counter("Avg. Disk Queue Length")?,
+            query,
+            space_alert_threshold,
+            last_snapshot: DiskSnapshot::default(),
+        };
+
+        // Initial data collection to establish baseline
+        let _ = monitor.query.collect();
+
+        // Small sleep to allow for first measurement
+        std::thread::sleep(std::time::Duration::from_millis(100));
+
+        // Collect again to get meaningful values
+        let _ = monitor.query.collect();
+
+        Ok(monitor)
+    }
+
+    /// Take a snapshot of disk activity and volume space
+    pub fn snapshot(&mut self) -> Result<DiskSnapshot, CoreError> {
+        if let Err(e) = self.query.collect() {
+            warn!("{}", e);
+            return Ok(self.last_snapshot.clone()); // Return last known value
+        }
+
+        let disk_time = self.query.values(self.disk_time)?;
+        let read_bytes = self.query.values(self.read_bytes)?;
+        let write_bytes = self.query.values(self.write_bytes)?;
+        let reads = self.query.values(self.reads)?;
+        let writes = self.query.values(self.writes)?;
+        let queue_length = self.query.values(self.queue_length)?;
+
+        let disks = disk_time
+            .keys()
+            .filter(|name| name.as_str() != TOTAL)
+            .map(|name| {
+                let value = |values: &HashMap<String, f64>| values.get(name).copied().unwrap_or(0.0).max(0.0);
+                PhysicalDiskStats {
+                    name: name.clone(),
+                    // % Disk Time goes above 100 with several requests in flight
+                    busy: value(&disk_time).min(100.0) as f32,
+                    read_bytes: value(&read_bytes) as u64,
+                    write_bytes: value(&write_bytes) as u64,
+                    read_ops: value(&reads) as f32,
+                    write_ops: value(&writes) as f32,
+                    queue_length: value(&queue_length) as f32,
+                }
+            })
+            .collect();
+
+        let snapshot = DiskSnapshot::new(disks, Self::volumes(), self.space_alert_threshold);
+        debug!(
+            "Disk: {:.1}% busy, Read: {:.2} MB/s, Write: {:.2} MB/s, Free: {} bytes on {} volumes",
+            snapshot.busy,
+            snapshot.read_bytes as f64 / 1_048_576.0,
+            snapshot.write_bytes as f64 / 1_048_576.0,
+            snapshot.free_bytes,
+            snapshot.volumes.len()
+        );
+        self.last_snapshot = snapshot.clone();
+        Ok(snapshot)
+    }
+
+    /// Space on every drive letter that has a volume mounted. Drives
+    /// without media, such as an empty card reader, are left out.
+    pub fn volumes() -> Vec<VolumeStats> {
+        // Bit n is set when drive letter n is in use
+        let available_drives = unsafe { GetLogicalDrives() };
+
+        (0..26u8)
+            .filter(|i| available_drives & (1 << i) != 0)
+            .filter_map(|i| {
+                let mount_point = format!("{}:\\", (b'A' + i) as char);
+                let root = HSTRING::from(mount_point.as_str());
+
+                let (mut free_bytes, mut total_bytes) = (0u64, 0u64);
+                unsafe { GetDiskFreeSpaceExW(&root, Some(&mut free_bytes), Some(&mut total_bytes), None) }.ok()?;
+
+                let mut filesystem = [0u16; 32];
+                let filesystem = match unsafe { GetVolumeInformationW(&root, None, None, None, None, Some(&mut filesystem)) } {
+                    Ok(()) => String::from_utf16_lossy(&filesystem).trim_end_matches('\0').to_string(),
+                    Err(_) => String::new(),
+                };
+
+                Some(VolumeStats {
+                    mount_point,
+                    device: String::new(),
+                    filesystem,
+                    total_bytes,
+                    free_bytes,
+                    low_space: false,
+                })
+            })
+            .collect()
+    }
+}
+
+impl MetricsSource for DiskMonitor {
+    type Sample = DiskSnapshot;
+
+    fn backend(&self) -> &'static str {
+        "pdh"
+    }
+
+    fn sample(&mut self) -> CoreResult<DiskSnapshot> {
+        self.snapshot()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_disk_monitor_creation() {
+        // This test just verifies that we can create the monitor without errors
+        match DiskMonitor::new(10) {
+            Ok(_) => {} // Success
+            Err(e) => {
+                eprintln!("Note: Disk monitor creation failed, but this might be expected in CI: {}", e);
+                // Don't fail the test as it might be running in CI without proper access
+            }
+        }
+    }
+}
@@ -0,0 +1,305 @@
+// Disk monitoring from /proc/diskstats and statvfs on Linux
+//
+// I/O counters of the physical disks come from /proc/diskstats, volumes
+// from /proc/mounts with their space from statvfs.
+
+use super::{DiskSnapshot, PhysicalDiskStats, VolumeStats};
+use crate::error::CoreResult;
+use crate::monitor::procfs::{ProcFs, counters, rate};
+use crate::monitor::source::MetricsSource;
+use log::debug;
+use std::collections::HashSet;
+use std::ffi::CString;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// Sectors in /proc/diskstats are always 512 bytes.
+const SECTOR_SIZE: u64 = 512;
+
+/// Block devices that are not physical disks, or that would count the I/O
+/// of the disks below them again.
+const VIRTUAL_DEVICES: &[&str] = &["loop", "ram", "zram", "dm-", "md", "sr", "fd", "nbd"];
+
+/// Filesystems without storage of their own.
+const PSEUDO_FILESYSTEMS: &[&str] = &[
+    "autofs", "binfmt_misc", "bpf", "cgroup", "cgroup2", "configfs", "debugfs", "devpts", "devtmpfs", "efivarfs",
+    "fusectl", "hugetlbfs", "mqueue", "nsfs", "proc", "pstore", "ramfs", "rpc_pipefs", "securityfs", "selinuxfs",
+    "squashfs", "sysfs", "tmpfs", "tracefs",
+];
+
+/// I/O counters of one block device since boot.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct DeviceCounters {
+    name: String,
+    reads: u64,
+    sectors_read: u64,
+    writes: u64,
+    sectors_written: u64,
+    /// Milliseconds spent doing I/O
+    io_ms: u64,
+    /// Milliseconds spent doing I/O, weighted by the requests in flight
+    weighted_io_ms: u64,
+}
+
+impl DeviceCounters {
+    /// Activity since `previous`, `elapsed` ago.
+    fn stats_since(&self, previous: &Self, elapsed: Duration) -> PhysicalDiskStats {
+        let elapsed_ms = elapsed.as_secs_f64() * 1000.0;
+        let per_ms = |current: u64, previous: u64| {
+            if elapsed_ms <= 0.0 {
+                return 0.0;
+            }
+            current.saturating_sub(previous) as f64 / elapsed_ms
+        };
+        PhysicalDiskStats {
+            name: self.name.clone(),
+            busy: (per_ms(self.io_ms, previous.io_ms) * 100.0).clamp(0.0, 100.0) as f32,
+            read_bytes: rate(previous.sectors_read * SECTOR_SIZE, self.sectors_read * SECTOR_SIZE, elapsed),
+            write_bytes: rate(previous.sectors_written * SECTOR_SIZE, self.sectors_written * SECTOR_SIZE, elapsed),
+            read_ops: (per_ms(self.reads, previous.reads) * 1000.0) as f32,
+            write_ops: (per_ms(self.writes, previous.writes) * 1000.0) as f32,
+            queue_length: per_ms(self.weighted_io_ms, previous.weighted_io_ms) as f32,
+        }
+    }
+}
+
+/// Activity of the physical disks in /proc/diskstats, and space on the
+/// mounted filesystems.
+#[derive(Debug)]
+pub struct ProcDisk {
+    proc_fs: ProcFs,
+    // Whole disks are the devices listed in <sys>/block
+    sys_root: PathBuf,
+    space_alert_threshold: u32,
+    previous: (Vec<DeviceCounters>, Instant),
+}
+
+impl ProcDisk {
+    pub fn new(space_alert_threshold: u32) -> CoreResult<Self> {
+        Self::with_roots("/proc", "/sys", space_alert_threshold)
+    }
+
+    /// Read from `proc_root` and `sys_root` instead of /proc and /sys.
+    pub fn with_roots(proc_root: impl Into<PathBuf>, sys_root: impl Into<PathBuf>, space_alert_threshold: u32) -> CoreResult<Self> {
+        let mut disk = Self {
+            proc_fs: ProcFs::new(proc_root),
+            sys_root: sys_root.into(),
+            space_alert_threshold,
+            previous: (Vec::new(), Instant::now()),
+        };
+        disk.previous.0 = disk.disks()?;
+        Ok(disk)
+    }
+
+    /// Counters of the physical disks.
+    fn disks(&self) -> CoreResult<Vec<DeviceCounters>> {
+        let devices = parse_diskstats(&self.proc_fs.read("diskstats")?);
+        Ok(devices.into_iter().filter(|device| self.is_disk(&device.name)).collect())
+    }
+
+    fn is_disk(&self, name: &str) -> bool {
+        !VIRTUAL_DEVICES.iter().any(|prefix| name.starts_with(prefix)) && self.sys_root.join("block").join(name).exists()
+    }
+
+    fn sample_at(&mut self, now: Instant) -> CoreResult<DiskSnapshot> {
+        let disks = self.disks()?;
+        let (previous, previous_at) = &self.previous;
+        let elapsed = now.saturating_duration_since(*previous_at);
+
+        let stats = disks
+            .iter()
+            .map(|disk| {
+                // Disks that just appeared have nothing to compare with
+                let before = previous.iter().find(|before| before.name == disk.name).unwrap_or(disk);
+                disk.stats_since(before, elapsed)
+            })
+            .collect();
+        let snapshot = DiskSnapshot::new(stats, self.volumes()?, self.space_alert_threshold);
+        self.previous = (disks, now);
+        Ok(snapshot)
+    }
+
+    /// Every mounted filesystem with storage, counting each device once.
+    fn volumes(&self) -> CoreResult<Vec<VolumeStats>> {
+        let mounts = parse_mounts(&self.proc_fs.read("mounts")?);
+        let mut seen = HashSet::new();
+        let mut volumes = Vec::new();
+        for mount in mounts {
+            if PSEUDO_FILESYSTEMS.contains(&mount.fs_type.as_str()) || !seen.insert(mount.device.clone()) {
+                continue;
+            }
+            match space(Path::new(&mount.mount_point)) {
+                Ok((total_bytes, free_bytes)) => volumes.push(VolumeStats {
+                    mount_point: mount.mount_point,
+                    device: mount.device,
+                    filesystem: mount.fs_type,
+                    total_bytes,
+                    free_bytes,
+                    low_space: false,
+                }),
+                Err(e) => debug!("Failed to get free space on {}: {}", mount.mount_point, e),
+            }
+        }
+        Ok(volumes)
+    }
+}
+
+impl MetricsSource for ProcDisk {
+    type Sample = DiskSnapshot;
+
+    fn backend(&self) -> &'static str {
+        "procfs"
+    }
+
+    fn sample(&mut self) -> CoreResult<DiskSnapshot> {
+        let snapshot = self.sample_at(Instant::now())?;
+        debug!(
+            "Disk: {:.1}% busy, Read: {:.2} MB/s, Write: {:.2} MB/s, Free: {} bytes on {} volumes",
+            snapshot.busy,
+            snapshot.read_bytes as f64 / 1_048_576.0,
+            snapshot.write_bytes as f64 / 1_048_576.0,
+            snapshot.free_bytes,
+            snapshot.volumes.len()
+        );
+        Ok(snapshot)
+    }
+}
+
+/// Parse "major minor name reads merged sectors ms writes merged sectors ms
+/// in_flight io_ms weighted_io_ms ..." lines.
+fn parse_diskstats(text: &str) -> Vec<DeviceCounters> {
+    text.lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace().skip(2);
+            let name = fields.next()?.to_string();
+            let values = counters(fields);
+            Some(DeviceCounters {
+                name,
+                reads: *values.first()?,
+                sectors_read: *values.get(2)?,
+                writes: *values.get(4)?,
+                sectors_written: *values.get(6)?,
+                io_ms: *values.get(9)?,
+                weighted_io_ms: *values.get(10)?,
+            })
+        })
+        .collect()
+}
+
+/// One line of /proc/mounts.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Mount {
+    device: String,
+    mount_point: String,
+    fs_type: String,
+}
+
+/// Parse "device mount_point type options dump pass" lines. Spaces and
+/// other special characters in paths are written as octal escapes.
+fn parse_mounts(text: &str) -> Vec<Mount> {
+    text.lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            Some(Mount {
+                device: unescape(fields.next()?),
+                mount_point: unescape(fields.next()?),
+                fs_type: fields.next()?.to_string(),
+            })
+        })
+        .collect()
+}
+
+fn unescape(field: &str) -> String {
+    let bytes = field.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let octal = bytes.get(i + 1..i + 4).and_then(|digits| u8::from_str_radix(std::str::from_utf8(digits).ok()?, 8).ok());
+        match octal {
+            Some(byte) if bytes[i] == b'\\' => {
+                out.push(byte);
+                i += 4;
+            }
+            _ => {
+                out.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// Total and available bytes of the filesystem holding `path`.
+fn space(path: &Path) -> io::Result<(u64, u64)> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let path = CString::new(path.as_os_str().as_bytes()).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    // SAFETY: statvfs only writes to the struct it is given
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let block = stat.f_frsize as u64;
+    Ok((stat.f_blocks as u64 * block, stat.f_bavail as u64 * block))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::monitor::procfs::fixture;
+    use std::time::Duration;
+
+    /// One sda line from (reads, sectors read, writes, sectors written,
+    /// io ms, weighted io ms), plus a partition and a loop device.
+    fn diskstats(sda: [u64; 6]) -> String {
+        let [reads, read, writes, written, io_ms, weighted] = sda;
+        format!(
+            "   8       0 sda {reads} 0 {read} 0 {writes} 0 {written} 0 0 {io_ms} {weighted}\n   8       1 sda1 {reads} 0 {read} 0 {writes} 0 {written} 0 0 {io_ms} {weighted}\n   7       0 loop0 10 0 999999 0 0 0 0 0 0 999999 0\n"
+        )
+    }
+
+    #[test]
+    fn test_disk_rates_and_volumes() {
+        // The root filesystem is mounted twice; it is counted once
+        let mounts = "/dev/root / ext4 rw 0 0\nproc /proc proc rw 0 0\n/dev/root /mnt/with\\040space ext4 rw 0 0\n";
+        let root = fixture(
+            "disk",
+            &[
+                ("proc/diskstats", &diskstats([0; 6])),
+                ("proc/mounts", mounts),
+                ("sys/block/sda/size", "1"),
+                ("sys/block/loop0/size", "1"),
+            ],
+        );
+        let mut disk = ProcDisk::with_roots(root.join("proc"), root.join("sys"), 100).unwrap();
+        let start = disk.previous.1;
+
+        std::fs::write(root.join("proc/diskstats"), diskstats([200, 4096, 100, 2048, 500, 3000])).unwrap();
+        let snapshot = disk.sample_at(start + Duration::from_secs(2)).unwrap();
+        assert_eq!((snapshot.busy, snapshot.read_bytes, snapshot.write_bytes), (25.0, 1024 * 1024, 512 * 1024));
+
+        assert_eq!(snapshot.disks.len(), 1);
+        let sda = &snapshot.disks[0];
+        assert_eq!(sda.name, "sda");
+        assert_eq!((sda.read_ops, sda.write_ops, sda.queue_length), (100.0, 50.0, 1.5));
+
+        assert_eq!(snapshot.volumes.len(), 1);
+        let volume = &snapshot.volumes[0];
+        assert_eq!((volume.mount_point.as_str(), volume.device.as_str(), volume.filesystem.as_str()), ("/", "/dev/root", "ext4"));
+        let (total, available) = space(Path::new("/")).unwrap();
+        assert_eq!(volume.total_bytes, total);
+        assert!(volume.free_bytes.abs_diff(available) < 1 << 30, "{} vs {}", volume.free_bytes, available);
+        // Any volume is below a 100% free threshold
+        assert!(volume.low_space);
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_parse_mounts() {
+        let mounts = parse_mounts("/dev/sdb1 /media/My\\040Disk vfat rw 0 0\n");
+        assert_eq!(mounts[0].mount_point, "/media/My Disk");
+        assert_eq!(mounts[0].fs_type, "vfat");
+        assert_eq!(unescape("a\\134b\\"), "a\\b\\");
+    }
+}
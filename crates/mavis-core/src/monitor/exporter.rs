@@ -0,0 +1,431 @@
+// Prometheus exporter
+//
+// Renders each `ResourceUsage` in the Prometheus text exposition format,
+// with per-core, per-interface, per-disk and per-volume series, and
+// publishes it two ways: served over HTTP on a localhost port
+// (`monitoring.exporter_port`), and written to a file after every sample for
+// node_exporter's textfile collector (`monitoring.exporter_textfile`). The
+// file is written beside the target and renamed over it, so the collector
+// never reads a partial file. Only built with the "prometheus" feature.
+
+use super::disk::{PhysicalDiskStats, VolumeStats};
+use super::network::InterfaceCounters;
+use super::{ResourceUsage, SourceHealth, UsageSubscription};
+use crate::config::MonitoringConfig;
+use crate::error::{CoreError, CoreResult};
+use log::{debug, error, info, warn};
+use std::fmt::Write as _;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::watch;
+
+/// Content type of the text exposition format.
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// Longest request head read before giving up on a client.
+const MAX_REQUEST_BYTES: usize = 8192;
+
+/// How long a client may take to send its request.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Render `usage` in the Prometheus text exposition format.
+pub fn render(usage: &ResourceUsage) -> String {
+    let mut out = Exposition::default();
+    let cpu = &usage.cpu;
+
+    out.family("mavis_cpu_usage_percent", "gauge", "CPU usage across all cores")
+        .sample("mavis_cpu_usage_percent", &[], usage.cpu_usage as f64);
+    out.family("mavis_cpu_time_percent", "gauge", "Share of CPU time by mode");
+    for (mode, percent) in [
+        ("user", cpu.breakdown.user),
+        ("system", cpu.breakdown.system),
+        ("iowait", cpu.breakdown.iowait),
+        ("idle", cpu.breakdown.idle),
+    ] {
+        out.sample("mavis_cpu_time_percent", &[("mode", mode)], percent as f64);
+    }
+    out.family("mavis_cpu_core_usage_percent", "gauge", "CPU usage per logical core");
+    for (index, core) in cpu.cores.iter().enumerate() {
+        out.sample("mavis_cpu_core_usage_percent", &[("core", &index.to_string())], core.usage as f64);
+    }
+    if let Some(mhz) = cpu.frequency_mhz {
+        out.family("mavis_cpu_frequency_mhz", "gauge", "Current CPU frequency")
+            .sample("mavis_cpu_frequency_mhz", &[], mhz as f64);
+    }
+    if let Some(load) = cpu.load_average {
+        out.family("mavis_load_average", "gauge", "Load average");
+        for (period, value) in ["1m", "5m", "15m"].into_iter().zip(load) {
+            out.sample("mavis_load_average", &[("period", period)], value as f64);
+        }
+    }
+    out.family("mavis_processes", "gauge", "Running processes")
+        .sample("mavis_processes", &[], cpu.processes as f64);
+    out.family("mavis_threads", "gauge", "Running threads")
+        .sample("mavis_threads", &[], cpu.threads as f64);
+
+    out.family("mavis_memory_total_bytes", "gauge", "Physical memory")
+        .sample("mavis_memory_total_bytes", &[], usage.total_memory as f64);
+    out.family("mavis_memory_used_bytes", "gauge", "Physical memory in use")
+        .sample("mavis_memory_used_bytes", &[], usage.used_memory as f64);
+    out.family("mavis_memory_usage_percent", "gauge", "Physical memory in use")
+        .sample("mavis_memory_usage_percent", &[], usage.memory_usage as f64);
+
+    let interfaces = &usage.network.interfaces;
+    out.family("mavis_network_up", "gauge", "Whether the interface link is up");
+    for interface in interfaces {
+        out.sample("mavis_network_up", &[("interface", &interface.name)], interface.up as u8 as f64);
+    }
+    out.family("mavis_network_bytes_per_second", "gauge", "Network throughput per interface");
+    for interface in interfaces {
+        for (direction, bytes) in [("receive", interface.rx_bytes), ("transmit", interface.tx_bytes)] {
+            let labels = [("interface", interface.name.as_str()), ("direction", direction)];
+            out.sample("mavis_network_bytes_per_second", &labels, bytes as f64);
+        }
+    }
+    for (name, help, counters) in [
+        (
+            "mavis_network_bytes_total",
+            "Bytes since monitoring started",
+            (|totals| (totals.rx_bytes, totals.tx_bytes)) as fn(&InterfaceCounters) -> (u64, u64),
+        ),
+        ("mavis_network_errors_total", "Errors since monitoring started", |totals| (totals.rx_errors, totals.tx_errors)),
+        ("mavis_network_dropped_total", "Dropped packets since monitoring started", |totals| {
+            (totals.rx_dropped, totals.tx_dropped)
+        }),
+    ] {
+        out.family(name, "counter", help);
+        for interface in interfaces {
+            let (receive, transmit) = counters(&interface.totals);
+            for (direction, value) in [("receive", receive), ("transmit", transmit)] {
+                let labels = [("interface", interface.name.as_str()), ("direction", direction)];
+                out.sample(name, &labels, value as f64);
+            }
+        }
+    }
+
+    let disks = &usage.disk.disks;
+    for (name, help, value) in [
+        ("mavis_disk_busy_percent", "Time the disk was busy", (|disk| disk.busy as f64) as fn(&PhysicalDiskStats) -> f64),
+        ("mavis_disk_read_bytes_per_second", "Disk read throughput", |disk| disk.read_bytes as f64),
+        ("mavis_disk_write_bytes_per_second", "Disk write throughput", |disk| disk.write_bytes as f64),
+        ("mavis_disk_read_ops_per_second", "Disk reads per second", |disk| disk.read_ops as f64),
+        ("mavis_disk_write_ops_per_second", "Disk writes per second", |disk| disk.write_ops as f64),
+        ("mavis_disk_queue_length", "Requests waiting for the disk", |disk| disk.queue_length as f64),
+    ] {
+        out.family(name, "gauge", help);
+        for disk in disks {
+            out.sample(name, &[("disk", &disk.name)], value(disk));
+        }
+    }
+
+    let volumes = &usage.disk.volumes;
+    for (name, help, bytes) in [
+        ("mavis_volume_size_bytes", "Size of the volume", (|volume| volume.total_bytes) as fn(&VolumeStats) -> u64),
+        ("mavis_volume_free_bytes", "Free space on the volume", |volume| volume.free_bytes),
+    ] {
+        out.family(name, "gauge", help);
+        for volume in volumes {
+            let labels = [
+                ("mount_point", volume.mount_point.as_str()),
+                ("device", volume.device.as_str()),
+                ("fstype", volume.filesystem.as_str()),
+            ];
+            out.sample(name, &labels, bytes(volume) as f64);
+        }
+    }
+
+    out.family("mavis_source_up", "gauge", "Whether the metric source sampled successfully");
+    for status in usage.sources.iter().filter(|status| status.health != SourceHealth::Disabled) {
+        let up = status.health == SourceHealth::Ok;
+        out.sample("mavis_source_up", &[("family", status.family)], up as u8 as f64);
+    }
+
+    out.text
+}
+
+/// Text exposition being written.
+#[derive(Debug, Default)]
+struct Exposition {
+    text: String,
+}
+
+impl Exposition {
+    /// Start a metric family; its samples follow.
+    fn family(&mut self, name: &str, kind: &str, help: &str) -> &mut Self {
+        let _ = writeln!(self.text, "# HELP {} {}", name, help);
+        let _ = writeln!(self.text, "# TYPE {} {}", name, kind);
+        self
+    }
+
+    fn sample(&mut self, name: &str, labels: &[(&str, &str)], value: f64) -> &mut Self {
+        self.text.push_str(name);
+        if !labels.is_empty() {
+            let labels: Vec<String> = labels
+                .iter()
+                .map(|(label, value)| format!("{}=\"{}\"", label, escape_label(value)))
+                .collect();
+            let _ = write!(self.text, "{{{}}}", labels.join(","));
+        }
+        let _ = writeln!(self.text, " {}", format_value(value));
+        self
+    }
+}
+
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+fn format_value(value: f64) -> String {
+    match value {
+        value if value.is_nan() => "NaN".to_string(),
+        f64::INFINITY => "+Inf".to_string(),
+        f64::NEG_INFINITY => "-Inf".to_string(),
+        value => value.to_string(),
+    }
+}
+
+/// Publishes monitor samples in the Prometheus text format until dropped.
+#[derive(Debug)]
+pub struct MetricsExporter {
+    address: Option<SocketAddr>,
+    stop_tx: watch::Sender<bool>,
+    thread_handle: Option<JoinHandle<()>>,
+}
+
+impl MetricsExporter {
+    /// Serve and write the samples from `updates` as `config` asks. Returns
+    /// None if neither an exporter port nor a textfile is configured.
+    pub fn start(config: &MonitoringConfig, updates: UsageSubscription) -> CoreResult<Option<Self>> {
+        if config.exporter_port.is_none() && config.exporter_textfile.is_none() {
+            return Ok(None);
+        }
+
+        // Bind here so a port in use is reported to the caller
+        let listener = match config.exporter_port {
+            Some(port) => {
+                let listener = std::net::TcpListener::bind((Ipv4Addr::LOCALHOST, port))?;
+                listener.set_nonblocking(true)?;
+                Some(listener)
+            }
+            None => None,
+        };
+        let address = listener.as_ref().map(|listener| listener.local_addr()).transpose()?;
+        let textfile = config.exporter_textfile.clone();
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .map_err(|e| CoreError::InitializationError(format!("Failed to create exporter runtime: {}", e)))?;
+        let (stop_tx, mut stop_rx) = watch::channel(false);
+
+        let thread_handle = thread::spawn(move || {
+            rt.block_on(async move {
+                let latest = Arc::new(Mutex::new(String::new()));
+                let server = serve(listener, latest.clone());
+                let publisher = publish(updates, latest, textfile);
+                tokio::select! {
+                    _ = server => {}
+                    _ = publisher => {}
+                    _ = stop_rx.wait_for(|stop| *stop) => {}
+                }
+            });
+        });
+
+        if let Some(address) = address {
+            info!("Serving Prometheus metrics on http://{}/metrics", address);
+        }
+        Ok(Some(Self {
+            address,
+            stop_tx,
+            thread_handle: Some(thread_handle),
+        }))
+    }
+
+    /// The address metrics are served on, if a port is configured.
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.address
+    }
+}
+
+impl Drop for MetricsExporter {
+    fn drop(&mut self) {
+        let _ = self.stop_tx.send(true);
+        if let Some(handle) = self.thread_handle.take()
+            && handle.join().is_err()
+        {
+            error!("Metrics exporter thread panicked");
+        }
+    }
+}
+
+/// Render each new sample into `latest` and the textfile.
+async fn publish(mut updates: UsageSubscription, latest: Arc<Mutex<String>>, textfile: Option<PathBuf>) {
+    while let Some(usage) = updates.next().await {
+        let text = render(&usage);
+        if let Some(path) = &textfile
+            && let Err(e) = write_textfile(path, &text).await
+        {
+            warn!("Failed to write metrics to {:?}: {}", path, e);
+        }
+        if let Ok(mut latest) = latest.lock() {
+            *latest = text;
+        }
+    }
+    info!("Resource monitor gone; metrics exporter stopping");
+}
+
+/// Replace `path` with `text` in one step.
+async fn write_textfile(path: &Path, text: &str) -> std::io::Result<()> {
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    let temporary = path.with_file_name(format!(".{}.tmp", file_name));
+    tokio::fs::write(&temporary, text).await?;
+    tokio::fs::rename(&temporary, path).await
+}
+
+/// Answer HTTP requests with the latest rendered sample.
+async fn serve(listener: Option<std::net::TcpListener>, latest: Arc<Mutex<String>>) {
+    let Some(listener) = listener else {
+        return std::future::pending().await;
+    };
+    let listener = match TcpListener::from_std(listener) {
+        Ok(listener) => listener,
+        Err(e) => {
+            error!("Failed to start metrics server: {}", e);
+            return std::future::pending().await;
+        }
+    };
+    loop {
+        match listener.accept().await {
+            Ok((stream, peer)) => {
+                let latest = latest.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = respond(stream, &latest).await {
+                        debug!("Metrics request from {} failed: {}", peer, e);
+                    }
+                });
+            }
+            Err(e) => warn!("Failed to accept metrics connection: {}", e),
+        }
+    }
+}
+
+async fn respond(mut stream: TcpStream, latest: &Mutex<String>) -> std::io::Result<()> {
+    let mut request = Vec::new();
+    let mut buffer = [0u8; 1024];
+    while !request.windows(4).any(|window| window == b"\r\n\r\n") && request.len() < MAX_REQUEST_BYTES {
+        let read = tokio::time::timeout(REQUEST_TIMEOUT, stream.read(&mut buffer))
+            .await
+            .map_err(|_| std::io::Error::new(std::io::ErrorKind::TimedOut, "request timed out"))??;
+        if read == 0 {
+            break;
+        }
+        request.extend_from_slice(&buffer[..read]);
+    }
+
+    let request = String::from_utf8_lossy(&request);
+    let mut request_line = request.lines().next().unwrap_or_default().split_whitespace();
+    let (status, body) = match (request_line.next(), request_line.next()) {
+        (Some("GET"), Some("/metrics" | "/")) => ("200 OK", latest.lock().map(|text| text.clone()).unwrap_or_default()),
+        (Some("GET"), _) => ("404 Not Found", "Not found\n".to_string()),
+        _ => ("405 Method Not Allowed", "Only GET is supported\n".to_string()),
+    };
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        CONTENT_TYPE,
+        body.len(),
+        body
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::monitor::disk::DiskSnapshot;
+    use crate::monitor::network::{InterfaceStats, NetworkSnapshot};
+    use std::io::{Read, Write};
+
+    fn usage() -> ResourceUsage {
+        ResourceUsage {
+            cpu_usage: 12.5,
+            network: NetworkSnapshot {
+                interfaces: vec![InterfaceStats {
+                    name: "eth0".to_string(),
+                    up: true,
+                    rx_bytes: 2048,
+                    ..Default::default()
+                }],
+                ..Default::default()
+            },
+            disk: DiskSnapshot {
+                volumes: vec![VolumeStats {
+                    mount_point: "C:\\".to_string(),
+                    free_bytes: 100,
+                    ..Default::default()
+                }],
+                ..Default::default()
+            },
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_render_text_format() {
+        let text = render(&usage());
+        assert!(text.contains("# TYPE mavis_cpu_usage_percent gauge\nmavis_cpu_usage_percent 12.5\n"));
+        assert!(text.contains("mavis_network_bytes_per_second{interface=\"eth0\",direction=\"receive\"} 2048\n"));
+        assert!(text.contains("# TYPE mavis_network_bytes_total counter\n"));
+        assert!(text.contains("mavis_volume_free_bytes{mount_point=\"C:\\\\\",device=\"\",fstype=\"\"} 100\n"));
+        assert!(!text.contains("mavis_load_average"));
+        assert_eq!(format_value(f64::NAN), "NaN");
+        assert_eq!(escape_label("a \"b\"\n"), "a \\\"b\\\"\\n");
+    }
+
+    #[test]
+    fn test_exporter_serves_and_writes_samples() {
+        let directory = std::env::temp_dir().join(format!("mavis-exporter-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let textfile = directory.join("mavis.prom");
+        let mut config = crate::config::Config::default().monitoring;
+        assert!(MetricsExporter::start(&config, usage_updates().1).unwrap().is_none());
+
+        config.exporter_port = Some(0);
+        config.exporter_textfile = Some(textfile.clone());
+        let (sender, updates) = usage_updates();
+        let exporter = MetricsExporter::start(&config, updates).unwrap().unwrap();
+        sender.send_replace(usage());
+
+        let deadline = std::time::Instant::now() + Duration::from_secs(5);
+        while !textfile.exists() && std::time::Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(5));
+        }
+        assert!(std::fs::read_to_string(&textfile).unwrap().contains("mavis_cpu_usage_percent 12.5\n"));
+
+        let get = |path: &str| {
+            let mut stream = std::net::TcpStream::connect(exporter.local_addr().unwrap()).unwrap();
+            write!(stream, "GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response
+        };
+        let response = get("/metrics");
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains(CONTENT_TYPE));
+        assert!(response.contains("mavis_cpu_usage_percent 12.5\n"));
+        assert!(get("/other").starts_with("HTTP/1.1 404"));
+
+        drop(exporter);
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    fn usage_updates() -> (watch::Sender<ResourceUsage>, UsageSubscription) {
+        let (sender, receiver) = watch::channel(ResourceUsage::default());
+        (sender, UsageSubscription::new(receiver, Duration::ZERO))
+    }
+}
@@ -0,0 +1,116 @@
+// Scripted metrics source for tests
+//
+// `FakeSource` returns samples or errors queued by the test, then keeps
+// repeating the last one. Clones share the queue, so a test can keep one
+// clone to feed a source that has been moved into the monitor thread.
+
+use super::source::MetricsSource;
+use crate::error::{CoreError, CoreResult};
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+#[derive(Debug)]
+struct FakeState<S> {
+    queue: VecDeque<Result<S, String>>,
+    last: Option<Result<S, String>>,
+    samples_taken: usize,
+}
+
+/// A source that returns queued samples or errors.
+#[derive(Debug)]
+pub struct FakeSource<S> {
+    state: Arc<Mutex<FakeState<S>>>,
+}
+
+impl<S> Clone for FakeSource<S> {
+    fn clone(&self) -> Self {
+        Self {
+            state: self.state.clone(),
+        }
+    }
+}
+
+impl<S: Clone> FakeSource<S> {
+    /// Return `samples` in order, then repeat the last one.
+    pub fn new(samples: impl IntoIterator<Item = S>) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(FakeState {
+                queue: samples.into_iter().map(Ok).collect(),
+                last: None,
+                samples_taken: 0,
+            })),
+        }
+    }
+
+    /// Always return `sample`.
+    pub fn constant(sample: S) -> Self {
+        Self::new([sample])
+    }
+
+    /// Fail every sample with `message` until something else is queued.
+    pub fn failing(message: &str) -> Self {
+        let source = Self::new([]);
+        source.fail(message);
+        source
+    }
+
+    /// Queue a sample.
+    pub fn push(&self, sample: S) {
+        self.lock().queue.push_back(Ok(sample));
+    }
+
+    /// Queue a failure.
+    pub fn fail(&self, message: &str) {
+        self.lock().queue.push_back(Err(message.to_string()));
+    }
+
+    /// How many times the source was sampled.
+    pub fn samples_taken(&self) -> usize {
+        self.lock().samples_taken
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, FakeState<S>> {
+        // A test that panicked while holding the lock has failed already
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl<S: Clone + Send> MetricsSource for FakeSource<S> {
+    type Sample = S;
+
+    fn backend(&self) -> &'static str {
+        "fake"
+    }
+
+    fn sample(&mut self) -> CoreResult<S> {
+        let mut state = self.lock();
+        state.samples_taken += 1;
+        let next = match state.queue.pop_front() {
+            Some(next) => next,
+            None => state.last.clone().unwrap_or_else(|| Err("No samples queued".to_string())),
+        };
+        state.last = Some(next.clone());
+        next.map_err(CoreError::InitializationError)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fake_source_replays_queue() {
+        let mut source = FakeSource::new([1, 2]);
+        let handle = source.clone();
+        assert_eq!(source.sample().unwrap(), 1);
+        assert_eq!(source.sample().unwrap(), 2);
+        assert_eq!(source.sample().unwrap(), 2);
+
+        handle.fail("unplugged");
+        assert!(source.sample().is_err());
+        assert!(source.sample().is_err());
+        handle.push(3);
+        assert_eq!(source.sample().unwrap(), 3);
+        assert_eq!(handle.samples_taken(), 6);
+    }
+}
@@ -0,0 +1,74 @@
+// Include/exclude lists for monitored devices
+//
+// Patterns match whole names, ignoring ASCII case; `*` matches any run of
+// characters and `?` a single one, so "vEthernet*" covers every Hyper-V
+// adapter.
+
+/// Decides which devices of a metric family are monitored.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NameFilter {
+    include: Vec<String>,
+    exclude: Vec<String>,
+}
+
+impl NameFilter {
+    /// Monitor names matching any `include` pattern (all names if there are
+    /// none) unless they match an `exclude` pattern.
+    pub fn new(include: Vec<String>, exclude: Vec<String>) -> Self {
+        Self { include, exclude }
+    }
+
+    pub fn allows(&self, name: &str) -> bool {
+        let matches_any = |patterns: &[String]| patterns.iter().any(|pattern| matches(pattern, name));
+        (self.include.is_empty() || matches_any(&self.include)) && !matches_any(&self.exclude)
+    }
+}
+
+/// Match `name` against a glob `pattern`.
+fn matches(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().map(|c| c.to_ascii_lowercase()).collect();
+    let name: Vec<char> = name.chars().map(|c| c.to_ascii_lowercase()).collect();
+
+    let (mut p, mut n) = (0, 0);
+    // Position after the last `*` and the name position it was tried at
+    let mut backtrack = None;
+    while n < name.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == name[n]) {
+            p += 1;
+            n += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            p += 1;
+            backtrack = Some((p, n));
+        } else if let Some((star, tried)) = backtrack {
+            // Let the last `*` swallow one more character
+            p = star;
+            n = tried + 1;
+            backtrack = Some((star, n));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_include_and_exclude_patterns() {
+        assert!(matches("eth*", "eth0"));
+        assert!(matches("*Ethernet*", "vEthernet (WSL)"));
+        assert!(matches("WLAN?", "wlan0"));
+        assert!(!matches("eth?", "eth10"));
+        assert!(matches("*", ""));
+
+        let all = NameFilter::default();
+        assert!(all.allows("lo"));
+
+        let filter = NameFilter::new(vec!["eth*".into(), "wlan*".into()], vec!["*1".into()]);
+        assert!(filter.allows("eth0"));
+        assert!(!filter.allows("eth1"));
+        assert!(!filter.allows("docker0"));
+    }
+}
@@ -0,0 +1,373 @@
+// Metrics history
+//
+// Every sample the monitor publishes is also recorded here, one `Series` per
+// `Metric`. A series keeps recent samples at full resolution and folds all
+// samples into fixed-width buckets that are kept for longer, so queries over
+// the last few minutes are exact and queries over the last hour are
+// summaries of the buckets.
+
+use super::ResourceUsage;
+use crate::config::MonitoringConfig;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// A metric recorded over time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Metric {
+    /// CPU usage as a percentage (0-100)
+    CpuUsage,
+    /// Memory usage as a percentage (0-100)
+    MemoryUsage,
+    /// Disk busy time as a percentage (0-100)
+    DiskBusy,
+    /// Disk read speed in bytes per second
+    DiskRead,
+    /// Disk write speed in bytes per second
+    DiskWrite,
+    /// Network download speed in bytes per second
+    NetworkDown,
+    /// Network upload speed in bytes per second
+    NetworkUp,
+}
+
+impl Metric {
+    pub const ALL: [Metric; 7] = [
+        Self::CpuUsage,
+        Self::MemoryUsage,
+        Self::DiskBusy,
+        Self::DiskRead,
+        Self::DiskWrite,
+        Self::NetworkDown,
+        Self::NetworkUp,
+    ];
+
+    /// Name used in configuration and Lua, e.g. "cpu_usage".
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::CpuUsage => "cpu_usage",
+            Self::MemoryUsage => "memory_usage",
+            Self::DiskBusy => "disk_busy",
+            Self::DiskRead => "disk_read",
+            Self::DiskWrite => "disk_write",
+            Self::NetworkDown => "network_down",
+            Self::NetworkUp => "network_up",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|metric| metric.name() == name)
+    }
+
+    /// Human-readable name, e.g. "CPU usage".
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::CpuUsage => "CPU usage",
+            Self::MemoryUsage => "Memory usage",
+            Self::DiskBusy => "Disk busy time",
+            Self::DiskRead => "Disk reads",
+            Self::DiskWrite => "Disk writes",
+            Self::NetworkDown => "Network download",
+            Self::NetworkUp => "Network upload",
+        }
+    }
+
+    /// Whether values are percentages (0-100) rather than bytes per second.
+    pub fn is_percentage(&self) -> bool {
+        matches!(self, Self::CpuUsage | Self::MemoryUsage | Self::DiskBusy)
+    }
+
+    /// The value of this metric in `usage`.
+    pub fn value(&self, usage: &ResourceUsage) -> f64 {
+        match self {
+            Self::CpuUsage => usage.cpu_usage as f64,
+            Self::MemoryUsage => usage.memory_usage as f64,
+            Self::DiskBusy => usage.disk_usage as f64,
+            Self::DiskRead => usage.disk_read_bytes as f64,
+            Self::DiskWrite => usage.disk_write_bytes as f64,
+            Self::NetworkDown => usage.network_down_bytes as f64,
+            Self::NetworkUp => usage.network_up_bytes as f64,
+        }
+    }
+}
+
+/// Minimum, maximum and average of a metric over a window.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Summary {
+    pub min: f64,
+    pub max: f64,
+    pub avg: f64,
+    /// Number of samples the summary covers
+    pub samples: usize,
+}
+
+/// Samples folded together over one bucket width.
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    start: Instant,
+    min: f64,
+    max: f64,
+    sum: f64,
+    count: usize,
+}
+
+impl Bucket {
+    fn new(start: Instant, value: f64) -> Self {
+        Self {
+            start,
+            min: value,
+            max: value,
+            sum: value,
+            count: 1,
+        }
+    }
+
+    fn add(&mut self, value: f64) {
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+        self.sum += value;
+        self.count += 1;
+    }
+
+    fn average(&self) -> f64 {
+        self.sum / self.count as f64
+    }
+}
+
+/// How long a series keeps its samples.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Retention {
+    /// Samples younger than this are kept as they are
+    pub full: Duration,
+    /// Buckets younger than this are kept
+    pub downsampled: Duration,
+    /// Time covered by one bucket
+    pub bucket: Duration,
+}
+
+impl Retention {
+    pub fn from_config(config: &MonitoringConfig) -> Self {
+        Self {
+            full: Duration::from_secs(config.history_retention_secs as u64),
+            downsampled: Duration::from_secs(config.history_downsampled_secs as u64),
+            bucket: Duration::from_secs(config.history_bucket_secs.max(1) as u64),
+        }
+    }
+}
+
+/// The recorded values of one metric, oldest first.
+#[derive(Debug, Clone)]
+pub struct Series {
+    retention: Retention,
+    points: VecDeque<(Instant, f64)>,
+    buckets: VecDeque<Bucket>,
+}
+
+impl Series {
+    pub fn new(retention: Retention) -> Self {
+        Self {
+            retention,
+            points: VecDeque::new(),
+            buckets: VecDeque::new(),
+        }
+    }
+
+    /// Record `value` measured at `at`, dropping what has aged out.
+    pub fn record(&mut self, at: Instant, value: f64) {
+        self.points.push_back((at, value));
+        while let Some((oldest, _)) = self.points.front()
+            && at.saturating_duration_since(*oldest) > self.retention.full
+        {
+            self.points.pop_front();
+        }
+
+        match self.buckets.back_mut() {
+            Some(bucket) if at.saturating_duration_since(bucket.start) < self.retention.bucket => bucket.add(value),
+            _ => self.buckets.push_back(Bucket::new(at, value)),
+        }
+        while let Some(oldest) = self.buckets.front()
+            && at.saturating_duration_since(oldest.start) > self.retention.downsampled
+        {
+            self.buckets.pop_front();
+        }
+    }
+
+    /// The most recent value.
+    pub fn latest(&self) -> Option<f64> {
+        self.points.back().map(|(_, value)| *value)
+    }
+
+    /// Values over the last `window`, oldest first: every sample if the
+    /// window fits in the full-resolution retention, else one average per
+    /// bucket.
+    pub fn values(&self, window: Duration) -> Vec<f64> {
+        if self.is_full_resolution(window) {
+            self.recent_points(window).map(|(_, value)| *value).collect()
+        } else {
+            self.recent_buckets(window).map(Bucket::average).collect()
+        }
+    }
+
+    /// Minimum, maximum and average over the last `window`, or None if
+    /// nothing was recorded in it.
+    pub fn summary(&self, window: Duration) -> Option<Summary> {
+        let (min, max, sum, samples) = if self.is_full_resolution(window) {
+            self.recent_points(window).fold((f64::MAX, f64::MIN, 0.0, 0), |(min, max, sum, count), (_, value)| {
+                (min.min(*value), max.max(*value), sum + value, count + 1)
+            })
+        } else {
+            self.recent_buckets(window).fold((f64::MAX, f64::MIN, 0.0, 0), |(min, max, sum, count), bucket| {
+                (min.min(bucket.min), max.max(bucket.max), sum + bucket.sum, count + bucket.count)
+            })
+        };
+        (samples > 0).then(|| Summary {
+            min,
+            max,
+            avg: sum / samples as f64,
+            samples,
+        })
+    }
+
+    /// The value below which `percentile` percent (0-100) of the values
+    /// over the last `window` fall, by nearest rank. Windows longer than the
+    /// full-resolution retention rank bucket averages, so spikes shorter
+    /// than a bucket are smoothed out.
+    pub fn percentile(&self, window: Duration, percentile: f64) -> Option<f64> {
+        let mut values = self.values(window);
+        if values.is_empty() {
+            return None;
+        }
+        values.sort_by(f64::total_cmp);
+        let rank = (percentile.clamp(0.0, 100.0) / 100.0 * values.len() as f64).ceil() as usize;
+        Some(values[rank.saturating_sub(1)])
+    }
+
+    fn is_full_resolution(&self, window: Duration) -> bool {
+        window <= self.retention.full
+    }
+
+    fn newest(&self) -> Option<Instant> {
+        self.points.back().map(|(at, _)| *at)
+    }
+
+    fn recent_points(&self, window: Duration) -> impl Iterator<Item = &(Instant, f64)> {
+        let newest = self.newest();
+        self.points
+            .iter()
+            .filter(move |(at, _)| newest.is_some_and(|newest| newest.saturating_duration_since(*at) <= window))
+    }
+
+    fn recent_buckets(&self, window: Duration) -> impl Iterator<Item = &Bucket> {
+        let newest = self.newest();
+        self.buckets.iter().filter(move |bucket| {
+            newest.is_some_and(|newest| newest.saturating_duration_since(bucket.start) <= window)
+        })
+    }
+}
+
+/// One series per metric.
+#[derive(Debug, Clone)]
+pub struct MetricsHistory {
+    retention: Retention,
+    series: Vec<Series>,
+}
+
+/// History shared between the monitor thread and its readers.
+pub type SharedHistory = Arc<Mutex<MetricsHistory>>;
+
+impl Default for MetricsHistory {
+    fn default() -> Self {
+        Self::new(Retention::from_config(&crate::config::Config::default().monitoring))
+    }
+}
+
+impl MetricsHistory {
+    pub fn new(retention: Retention) -> Self {
+        Self {
+            retention,
+            series: Metric::ALL.iter().map(|_| Series::new(retention)).collect(),
+        }
+    }
+
+    pub fn retention(&self) -> Retention {
+        self.retention
+    }
+
+    /// Keep samples for `retention` from now on; what is already recorded
+    /// ages out under the new limits with the next sample.
+    pub fn set_retention(&mut self, retention: Retention) {
+        self.retention = retention;
+        for series in &mut self.series {
+            series.retention = retention;
+        }
+    }
+
+    /// Record every metric of `usage` at its timestamp.
+    pub fn record(&mut self, usage: &ResourceUsage) {
+        for (metric, series) in Metric::ALL.iter().zip(&mut self.series) {
+            series.record(usage.timestamp, metric.value(usage));
+        }
+    }
+
+    pub fn series(&self, metric: Metric) -> &Series {
+        let index = Metric::ALL.iter().position(|candidate| *candidate == metric).unwrap_or_default();
+        &self.series[index]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn retention() -> Retention {
+        Retention {
+            full: Duration::from_secs(10),
+            downsampled: Duration::from_secs(60),
+            bucket: Duration::from_secs(5),
+        }
+    }
+
+    #[test]
+    fn test_series_keeps_full_and_downsampled_windows() {
+        let start = Instant::now();
+        let mut series = Series::new(retention());
+        // One sample a second for 30 seconds: 0, 1, ..., 29
+        for second in 0..30u64 {
+            series.record(start + Duration::from_secs(second), second as f64);
+        }
+
+        // The last 10 seconds are kept as they are
+        assert_eq!(series.values(Duration::from_secs(3)), [26.0, 27.0, 28.0, 29.0]);
+        assert_eq!(series.values(Duration::from_secs(10)).len(), 11);
+        let summary = series.summary(Duration::from_secs(4)).unwrap();
+        assert_eq!((summary.min, summary.max, summary.avg, summary.samples), (25.0, 29.0, 27.0, 5));
+        assert_eq!(series.percentile(Duration::from_secs(10), 50.0), Some(24.0));
+        assert_eq!(series.latest(), Some(29.0));
+
+        // Older samples only survive in five-second buckets
+        assert_eq!(series.values(Duration::from_secs(60)), [2.0, 7.0, 12.0, 17.0, 22.0, 27.0]);
+        let summary = series.summary(Duration::from_secs(60)).unwrap();
+        assert_eq!((summary.min, summary.max, summary.avg, summary.samples), (0.0, 29.0, 14.5, 30));
+        assert_eq!(series.percentile(Duration::from_secs(60), 100.0), Some(27.0));
+
+        // A minute later the buckets have aged out as well
+        series.record(start + Duration::from_secs(120), 1.0);
+        assert_eq!(series.values(Duration::from_secs(60)), [1.0]);
+        assert_eq!(Series::new(retention()).summary(Duration::from_secs(10)), None);
+    }
+
+    #[test]
+    fn test_history_records_every_metric() {
+        let mut history = MetricsHistory::new(retention());
+        history.record(&ResourceUsage {
+            cpu_usage: 40.0,
+            network_up_bytes: 2048,
+            ..Default::default()
+        });
+        assert_eq!(history.series(Metric::CpuUsage).latest(), Some(40.0));
+        assert_eq!(history.series(Metric::NetworkUp).latest(), Some(2048.0));
+        assert_eq!(history.series(Metric::DiskRead).latest(), Some(0.0));
+        assert_eq!(Metric::from_name("network_up"), Some(Metric::NetworkUp));
+        assert_eq!(Metric::from_name("gpu"), None);
+    }
+}
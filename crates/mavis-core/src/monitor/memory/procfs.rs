@@ -0,0 +1,87 @@
+// Memory monitoring from /proc/meminfo on Linux
+
+use super::MemorySample;
+use crate::error::{CoreError, CoreResult};
+use crate::monitor::procfs::ProcFs;
+use crate::monitor::source::MetricsSource;
+use log::debug;
+use std::path::PathBuf;
+
+/// Memory usage from /proc/meminfo. Memory the kernel could reclaim for
+/// programs (MemAvailable) counts as free.
+#[derive(Debug, Default)]
+pub struct ProcMemory {
+    proc_fs: ProcFs,
+}
+
+impl ProcMemory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Read from `root` instead of /proc.
+    pub fn with_root(root: impl Into<PathBuf>) -> Self {
+        Self {
+            proc_fs: ProcFs::new(root),
+        }
+    }
+}
+
+impl MetricsSource for ProcMemory {
+    type Sample = MemorySample;
+
+    fn backend(&self) -> &'static str {
+        "procfs"
+    }
+
+    fn sample(&mut self) -> CoreResult<MemorySample> {
+        let sample = parse_meminfo(&self.proc_fs.read("meminfo")?)?;
+        debug!(
+            "Memory: {:.1} GB total, {:.1} GB used ({:.1}%)",
+            sample.total as f64 / 1_073_741_824.0,
+            sample.used as f64 / 1_073_741_824.0,
+            sample.usage()
+        );
+        Ok(sample)
+    }
+}
+
+/// Parse "Name:   1234 kB" lines. Kernels older than 3.14 have no
+/// MemAvailable; free, buffer and cache memory stand in for it.
+fn parse_meminfo(text: &str) -> CoreResult<MemorySample> {
+    let field = |name: &str| {
+        text.lines().find_map(|line| {
+            let value = line.strip_prefix(name)?.strip_prefix(':')?;
+            let kilobytes: u64 = value.split_whitespace().next()?.parse().ok()?;
+            Some(kilobytes * 1024)
+        })
+    };
+
+    let total = field("MemTotal").ok_or_else(|| CoreError::InitializationError("No MemTotal in /proc/meminfo".to_string()))?;
+    let available = field("MemAvailable").unwrap_or_else(|| {
+        ["MemFree", "Buffers", "Cached"].iter().filter_map(|name| field(name)).sum()
+    });
+    Ok(MemorySample {
+        total,
+        used: total.saturating_sub(available),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::monitor::procfs::fixture;
+
+    #[test]
+    fn test_meminfo() {
+        let root = fixture("memory", &[("meminfo", "MemTotal:       16000 kB\nMemFree:         2000 kB\nMemAvailable:    4000 kB\nBuffers:          500 kB\n")]);
+        let sample = ProcMemory::with_root(&root).sample().unwrap();
+        assert_eq!(sample, MemorySample { total: 16000 * 1024, used: 12000 * 1024 });
+        assert_eq!(sample.usage(), 75.0);
+        std::fs::remove_dir_all(root).unwrap();
+
+        let old_kernel = parse_meminfo("MemTotal: 1000 kB\nMemFree: 100 kB\nBuffers: 100 kB\nCached: 300 kB\n").unwrap();
+        assert_eq!(old_kernel.used, 500 * 1024);
+        assert!(parse_meminfo("MemFree: 1 kB\n").is_err());
+    }
+}
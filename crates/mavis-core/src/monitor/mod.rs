@@ -1,15 +1,41 @@
 // System resource monitoring module for MAVIS
+//
+// Each metric family is measured by a `MetricsSource` for the platform (see
+// `source`); the monitor thread samples them on a timer, records the
+// combined `ResourceUsage` in the metrics `history`, checks it against the
+// `alerts` rules and publishes it to every `subscription`. Its settings can
+// be changed while it runs (see `control`).
 
+pub mod alerts;
+pub mod control;
 pub mod cpu;
 pub mod memory;
 pub mod network;
 pub mod disk;
+#[cfg(feature = "prometheus")]
+pub mod exporter;
+pub mod fake;
+pub mod filter;
+pub mod history;
+pub mod process;
+#[cfg(windows)]
+mod pdh;
+#[cfg(target_os = "linux")]
+mod procfs;
+pub mod source;
+pub mod subscription;
+
+pub use alerts::{AlertEngine, AlertEvent, AlertRule, AlertSubscription, SharedAlerts};
+pub use control::MonitorControl;
+pub use history::{Metric, MetricsHistory, SharedHistory};
+pub use source::{Backends, DynSource, MetricsSource, MetricsSources, SourceHealth, SourceStatus};
+pub use subscription::UsageSubscription;
 
 use std::sync::{Arc, Mutex};
 use std::thread::{self, JoinHandle};
 use std::time::Duration;
 
-use crate::config::Config;
+use crate::config::{Config, MonitoringConfig};
 use crate::error::CoreError;
 use log::{error, info};
 use tokio::sync::watch;
@@ -19,6 +45,8 @@ use tokio::sync::watch;
 pub struct ResourceUsage {
     /// CPU usage as a percentage (0-100)
     pub cpu_usage: f32,
+    /// Per-core and per-state CPU details
+    pub cpu: cpu::CpuSnapshot,
     /// Total physical memory in bytes
     pub total_memory: u64,
     /// Used physical memory in bytes
@@ -29,6 +57,8 @@ pub struct ResourceUsage {
     pub network_down_bytes: u64,
     /// Network upload speed in bytes per second
     pub network_up_bytes: u64,
+    /// Per-interface network statistics
+    pub network: network::NetworkSnapshot,
     /// Disk busy time as a percentage (0-100)
     pub disk_usage: f32,
     /// Disk read speed in bytes per second
@@ -37,6 +67,12 @@ pub struct ResourceUsage {
     pub disk_write_bytes: u64,
     /// Total free disk space in bytes
     pub disk_free_bytes: u64,
+    /// Per-disk I/O and per-volume space
+    pub disk: disk::DiskSnapshot,
+    /// Running processes
+    pub processes: process::ProcessSnapshot,
+    /// Health of each metric family's source
+    pub sources: Vec<SourceStatus>,
     /// Time when this data was collected
     pub timestamp: std::time::Instant,
 }
@@ -45,15 +81,20 @@ impl Default for ResourceUsage {
     fn default() -> Self {
         Self {
             cpu_usage: 0.0,
+            cpu: cpu::CpuSnapshot::default(),
             total_memory: 0,
             used_memory: 0,
             memory_usage: 0.0,
             network_down_bytes: 0,
             network_up_bytes: 0,
+            network: network::NetworkSnapshot::default(),
             disk_usage: 0.0,
             disk_read_bytes: 0,
             disk_write_bytes: 0,
             disk_free_bytes: 0,
+            disk: disk::DiskSnapshot::default(),
+            processes: process::ProcessSnapshot::default(),
+            sources: Vec::new(),
             timestamp: std::time::Instant::now(),
         }
     }
@@ -61,10 +102,14 @@ impl Default for ResourceUsage {
 
 /// Manages system resource monitoring
 pub struct ResourceMonitor {
-    /// Latest resource usage information
-    usage: Arc<Mutex<ResourceUsage>>,
-    /// Monitoring interval in milliseconds
-    update_interval: u32,
+    /// Latest resource usage information, published to subscribers
+    usage: watch::Sender<ResourceUsage>,
+    /// Every sample within the configured retention
+    history: SharedHistory,
+    /// Alert rules, checked against every sample
+    alerts: SharedAlerts,
+    /// Current settings, watched by the monitor thread
+    settings: watch::Sender<MonitoringConfig>,
     /// Whether monitoring is currently active
     active: bool,
     /// Monitor thread handle
@@ -78,14 +123,18 @@ pub struct ResourceMonitor {
 impl ResourceMonitor {
     /// Create a new resource monitor with the specified configuration
     pub fn new(config: &Config) -> Result<Self, CoreError> {
-        let update_interval = config.monitoring.update_interval_ms;
-        let usage = Arc::new(Mutex::new(ResourceUsage::default()));
+        let (settings, _) = watch::channel(config.monitoring.clone());
+        let (usage, _) = watch::channel(ResourceUsage::default());
+        let history = Arc::new(Mutex::new(MetricsHistory::new(history::Retention::from_config(&config.monitoring))));
+        let alerts = Arc::new(Mutex::new(AlertEngine::from_config(&config.monitoring)));
         // Create a watch channel for the stop signal (initially false)
         let (stop_tx, stop_rx) = watch::channel(false);
         
         Ok(Self {
             usage,
-            update_interval,
+            history,
+            alerts,
+            settings,
             active: false,
             thread_handle: None,
             stop_tx,
@@ -93,18 +142,32 @@ impl ResourceMonitor {
         })
     }
     
-    /// Start monitoring system resources
-    pub fn start(&mut self, config: &Config) -> Result<(), CoreError> {
+    /// Start monitoring system resources with the platform's sources and
+    /// the current settings
+    pub fn start(&mut self) -> Result<(), CoreError> {
+        // Sources are created on the monitor thread; some take a moment to
+        // establish their baseline
+        self.spawn(MetricsSources::native)
+    }
+
+    /// Start monitoring with the given sources, e.g. fakes in tests.
+    /// Families enabled later by `reconfigure` use the platform's sources
+    pub fn start_with_sources(&mut self, sources: MetricsSources) -> Result<(), CoreError> {
+        self.spawn(move |_| sources)
+    }
+
+    fn spawn(
+        &mut self,
+        make_sources: impl FnOnce(&MonitoringConfig) -> MetricsSources + Send + 'static,
+    ) -> Result<(), CoreError> {
         if self.active {
             return Ok(());
         }
         
-        let usage_clone = self.usage.clone();
-        let interval = Duration::from_millis(self.update_interval as u64);
-        let monitor_cpu = config.monitoring.monitor_cpu;
-        let monitor_ram = config.monitoring.monitor_ram;
-        let monitor_network = config.monitoring.monitor_network;
-        let monitor_disk = config.monitoring.monitor_disk;
+        let usage = self.usage.clone();
+        let history = self.history.clone();
+        let alerts = self.alerts.clone();
+        let mut settings = self.settings.subscribe();
         
         // Create a runtime for the async monitor tasks
         let rt = tokio::runtime::Builder::new_multi_thread()
@@ -119,108 +182,45 @@ impl ResourceMonitor {
         // Spawn monitoring thread
         let thread_handle = thread::spawn(move || {
             rt.block_on(async {
-                let mut interval_timer = tokio::time::interval(interval);
-                
-                // Initialize monitors
-                let mut cpu_monitor = if monitor_cpu {
-                    match cpu::CpuMonitor::new() {
-                        Ok(monitor) => Some(monitor),
-                        Err(e) => {
-                            error!("Failed to initialize CPU monitor: {}", e);
-                            None
-                        }
-                    }
-                } else {
-                    None
-                };
-                
-                let mut memory_monitor = if monitor_ram {
-                    match memory::MemoryMonitor::new() {
-                        Ok(monitor) => Some(monitor),
-                        Err(e) => {
-                            error!("Failed to initialize memory monitor: {}", e);
-                            None
-                        }
-                    }
-                } else {
-                    None
-                };
-                
-                let mut network_monitor = if monitor_network {
-                    match network::NetworkMonitor::new() {
-                        Ok(monitor) => Some(monitor),
-                        Err(e) => {
-                            error!("Failed to initialize network monitor: {}", e);
-                            None
-                        }
-                    }
-                } else {
-                    None
-                };
-                
-                let mut disk_monitor = if monitor_disk {
-                    match disk::DiskMonitor::new() {
-                        Ok(monitor) => Some(monitor),
-                        Err(e) => {
-                            error!("Failed to initialize disk monitor: {}", e);
-                            None
-                        }
-                    }
-                } else {
-                    None
-                };
+                let backends = Backends::native();
+                let mut config = settings.borrow_and_update().clone();
+                let mut interval_timer = tokio::time::interval(sample_interval(&config));
+                let mut sources = make_sources(&config);
                 
-                info!("Resource monitoring started with interval {} ms", interval.as_millis());
+                info!("Resource monitoring started with interval {} ms", config.update_interval_ms);
                 
                 loop {
                     tokio::select! {
                         _ = interval_timer.tick() => {
-                            // Create a new usage object for this collection cycle
-                            let mut new_usage = ResourceUsage {
-                                timestamp: std::time::Instant::now(),
-                                ..Default::default()
-                            };
-                            
-                            // Update CPU usage
-                            if let Some(cpu_mon) = &mut cpu_monitor {
-                                if let Ok(usage) = cpu_mon.get_usage() {
-                                    new_usage.cpu_usage = usage;
-                                }
+                            let new_usage = sources.collect();
+                            if let Ok(mut history) = history.lock() {
+                                history.record(&new_usage);
                             }
-                            
-                            // Update memory usage
-                            if let Some(mem_mon) = &mut memory_monitor {
-                                if let Ok((total, used, percentage)) = mem_mon.get_usage() {
-                                    new_usage.total_memory = total;
-                                    new_usage.used_memory = used;
-                                    new_usage.memory_usage = percentage;
-                                }
+                            if let Ok(mut alerts) = alerts.lock() {
+                                alerts.evaluate(&new_usage);
                             }
                             
-                            // Update network usage
-                            if let Some(net_mon) = &mut network_monitor {
-                                if let Ok((down, up)) = net_mon.get_usage() {
-                                    new_usage.network_down_bytes = down;
-                                    new_usage.network_up_bytes = up;
-                                }
+                            // Publish to subscribers; this never waits on them
+                            usage.send_replace(new_usage);
+                        }
+                        
+                        // Apply new settings, then sample right away
+                        Ok(_) = settings.changed() => {
+                            let new_config = settings.borrow_and_update().clone();
+                            sources.reconfigure(&backends, Some(&config), &new_config);
+                            if let Ok(mut history) = history.lock() {
+                                history.set_retention(history::Retention::from_config(&new_config));
                             }
-                            
-                            // Update disk usage
-                            if let Some(disk_mon) = &mut disk_monitor {
-                                if let Ok((usage, read, write, free)) = disk_mon.get_usage() {
-                                    new_usage.disk_usage = usage;
-                                    new_usage.disk_read_bytes = read;
-                                    new_usage.disk_write_bytes = write;
-                                    new_usage.disk_free_bytes = free;
-                                }
+                            if let Ok(mut alerts) = alerts.lock() {
+                                alerts.reconfigure(&config, &new_config);
                             }
-                            
-                            // Update the shared usage data
-                            if let Ok(mut current_usage) = usage_clone.lock() {
-                                *current_usage = new_usage;
+                            if new_config.update_interval_ms != config.update_interval_ms {
+                                info!("Monitor interval changed to {} ms", new_config.update_interval_ms);
                             }
+                            interval_timer = tokio::time::interval(sample_interval(&new_config));
+                            config = new_config;
                         }
-                        
+
                         // Check for stop signal
                         Ok(_) = stop_rx_clone.changed() => {
                             if *stop_rx_clone.borrow() { // Check if the signal is true (stop)
@@ -266,10 +266,57 @@ impl ResourceMonitor {
     
     /// Get the current resource usage
     pub fn get_usage(&self) -> ResourceUsage {
-        self.usage.lock().unwrap().clone()
+        self.usage.borrow().clone()
+    }
+
+    /// Receive each new sample from now on, at most one per `min_interval`
+    pub fn subscribe(&self, min_interval: Duration) -> UsageSubscription {
+        UsageSubscription::new(self.usage.subscribe(), min_interval)
+    }
+
+    /// The settings the monitor runs with
+    pub fn settings(&self) -> MonitoringConfig {
+        self.settings.borrow().clone()
+    }
+
+    /// Change the settings; a running monitor applies them before its next
+    /// sample, starting and dropping sources as families are enabled and
+    /// disabled, and updating the alert thresholds and history retention
+    pub fn reconfigure(&self, config: &MonitoringConfig) {
+        self.control().reconfigure(config.clone());
+    }
+
+    /// A handle for changing the settings and checking the sources
+    pub fn control(&self) -> MonitorControl {
+        MonitorControl::new(self.settings.clone(), self.usage.subscribe())
+    }
+
+    /// The health of each metric family's source as of the latest sample
+    pub fn health(&self) -> Vec<SourceStatus> {
+        self.usage.borrow().sources.clone()
+    }
+
+    /// The history of every sample, shared with the monitor thread
+    pub fn history(&self) -> SharedHistory {
+        self.history.clone()
+    }
+
+    /// The alert engine, shared with the monitor thread
+    pub fn alerts(&self) -> SharedAlerts {
+        self.alerts.clone()
+    }
+
+    /// Receive every alert that fires or clears from now on
+    pub fn subscribe_alerts(&self) -> AlertSubscription {
+        self.alerts.lock().unwrap().subscribe()
     }
 }
 
+/// Time between samples; never zero, which the timer does not allow
+fn sample_interval(config: &MonitoringConfig) -> Duration {
+    Duration::from_millis(config.update_interval_ms.max(1) as u64)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -281,4 +328,97 @@ mod tests {
         let monitor = ResourceMonitor::new(&config);
         assert!(monitor.is_ok());
     }
+
+    #[test]
+    fn test_monitor_publishes_source_samples() {
+        let mut config = Config::default();
+        config.monitoring.update_interval_ms = 10;
+        config.monitoring.cpu_alert_threshold = 10;
+        config.monitoring.alert_sustain_secs = 0;
+        let mut monitor = ResourceMonitor::new(&config).unwrap();
+        let mut alerts = monitor.subscribe_alerts();
+        let mut updates = monitor.subscribe(Duration::ZERO);
+        let cpu = fake::FakeSource::new([cpu::CpuSnapshot {
+            usage: 12.5,
+            ..Default::default()
+        }]);
+        monitor
+            .start_with_sources(MetricsSources {
+                cpu: source::Slot::new(Box::new(cpu.clone())),
+                ..Default::default()
+            })
+            .unwrap();
+
+        let deadline = std::time::Instant::now() + Duration::from_secs(5);
+        while monitor.get_usage().cpu_usage != 12.5 && std::time::Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(5));
+        }
+        assert_eq!(monitor.get_usage().cpu_usage, 12.5);
+        assert_eq!(updates.try_next().map(|usage| usage.cpu_usage), Some(12.5));
+        assert_eq!(monitor.history().lock().unwrap().series(Metric::CpuUsage).latest(), Some(12.5));
+        assert_eq!(alerts.try_next().map(|event| event.rule), Some("cpu".to_string()));
+
+        tokio::runtime::Runtime::new().unwrap().block_on(monitor.stop()).unwrap();
+        let taken = cpu.samples_taken();
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(cpu.samples_taken(), taken);
+    }
+
+    #[test]
+    fn test_monitor_applies_new_settings_while_running() {
+        let mut config = Config::default();
+        config.monitoring.update_interval_ms = 3_600_000;
+        config.monitoring.monitor_ram = false;
+        config.monitoring.monitor_network = false;
+        config.monitoring.monitor_disk = false;
+        config.monitoring.monitor_processes = false;
+        let mut monitor = ResourceMonitor::new(&config).unwrap();
+        let cpu = fake::FakeSource::constant(cpu::CpuSnapshot {
+            usage: 50.0,
+            ..Default::default()
+        });
+        monitor
+            .start_with_sources(MetricsSources {
+                cpu: source::Slot::new(Box::new(cpu.clone())),
+                ..Default::default()
+            })
+            .unwrap();
+        let wait_for = |done: &dyn Fn(&ResourceUsage) -> bool| {
+            let deadline = std::time::Instant::now() + Duration::from_secs(5);
+            while !done(&monitor.get_usage()) && std::time::Instant::now() < deadline {
+                thread::sleep(Duration::from_millis(5));
+            }
+            assert!(done(&monitor.get_usage()));
+        };
+        wait_for(&|usage| usage.cpu_usage == 50.0);
+        assert_eq!(monitor.health()[0].backend, Some("fake"));
+
+        // Only the first sample of the hour is taken until the interval changes
+        let control = monitor.control();
+        control.update(|settings| settings.update_interval_ms = 10);
+        wait_for(&|_| cpu.samples_taken() > 3);
+
+        // Disabling CPU monitoring drops the source
+        control.update(|settings| settings.monitor_cpu = false);
+        wait_for(&|usage| usage.sources.first().is_some_and(|status| status.health == SourceHealth::Disabled));
+        assert_eq!(monitor.get_usage().cpu_usage, 0.0);
+        assert!(!monitor.settings().monitor_cpu);
+        control.update(|settings| settings.allow_process_control = true);
+        assert!(control.process_controller().is_allowed());
+
+        // New thresholds replace the default alert rules
+        control.update(|settings| settings.cpu_alert_threshold = 70);
+        let cpu_threshold = || {
+            let alerts = monitor.alerts();
+            let alerts = alerts.lock().unwrap();
+            alerts.rules().iter().find(|rule| rule.name == "cpu").unwrap().threshold
+        };
+        let deadline = std::time::Instant::now() + Duration::from_secs(5);
+        while cpu_threshold() != 70.0 && std::time::Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(5));
+        }
+        assert_eq!(cpu_threshold(), 70.0);
+
+        tokio::runtime::Runtime::new().unwrap().block_on(monitor.stop()).unwrap();
+    }
 }
\ No newline at end of file
@@ -1,167 +1,129 @@
-// Network monitoring for Windows systems
+// Network monitoring
+//
+// PDH network interface counters on Windows, /proc/net/dev on Linux. Each
+// interface is reported on its own; `MonitoringConfig::network_include` and
+// `network_exclude` pick which ones are monitored and counted in the totals.
 
-use crate::error::CoreError;
-use log::{debug, warn};
-use std::time::Duration;
-use windows::Win32::System::Performance::{
-    PdhAddEnglishCounterW, PdhCollectQueryData, PdhGetFormattedCounterValue,
-    PdhOpenQueryW, PDH_FMT_LARGE, PDH_FMT_COUNTERVALUE,
-};
+#[cfg(windows)]
+mod pdh;
+#[cfg(target_os = "linux")]
+mod procfs;
 
-/// Network monitoring functionality
-pub struct NetworkMonitor {
-    query_handle: isize,
-    bytes_in_counter: isize,
-    bytes_out_counter: isize,
-    last_in_rate: u64,
-    last_out_rate: u64,
+#[cfg(windows)]
+pub use pdh::NetworkMonitor;
+#[cfg(target_os = "linux")]
+pub use procfs::ProcNetwork;
+
+use super::filter::NameFilter;
+use super::source::DynSource;
+use crate::error::CoreResult;
+
+/// Traffic counters of one interface.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct InterfaceCounters {
+    pub rx_bytes: u64,
+    pub tx_bytes: u64,
+    pub rx_packets: u64,
+    pub tx_packets: u64,
+    pub rx_errors: u64,
+    pub tx_errors: u64,
+    pub rx_dropped: u64,
+    pub tx_dropped: u64,
 }
 
-impl NetworkMonitor {
-    /// Create a new network monitor
-    pub fn new() -> Result<Self, CoreError> {
-        let mut query_handle = 0;
-        let mut bytes_in_counter = 0;
-        let mut bytes_out_counter = 0;
-        
-        // Initialize PDH query
-        let query_result = unsafe {
-            PdhOpenQueryW(None, 0, &mut query_handle)
-        };
-        
-        if query_result != 0 {
-            return Err(CoreError::PdhError(format!(
-                "Failed to open PDH query for network: error code {}",
-                query_result
-            )));
-        }
-        
-        // Add network counter for bytes received
-        let counter_path = windows::core::HSTRING::from("\\Network Interface(*)\\Bytes Received/sec");
-        let counter_result = unsafe {
-            PdhAddEnglishCounterW(
-                query_handle,
-                &counter_path,
-                0,
-                &mut bytes_in_counter,
-            )
-        };
-        
-        if counter_result != 0 {
-            unsafe {
-                windows::Win32::System::Performance::PdhCloseQuery(query_handle);
-            }
-            
-            return Err(CoreError::PdhError(format!(
-                "Failed to add network bytes received counter: error code {}",
-                counter_result
-            )));
-        }
-        
-        // Add network counter for bytes sent
-        let counter_path = windows::core::HSTRING::from("\\Network Interface(*)\\Bytes Sent/sec");
-        let counter_result = unsafe {
-            PdhAddEnglishCounterW(
-                query_handle,
-                &counter_path,
-                0,
-                &mut bytes_out_counter,
-            )
-        };
-        
-        if counter_result != 0 {
-            unsafe {
-                windows::Win32::System::Performance::PdhCloseQuery(query_handle);
-            }
-            
-            return Err(CoreError::PdhError(format!(
-                "Failed to add network bytes sent counter: error code {}",
-                counter_result
-            )));
-        }
-        
-        // Initial data collection to establish baseline
-        unsafe {
-            PdhCollectQueryData(query_handle);
+impl InterfaceCounters {
+    /// How much each counter grew since `previous`. A counter that went
+    /// backwards was reset and counts as zero.
+    pub fn since(&self, previous: &Self) -> Self {
+        Self {
+            rx_bytes: self.rx_bytes.saturating_sub(previous.rx_bytes),
+            tx_bytes: self.tx_bytes.saturating_sub(previous.tx_bytes),
+            rx_packets: self.rx_packets.saturating_sub(previous.rx_packets),
+            tx_packets: self.tx_packets.saturating_sub(previous.tx_packets),
+            rx_errors: self.rx_errors.saturating_sub(previous.rx_errors),
+            tx_errors: self.tx_errors.saturating_sub(previous.tx_errors),
+            rx_dropped: self.rx_dropped.saturating_sub(previous.rx_dropped),
+            tx_dropped: self.tx_dropped.saturating_sub(previous.tx_dropped),
         }
-        
-        // Small sleep to allow for first measurement
-        std::thread::sleep(Duration::from_millis(100));
-        
-        let monitor = Self {
-            query_handle,
-            bytes_in_counter,
-            bytes_out_counter,
-            last_in_rate: 0,
-            last_out_rate: 0,
-        };
-        
-        Ok(monitor)
     }
-    
-    /// Get current network usage in bytes per second: (download_bytes_per_sec, upload_bytes_per_sec)
-    pub fn get_usage(&mut self) -> Result<(u64, u64), CoreError> {
-        unsafe {
-            // Collect new data
-            let collect_result = PdhCollectQueryData(self.query_handle);
-            
-            if collect_result != 0 {
-                warn!("Failed to collect PDH data for network: error code {}", collect_result);
-                return Ok((self.last_in_rate, self.last_out_rate));
-            }
-            
-            // Get network bytes received
-            let mut bytes_in_value = PDH_FMT_COUNTERVALUE::default();
-            let format_result = PdhGetFormattedCounterValue(
-                self.bytes_in_counter,
-                PDH_FMT_LARGE,
-                None,
-                &mut bytes_in_value,
-            );
-            
-            if format_result != 0 {
-                warn!("Failed to format network bytes received counter: error code {}", format_result);
-                return Ok((self.last_in_rate, self.last_out_rate));
-            }
-            
-            // Get network bytes sent
-            let mut bytes_out_value = PDH_FMT_COUNTERVALUE::default();
-            let format_result = PdhGetFormattedCounterValue(
-                self.bytes_out_counter,
-                PDH_FMT_LARGE,
-                None,
-                &mut bytes_out_value,
-            );
-            
-            if format_result != 0 {
-                warn!("Failed to format network bytes sent counter: error code {}", format_result);
-                return Ok((self.last_in_rate, self.last_out_rate));
-            }
-            
-            // Calculate rates based on counter values
-            let bytes_in = bytes_in_value.Anonymous.largeValue as u64;
-            let bytes_out = bytes_out_value.Anonymous.largeValue as u64;
-            
-            // Windows already provides bytes per second, so we can use these values directly
-            self.last_in_rate = bytes_in;
-            self.last_out_rate = bytes_out;
-            
-            debug!(
-                "Network: {:.2} MB/s down, {:.2} MB/s up",
-                bytes_in as f64 / 1_048_576.0,
-                bytes_out as f64 / 1_048_576.0
-            );
-            
-            Ok((bytes_in, bytes_out))
-        }
+
+    /// Add `other` to every counter.
+    pub fn add(&mut self, other: &Self) {
+        self.rx_bytes += other.rx_bytes;
+        self.tx_bytes += other.tx_bytes;
+        self.rx_packets += other.rx_packets;
+        self.tx_packets += other.tx_packets;
+        self.rx_errors += other.rx_errors;
+        self.tx_errors += other.tx_errors;
+        self.rx_dropped += other.rx_dropped;
+        self.tx_dropped += other.tx_dropped;
     }
 }
 
-impl Drop for NetworkMonitor {
-    fn drop(&mut self) {
-        // Clean up PDH resources
-        unsafe {
-            windows::Win32::System::Performance::PdhCloseQuery(self.query_handle);
+/// One network interface over the sampling interval.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct InterfaceStats {
+    /// Interface name, e.g. "eth0" or "Intel[R] Ethernet Connection"
+    pub name: String,
+    /// Whether the link is up
+    pub up: bool,
+    /// Download speed in bytes per second
+    pub rx_bytes: u64,
+    /// Upload speed in bytes per second
+    pub tx_bytes: u64,
+    /// Packets received per second
+    pub rx_packets: u64,
+    /// Packets sent per second
+    pub tx_packets: u64,
+    /// Traffic, errors and drops since monitoring started
+    pub totals: InterfaceCounters,
+}
+
+/// Network throughput over the sampling interval.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NetworkSnapshot {
+    /// Download speed of all monitored interfaces in bytes per second
+    pub down_bytes: u64,
+    /// Upload speed of all monitored interfaces in bytes per second
+    pub up_bytes: u64,
+    /// The monitored interfaces, sorted by name
+    pub interfaces: Vec<InterfaceStats>,
+}
+
+impl NetworkSnapshot {
+    /// A snapshot of `interfaces` with their summed throughput.
+    pub fn new(mut interfaces: Vec<InterfaceStats>) -> Self {
+        interfaces.sort_by(|a, b| a.name.cmp(&b.name));
+        Self {
+            down_bytes: interfaces.iter().map(|interface| interface.rx_bytes).sum(),
+            up_bytes: interfaces.iter().map(|interface| interface.tx_bytes).sum(),
+            interfaces,
         }
     }
-}
\ No newline at end of file
+
+    /// The interface named `name`.
+    pub fn interface(&self, name: &str) -> Option<&InterfaceStats> {
+        self.interfaces.iter().find(|interface| interface.name == name)
+    }
+}
+
+/// The network source for the current platform, monitoring the interfaces
+/// `filter` allows.
+#[cfg(windows)]
+pub fn native(filter: NameFilter) -> CoreResult<DynSource<NetworkSnapshot>> {
+    Ok(Box::new(NetworkMonitor::new(filter)?))
+}
+
+/// The network source for the current platform, monitoring the interfaces
+/// `filter` allows.
+#[cfg(target_os = "linux")]
+pub fn native(filter: NameFilter) -> CoreResult<DynSource<NetworkSnapshot>> {
+    Ok(Box::new(ProcNetwork::new(filter)?))
+}
+
+/// The network source for the current platform, monitoring the interfaces
+/// `filter` allows.
+#[cfg(not(any(windows, target_os = "linux")))]
+pub fn native(_filter: NameFilter) -> CoreResult<DynSource<NetworkSnapshot>> {
+    Err(super::source::unsupported("network"))
+}
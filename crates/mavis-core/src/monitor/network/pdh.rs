@@ -0,0 +1,167 @@
+// Network monitoring for Windows systems
+//
+// The `Network Interface` object has one instance per adapter. Traffic is
+// reported as rates, so totals are integrated over the sampling interval;
+// errors and discards are raw counts since boot.
+
+use super::{InterfaceCounters, InterfaceStats, NetworkSnapshot};
+use crate::error::{CoreError, CoreResult};
+use crate::monitor::filter::NameFilter;
+use crate::monitor::pdh::PdhQuery;
+use crate::monitor::source::MetricsSource;
+use log::{debug, warn};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// An adapter seen at the previous sample.
+#[derive(Debug, Clone, Copy, Default)]
+struct Tracked {
+    /// Error and discard counts since boot at the previous sample
+    last: InterfaceCounters,
+    /// Growth since monitoring started
+    totals: InterfaceCounters,
+}
+
+/// Network monitoring functionality
+pub struct NetworkMonitor {
+    query: PdhQuery,
+    bytes_in: isize,
+    bytes_out: isize,
+    packets_in: isize,
+    packets_out: isize,
+    errors_in: isize,
+    errors_out: isize,
+    discarded_in: isize,
+    discarded_out: isize,
+    bandwidth: isize,
+    filter: NameFilter,
+    interfaces: HashMap<String, Tracked>,
+    previous_at: Instant,
+    last_snapshot: NetworkSnapshot,
+}
+
+impl NetworkMonitor {
+    /// Create a new network monitor for the adapters `filter` allows
+    pub fn new(filter: NameFilter) -> Result<Self, CoreError> {
+        let query = PdhQuery::open()?;
+        let counter = |name: &str| query.add(&format!("\\Network Interface(*)\\{}", name));
+        let mut monitor = Self {
+            bytes_in: counter("Bytes Received/sec")?,
+            bytes_out: counter("Bytes Sent/sec")?,
+            packets_in: counter("Packets Received/sec")?,
+            packets_out: counter("Packets Sent/sec")?,
+            errors_in: counter("Packets Received Errors")?,
+            errors_out: counter("Packets Outbound Errors")?,
+            discarded_in: counter("Packets Received Discarded")?,
+            discarded_out: counter("Packets Outbound Discarded")?,
+            bandwidth: counter("Current Bandwidth")?,
+            query,
+            filter,
+            interfaces: HashMap::new(),
+            previous_at: Instant::now(),
+            last_snapshot: NetworkSnapshot::default(),
+        };
+
+        // Initial data collection to establish baseline
+        let _ = monitor.query.collect();
+
+        // Small sleep to allow for first measurement
+        std::thread::sleep(Duration::from_millis(100));
+
+        // Error counts are counted from here
+        monitor.snapshot_at(Instant::now())?;
+
+        Ok(monitor)
+    }
+
+    /// Get current network usage in bytes per second: (download_bytes_per_sec, upload_bytes_per_sec)
+    pub fn get_usage(&mut self) -> Result<(u64, u64), CoreError> {
+        let snapshot = self.snapshot_at(Instant::now())?;
+        Ok((snapshot.down_bytes, snapshot.up_bytes))
+    }
+
+    fn snapshot_at(&mut self, now: Instant) -> CoreResult<NetworkSnapshot> {
+        if let Err(e) = self.query.collect() {
+            warn!("{}", e);
+            return Ok(self.last_snapshot.clone()); // Return last known value
+        }
+        let elapsed = now.saturating_duration_since(self.previous_at).as_secs_f64();
+        self.previous_at = now;
+
+        let bytes_in = self.query.values(self.bytes_in)?;
+        let bytes_out = self.query.values(self.bytes_out)?;
+        let packets_in = self.query.values(self.packets_in)?;
+        let packets_out = self.query.values(self.packets_out)?;
+        let errors_in = self.query.values(self.errors_in)?;
+        let errors_out = self.query.values(self.errors_out)?;
+        let discarded_in = self.query.values(self.discarded_in)?;
+        let discarded_out = self.query.values(self.discarded_out)?;
+        let bandwidth = self.query.values(self.bandwidth)?;
+
+        let mut interfaces = HashMap::with_capacity(bytes_in.len());
+        let mut stats = Vec::with_capacity(bytes_in.len());
+        for name in bytes_in.keys().filter(|name| self.filter.allows(name)) {
+            let value = |values: &HashMap<String, f64>| values.get(name).copied().unwrap_or(0.0).max(0.0) as u64;
+            let counts = InterfaceCounters {
+                rx_errors: value(&errors_in),
+                tx_errors: value(&errors_out),
+                rx_dropped: value(&discarded_in),
+                tx_dropped: value(&discarded_out),
+                ..Default::default()
+            };
+            let rates = InterfaceStats {
+                name: name.clone(),
+                up: value(&bandwidth) > 0,
+                rx_bytes: value(&bytes_in),
+                tx_bytes: value(&bytes_out),
+                rx_packets: value(&packets_in),
+                tx_packets: value(&packets_out),
+                totals: InterfaceCounters::default(),
+            };
+
+            // An adapter that just appeared is counted from now on
+            let mut tracked = self.interfaces.get(name).copied().unwrap_or(Tracked {
+                last: counts,
+                totals: InterfaceCounters::default(),
+            });
+            let over_interval = |per_second: u64| (per_second as f64 * elapsed) as u64;
+            tracked.totals.add(&InterfaceCounters {
+                rx_bytes: over_interval(rates.rx_bytes),
+                tx_bytes: over_interval(rates.tx_bytes),
+                rx_packets: over_interval(rates.rx_packets),
+                tx_packets: over_interval(rates.tx_packets),
+                ..counts.since(&tracked.last)
+            });
+            tracked.last = counts;
+
+            stats.push(InterfaceStats {
+                totals: tracked.totals,
+                ..rates
+            });
+            interfaces.insert(name.clone(), tracked);
+        }
+        self.interfaces = interfaces;
+
+        let snapshot = NetworkSnapshot::new(stats);
+        debug!(
+            "Network: {:.2} MB/s down, {:.2} MB/s up over {} interfaces",
+            snapshot.down_bytes as f64 / 1_048_576.0,
+            snapshot.up_bytes as f64 / 1_048_576.0,
+            snapshot.interfaces.len()
+        );
+        self.last_snapshot = snapshot.clone();
+        Ok(snapshot)
+    }
+}
+
+impl MetricsSource for NetworkMonitor {
+    type Sample = NetworkSnapshot;
+
+    fn backend(&self) -> &'static str {
+        "pdh"
+    }
+
+    fn sample(&mut self) -> CoreResult<NetworkSnapshot> {
+        self.snapshot_at(Instant::now())
+    }
+}
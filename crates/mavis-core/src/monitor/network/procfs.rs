@@ -0,0 +1,222 @@
+// Network monitoring from /proc/net/dev on Linux
+//
+// Traffic counters come from /proc/net/dev and the link state from
+// /sys/class/net/<interface>/operstate.
+
+use super::{InterfaceCounters, InterfaceStats, NetworkSnapshot};
+use crate::error::CoreResult;
+use crate::monitor::filter::NameFilter;
+use crate::monitor::procfs::{ProcFs, counters, rate};
+use crate::monitor::source::MetricsSource;
+use log::debug;
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::time::Instant;
+
+/// An interface seen at the previous sample.
+#[derive(Debug, Clone, Copy, Default)]
+struct Tracked {
+    /// Counters since boot at the previous sample
+    last: InterfaceCounters,
+    /// Growth since monitoring started
+    totals: InterfaceCounters,
+}
+
+/// Per-interface traffic from the counters in /proc/net/dev.
+#[derive(Debug)]
+pub struct ProcNetwork {
+    proc_fs: ProcFs,
+    sys_root: PathBuf,
+    filter: NameFilter,
+    interfaces: HashMap<String, Tracked>,
+    previous_at: Instant,
+}
+
+impl ProcNetwork {
+    pub fn new(filter: NameFilter) -> CoreResult<Self> {
+        Self::with_roots("/proc", "/sys", filter)
+    }
+
+    /// Read from `proc_root` and `sys_root` instead of /proc and /sys.
+    pub fn with_roots(proc_root: impl Into<PathBuf>, sys_root: impl Into<PathBuf>, filter: NameFilter) -> CoreResult<Self> {
+        let proc_fs = ProcFs::new(proc_root);
+        // Rates and totals are measured from here
+        let interfaces = read_counters(&proc_fs, &filter)?
+            .into_iter()
+            .map(|(name, last)| {
+                let tracked = Tracked {
+                    last,
+                    totals: InterfaceCounters::default(),
+                };
+                (name, tracked)
+            })
+            .collect();
+        Ok(Self {
+            proc_fs,
+            sys_root: sys_root.into(),
+            filter,
+            interfaces,
+            previous_at: Instant::now(),
+        })
+    }
+
+    fn sample_at(&mut self, now: Instant) -> CoreResult<NetworkSnapshot> {
+        let current = read_counters(&self.proc_fs, &self.filter)?;
+        let elapsed = now.saturating_duration_since(self.previous_at);
+        self.previous_at = now;
+
+        let mut interfaces = HashMap::with_capacity(current.len());
+        let mut stats = Vec::with_capacity(current.len());
+        for (name, counters) in current {
+            // An interface that just appeared is measured from now on
+            let mut tracked = self.interfaces.get(&name).copied().unwrap_or(Tracked {
+                last: counters,
+                totals: InterfaceCounters::default(),
+            });
+            let previous = tracked.last;
+            tracked.totals.add(&counters.since(&previous));
+            tracked.last = counters;
+
+            stats.push(InterfaceStats {
+                up: self.is_up(&name),
+                rx_bytes: rate(previous.rx_bytes, counters.rx_bytes, elapsed),
+                tx_bytes: rate(previous.tx_bytes, counters.tx_bytes, elapsed),
+                rx_packets: rate(previous.rx_packets, counters.rx_packets, elapsed),
+                tx_packets: rate(previous.tx_packets, counters.tx_packets, elapsed),
+                totals: tracked.totals,
+                name: name.clone(),
+            });
+            interfaces.insert(name, tracked);
+        }
+        self.interfaces = interfaces;
+        Ok(NetworkSnapshot::new(stats))
+    }
+
+    /// Interfaces without carrier detection (loopback, most tunnels) report
+    /// "unknown" and count as up.
+    fn is_up(&self, name: &str) -> bool {
+        let path = self.sys_root.join("class/net").join(name).join("operstate");
+        match fs::read_to_string(path) {
+            Ok(state) => matches!(state.trim(), "up" | "unknown"),
+            Err(_) => true,
+        }
+    }
+}
+
+impl MetricsSource for ProcNetwork {
+    type Sample = NetworkSnapshot;
+
+    fn backend(&self) -> &'static str {
+        "procfs"
+    }
+
+    fn sample(&mut self) -> CoreResult<NetworkSnapshot> {
+        let snapshot = self.sample_at(Instant::now())?;
+        debug!(
+            "Network: {:.2} MB/s down, {:.2} MB/s up over {} interfaces",
+            snapshot.down_bytes as f64 / 1_048_576.0,
+            snapshot.up_bytes as f64 / 1_048_576.0,
+            snapshot.interfaces.len()
+        );
+        Ok(snapshot)
+    }
+}
+
+/// Counters of every interface `filter` allows.
+fn read_counters(proc_fs: &ProcFs, filter: &NameFilter) -> CoreResult<Vec<(String, InterfaceCounters)>> {
+    Ok(parse_net_dev(&proc_fs.read("net/dev")?)
+        .into_iter()
+        .filter(|(name, _)| filter.allows(name))
+        .collect())
+}
+
+/// Parse the interface lines after the two header lines: "eth0:" followed
+/// by eight receive fields (bytes packets errs drop fifo frame compressed
+/// multicast) and eight transmit fields (bytes packets errs drop fifo colls
+/// carrier compressed).
+fn parse_net_dev(text: &str) -> Vec<(String, InterfaceCounters)> {
+    text.lines()
+        .skip(2)
+        .filter_map(|line| {
+            let (name, values) = line.split_once(':')?;
+            let values = counters(values.split_whitespace());
+            if values.len() < 16 {
+                return None;
+            }
+            let counters = InterfaceCounters {
+                rx_bytes: values[0],
+                rx_packets: values[1],
+                rx_errors: values[2],
+                rx_dropped: values[3],
+                tx_bytes: values[8],
+                tx_packets: values[9],
+                tx_errors: values[10],
+                tx_dropped: values[11],
+            };
+            Some((name.trim().to_string(), counters))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::monitor::procfs::fixture;
+    use std::time::Duration;
+
+    const HEADER: &str = "Inter-|   Receive                                                |  Transmit\n face |bytes    packets errs drop fifo frame compressed multicast|bytes    packets errs drop fifo colls carrier compressed\n";
+
+    fn net_dev(lo: u64, eth: u64, wlan: u64) -> String {
+        format!(
+            "{}    lo: {lo} 10 0 0 0 0 0 0 {lo} 10 0 0 0 0 0 0\n  eth0: {eth} {} 1 2 0 0 0 0 {} 10 3 4 0 0 0 0\nwlan0:{wlan} 10 0 0 0 0 0 0 0 10 0 0 0 0 0 0\n",
+            HEADER,
+            eth / 100,
+            eth / 2
+        )
+    }
+
+    #[test]
+    fn test_per_interface_rates_and_totals() {
+        let root = fixture(
+            "network",
+            &[
+                ("proc/net/dev", &net_dev(1000, 1000, 0)),
+                ("sys/class/net/eth0/operstate", "up\n"),
+                ("sys/class/net/wlan0/operstate", "down\n"),
+            ],
+        );
+        let filter = NameFilter::new(Vec::new(), vec!["lo".to_string()]);
+        let mut network = ProcNetwork::with_roots(root.join("proc"), root.join("sys"), filter).unwrap();
+        let start = network.previous_at;
+
+        std::fs::write(root.join("proc/net/dev"), net_dev(900_000, 5000, 1000)).unwrap();
+        let snapshot = network.sample_at(start + Duration::from_secs(2)).unwrap();
+        assert_eq!((snapshot.down_bytes, snapshot.up_bytes), (2500, 1000));
+        assert_eq!(snapshot.interfaces.len(), 2);
+        assert!(snapshot.interface("lo").is_none());
+
+        let eth = snapshot.interface("eth0").unwrap();
+        assert!(eth.up);
+        assert_eq!((eth.rx_bytes, eth.tx_bytes, eth.rx_packets), (2000, 1000, 20));
+        assert_eq!((eth.totals.rx_bytes, eth.totals.tx_bytes), (4000, 2000));
+        let wlan = snapshot.interface("wlan0").unwrap();
+        assert!(!wlan.up);
+        assert_eq!(wlan.totals.rx_bytes, 1000);
+
+        // Counters reset when an interface goes away and comes back; the
+        // totals keep what was counted before
+        std::fs::write(root.join("proc/net/dev"), net_dev(0, 0, 0)).unwrap();
+        let snapshot = network.sample_at(start + Duration::from_secs(3)).unwrap();
+        assert_eq!((snapshot.down_bytes, snapshot.up_bytes), (0, 0));
+        assert_eq!(snapshot.interface("eth0").unwrap().totals.rx_bytes, 4000);
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_parse_net_dev_errors_and_drops() {
+        let interfaces = parse_net_dev(&net_dev(0, 100, 0));
+        let (_, eth) = interfaces.iter().find(|(name, _)| name == "eth0").unwrap();
+        assert_eq!((eth.rx_errors, eth.rx_dropped, eth.tx_errors, eth.tx_dropped), (1, 2, 3, 4));
+    }
+}
@@ -0,0 +1,94 @@
+// Performance Data Helper queries for the Windows metrics backends
+//
+// A `PdhQuery` owns one PDH query handle and the counters added to it.
+// Rate counters need two collections before they report a value, so sources
+// collect once when they are created and then once per sample.
+
+use crate::error::{CoreError, CoreResult};
+use std::collections::HashMap;
+use windows::Win32::System::Performance::{
+    PdhAddEnglishCounterW, PdhCloseQuery, PdhCollectQueryData, PdhGetFormattedCounterArrayW,
+    PdhGetFormattedCounterValue, PdhOpenQueryW, PDH_CSTATUS_VALID_DATA, PDH_FMT_COUNTERVALUE,
+    PDH_FMT_COUNTERVALUE_ITEM_W, PDH_FMT_DOUBLE, PDH_MORE_DATA,
+};
+
+/// An open PDH query.
+#[derive(Debug)]
+pub struct PdhQuery {
+    handle: isize,
+}
+
+impl PdhQuery {
+    pub fn open() -> CoreResult<Self> {
+        let mut handle = 0;
+        let result = unsafe { PdhOpenQueryW(None, 0, &mut handle) };
+        check(result, "open PDH query")?;
+        Ok(Self { handle })
+    }
+
+    /// Add a counter by its English path, e.g. `\Processor(*)\% Idle Time`.
+    pub fn add(&self, path: &str) -> CoreResult<isize> {
+        let mut counter = 0;
+        let counter_path = windows::core::HSTRING::from(path);
+        let result = unsafe { PdhAddEnglishCounterW(self.handle, &counter_path, 0, &mut counter) };
+        check(result, &format!("add counter {}", path))?;
+        Ok(counter)
+    }
+
+    /// Collect new values for every counter of the query.
+    pub fn collect(&self) -> CoreResult<()> {
+        check(unsafe { PdhCollectQueryData(self.handle) }, "collect PDH data")
+    }
+
+    /// The value of a single-instance counter.
+    pub fn value(&self, counter: isize) -> CoreResult<f64> {
+        let mut value = PDH_FMT_COUNTERVALUE::default();
+        let result = unsafe { PdhGetFormattedCounterValue(counter, PDH_FMT_DOUBLE, None, &mut value) };
+        check(result, "format counter value")?;
+        Ok(unsafe { value.Anonymous.doubleValue })
+    }
+
+    /// The values of a wildcard counter by instance name. Instances
+    /// without valid data are left out.
+    pub fn values(&self, counter: isize) -> CoreResult<HashMap<String, f64>> {
+        let mut size = 0u32;
+        let mut count = 0u32;
+        let result = unsafe { PdhGetFormattedCounterArrayW(counter, PDH_FMT_DOUBLE, &mut size, &mut count, None) };
+        if result != PDH_MORE_DATA {
+            check(result, "size counter array")?;
+            return Ok(HashMap::new());
+        }
+
+        // The buffer holds the items followed by their names
+        let item_size = std::mem::size_of::<PDH_FMT_COUNTERVALUE_ITEM_W>();
+        let mut buffer = vec![PDH_FMT_COUNTERVALUE_ITEM_W::default(); (size as usize).div_ceil(item_size)];
+        let result = unsafe {
+            PdhGetFormattedCounterArrayW(counter, PDH_FMT_DOUBLE, &mut size, &mut count, Some(buffer.as_mut_ptr()))
+        };
+        check(result, "format counter array")?;
+
+        Ok(buffer[..count as usize]
+            .iter()
+            .filter(|item| item.FmtValue.CStatus == PDH_CSTATUS_VALID_DATA)
+            .map(|item| unsafe {
+                (item.szName.to_string().unwrap_or_default(), item.FmtValue.Anonymous.doubleValue)
+            })
+            .collect())
+    }
+}
+
+impl Drop for PdhQuery {
+    fn drop(&mut self) {
+        unsafe {
+            PdhCloseQuery(self.handle);
+        }
+    }
+}
+
+fn check(result: u32, action: &str) -> CoreResult<()> {
+    if result == 0 {
+        Ok(())
+    } else {
+        Err(CoreError::PdhError(format!("Failed to {}: error code {:#x}", action, result)))
+    }
+}
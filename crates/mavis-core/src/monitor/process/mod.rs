@@ -0,0 +1,303 @@
+// Process monitoring
+//
+// A Task Manager style table of the running processes: PDH `Process`
+// counters on Windows, /proc/<pid> on Linux. `ProcessController` ends
+// processes or changes their priority when `allow_process_control` is on.
+
+#[cfg(windows)]
+mod pdh;
+#[cfg(target_os = "linux")]
+mod procfs;
+
+#[cfg(windows)]
+pub use pdh::ProcessMonitor;
+#[cfg(target_os = "linux")]
+pub use procfs::ProcProcesses;
+
+use super::source::DynSource;
+use crate::config::MonitoringConfig;
+use crate::error::{CoreError, CoreResult};
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::time::SystemTime;
+
+/// One running process over the sampling interval.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProcessInfo {
+    pub pid: u32,
+    /// Process that started this one, if it is known
+    pub parent: Option<u32>,
+    /// Executable name, e.g. "bash" or "explorer"
+    pub name: String,
+    /// Full command line; empty when it cannot be read (kernel threads,
+    /// protected processes)
+    pub command_line: String,
+    /// Share of the whole machine's CPU time, as a percentage (0-100)
+    pub cpu_usage: f32,
+    /// Resident set / working set in bytes
+    pub memory_bytes: u64,
+    /// Bytes read per second, from files, pipes, sockets and devices
+    pub read_bytes: u64,
+    /// Bytes written per second
+    pub write_bytes: u64,
+    pub start_time: Option<SystemTime>,
+}
+
+/// Columns the process table can be sorted by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProcessColumn {
+    Pid,
+    Name,
+    #[default]
+    Cpu,
+    Memory,
+    /// Reads and writes combined
+    Io,
+    StartTime,
+}
+
+impl ProcessColumn {
+    fn compare(&self, a: &ProcessInfo, b: &ProcessInfo) -> Ordering {
+        match self {
+            Self::Pid => a.pid.cmp(&b.pid),
+            Self::Name => a.name.to_lowercase().cmp(&b.name.to_lowercase()),
+            Self::Cpu => a.cpu_usage.total_cmp(&b.cpu_usage),
+            Self::Memory => a.memory_bytes.cmp(&b.memory_bytes),
+            Self::Io => (a.read_bytes + a.write_bytes).cmp(&(b.read_bytes + b.write_bytes)),
+            Self::StartTime => a.start_time.cmp(&b.start_time),
+        }
+    }
+}
+
+/// The process table at one sample.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ProcessSnapshot {
+    /// Processes sorted by pid
+    pub processes: Vec<ProcessInfo>,
+}
+
+impl ProcessSnapshot {
+    /// A snapshot of `processes`, sorted by pid.
+    pub fn new(mut processes: Vec<ProcessInfo>) -> Self {
+        processes.sort_by_key(|process| process.pid);
+        Self { processes }
+    }
+
+    /// Sort the table by `column`; ties keep pid order.
+    pub fn sort(&mut self, column: ProcessColumn, descending: bool) {
+        self.processes.sort_by(|a, b| {
+            let order = column.compare(a, b);
+            let order = if descending { order.reverse() } else { order };
+            order.then(a.pid.cmp(&b.pid))
+        });
+    }
+
+    /// Processes whose name or command line contains `query`, ignoring
+    /// case, or whose pid is `query`. An empty query matches everything.
+    pub fn filter<'a>(&'a self, query: &str) -> impl Iterator<Item = &'a ProcessInfo> + 'a {
+        let query = query.trim().to_lowercase();
+        let pid = query.parse::<u32>().ok();
+        self.processes.iter().filter(move |process| {
+            query.is_empty()
+                || Some(process.pid) == pid
+                || process.name.to_lowercase().contains(&query)
+                || process.command_line.to_lowercase().contains(&query)
+        })
+    }
+
+    pub fn get(&self, pid: u32) -> Option<&ProcessInfo> {
+        self.processes.iter().find(|process| process.pid == pid)
+    }
+}
+
+/// Scheduling priority, from Windows' priority classes; on Linux each maps
+/// to a nice value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProcessPriority {
+    Idle,
+    BelowNormal,
+    Normal,
+    AboveNormal,
+    High,
+}
+
+impl ProcessPriority {
+    pub const ALL: [ProcessPriority; 5] = [Self::Idle, Self::BelowNormal, Self::Normal, Self::AboveNormal, Self::High];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Idle => "Idle",
+            Self::BelowNormal => "Below normal",
+            Self::Normal => "Normal",
+            Self::AboveNormal => "Above normal",
+            Self::High => "High",
+        }
+    }
+
+    /// The nice value for this priority.
+    pub fn nice(&self) -> i32 {
+        match self {
+            Self::Idle => 19,
+            Self::BelowNormal => 10,
+            Self::Normal => 0,
+            Self::AboveNormal => -5,
+            Self::High => -10,
+        }
+    }
+}
+
+/// Ends processes and changes their priority, if the configuration allows
+/// it. The operating system may still refuse, e.g. for processes of other
+/// users.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProcessController {
+    allowed: bool,
+}
+
+impl ProcessController {
+    pub fn new(config: &MonitoringConfig) -> Self {
+        Self {
+            allowed: config.allow_process_control,
+        }
+    }
+
+    /// Whether processes may be controlled at all.
+    pub fn is_allowed(&self) -> bool {
+        self.allowed
+    }
+
+    /// Ask the process to exit: SIGTERM on Linux, TerminateProcess on
+    /// Windows.
+    pub fn terminate(&self, pid: u32) -> CoreResult<()> {
+        self.check(pid)?;
+        platform::terminate(pid)
+    }
+
+    pub fn set_priority(&self, pid: u32, priority: ProcessPriority) -> CoreResult<()> {
+        self.check(pid)?;
+        platform::set_priority(pid, priority)
+    }
+
+    fn check(&self, pid: u32) -> CoreResult<()> {
+        if !self.allowed {
+            return Err(CoreError::PermissionDenied(
+                "Process control is disabled by monitoring.allow_process_control".to_string(),
+            ));
+        }
+        // MAVIS is the shell; ending it from its own task list would leave
+        // the user without one. Low pids are the kernel and init.
+        if pid == std::process::id() || pid <= 4 {
+            return Err(CoreError::PermissionDenied(format!("Process {} cannot be controlled from MAVIS", pid)));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(windows)]
+use pdh as platform;
+#[cfg(target_os = "linux")]
+use procfs as platform;
+
+#[cfg(not(any(windows, target_os = "linux")))]
+mod platform {
+    use super::ProcessPriority;
+    use crate::error::CoreResult;
+
+    pub fn terminate(_pid: u32) -> CoreResult<()> {
+        Err(crate::monitor::source::unsupported("process control"))
+    }
+
+    pub fn set_priority(_pid: u32, _priority: ProcessPriority) -> CoreResult<()> {
+        Err(crate::monitor::source::unsupported("process control"))
+    }
+}
+
+/// The process source for the current platform.
+#[cfg(windows)]
+pub fn native() -> CoreResult<DynSource<ProcessSnapshot>> {
+    Ok(Box::new(ProcessMonitor::new()?))
+}
+
+/// The process source for the current platform.
+#[cfg(target_os = "linux")]
+pub fn native() -> CoreResult<DynSource<ProcessSnapshot>> {
+    Ok(Box::new(ProcProcesses::new()?))
+}
+
+/// The process source for the current platform.
+#[cfg(not(any(windows, target_os = "linux")))]
+pub fn native() -> CoreResult<DynSource<ProcessSnapshot>> {
+    Err(super::source::unsupported("process"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    fn process(pid: u32, name: &str, cpu_usage: f32, memory_bytes: u64) -> ProcessInfo {
+        ProcessInfo {
+            pid,
+            parent: Some(1),
+            name: name.to_string(),
+            command_line: format!("/usr/bin/{} --flag", name),
+            cpu_usage,
+            memory_bytes,
+            read_bytes: 0,
+            write_bytes: 0,
+            start_time: None,
+        }
+    }
+
+    #[test]
+    fn test_sort_and_filter() {
+        let mut snapshot = ProcessSnapshot::new(vec![
+            process(30, "Firefox", 5.0, 300),
+            process(10, "bash", 0.5, 100),
+            process(20, "cargo", 50.0, 200),
+        ]);
+        let pids = |snapshot: &ProcessSnapshot| snapshot.processes.iter().map(|process| process.pid).collect::<Vec<_>>();
+        assert_eq!(pids(&snapshot), [10, 20, 30]);
+
+        snapshot.sort(ProcessColumn::Cpu, true);
+        assert_eq!(pids(&snapshot), [20, 30, 10]);
+        snapshot.sort(ProcessColumn::Name, false);
+        assert_eq!(pids(&snapshot), [10, 20, 30]);
+        snapshot.sort(ProcessColumn::Memory, true);
+        assert_eq!(pids(&snapshot), [30, 20, 10]);
+
+        let names = |query: &str| snapshot.filter(query).map(|process| process.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(names("FIRE"), ["Firefox"]);
+        assert_eq!(names("20"), ["cargo"]);
+        assert_eq!(names("usr/bin").len(), 3);
+        assert_eq!(names(" ").len(), 3);
+    }
+
+    #[test]
+    fn test_controller_is_gated() {
+        let mut config = Config::default().monitoring;
+        config.allow_process_control = false;
+        let error = ProcessController::new(&config).terminate(12345).unwrap_err();
+        assert!(matches!(error, CoreError::PermissionDenied(_)));
+
+        config.allow_process_control = true;
+        let controller = ProcessController::new(&config);
+        assert!(matches!(controller.terminate(std::process::id()), Err(CoreError::PermissionDenied(_))));
+        assert!(matches!(controller.set_priority(1, ProcessPriority::High), Err(CoreError::PermissionDenied(_))));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_controller_rejects_out_of_range_pid() {
+        let mut config = Config::default().monitoring;
+        config.allow_process_control = true;
+        let controller = ProcessController::new(&config);
+        let invalid = |result: CoreResult<()>| {
+            matches!(result, Err(CoreError::IoError(error)) if error.kind() == std::io::ErrorKind::InvalidInput)
+        };
+        assert!(invalid(controller.terminate(u32::MAX)));
+        assert!(invalid(controller.set_priority(u32::MAX, ProcessPriority::Idle)));
+    }
+}
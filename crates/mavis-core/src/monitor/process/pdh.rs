@@ -0,0 +1,237 @@
+// Process monitoring for Windows systems
+//
+// The `Process` object has one instance per process, named after the
+// executable with "#1", "#2" appended to tell apart processes of the same
+// name, plus "_Total" and the "Idle" pseudo-process. Command lines come from
+// NtQueryInformationProcess, which protected processes refuse.
+
+use super::{ProcessInfo, ProcessPriority, ProcessSnapshot};
+use crate::error::{CoreError, CoreResult};
+use crate::monitor::pdh::PdhQuery;
+use crate::monitor::source::MetricsSource;
+use log::{debug, warn};
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime};
+use windows::Wdk::System::Threading::{NtQueryInformationProcess, ProcessCommandLineInformation};
+use windows::Win32::Foundation::{CloseHandle, E_ACCESSDENIED, HANDLE, STATUS_INFO_LENGTH_MISMATCH, UNICODE_STRING};
+use windows::Win32::System::Threading::{
+    ABOVE_NORMAL_PRIORITY_CLASS, BELOW_NORMAL_PRIORITY_CLASS, HIGH_PRIORITY_CLASS, IDLE_PRIORITY_CLASS,
+    NORMAL_PRIORITY_CLASS, OpenProcess, PROCESS_ACCESS_RIGHTS, PROCESS_QUERY_LIMITED_INFORMATION,
+    PROCESS_SET_INFORMATION, PROCESS_TERMINATE, SetPriorityClass, TerminateProcess,
+};
+
+/// Instances that are not processes.
+const PSEUDO_PROCESSES: &[&str] = &["_Total", "Idle"];
+
+/// Process monitoring functionality
+pub struct ProcessMonitor {
+    query: PdhQuery,
+    pid: isize,
+    parent: isize,
+    processor_time: isize,
+    working_set: isize,
+    read_bytes: isize,
+    write_bytes: isize,
+    elapsed_time: isize,
+    /// `% Processor Time` is relative to one core
+    core_count: f64,
+    /// Command lines by pid, with the start time that tells a reused pid
+    /// apart, kept while the process is running
+    command_lines: HashMap<u32, (SystemTime, String)>,
+    last_snapshot: ProcessSnapshot,
+}
+
+impl ProcessMonitor {
+    /// Create a new process monitor
+    pub fn new() -> Result<Self, CoreError> {
+        let query = PdhQuery::open()?;
+        let counter = |name: &str| query.add(&format!("\\Process(*)\\{}", name));
+        let monitor = Self {
+            pid: counter("ID Process")?,
+            parent: counter("Creating Process ID")?,
+            processor_time: counter("% Processor Time")?,
+            working_set: counter("Working Set")?,
+            read_bytes: counter("IO Read Bytes/sec")?,
+            write_bytes: counter("IO Write Bytes/sec")?,
+            elapsed_time: counter("Elapsed Time")?,
+            query,
+            core_count: std::thread::available_parallelism().map_or(1, |count| count.get()) as f64,
+            command_lines: HashMap::new(),
+            last_snapshot: ProcessSnapshot::default(),
+        };
+
+        // Initial data collection to establish baseline
+        let _ = monitor.query.collect();
+
+        // Small sleep to allow for first measurement
+        std::thread::sleep(Duration::from_millis(100));
+
+        // Collect again to get meaningful values
+        let _ = monitor.query.collect();
+
+        Ok(monitor)
+    }
+
+    /// Take a snapshot of the running processes
+    pub fn snapshot(&mut self) -> Result<ProcessSnapshot, CoreError> {
+        if let Err(e) = self.query.collect() {
+            warn!("{}", e);
+            return Ok(self.last_snapshot.clone()); // Return last known value
+        }
+
+        let pid = self.query.values(self.pid)?;
+        let parent = self.query.values(self.parent)?;
+        let processor_time = self.query.values(self.processor_time)?;
+        let working_set = self.query.values(self.working_set)?;
+        let read_bytes = self.query.values(self.read_bytes)?;
+        let write_bytes = self.query.values(self.write_bytes)?;
+        let elapsed_time = self.query.values(self.elapsed_time)?;
+        let now = SystemTime::now();
+
+        let mut command_lines = HashMap::with_capacity(pid.len());
+        let mut processes = Vec::with_capacity(pid.len());
+        for (instance, id) in pid.iter().filter(|(instance, _)| !PSEUDO_PROCESSES.contains(&instance.as_str())) {
+            let id = *id as u32;
+            let value = |values: &HashMap<String, f64>| values.get(instance).copied().unwrap_or(0.0).max(0.0);
+            let start_time = elapsed_time
+                .get(instance)
+                .and_then(|seconds| now.checked_sub(Duration::from_secs_f64(seconds.max(0.0))));
+            let command_line = match (self.command_lines.remove(&id), start_time) {
+                (Some((started, command_line)), Some(start_time)) if same_start(started, start_time) => command_line,
+                _ => command_line(id).unwrap_or_default(),
+            };
+
+            processes.push(ProcessInfo {
+                pid: id,
+                parent: Some(value(&parent) as u32).filter(|parent| *parent != 0),
+                name: instance.split_once('#').map_or(instance.as_str(), |(name, _)| name).to_string(),
+                command_line: command_line.clone(),
+                cpu_usage: (value(&processor_time) / self.core_count).min(100.0) as f32,
+                memory_bytes: value(&working_set) as u64,
+                read_bytes: value(&read_bytes) as u64,
+                write_bytes: value(&write_bytes) as u64,
+                start_time,
+            });
+            if let Some(start_time) = start_time {
+                command_lines.insert(id, (start_time, command_line));
+            }
+        }
+        self.command_lines = command_lines;
+
+        let snapshot = ProcessSnapshot::new(processes);
+        debug!("Sampled {} processes", snapshot.processes.len());
+        self.last_snapshot = snapshot.clone();
+        Ok(snapshot)
+    }
+}
+
+impl MetricsSource for ProcessMonitor {
+    type Sample = ProcessSnapshot;
+
+    fn backend(&self) -> &'static str {
+        "pdh"
+    }
+
+    fn sample(&mut self) -> CoreResult<ProcessSnapshot> {
+        self.snapshot()
+    }
+}
+
+/// End `pid` with exit code 1.
+pub fn terminate(pid: u32) -> CoreResult<()> {
+    with_process(pid, PROCESS_TERMINATE, "terminate", |handle| unsafe { TerminateProcess(handle, 1) })
+}
+
+/// Set the priority class of `pid`.
+pub fn set_priority(pid: u32, priority: ProcessPriority) -> CoreResult<()> {
+    let class = match priority {
+        ProcessPriority::Idle => IDLE_PRIORITY_CLASS,
+        ProcessPriority::BelowNormal => BELOW_NORMAL_PRIORITY_CLASS,
+        ProcessPriority::Normal => NORMAL_PRIORITY_CLASS,
+        ProcessPriority::AboveNormal => ABOVE_NORMAL_PRIORITY_CLASS,
+        ProcessPriority::High => HIGH_PRIORITY_CLASS,
+    };
+    with_process(pid, PROCESS_SET_INFORMATION, "change the priority of", |handle| unsafe {
+        SetPriorityClass(handle, class)
+    })
+}
+
+/// Open `pid` with `access`, run `action` on it and close it again.
+fn with_process(
+    pid: u32,
+    access: PROCESS_ACCESS_RIGHTS,
+    description: &str,
+    action: impl FnOnce(HANDLE) -> windows::core::Result<()>,
+) -> CoreResult<()> {
+    let result = unsafe { OpenProcess(access, false, pid) }.and_then(|handle| {
+        let result = action(handle);
+        unsafe {
+            let _ = CloseHandle(handle);
+        }
+        result
+    });
+    result.map_err(|e| {
+        if e.code() == E_ACCESSDENIED {
+            CoreError::PermissionDenied(format!("Not allowed to {} process {}", description, pid))
+        } else {
+            CoreError::WindowsError(e)
+        }
+    })
+}
+
+/// Start times worked out from `Elapsed Time` shift with the moment of
+/// sampling, so those within a second belong to the same process.
+fn same_start(a: SystemTime, b: SystemTime) -> bool {
+    let difference = a.duration_since(b).unwrap_or_else(|e| e.duration());
+    difference < Duration::from_secs(1)
+}
+
+/// The command line `pid` was started with.
+fn command_line(pid: u32) -> Option<String> {
+    let handle = unsafe { OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, false, pid) }.ok()?;
+    let command_line = query_command_line(handle);
+    unsafe {
+        let _ = CloseHandle(handle);
+    }
+    command_line
+}
+
+fn query_command_line(handle: HANDLE) -> Option<String> {
+    // The first call reports the size needed: a UNICODE_STRING followed by
+    // the text it points to
+    let mut size = 0u32;
+    let status =
+        unsafe { NtQueryInformationProcess(handle, ProcessCommandLineInformation, std::ptr::null_mut(), 0, &mut size) };
+    if status != STATUS_INFO_LENGTH_MISMATCH || (size as usize) < std::mem::size_of::<UNICODE_STRING>() {
+        return None;
+    }
+    // u64s keep the UNICODE_STRING at the start aligned
+    let mut buffer = vec![0u64; (size as usize).div_ceil(8)];
+    let information = buffer.as_mut_ptr().cast();
+    unsafe { NtQueryInformationProcess(handle, ProcessCommandLineInformation, information, size, &mut size) }
+        .ok()
+        .ok()?;
+    let string = unsafe { &*buffer.as_ptr().cast::<UNICODE_STRING>() };
+    if string.Length == 0 || string.Buffer.is_null() {
+        return Some(String::new());
+    }
+    let text = unsafe { std::slice::from_raw_parts(string.Buffer.0, string.Length as usize / 2) };
+    Some(String::from_utf16_lossy(text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_process_monitor_creation() {
+        // This test just verifies that we can create the monitor without errors
+        match ProcessMonitor::new() {
+            Ok(_) => {} // Success
+            Err(e) => {
+                eprintln!("Note: Process monitor creation failed, but this might be expected in CI: {}", e);
+                // Don't fail the test as it might be running in CI without proper access
+            }
+        }
+    }
+}
@@ -0,0 +1,322 @@
+// Process monitoring from /proc/<pid> on Linux
+//
+// Times and memory come from /proc/<pid>/stat, the command line from
+// cmdline and I/O from io, which is only readable for our own processes
+// unless MAVIS runs as root. CPU shares are measured against the "cpu" line
+// of /proc/stat, so they need no clock tick rate.
+
+use super::{ProcessInfo, ProcessPriority, ProcessSnapshot};
+use crate::error::{CoreError, CoreResult};
+use crate::monitor::procfs::{ProcFs, counters, rate};
+use crate::monitor::source::MetricsSource;
+use log::debug;
+use std::collections::HashMap;
+use std::io;
+use std::path::PathBuf;
+use std::time::{Duration, Instant, SystemTime};
+
+/// Counters of one process since it started.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct ProcessCounters {
+    parent: u32,
+    name: String,
+    /// Jiffies spent in user and kernel mode
+    cpu_ticks: u64,
+    /// Clock ticks after boot at which the process started
+    start_ticks: u64,
+    /// Resident set in pages
+    rss_pages: u64,
+    read_bytes: u64,
+    write_bytes: u64,
+}
+
+impl ProcessCounters {
+    /// Parse "pid (comm) state ppid ... utime stime ... starttime vsize rss".
+    /// The name may contain spaces and parentheses, so fields are counted
+    /// from the last ')'.
+    fn parse_stat(stat: &str) -> Option<Self> {
+        let (_pid, rest) = stat.split_once(" (")?;
+        let (name, fields) = rest.rsplit_once(") ")?;
+        let fields: Vec<&str> = fields.split_whitespace().collect();
+        if fields.len() < 22 {
+            return None;
+        }
+        let values = counters(fields.iter().copied());
+        Some(Self {
+            parent: values[1] as u32,
+            name: name.to_string(),
+            cpu_ticks: values[11] + values[12],
+            start_ticks: values[19],
+            rss_pages: values[21],
+            ..Default::default()
+        })
+    }
+}
+
+/// A process seen at the previous sample.
+#[derive(Debug, Clone, Copy)]
+struct Tracked {
+    /// Tells a reused pid from the process that had it before
+    start_ticks: u64,
+    cpu_ticks: u64,
+    read_bytes: u64,
+    write_bytes: u64,
+}
+
+/// The running processes from /proc/<pid>.
+#[derive(Debug)]
+pub struct ProcProcesses {
+    proc_fs: ProcFs,
+    ticks_per_second: u64,
+    page_size: u64,
+    /// Jiffies of all processors at the previous sample
+    previous_ticks: u64,
+    previous_at: Instant,
+    tracked: HashMap<u32, Tracked>,
+}
+
+impl ProcProcesses {
+    pub fn new() -> CoreResult<Self> {
+        Self::with_root("/proc")
+    }
+
+    /// Read from `root` instead of /proc.
+    pub fn with_root(root: impl Into<PathBuf>) -> CoreResult<Self> {
+        let mut processes = Self {
+            proc_fs: ProcFs::new(root),
+            ticks_per_second: sysconf(libc::_SC_CLK_TCK, 100),
+            page_size: sysconf(libc::_SC_PAGESIZE, 4096),
+            previous_ticks: 0,
+            previous_at: Instant::now(),
+            tracked: HashMap::new(),
+        };
+        // The first sample measures from here
+        processes.sample_at(Instant::now())?;
+        Ok(processes)
+    }
+
+    /// Counters of one process, or None if it exited while being read.
+    fn read_process(&self, pid: u32) -> Option<(ProcessCounters, String)> {
+        let mut counters = ProcessCounters::parse_stat(&self.proc_fs.read(&format!("{}/stat", pid)).ok()?)?;
+        if let Ok(io) = self.proc_fs.read(&format!("{}/io", pid)) {
+            (counters.read_bytes, counters.write_bytes) = parse_io(&io);
+        }
+        // Kernel threads have an empty command line
+        let command_line = self
+            .proc_fs
+            .read(&format!("{}/cmdline", pid))
+            .map(|cmdline| cmdline.trim_end_matches('\0').replace('\0', " "))
+            .unwrap_or_default();
+        Some((counters, command_line))
+    }
+
+    fn sample_at(&mut self, now: Instant) -> CoreResult<ProcessSnapshot> {
+        let (total_ticks, boot_time) = parse_stat(&self.proc_fs.read("stat")?)
+            .ok_or_else(|| CoreError::InitializationError("No cpu line in /proc/stat".to_string()))?;
+        let ticks = total_ticks.saturating_sub(self.previous_ticks);
+        let elapsed = now.saturating_duration_since(self.previous_at);
+
+        let mut tracked = HashMap::with_capacity(self.tracked.len());
+        let mut processes = Vec::with_capacity(self.tracked.len());
+        for pid in self.proc_fs.pids()? {
+            let Some((counters, command_line)) = self.read_process(pid) else {
+                continue;
+            };
+            let current = Tracked {
+                start_ticks: counters.start_ticks,
+                cpu_ticks: counters.cpu_ticks,
+                read_bytes: counters.read_bytes,
+                write_bytes: counters.write_bytes,
+            };
+            // A process that just appeared is measured from now on
+            let previous = self
+                .tracked
+                .get(&pid)
+                .filter(|previous| previous.start_ticks == current.start_ticks)
+                .copied()
+                .unwrap_or(current);
+
+            let cpu_usage = if ticks == 0 {
+                0.0
+            } else {
+                (current.cpu_ticks.saturating_sub(previous.cpu_ticks) as f64 / ticks as f64 * 100.0).clamp(0.0, 100.0)
+            };
+            processes.push(ProcessInfo {
+                pid,
+                parent: (counters.parent != 0).then_some(counters.parent),
+                name: counters.name,
+                command_line,
+                cpu_usage: cpu_usage as f32,
+                memory_bytes: counters.rss_pages * self.page_size,
+                read_bytes: rate(previous.read_bytes, current.read_bytes, elapsed),
+                write_bytes: rate(previous.write_bytes, current.write_bytes, elapsed),
+                start_time: boot_time.map(|boot_time| {
+                    boot_time + Duration::from_secs_f64(counters.start_ticks as f64 / self.ticks_per_second as f64)
+                }),
+            });
+            tracked.insert(pid, current);
+        }
+        self.tracked = tracked;
+        self.previous_ticks = total_ticks;
+        self.previous_at = now;
+
+        debug!("Sampled {} processes", processes.len());
+        Ok(ProcessSnapshot::new(processes))
+    }
+}
+
+impl MetricsSource for ProcProcesses {
+    type Sample = ProcessSnapshot;
+
+    fn backend(&self) -> &'static str {
+        "procfs"
+    }
+
+    fn sample(&mut self) -> CoreResult<ProcessSnapshot> {
+        self.sample_at(Instant::now())
+    }
+}
+
+/// Send SIGTERM to `pid`.
+pub fn terminate(pid: u32) -> CoreResult<()> {
+    if unsafe { libc::kill(process_id(pid)?, libc::SIGTERM) } == -1 {
+        return Err(control_error(pid, "terminate"));
+    }
+    Ok(())
+}
+
+/// Set the nice value of `pid`. Raising the priority above normal needs
+/// CAP_SYS_NICE.
+pub fn set_priority(pid: u32, priority: ProcessPriority) -> CoreResult<()> {
+    let id = process_id(pid)? as libc::id_t;
+    if unsafe { libc::setpriority(libc::PRIO_PROCESS, id, priority.nice()) } == -1 {
+        return Err(control_error(pid, "change the priority of"));
+    }
+    Ok(())
+}
+
+// Pids past i32::MAX would turn negative, and kill() treats negative pids
+// as process groups, or with -1 as every process it may signal.
+fn process_id(pid: u32) -> CoreResult<libc::pid_t> {
+    libc::pid_t::try_from(pid).map_err(|_| {
+        CoreError::IoError(io::Error::new(io::ErrorKind::InvalidInput, format!("Invalid process id {}", pid)))
+    })
+}
+
+fn control_error(pid: u32, action: &str) -> CoreError {
+    let error = io::Error::last_os_error();
+    if error.kind() == io::ErrorKind::PermissionDenied {
+        CoreError::PermissionDenied(format!("Not allowed to {} process {}", action, pid))
+    } else {
+        CoreError::IoError(io::Error::new(error.kind(), format!("Failed to {} process {}: {}", action, pid, error)))
+    }
+}
+
+/// A sysconf value, or `fallback` if the system does not report it.
+fn sysconf(name: libc::c_int, fallback: u64) -> u64 {
+    match unsafe { libc::sysconf(name) } {
+        value if value > 0 => value as u64,
+        _ => fallback,
+    }
+}
+
+/// The jiffies of all processors and the boot time from /proc/stat.
+fn parse_stat(stat: &str) -> Option<(u64, Option<SystemTime>)> {
+    let mut total = None;
+    let mut boot_time = None;
+    for line in stat.lines() {
+        if let Some(times) = line.strip_prefix("cpu ") {
+            // Guest time is already counted in user time
+            total = Some(counters(times.split_whitespace().take(8)).iter().sum());
+        } else if let Some(btime) = line.strip_prefix("btime ") {
+            boot_time = btime.trim().parse().ok().map(|seconds| SystemTime::UNIX_EPOCH + Duration::from_secs(seconds));
+        }
+    }
+    Some((total?, boot_time))
+}
+
+/// Parse the "rchar" and "wchar" lines of /proc/<pid>/io.
+fn parse_io(io: &str) -> (u64, u64) {
+    let mut read_bytes = 0;
+    let mut write_bytes = 0;
+    for line in io.lines() {
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        match key {
+            "rchar" => read_bytes = value.trim().parse().unwrap_or(0),
+            "wchar" => write_bytes = value.trim().parse().unwrap_or(0),
+            _ => {}
+        }
+    }
+    (read_bytes, write_bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::monitor::procfs::fixture;
+
+    fn stat(pid: u32, name: &str, parent: u32, utime: u64, stime: u64, start: u64, rss: u64) -> String {
+        format!(
+            "{} ({}) S {} {} {} 0 -1 4194560 100 0 0 0 {} {} 0 0 20 0 1 0 {} 1000000 {} 18446744073709551615\n",
+            pid, name, parent, pid, pid, utime, stime, start, rss
+        )
+    }
+
+    #[test]
+    fn test_processes_from_proc() {
+        let root = fixture(
+            "processes",
+            &[
+                ("stat", "cpu  100 0 100 800 0 0 0 0 0 0\ncpu0 100 0 100 800 0 0 0 0 0 0\nbtime 1700000000\n"),
+                ("1/stat", &stat(1, "init", 0, 10, 10, 1, 100)),
+                ("1/cmdline", "/sbin/init\0splash\0"),
+                ("300/stat", &stat(300, "Web Content (x)", 1, 100, 50, 500, 2000)),
+                ("300/cmdline", "/usr/lib/firefox\0-contentproc\0"),
+                ("300/io", "rchar: 1000\nwchar: 500\nsyscr: 1\n"),
+                ("2/stat", &stat(2, "kthreadd", 0, 0, 0, 0, 0)),
+            ],
+        );
+        let mut processes = ProcProcesses::with_root(&root).unwrap();
+        processes.page_size = 4096;
+        processes.ticks_per_second = 100;
+        let start = processes.previous_at;
+
+        // 400 more jiffies, 100 of them used by the content process
+        std::fs::write(root.join("stat"), "cpu  300 0 200 900 0 0 0 0 0 0\nbtime 1700000000\n").unwrap();
+        std::fs::write(root.join("300/stat"), stat(300, "Web Content (x)", 1, 150, 100, 500, 2000)).unwrap();
+        std::fs::write(root.join("300/io"), "rchar: 3000\nwchar: 1500\n").unwrap();
+        let snapshot = processes.sample_at(start + Duration::from_secs(2)).unwrap();
+
+        let pids: Vec<u32> = snapshot.processes.iter().map(|process| process.pid).collect();
+        assert_eq!(pids, [1, 2, 300]);
+        let init = snapshot.get(1).unwrap();
+        assert_eq!((init.parent, init.command_line.as_str()), (None, "/sbin/init splash"));
+        assert_eq!(snapshot.get(2).unwrap().command_line, "");
+
+        let content = snapshot.get(300).unwrap();
+        assert_eq!((content.name.as_str(), content.parent), ("Web Content (x)", Some(1)));
+        assert_eq!(content.cpu_usage, 25.0);
+        assert_eq!(content.memory_bytes, 2000 * 4096);
+        assert_eq!((content.read_bytes, content.write_bytes), (1000, 500));
+        assert_eq!(content.start_time, Some(SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_005)));
+
+        // Pid 300 was reused by a process that started later
+        std::fs::write(root.join("stat"), "cpu  800 0 700 900 0 0 0 0 0 0\nbtime 1700000000\n").unwrap();
+        std::fs::write(root.join("300/stat"), stat(300, "bash", 1, 500, 0, 900, 10)).unwrap();
+        std::fs::remove_file(root.join("300/io")).unwrap();
+        let snapshot = processes.sample_at(start + Duration::from_secs(3)).unwrap();
+        let bash = snapshot.get(300).unwrap();
+        assert_eq!((bash.cpu_usage, bash.read_bytes), (0.0, 0));
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_parse_stat_and_io() {
+        assert_eq!(ProcessCounters::parse_stat("12 (short) S 1 2"), None);
+        assert_eq!(parse_stat("intr 1 2 3\n"), None);
+        assert_eq!(parse_io("rchar: 10\nread_bytes: 4\n"), (10, 0));
+    }
+}
@@ -0,0 +1,92 @@
+// Reading Linux procfs for the metrics backends
+//
+// The procfs sources read below a root directory that defaults to /proc, so
+// tests can point them at fixture files. Most of what procfs reports are
+// counters since boot; sources keep the previous reading and turn the
+// difference into a rate.
+
+use crate::error::{CoreError, CoreResult};
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Where a procfs source reads from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProcFs {
+    root: PathBuf,
+}
+
+impl Default for ProcFs {
+    fn default() -> Self {
+        Self::new("/proc")
+    }
+}
+
+impl ProcFs {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    /// Read a file below the root, e.g. "stat" or "net/dev".
+    pub fn read(&self, name: &str) -> CoreResult<String> {
+        let path = self.root.join(name);
+        fs::read_to_string(&path)
+            .map_err(|e| CoreError::IoError(io::Error::new(e.kind(), format!("{}: {}", path.display(), e))))
+    }
+
+    /// The ids of the running processes.
+    pub fn pids(&self) -> CoreResult<Vec<u32>> {
+        Ok(fs::read_dir(&self.root)?
+            .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse().ok())
+            .collect())
+    }
+}
+
+/// Per-second rate of a counter that went from `previous` to `current` in
+/// `elapsed`. A counter that went backwards was reset and counts as zero.
+pub fn rate(previous: u64, current: u64, elapsed: Duration) -> u64 {
+    let seconds = elapsed.as_secs_f64();
+    if seconds <= 0.0 {
+        return 0;
+    }
+    (current.saturating_sub(previous) as f64 / seconds) as u64
+}
+
+/// Parse whitespace-separated counters, treating anything unparsable as 0.
+pub fn counters<'a>(fields: impl Iterator<Item = &'a str>) -> Vec<u64> {
+    fields.map(|field| field.parse().unwrap_or(0)).collect()
+}
+
+/// Write `files` into a fresh fixture directory named after `name`.
+#[cfg(test)]
+pub fn fixture(name: &str, files: &[(&str, &str)]) -> PathBuf {
+    let root = std::env::temp_dir().join(format!("mavis-procfs-{}-{}", name, std::process::id()));
+    for (file, contents) in files {
+        let path = root.join(file);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+    root
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_and_rate() {
+        let root = fixture("read", &[("net/dev", "data")]);
+        let proc_fs = ProcFs::new(&root);
+        assert_eq!(proc_fs.read("net/dev").unwrap(), "data");
+        let error = proc_fs.read("missing").unwrap_err().to_string();
+        assert!(error.contains("missing"), "{}", error);
+        assert!(proc_fs.pids().unwrap().is_empty());
+        fs::remove_dir_all(root).unwrap();
+
+        assert_eq!(rate(100, 1100, Duration::from_millis(500)), 2000);
+        assert_eq!(rate(1100, 100, Duration::from_secs(1)), 0);
+        assert_eq!(rate(0, 100, Duration::ZERO), 0);
+        assert_eq!(counters("1 x 3".split(' ')), vec![1, 0, 3]);
+    }
+}
@@ -0,0 +1,346 @@
+// Metrics backends
+//
+// Each metric family (CPU, memory, disk, network, processes) has a sample
+// type and one `MetricsSource` implementation per platform: PDH and Win32 on
+// Windows, procfs and statvfs on Linux. `MetricsSources` bundles one source
+// per family and turns their samples into a `ResourceUsage`, so the monitor
+// thread never needs to know which backend it is driving. Tests use
+// `fake::FakeSource` instead.
+//
+// Sources are started through `Backends` when their family is enabled and
+// dropped when it is disabled, so the monitor can be reconfigured while it
+// runs. Each family reports its `SourceHealth` with every sample.
+
+use super::ResourceUsage;
+use super::filter::NameFilter;
+use super::{cpu, disk, memory, network, process};
+use crate::config::MonitoringConfig;
+use crate::error::CoreResult;
+use log::{debug, error, info};
+use std::time::Instant;
+
+/// Measures one family of metrics.
+pub trait MetricsSource: Send {
+    type Sample;
+
+    /// Short name of the backend, such as "pdh" or "procfs", for logs.
+    fn backend(&self) -> &'static str;
+
+    /// Take a measurement. Rates are averaged over the time since the
+    /// previous sample, or since the source was created.
+    fn sample(&mut self) -> CoreResult<Self::Sample>;
+}
+
+/// A source of any backend.
+pub type DynSource<S> = Box<dyn MetricsSource<Sample = S>>;
+
+/// How the source of a metric family is doing.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum SourceHealth {
+    /// Turned off in the configuration
+    #[default]
+    Disabled,
+    /// The last sample succeeded, or none was taken yet
+    Ok,
+    /// The source could not be started
+    InitFailed(String),
+    /// The last sample failed
+    Failing(String),
+}
+
+impl SourceHealth {
+    /// Name used in Lua, e.g. "init_failed".
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Disabled => "disabled",
+            Self::Ok => "ok",
+            Self::InitFailed(_) => "init_failed",
+            Self::Failing(_) => "failing",
+        }
+    }
+
+    pub fn error(&self) -> Option<&str> {
+        match self {
+            Self::InitFailed(error) | Self::Failing(error) => Some(error),
+            Self::Disabled | Self::Ok => None,
+        }
+    }
+}
+
+/// The health of one metric family's source.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceStatus {
+    /// "cpu", "memory", "disk", "network" or "processes"
+    pub family: &'static str,
+    /// Backend of the running source
+    pub backend: Option<&'static str>,
+    pub health: SourceHealth,
+}
+
+/// The source of one metric family, if it is running, and its health.
+pub struct Slot<S> {
+    source: Option<DynSource<S>>,
+    health: SourceHealth,
+}
+
+impl<S> Default for Slot<S> {
+    fn default() -> Self {
+        Self {
+            source: None,
+            health: SourceHealth::Disabled,
+        }
+    }
+}
+
+impl<S> Slot<S> {
+    /// A slot running `source`.
+    pub fn new(source: DynSource<S>) -> Self {
+        Self {
+            source: Some(source),
+            health: SourceHealth::Ok,
+        }
+    }
+
+    pub fn health(&self) -> &SourceHealth {
+        &self.health
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.source.is_some()
+    }
+
+    /// Start or drop the source to match `enabled`. A running source is
+    /// restarted if `changed` says its settings changed; one that failed to
+    /// start is tried again.
+    fn apply(&mut self, family: &str, enabled: bool, changed: bool, start: impl FnOnce() -> CoreResult<DynSource<S>>) {
+        if !enabled {
+            if self.is_running() {
+                info!("Stopped monitoring {}", family);
+            }
+            *self = Self::default();
+        } else if !self.is_running() || changed {
+            *self = match start() {
+                Ok(source) => {
+                    info!("Monitoring {} with the {} backend", family, source.backend());
+                    Self::new(source)
+                }
+                Err(e) => {
+                    error!("Failed to initialize {} monitor: {}", family, e);
+                    Self {
+                        source: None,
+                        health: SourceHealth::InitFailed(e.to_string()),
+                    }
+                }
+            };
+        }
+    }
+
+    fn sample(&mut self, family: &str) -> Option<S> {
+        match self.source.as_mut()?.sample() {
+            Ok(sample) => {
+                self.health = SourceHealth::Ok;
+                Some(sample)
+            }
+            Err(e) => {
+                debug!("Failed to sample {}: {}", family, e);
+                self.health = SourceHealth::Failing(e.to_string());
+                None
+            }
+        }
+    }
+
+    fn status(&self, family: &'static str) -> SourceStatus {
+        SourceStatus {
+            family,
+            backend: self.source.as_ref().map(|source| source.backend()),
+            health: self.health.clone(),
+        }
+    }
+}
+
+/// Starts the source of each metric family for a configuration.
+#[derive(Debug, Clone, Copy)]
+pub struct Backends {
+    pub cpu: fn(&MonitoringConfig) -> CoreResult<DynSource<cpu::CpuSnapshot>>,
+    pub memory: fn(&MonitoringConfig) -> CoreResult<DynSource<memory::MemorySample>>,
+    pub disk: fn(&MonitoringConfig) -> CoreResult<DynSource<disk::DiskSnapshot>>,
+    pub network: fn(&MonitoringConfig) -> CoreResult<DynSource<network::NetworkSnapshot>>,
+    pub processes: fn(&MonitoringConfig) -> CoreResult<DynSource<process::ProcessSnapshot>>,
+}
+
+impl Backends {
+    /// The platform's backends.
+    pub fn native() -> Self {
+        Self {
+            cpu: |_| cpu::native(),
+            memory: |_| memory::native(),
+            disk: |config| disk::native(config.disk_space_alert_threshold),
+            network: |config| {
+                network::native(NameFilter::new(config.network_include.clone(), config.network_exclude.clone()))
+            },
+            processes: |_| process::native(),
+        }
+    }
+}
+
+/// One source per metric family.
+#[derive(Default)]
+pub struct MetricsSources {
+    pub cpu: Slot<cpu::CpuSnapshot>,
+    pub memory: Slot<memory::MemorySample>,
+    pub disk: Slot<disk::DiskSnapshot>,
+    pub network: Slot<network::NetworkSnapshot>,
+    pub processes: Slot<process::ProcessSnapshot>,
+}
+
+impl MetricsSources {
+    /// The platform's sources for the families enabled in `config`. A
+    /// family whose source fails to start is left out.
+    pub fn native(config: &MonitoringConfig) -> Self {
+        let mut sources = Self::default();
+        sources.reconfigure(&Backends::native(), None, config);
+        sources
+    }
+
+    /// Start the sources of families enabled in `config` that are not
+    /// running, drop those of disabled families, and restart sources whose
+    /// settings differ from `previous`.
+    pub fn reconfigure(&mut self, backends: &Backends, previous: Option<&MonitoringConfig>, config: &MonitoringConfig) {
+        let disk_changed = previous
+            .is_some_and(|previous| previous.disk_space_alert_threshold != config.disk_space_alert_threshold);
+        let network_changed = previous.is_some_and(|previous| {
+            previous.network_include != config.network_include || previous.network_exclude != config.network_exclude
+        });
+        self.cpu.apply("CPU", config.monitor_cpu, false, || (backends.cpu)(config));
+        self.memory.apply("memory", config.monitor_ram, false, || (backends.memory)(config));
+        self.disk.apply("disk", config.monitor_disk, disk_changed, || (backends.disk)(config));
+        self.network.apply("network", config.monitor_network, network_changed, || (backends.network)(config));
+        self.processes.apply("process", config.monitor_processes, false, || (backends.processes)(config));
+    }
+
+    /// The health of every family's source.
+    pub fn status(&self) -> Vec<SourceStatus> {
+        vec![
+            self.cpu.status("cpu"),
+            self.memory.status("memory"),
+            self.disk.status("disk"),
+            self.network.status("network"),
+            self.processes.status("processes"),
+        ]
+    }
+
+    /// Sample every source. Families without a source, or whose source
+    /// failed this time, are left at zero.
+    pub fn collect(&mut self) -> ResourceUsage {
+        let mut usage = ResourceUsage {
+            timestamp: Instant::now(),
+            ..Default::default()
+        };
+
+        if let Some(sample) = self.cpu.sample("CPU") {
+            usage.cpu_usage = sample.usage;
+            usage.cpu = sample;
+        }
+        if let Some(sample) = self.memory.sample("memory") {
+            usage.total_memory = sample.total;
+            usage.used_memory = sample.used;
+            usage.memory_usage = sample.usage();
+        }
+        if let Some(sample) = self.network.sample("network") {
+            usage.network_down_bytes = sample.down_bytes;
+            usage.network_up_bytes = sample.up_bytes;
+            usage.network = sample;
+        }
+        if let Some(sample) = self.disk.sample("disk") {
+            usage.disk_usage = sample.busy;
+            usage.disk_read_bytes = sample.read_bytes;
+            usage.disk_write_bytes = sample.write_bytes;
+            usage.disk_free_bytes = sample.free_bytes;
+            usage.disk = sample;
+        }
+        if let Some(sample) = self.processes.sample("process") {
+            usage.processes = sample;
+        }
+        usage.sources = self.status();
+        usage
+    }
+}
+
+/// The error for a metric family this platform has no backend for.
+#[cfg(not(any(windows, target_os = "linux")))]
+pub(super) fn unsupported(family: &str) -> crate::error::CoreError {
+    crate::error::CoreError::InitializationError(format!("{} monitoring is not supported on this platform", family))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::CoreError;
+    use crate::monitor::fake::FakeSource;
+
+    #[test]
+    fn test_collect_maps_samples() {
+        let mut sources = MetricsSources {
+            cpu: Slot::new(Box::new(FakeSource::constant(cpu::CpuSnapshot {
+                usage: 42.0,
+                cores: vec![cpu::CoreUsage::default(); 4],
+                ..Default::default()
+            }))),
+            memory: Slot::new(Box::new(FakeSource::constant(memory::MemorySample { total: 400, used: 100 }))),
+            disk: Slot::new(Box::new(FakeSource::<disk::DiskSnapshot>::failing("no disks"))),
+            network: Slot::default(),
+            processes: Slot::default(),
+        };
+
+        let usage = sources.collect();
+        assert_eq!((usage.cpu_usage, usage.cpu.cores.len()), (42.0, 4));
+        assert_eq!((usage.total_memory, usage.used_memory, usage.memory_usage), (400, 100, 25.0));
+        assert_eq!((usage.disk_usage, usage.disk_free_bytes), (0.0, 0));
+        assert_eq!(usage.network_down_bytes, 0);
+        let health: Vec<_> = usage.sources.iter().map(|status| status.health.name()).collect();
+        assert_eq!(health, ["ok", "ok", "failing", "disabled", "disabled"]);
+        assert_eq!(usage.sources[2].health.error(), Some("Initialization error: no disks"));
+    }
+
+    fn fake_backends() -> Backends {
+        Backends {
+            cpu: |_| Ok(Box::new(FakeSource::constant(cpu::CpuSnapshot::default()))),
+            memory: |_| Err(CoreError::InitializationError("no meminfo".to_string())),
+            disk: |_| Ok(Box::new(FakeSource::constant(disk::DiskSnapshot::default()))),
+            network: |_| Ok(Box::new(FakeSource::constant(network::NetworkSnapshot::default()))),
+            processes: |_| Ok(Box::new(FakeSource::constant(process::ProcessSnapshot::default()))),
+        }
+    }
+
+    #[test]
+    fn test_reconfigure_starts_and_drops_sources() {
+        let backends = fake_backends();
+        let mut config = crate::config::Config::default().monitoring;
+        config.monitor_processes = false;
+        let mut sources = MetricsSources::default();
+        sources.reconfigure(&backends, None, &config);
+        assert!(sources.cpu.is_running() && sources.disk.is_running() && sources.network.is_running());
+        assert!(!sources.processes.is_running());
+        assert_eq!(sources.memory.health().error(), Some("Initialization error: no meminfo"));
+
+        // Disabling a family drops its source; enabling one starts it
+        let previous = config.clone();
+        config.monitor_cpu = false;
+        config.monitor_processes = true;
+        sources.reconfigure(&backends, Some(&previous), &config);
+        assert_eq!(*sources.cpu.health(), SourceHealth::Disabled);
+        assert!(sources.processes.is_running());
+
+        // Only sources whose settings changed are restarted
+        let network = FakeSource::constant(network::NetworkSnapshot::default());
+        sources.network = Slot::new(Box::new(network.clone()));
+        sources.reconfigure(&backends, Some(&config), &config);
+        sources.collect();
+        assert_eq!(network.samples_taken(), 1);
+        let previous = config.clone();
+        config.network_exclude.push("eth*".to_string());
+        sources.reconfigure(&backends, Some(&previous), &config);
+        sources.collect();
+        assert_eq!(network.samples_taken(), 1);
+    }
+}
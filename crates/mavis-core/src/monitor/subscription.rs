@@ -0,0 +1,106 @@
+// Usage subscriptions
+//
+// The monitor thread publishes every sample on a `watch` channel, which only
+// keeps the latest value: publishing never waits for subscribers, and a
+// subscriber that falls behind skips straight to the newest sample. Each
+// subscriber can also ask for at most one sample per `min_interval`, so a
+// slow consumer such as a script or an exporter sets its own pace.
+
+use super::ResourceUsage;
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+
+/// Samples published by a `ResourceMonitor`, newest only.
+#[derive(Debug)]
+pub struct UsageSubscription {
+    receiver: watch::Receiver<ResourceUsage>,
+    min_interval: Duration,
+    last_delivered: Option<Instant>,
+}
+
+impl UsageSubscription {
+    pub(super) fn new(receiver: watch::Receiver<ResourceUsage>, min_interval: Duration) -> Self {
+        Self {
+            receiver,
+            min_interval,
+            last_delivered: None,
+        }
+    }
+
+    pub fn min_interval(&self) -> Duration {
+        self.min_interval
+    }
+
+    /// The newest sample, without waiting, if one was published since the
+    /// last sample delivered and `min_interval` has passed since then.
+    pub fn try_next(&mut self) -> Option<ResourceUsage> {
+        if !self.is_due(Instant::now()) || !self.receiver.has_changed().unwrap_or(false) {
+            return None;
+        }
+        Some(self.deliver())
+    }
+
+    /// Wait for the next sample, no sooner than `min_interval` after the
+    /// last one delivered. Returns None once the monitor is gone.
+    pub async fn next(&mut self) -> Option<ResourceUsage> {
+        if let Some(last) = self.last_delivered {
+            tokio::time::sleep_until((last + self.min_interval).into()).await;
+        }
+        self.receiver.changed().await.ok()?;
+        Some(self.deliver())
+    }
+
+    fn is_due(&self, now: Instant) -> bool {
+        self.last_delivered
+            .is_none_or(|last| now.saturating_duration_since(last) >= self.min_interval)
+    }
+
+    fn deliver(&mut self) -> ResourceUsage {
+        self.last_delivered = Some(Instant::now());
+        self.receiver.borrow_and_update().clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn usage(cpu_usage: f32) -> ResourceUsage {
+        ResourceUsage {
+            cpu_usage,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_subscribers_get_the_newest_sample_at_their_own_pace() {
+        let (sender, _) = watch::channel(ResourceUsage::default());
+        let mut every = UsageSubscription::new(sender.subscribe(), Duration::ZERO);
+        let mut hourly = UsageSubscription::new(sender.subscribe(), Duration::from_secs(3600));
+        assert!(every.try_next().is_none());
+
+        sender.send_replace(usage(10.0));
+        assert_eq!(every.try_next().map(|usage| usage.cpu_usage), Some(10.0));
+        assert!(every.try_next().is_none());
+        assert_eq!(hourly.try_next().map(|usage| usage.cpu_usage), Some(10.0));
+
+        // Samples nobody read in time are skipped, never queued
+        sender.send_replace(usage(20.0));
+        sender.send_replace(usage(30.0));
+        assert_eq!(every.try_next().map(|usage| usage.cpu_usage), Some(30.0));
+        assert!(hourly.try_next().is_none());
+    }
+
+    #[test]
+    fn test_next_waits_for_a_sample_and_ends_with_the_monitor() {
+        let (sender, _) = watch::channel(ResourceUsage::default());
+        let mut subscription = UsageSubscription::new(sender.subscribe(), Duration::from_millis(10));
+        let runtime = tokio::runtime::Builder::new_current_thread().enable_time().build().unwrap();
+
+        sender.send_replace(usage(40.0));
+        let next = runtime.block_on(subscription.next());
+        assert_eq!(next.map(|usage| usage.cpu_usage), Some(40.0));
+        drop(sender);
+        assert!(runtime.block_on(subscription.next()).is_none());
+    }
+}
@@ -0,0 +1,309 @@
+// Description of the process to launch in a PTY
+
+use crate::config::{TerminalConfig, TerminalProfile};
+use std::{
+    collections::BTreeMap,
+    ffi::OsString,
+    path::{Path, PathBuf},
+};
+
+/// Builder for the program, arguments, working directory and environment of
+/// a PTY child.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PtyCommand {
+    program: String,
+    args: Vec<String>,
+    cwd: Option<PathBuf>,
+    /// Overrides applied on top of the inherited environment; `None` removes.
+    env: BTreeMap<String, Option<String>>,
+    title: Option<String>,
+}
+
+impl PtyCommand {
+    pub fn new(program: impl Into<String>) -> Self {
+        Self {
+            program: program.into(),
+            args: Vec::new(),
+            cwd: None,
+            env: BTreeMap::new(),
+            title: None,
+        }
+    }
+
+    /// Split a command line such as `"pwsh.exe -NoLogo"` into program and
+    /// arguments. Whitespace separates words and double quotes group words
+    /// containing spaces; backslashes are literal so Windows paths need no
+    /// escaping.
+    pub fn parse(command_line: &str) -> Self {
+        let mut words = split_command_line(command_line).into_iter();
+        let program = words.next().unwrap_or_default();
+        Self::new(program).args(words)
+    }
+
+    /// Build the shell command from the terminal settings.
+    pub fn from_config(config: &TerminalConfig) -> Self {
+        let mut command = Self::parse(&config.shell_executable).args(config.shell_args.iter().cloned());
+        if let Some(dir) = &config.working_directory {
+            command = command.cwd(dir);
+        }
+        for (key, value) in &config.environment {
+            command = match value {
+                Some(value) => command.env(key, value),
+                None => command.env_remove(key),
+            };
+        }
+        command
+    }
+
+    /// Build the command for a named profile. Fields the profile leaves unset
+    /// fall back to the terminal settings; its environment is applied on top.
+    pub fn from_profile(config: &TerminalConfig, profile: &TerminalProfile) -> Self {
+        let mut command = Self::from_config(config);
+        if let Some(line) = &profile.command {
+            let parsed = Self::parse(line);
+            command.program = parsed.program;
+            command.args = parsed.args;
+        }
+        command.args.extend(profile.args.iter().cloned());
+        if let Some(dir) = &profile.working_directory {
+            command.cwd = Some(dir.into());
+        }
+        command.env.extend(profile.environment.clone());
+        command.title(profile.title.as_deref().unwrap_or(&profile.name))
+    }
+
+    pub fn arg(mut self, arg: impl Into<String>) -> Self {
+        self.args.push(arg.into());
+        self
+    }
+
+    pub fn args<I, S>(mut self, args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.args.extend(args.into_iter().map(Into::into));
+        self
+    }
+
+    /// Working directory of the child; defaults to the current directory.
+    pub fn cwd(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.cwd = Some(dir.into());
+        self
+    }
+
+    /// Set an environment variable for the child.
+    pub fn env(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.env.insert(key.into(), Some(value.into()));
+        self
+    }
+
+    /// Remove an inherited environment variable from the child.
+    pub fn env_remove(mut self, key: impl Into<String>) -> Self {
+        self.env.insert(key.into(), None);
+        self
+    }
+
+    /// Initial window title. ConPTY passes it as the console title; the GUI
+    /// uses it for the tab until the program sets its own.
+    pub fn title(mut self, title: impl Into<String>) -> Self {
+        self.title = Some(title.into());
+        self
+    }
+
+    pub fn get_program(&self) -> &str {
+        &self.program
+    }
+
+    pub fn get_args(&self) -> &[String] {
+        &self.args
+    }
+
+    pub fn get_cwd(&self) -> Option<&Path> {
+        self.cwd.as_deref()
+    }
+
+    pub fn get_title(&self) -> Option<&str> {
+        self.title.as_deref()
+    }
+
+    /// Environment overrides; a `None` value marks a removal.
+    pub fn get_env(&self) -> impl Iterator<Item = (&str, Option<&str>)> {
+        self.env.iter().map(|(key, value)| (key.as_str(), value.as_deref()))
+    }
+
+    /// Program and arguments joined into a Windows command line, quoted so
+    /// that `CommandLineToArgvW` recovers the original words.
+    pub fn to_command_line(&self) -> String {
+        std::iter::once(&self.program)
+            .chain(&self.args)
+            .map(|word| quote_windows_arg(word))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// The full environment for the child: the current environment with the
+    /// overrides applied. `None` when there are no overrides, meaning the
+    /// child simply inherits ours.
+    pub fn environment(&self) -> Option<Vec<(OsString, OsString)>> {
+        if self.env.is_empty() {
+            return None;
+        }
+        Some(apply_env_overrides(std::env::vars_os(), &self.env))
+    }
+}
+
+/// Apply overrides to `base`. Names compare case-insensitively on Windows,
+/// where environment variable names are not case-sensitive.
+fn apply_env_overrides(
+    base: impl Iterator<Item = (OsString, OsString)>,
+    overrides: &BTreeMap<String, Option<String>>,
+) -> Vec<(OsString, OsString)> {
+    let same_name = |a: &OsString, b: &str| {
+        if cfg!(windows) {
+            a.to_string_lossy().eq_ignore_ascii_case(b)
+        } else {
+            a.as_os_str() == b
+        }
+    };
+
+    let mut vars: Vec<(OsString, OsString)> = base
+        .filter(|(key, _)| !overrides.keys().any(|name| same_name(key, name)))
+        .collect();
+    for (key, value) in overrides {
+        if let Some(value) = value {
+            vars.push((key.into(), value.into()));
+        }
+    }
+    vars
+}
+
+/// Quote one argument following the `CommandLineToArgvW` rules: backslashes
+/// are literal unless they precede a double quote.
+pub fn quote_windows_arg(arg: &str) -> String {
+    if !arg.is_empty() && !arg.contains([' ', '\t', '\n', '\u{b}', '"']) {
+        return arg.to_string();
+    }
+
+    let mut quoted = String::with_capacity(arg.len() + 2);
+    quoted.push('"');
+    let mut backslashes = 0;
+    for c in arg.chars() {
+        match c {
+            '\\' => backslashes += 1,
+            '"' => {
+                // Escape the preceding backslashes and the quote itself
+                quoted.extend(std::iter::repeat_n('\\', backslashes * 2 + 1));
+                quoted.push('"');
+                backslashes = 0;
+            }
+            _ => {
+                quoted.extend(std::iter::repeat_n('\\', backslashes));
+                quoted.push(c);
+                backslashes = 0;
+            }
+        }
+    }
+    // Backslashes before the closing quote must be doubled
+    quoted.extend(std::iter::repeat_n('\\', backslashes * 2));
+    quoted.push('"');
+    quoted
+}
+
+fn split_command_line(command_line: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut current = String::new();
+    let mut in_word = false;
+    let mut in_quotes = false;
+
+    for c in command_line.chars() {
+        match c {
+            '"' => {
+                in_quotes = !in_quotes;
+                in_word = true;
+            }
+            c if c.is_whitespace() && !in_quotes => {
+                if in_word {
+                    words.push(std::mem::take(&mut current));
+                    in_word = false;
+                }
+            }
+            c => {
+                current.push(c);
+                in_word = true;
+            }
+        }
+    }
+    if in_word {
+        words.push(current);
+    }
+    words
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_command_line() {
+        let command = PtyCommand::parse(r#""C:\Program Files\PowerShell\7\pwsh.exe" -NoLogo -Command "echo hi""#);
+        assert_eq!(command.get_program(), r"C:\Program Files\PowerShell\7\pwsh.exe");
+        assert_eq!(command.get_args(), ["-NoLogo", "-Command", "echo hi"]);
+        assert_eq!(PtyCommand::parse("  cmd.exe  ").get_args().len(), 0);
+    }
+
+    #[test]
+    fn test_windows_quoting() {
+        assert_eq!(quote_windows_arg("plain"), "plain");
+        assert_eq!(quote_windows_arg(""), r#""""#);
+        assert_eq!(quote_windows_arg(r"C:\Program Files\"), r#""C:\Program Files\\""#);
+        assert_eq!(quote_windows_arg(r#"say "hi""#), r#""say \"hi\"""#);
+        assert_eq!(quote_windows_arg(r#"a\"b"#), r#""a\\\"b""#);
+
+        let command = PtyCommand::new(r"C:\Tools\nu.exe").args(["-c", "ls | where size > 1kb"]);
+        assert_eq!(command.to_command_line(), r#"C:\Tools\nu.exe -c "ls | where size > 1kb""#);
+    }
+
+    #[test]
+    fn test_profile_overrides_config() {
+        let config: TerminalConfig = serde_json::from_value(serde_json::json!({
+            "shell_executable": "cmd.exe",
+            "shell_args": ["/k"],
+            "environment": { "A": "1", "B": "2" },
+            "columns": 80, "rows": 24, "scrollback_lines": 100,
+            "font_family": "Cascadia Code", "font_size": 12.0
+        }))
+        .unwrap();
+        let profile = TerminalProfile {
+            name: "nu".to_string(),
+            command: Some("nu.exe --login".to_string()),
+            environment: BTreeMap::from([("B".to_string(), None)]),
+            ..Default::default()
+        };
+        let command = PtyCommand::from_profile(&config, &profile);
+        assert_eq!(command.get_program(), "nu.exe");
+        assert_eq!(command.get_args(), ["--login"]);
+        assert_eq!(command.get_env().collect::<Vec<_>>(), vec![("A", Some("1")), ("B", None)]);
+        assert_eq!(command.get_title(), Some("nu"));
+    }
+
+    #[test]
+    fn test_environment_overrides() {
+        let base = vec![
+            (OsString::from("PATH"), OsString::from("/bin")),
+            (OsString::from("HOME"), OsString::from("/home/user")),
+            (OsString::from("SECRET"), OsString::from("x")),
+        ];
+        let command = PtyCommand::new("sh").env("HOME", "/tmp").env("EDITOR", "vi").env_remove("SECRET");
+        let vars = apply_env_overrides(base.into_iter(), &command.env);
+        assert_eq!(
+            vars,
+            vec![
+                (OsString::from("PATH"), OsString::from("/bin")),
+                (OsString::from("EDITOR"), OsString::from("vi")),
+                (OsString::from("HOME"), OsString::from("/tmp")),
+            ]
+        );
+        assert!(PtyCommand::new("sh").environment().is_none());
+    }
+}
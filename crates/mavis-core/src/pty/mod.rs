@@ -0,0 +1,89 @@
+// Platform-agnostic pseudo-terminal sessions
+//
+// `PtySession` is implemented by the Windows ConPTY backend and by a POSIX
+// openpty backend, so the terminal pipeline runs the same way on both.
+// `NativePty` names the backend for the platform being built.
+//
+// A session can be split into a reader, a writer and a shared control
+// handle. Each half owns its own handle to the terminal, so a thread blocked
+// in `read` never stops another thread from writing input or resizing.
+
+pub mod command;
+#[cfg(unix)]
+pub mod unix;
+
+use crate::error::CoreResult;
+use std::fmt::Debug;
+use std::io::{Read, Write};
+use std::sync::Arc;
+
+pub use command::PtyCommand;
+#[cfg(windows)]
+pub use crate::conpty::ConPtySession;
+#[cfg(unix)]
+pub use unix::UnixPty;
+
+/// The PTY backend for the current platform.
+#[cfg(windows)]
+pub type NativePty = ConPtySession;
+#[cfg(unix)]
+pub type NativePty = UnixPty;
+
+/// Output of the child. `read` blocks until data is available and returns
+/// 0 once the child has exited and the terminal is closed.
+pub type PtyReader = Box<dyn Read + Send>;
+
+/// Input for the child.
+pub type PtyWriter = Box<dyn Write + Send>;
+
+/// Control handle shared between the GUI and the I/O threads.
+pub type SharedPtyControl = Arc<dyn PtyControl>;
+
+/// Resize and lifecycle operations. These never wait for reads or writes.
+pub trait PtyControl: Send + Sync + Debug {
+    /// Change the terminal size seen by the child.
+    fn resize(&self, cols: u16, rows: u16) -> CoreResult<()>;
+
+    /// Exit code of the child, or `None` while it is still running.
+    /// On Unix a child killed by a signal reports `128 + signal`.
+    fn exit_status(&self) -> CoreResult<Option<u32>>;
+
+    /// Kill the child process. Pending and later reads then report the end
+    /// of the output.
+    fn terminate(&self) -> CoreResult<()>;
+}
+
+/// The independent halves of a split session.
+pub struct PtyHalves {
+    pub reader: PtyReader,
+    pub writer: PtyWriter,
+    /// Dropping the last clone of the control does not necessarily stop
+    /// the child; call `terminate` when the session is closed.
+    pub control: SharedPtyControl,
+}
+
+/// A child process attached to a pseudo-terminal.
+pub trait PtySession: PtyControl {
+    /// Spawn `command` in a new pseudo-terminal of `cols` x `rows` cells.
+    fn spawn(command: &PtyCommand, cols: u16, rows: u16) -> CoreResult<Self>
+    where
+        Self: Sized;
+
+    /// Read output from the child. Blocks until data is available and
+    /// returns 0 once the child has closed the terminal.
+    fn read(&mut self, buf: &mut [u8]) -> CoreResult<usize>;
+
+    /// Write input to the child, returning the number of bytes written.
+    fn write(&mut self, data: &[u8]) -> CoreResult<usize>;
+
+    /// Split into a reader, a writer and a control handle that can be used
+    /// from different threads at the same time.
+    fn split(self) -> CoreResult<PtyHalves>
+    where
+        Self: Sized;
+}
+
+/// Spawn `command` with the native backend and split it into halves.
+pub fn spawn_split(command: &PtyCommand, cols: u16, rows: u16) -> CoreResult<PtyHalves> {
+    NativePty::spawn(command, cols, rows)?.split()
+}
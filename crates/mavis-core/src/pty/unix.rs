@@ -0,0 +1,307 @@
+// POSIX pseudo-terminal backend (openpty)
+
+use super::{PtyCommand, PtyControl, PtyHalves, PtySession};
+use crate::error::{CoreError, CoreResult};
+use log::{debug, error, info};
+use std::{
+    fs::File,
+    io::{self, Read, Write},
+    os::fd::{AsRawFd, FromRawFd, OwnedFd},
+    os::unix::process::{CommandExt, ExitStatusExt},
+    process::{Child, Command, Stdio},
+    sync::{Arc, Mutex},
+};
+
+/// A child process running on the slave side of an openpty pair.
+#[derive(Debug)]
+pub struct UnixPty {
+    master: File,
+    child: Mutex<Child>,
+}
+
+impl UnixPty {
+    /// Process ID of the child.
+    pub fn pid(&self) -> u32 {
+        self.child.lock().map(|child| child.id()).unwrap_or(0)
+    }
+}
+
+impl PtySession for UnixPty {
+    /// Runs `command` as the leader of a new session whose controlling
+    /// terminal is the PTY.
+    fn spawn(command: &PtyCommand, cols: u16, rows: u16) -> CoreResult<Self> {
+        info!("Creating PTY session for command: '{}'", command.get_program());
+
+        let (master, slave) = open_pty(cols, rows)?;
+
+        let mut cmd = Command::new(command.get_program());
+        cmd.args(command.get_args())
+            .env("TERM", "xterm-256color")
+            .stdin(Stdio::from(slave.try_clone()?))
+            .stdout(Stdio::from(slave.try_clone()?))
+            .stderr(Stdio::from(slave));
+        if let Some(dir) = command.get_cwd() {
+            cmd.current_dir(dir);
+        }
+        for (key, value) in command.get_env() {
+            match value {
+                Some(value) => cmd.env(key, value),
+                None => cmd.env_remove(key),
+            };
+        }
+
+        // Runs in the child between fork and exec.
+        unsafe {
+            cmd.pre_exec(|| {
+                if libc::setsid() == -1 {
+                    return Err(io::Error::last_os_error());
+                }
+                if libc::ioctl(0, libc::TIOCSCTTY as _, 0) == -1 {
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+
+        let child = cmd.spawn()?;
+        info!("Child process created with PID: {}", child.id());
+
+        Ok(Self {
+            master: File::from(master),
+            child: Mutex::new(child),
+        })
+    }
+
+    fn read(&mut self, buf: &mut [u8]) -> CoreResult<usize> {
+        let bytes_read = read_master(&mut self.master, buf)?;
+        debug!("Read {} bytes from PTY output", bytes_read);
+        Ok(bytes_read)
+    }
+
+    fn write(&mut self, data: &[u8]) -> CoreResult<usize> {
+        let bytes_written = self.master.write(data)?;
+        debug!("Wrote {} bytes to PTY input", bytes_written);
+        Ok(bytes_written)
+    }
+
+    /// The reader and writer get their own descriptors for the master; the
+    /// control keeps the original for resizing and owns the child.
+    fn split(self) -> CoreResult<PtyHalves> {
+        let reader = UnixPtyReader(self.master.try_clone()?);
+        let writer = self.master.try_clone()?;
+        Ok(PtyHalves {
+            reader: Box::new(reader),
+            writer: Box::new(writer),
+            control: Arc::new(self),
+        })
+    }
+}
+
+impl PtyControl for UnixPty {
+    fn resize(&self, cols: u16, rows: u16) -> CoreResult<()> {
+        debug!("Resizing PTY to {}x{}", cols, rows);
+        let size = window_size(cols, rows);
+        if unsafe { libc::ioctl(self.master.as_raw_fd(), libc::TIOCSWINSZ as _, &size) } == -1 {
+            return Err(io::Error::last_os_error().into());
+        }
+        Ok(())
+    }
+
+    fn exit_status(&self) -> CoreResult<Option<u32>> {
+        let mut child = lock_child(&self.child)?;
+        let status = child.try_wait()?;
+        Ok(status.map(|status| match (status.code(), status.signal()) {
+            (Some(code), _) => code as u32,
+            (None, Some(signal)) => 128 + signal as u32,
+            (None, None) => 1,
+        }))
+    }
+
+    fn terminate(&self) -> CoreResult<()> {
+        let mut child = lock_child(&self.child)?;
+        if child.try_wait()?.is_some() {
+            return Ok(());
+        }
+        info!("Terminating PTY session (PID: {})", child.id());
+        child.kill()?;
+        // Reap the child so it does not linger as a zombie
+        child.wait()?;
+        Ok(())
+    }
+}
+
+impl Drop for UnixPty {
+    fn drop(&mut self) {
+        if let Err(e) = self.terminate() {
+            error!("Error during UnixPty drop: {}", e);
+        }
+    }
+}
+
+/// Reading half of a split `UnixPty`.
+struct UnixPtyReader(File);
+
+impl Read for UnixPtyReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        read_master(&mut self.0, buf)
+    }
+}
+
+// Linux reports EIO on the master once every slave fd is closed; treat it as
+// the end of the output.
+fn read_master(master: &mut File, buf: &mut [u8]) -> io::Result<usize> {
+    match master.read(buf) {
+        Err(e) if e.raw_os_error() == Some(libc::EIO) => Ok(0),
+        result => result,
+    }
+}
+
+fn lock_child(child: &Mutex<Child>) -> CoreResult<std::sync::MutexGuard<'_, Child>> {
+    child
+        .lock()
+        .map_err(|_| CoreError::InitializationError("PTY child mutex poisoned".to_string()))
+}
+
+fn window_size(cols: u16, rows: u16) -> libc::winsize {
+    libc::winsize {
+        ws_row: rows,
+        ws_col: cols,
+        ws_xpixel: 0,
+        ws_ypixel: 0,
+    }
+}
+
+/// Open a master/slave pair with the given initial size.
+fn open_pty(cols: u16, rows: u16) -> CoreResult<(OwnedFd, OwnedFd)> {
+    let mut master: libc::c_int = -1;
+    let mut slave: libc::c_int = -1;
+    let mut size = window_size(cols, rows);
+
+    let result = unsafe {
+        libc::openpty(
+            &mut master,
+            &mut slave,
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+            // `*const` on Linux, `*mut` on the BSDs and macOS
+            std::ptr::addr_of_mut!(size),
+        )
+    };
+    if result != 0 {
+        return Err(io::Error::last_os_error().into());
+    }
+
+    let (master, slave) = unsafe { (OwnedFd::from_raw_fd(master), OwnedFd::from_raw_fd(slave)) };
+
+    // Keep both ends out of the child (and of any other process we spawn),
+    // or other tabs' children hold them open; the child still gets the
+    // slave as its stdio, which dup2 leaves open.
+    for fd in [&master, &slave] {
+        if unsafe { libc::fcntl(fd.as_raw_fd(), libc::F_SETFD, libc::FD_CLOEXEC) } == -1 {
+            return Err(io::Error::last_os_error().into());
+        }
+    }
+
+    Ok((master, slave))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{thread, time::Duration};
+
+    fn shell(script: &str) -> PtyCommand {
+        PtyCommand::new("/bin/sh").args(["-c", script])
+    }
+
+    fn read_to_end(session: &mut UnixPty) -> String {
+        let mut output = Vec::new();
+        let mut buffer = [0u8; 1024];
+        loop {
+            match session.read(&mut buffer) {
+                Ok(0) | Err(_) => break,
+                Ok(n) => output.extend_from_slice(&buffer[..n]),
+            }
+        }
+        String::from_utf8_lossy(&output).into_owned()
+    }
+
+    fn wait_for_exit(session: &dyn PtyControl) -> Option<u32> {
+        for _ in 0..100 {
+            if let Some(code) = session.exit_status().unwrap() {
+                return Some(code);
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+        None
+    }
+
+    #[test]
+    fn test_pty_fds_close_on_exec() {
+        let (master, slave) = open_pty(80, 24).unwrap();
+        for fd in [master, slave] {
+            let flags = unsafe { libc::fcntl(fd.as_raw_fd(), libc::F_GETFD) };
+            assert_ne!(flags & libc::FD_CLOEXEC, 0);
+        }
+    }
+
+    #[test]
+    fn test_echo_and_exit_code() {
+        let mut session = UnixPty::spawn(&shell("echo hello pty; exit 3"), 80, 24).unwrap();
+        assert!(read_to_end(&mut session).contains("hello pty"));
+        assert_eq!(wait_for_exit(&session), Some(3));
+    }
+
+    #[test]
+    fn test_window_size_and_resize() {
+        let mut session = UnixPty::spawn(&shell("sleep 0.2; stty size"), 80, 24).unwrap();
+        session.resize(100, 30).unwrap();
+        assert!(read_to_end(&mut session).contains("30 100"));
+    }
+
+    #[test]
+    fn test_write_input() {
+        let mut session = UnixPty::spawn(&shell("read line; echo \"got $line\""), 80, 24).unwrap();
+        session.write(b"abc\n").unwrap();
+        assert!(read_to_end(&mut session).contains("got abc"));
+    }
+
+    #[test]
+    fn test_cwd_and_environment() {
+        let command = shell("pwd; echo \"[$MAVIS_TEST]\" \"[$HOME]\"")
+            .cwd("/")
+            .env("MAVIS_TEST", "set")
+            .env_remove("HOME");
+        let mut session = UnixPty::spawn(&command, 80, 24).unwrap();
+        let output = read_to_end(&mut session);
+        assert!(output.contains("/\r\n[set] []"), "{:?}", output);
+    }
+
+    #[test]
+    fn test_split_halves_do_not_contend() {
+        let session = UnixPty::spawn(&shell("read line; stty size; echo \"got $line\""), 80, 24).unwrap();
+        let PtyHalves { mut reader, mut writer, control } = session.split().unwrap();
+        let output = thread::spawn(move || {
+            let mut output = String::new();
+            reader.read_to_string(&mut output).unwrap();
+            output
+        });
+
+        // The reader is blocked waiting for output while we resize and write
+        thread::sleep(Duration::from_millis(100));
+        control.resize(100, 30).unwrap();
+        writer.write_all(b"abc\n").unwrap();
+
+        let output = output.join().unwrap();
+        assert!(output.contains("30 100\r\ngot abc"), "{:?}", output);
+        assert_eq!(wait_for_exit(&*control), Some(0));
+    }
+
+    #[test]
+    fn test_terminate() {
+        let session = UnixPty::spawn(&PtyCommand::new("sleep").arg("30"), 80, 24).unwrap();
+        assert_eq!(session.exit_status().unwrap(), None);
+        session.terminate().unwrap();
+        assert_eq!(session.exit_status().unwrap(), Some(128 + libc::SIGKILL as u32));
+    }
+}
@@ -0,0 +1,290 @@
+// Command history from shell integration marks
+//
+// Shells with integration scripts (FinalTerm/OSC 133) mark where each
+// prompt starts, where the typed command starts, where its output starts
+// and when it finished, with its exit status. The history turns those
+// marks into one record per command, with positions addressed like
+// selections so the view can jump between prompts and a command's output
+// can be copied.
+
+use super::screen::{Screen, ShellMark};
+use super::selection::{Point, Selection, SelectionMode};
+use std::collections::VecDeque;
+use std::time::SystemTime;
+
+/// Oldest commands are forgotten beyond this many.
+const MAX_COMMANDS: usize = 1000;
+
+/// One prompt and the command run from it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Command {
+    /// Where the prompt starts.
+    pub prompt: Point,
+    /// Where the typed command starts, after the prompt.
+    pub input: Option<Point>,
+    /// Where the output starts, once the command was entered.
+    pub output: Option<Point>,
+    /// Where the output ends (exclusive), once the command finished.
+    pub end: Option<Point>,
+    /// The command line as shown on screen.
+    pub text: Option<String>,
+    pub exit_code: Option<i32>,
+    /// Directory reported with OSC 7 when the prompt was shown.
+    pub cwd: Option<String>,
+    pub started_at: Option<SystemTime>,
+    pub finished_at: Option<SystemTime>,
+}
+
+impl Command {
+    fn new(prompt: Point, cwd: Option<String>) -> Self {
+        Self {
+            prompt,
+            input: None,
+            output: None,
+            end: None,
+            text: None,
+            exit_code: None,
+            cwd,
+            started_at: None,
+            finished_at: None,
+        }
+    }
+
+    /// Entered but not finished.
+    pub fn is_running(&self) -> bool {
+        self.output.is_some() && self.finished_at.is_none()
+    }
+
+    /// Finished with a non-zero exit status.
+    pub fn failed(&self) -> bool {
+        self.exit_code.is_some_and(|code| code != 0)
+    }
+
+    /// The command's output so far. Running commands' output runs to the
+    /// end of the screen.
+    pub fn output_text(&self, screen: &Screen) -> Option<String> {
+        let start = self.output?;
+        let end = self.end.unwrap_or(Point::new(screen.total_lines(), 0));
+        Some(text_between(screen, start, end))
+    }
+
+    fn points_mut(&mut self) -> impl Iterator<Item = &mut Point> {
+        std::iter::once(&mut self.prompt).chain([&mut self.input, &mut self.output, &mut self.end].into_iter().flatten())
+    }
+}
+
+/// The commands of one terminal, oldest first.
+#[derive(Debug, Clone, Default)]
+pub struct CommandHistory {
+    commands: VecDeque<Command>,
+}
+
+impl CommandHistory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.commands.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &Command> + ExactSizeIterator {
+        self.commands.iter()
+    }
+
+    pub fn get(&self, index: usize) -> Option<&Command> {
+        self.commands.get(index)
+    }
+
+    /// The latest command, which may still be at its prompt.
+    pub fn last(&self) -> Option<&Command> {
+        self.commands.back()
+    }
+
+    /// The latest command that produced output and finished.
+    pub fn last_finished(&self) -> Option<&Command> {
+        self.commands.iter().rev().find(|c| c.output.is_some() && c.finished_at.is_some())
+    }
+
+    /// The last command whose prompt is above `line`.
+    pub fn previous_prompt(&self, line: usize) -> Option<&Command> {
+        self.commands.iter().rev().find(|c| c.prompt.line < line)
+    }
+
+    /// The first command whose prompt is below `line`.
+    pub fn next_prompt(&self, line: usize) -> Option<&Command> {
+        self.commands.iter().find(|c| c.prompt.line > line)
+    }
+
+    /// The command whose prompt starts on `line`.
+    pub fn at_prompt_line(&self, line: usize) -> Option<&Command> {
+        self.commands.iter().find(|c| c.prompt.line == line)
+    }
+
+    /// Record a mark that arrived at `point`. `cwd` is the directory the
+    /// shell last reported.
+    pub fn apply(&mut self, mark: ShellMark, point: Point, screen: &Screen, cwd: Option<&str>) {
+        match mark {
+            ShellMark::PromptStart => {
+                // A prompt without a finish mark ends the previous command
+                if let Some(last) = self.commands.back_mut()
+                    && last.output.is_some()
+                    && last.end.is_none()
+                {
+                    last.end = Some(point);
+                    last.finished_at = Some(SystemTime::now());
+                }
+                if self.commands.len() == MAX_COMMANDS {
+                    self.commands.pop_front();
+                }
+                self.commands.push_back(Command::new(point, cwd.map(str::to_string)));
+            }
+            ShellMark::CommandStart => {
+                if let Some(last) = self.commands.back_mut() {
+                    last.input = Some(point);
+                }
+            }
+            ShellMark::OutputStart => {
+                if let Some(last) = self.commands.back_mut() {
+                    let input = last.input.unwrap_or(last.prompt);
+                    last.text = Some(text_between(screen, input, point).trim().to_string());
+                    last.output = Some(point);
+                    last.started_at = Some(SystemTime::now());
+                }
+            }
+            ShellMark::CommandEnd(exit_code) => {
+                if let Some(last) = self.commands.back_mut()
+                    && last.finished_at.is_none()
+                {
+                    last.end = Some(point);
+                    last.exit_code = exit_code;
+                    last.finished_at = Some(SystemTime::now());
+                }
+            }
+        }
+    }
+
+    /// Shift positions up by `lines` after rows were dropped from the top of
+    /// the scrollback, forgetting commands whose prompt was dropped.
+    pub fn scroll_up(&mut self, lines: usize) {
+        if lines == 0 {
+            return;
+        }
+        self.commands.retain(|c| c.prompt.line >= lines);
+        for point in self.points_mut() {
+            point.line -= lines;
+        }
+    }
+
+    /// Every recorded position, for moving them when the screen reflows.
+    pub fn points_mut(&mut self) -> impl Iterator<Item = &mut Point> {
+        self.commands.iter_mut().flat_map(Command::points_mut)
+    }
+
+    pub fn clear(&mut self) {
+        self.commands.clear();
+    }
+}
+
+/// Text from `start` up to `end` (exclusive), with soft wraps joined.
+fn text_between(screen: &Screen, start: Point, end: Point) -> String {
+    let last = if end.col > 0 {
+        Point::new(end.line, end.col - 1)
+    } else if end.line > 0 {
+        Point::new(end.line - 1, screen.cols() - 1)
+    } else {
+        return String::new();
+    };
+    if last < start {
+        return String::new();
+    }
+    let mut selection = Selection::new(SelectionMode::Simple, start);
+    selection.update(last);
+    selection.text(screen).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::terminal::Terminal;
+
+    const PROMPT: &[u8] = b"\x1b]133;A\x07$ \x1b]133;B\x07";
+
+    fn run(terminal: &mut Terminal, command: &str, output: &str, status: i32) {
+        terminal.process(PROMPT);
+        terminal.process(command.as_bytes());
+        terminal.process(b"\r\n\x1b]133;C\x07");
+        terminal.process(output.as_bytes());
+        terminal.process(format!("\x1b]133;D;{}\x07", status).as_bytes());
+    }
+
+    #[test]
+    fn test_commands_are_recorded() {
+        let mut terminal = Terminal::new(20, 10, 100);
+        terminal.process(b"\x1b]7;file://host/home/me\x07");
+        run(&mut terminal, "ls", "a\r\nb\r\n", 0);
+        run(&mut terminal, "false", "", 1);
+        terminal.process(PROMPT);
+
+        let history = terminal.commands();
+        assert_eq!(history.len(), 3);
+        let ls = history.get(0).unwrap();
+        assert_eq!(ls.text.as_deref(), Some("ls"));
+        assert_eq!(ls.exit_code, Some(0));
+        assert_eq!(ls.cwd.as_deref(), Some("/home/me"));
+        assert_eq!(ls.output_text(terminal.screen()).unwrap(), "a\nb");
+        assert_eq!((ls.prompt.line, ls.output.unwrap().line, ls.end.unwrap().line), (0, 1, 3));
+
+        let failed = history.get(1).unwrap();
+        assert!(failed.failed() && !failed.is_running());
+        assert_eq!(failed.output_text(terminal.screen()).unwrap(), "");
+        assert_eq!(history.last_finished(), Some(failed));
+        assert!(history.last().unwrap().text.is_none());
+        assert_eq!(terminal.working_directory(), Some("/home/me"));
+    }
+
+    #[test]
+    fn test_prompt_navigation_and_eviction() {
+        let mut terminal = Terminal::new(20, 4, 3);
+        for i in 0..4 {
+            run(&mut terminal, &format!("echo {}", i), &format!("{}\r\n", i), 0);
+        }
+        terminal.process(PROMPT);
+
+        // 9 lines in 4 rows plus 3 of scrollback: the first command is gone
+        let history = terminal.commands();
+        assert_eq!(history.len(), 4);
+        assert_eq!(history.get(0).unwrap().text.as_deref(), Some("echo 1"));
+        assert_eq!(history.get(0).unwrap().prompt.line, 0);
+
+        assert!(terminal.scroll_to_previous_prompt());
+        assert_eq!(terminal.viewport_line(0), 2);
+        assert!(terminal.scroll_to_previous_prompt());
+        assert_eq!(terminal.viewport_line(0), 0);
+        assert!(!terminal.scroll_to_previous_prompt());
+        // The next prompt is on the live screen
+        assert!(terminal.scroll_to_next_prompt());
+        assert!(terminal.scroll_to_next_prompt());
+        assert_eq!(terminal.display_offset(), 0);
+        assert_eq!(terminal.last_output_text().as_deref(), Some("3"));
+    }
+
+    #[test]
+    fn test_marks_follow_reflow() {
+        let mut terminal = Terminal::new(10, 10, 100);
+        run(&mut terminal, "echo abcdefghij", "abcdefghij\r\n", 0);
+        terminal.process(PROMPT);
+        // The command line "$ echo abcdefghij" wraps onto a second row
+        assert_eq!(terminal.commands().get(0).unwrap().output.unwrap().line, 2);
+
+        terminal.resize(40, 10);
+        let history = terminal.commands();
+        let first = history.get(0).unwrap();
+        assert_eq!((first.output.unwrap().line, first.end.unwrap().line), (1, 2));
+        assert_eq!(history.get(1).unwrap().prompt.line, 2);
+        assert_eq!(first.output_text(terminal.screen()).unwrap(), "abcdefghij");
+    }
+}
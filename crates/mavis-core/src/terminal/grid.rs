@@ -0,0 +1,365 @@
+// Cell grid backing the terminal emulator
+
+use std::ops::{BitOr, BitOrAssign};
+
+/// Terminal colour as set by SGR sequences.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Color {
+    /// The theme's default foreground or background.
+    #[default]
+    Default,
+    /// One of the 256 indexed palette colours (0-15 are the ANSI colours).
+    Indexed(u8),
+    /// A 24-bit truecolour value.
+    Rgb(u8, u8, u8),
+}
+
+impl Color {
+    /// Resolve an indexed colour to RGB using the standard xterm palette.
+    /// Returns `None` for `Color::Default`, which the renderer resolves from its theme.
+    pub fn to_rgb(self) -> Option<(u8, u8, u8)> {
+        match self {
+            Color::Default => None,
+            Color::Rgb(r, g, b) => Some((r, g, b)),
+            Color::Indexed(index) => Some(xterm_palette(index)),
+        }
+    }
+}
+
+/// Standard xterm 256-colour palette.
+fn xterm_palette(index: u8) -> (u8, u8, u8) {
+    const ANSI: [(u8, u8, u8); 16] = [
+        (0, 0, 0),
+        (205, 0, 0),
+        (0, 205, 0),
+        (205, 205, 0),
+        (0, 0, 238),
+        (205, 0, 205),
+        (0, 205, 205),
+        (229, 229, 229),
+        (127, 127, 127),
+        (255, 0, 0),
+        (0, 255, 0),
+        (255, 255, 0),
+        (92, 92, 255),
+        (255, 0, 255),
+        (0, 255, 255),
+        (255, 255, 255),
+    ];
+
+    match index {
+        0..=15 => ANSI[index as usize],
+        16..=231 => {
+            // 6x6x6 colour cube
+            let i = index - 16;
+            let level = |v: u8| if v == 0 { 0 } else { 55 + v * 40 };
+            (level(i / 36), level((i / 6) % 6), level(i % 6))
+        }
+        232..=255 => {
+            let v = 8 + (index - 232) * 10;
+            (v, v, v)
+        }
+    }
+}
+
+/// Rendition flags for a cell.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Hash)]
+pub struct CellFlags(u16);
+
+impl CellFlags {
+    pub const BOLD: CellFlags = CellFlags(1 << 0);
+    pub const DIM: CellFlags = CellFlags(1 << 1);
+    pub const ITALIC: CellFlags = CellFlags(1 << 2);
+    pub const UNDERLINE: CellFlags = CellFlags(1 << 3);
+    pub const BLINK: CellFlags = CellFlags(1 << 4);
+    pub const INVERSE: CellFlags = CellFlags(1 << 5);
+    pub const HIDDEN: CellFlags = CellFlags(1 << 6);
+    pub const STRIKETHROUGH: CellFlags = CellFlags(1 << 7);
+    /// The cell holds a double-width glyph that also covers the next cell.
+    pub const WIDE: CellFlags = CellFlags(1 << 8);
+    /// Right half of a double-width glyph; carries no character of its own.
+    pub const WIDE_SPACER: CellFlags = CellFlags(1 << 9);
+
+    pub const fn empty() -> Self {
+        CellFlags(0)
+    }
+
+    pub fn contains(self, other: CellFlags) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn insert(&mut self, other: CellFlags) {
+        self.0 |= other.0;
+    }
+
+    pub fn remove(&mut self, other: CellFlags) {
+        self.0 &= !other.0;
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+}
+
+impl BitOr for CellFlags {
+    type Output = CellFlags;
+
+    fn bitor(self, rhs: CellFlags) -> CellFlags {
+        CellFlags(self.0 | rhs.0)
+    }
+}
+
+impl BitOrAssign for CellFlags {
+    fn bitor_assign(&mut self, rhs: CellFlags) {
+        self.0 |= rhs.0;
+    }
+}
+
+/// Identifies an OSC 8 hyperlink registered with the screen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct HyperlinkId(pub u32);
+
+/// Graphic rendition shared by a run of cells.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CellAttributes {
+    pub fg: Color,
+    pub bg: Color,
+    pub flags: CellFlags,
+    /// OSC 8 hyperlink the cell was printed under.
+    pub link: Option<HyperlinkId>,
+}
+
+/// A single character cell.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cell {
+    pub c: char,
+    pub attrs: CellAttributes,
+}
+
+impl Default for Cell {
+    fn default() -> Self {
+        Self {
+            c: ' ',
+            attrs: CellAttributes::default(),
+        }
+    }
+}
+
+impl Cell {
+    /// A blank cell keeping only the background colour of `attrs`
+    /// (erase operations use the current background, "BCE").
+    pub fn blank(attrs: &CellAttributes) -> Self {
+        Self {
+            c: ' ',
+            attrs: CellAttributes {
+                bg: attrs.bg,
+                ..Default::default()
+            },
+        }
+    }
+}
+
+/// One line of the grid.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Row {
+    pub cells: Vec<Cell>,
+    /// Set when the line continues onto the next row because of auto-wrap.
+    pub wrapped: bool,
+}
+
+impl Row {
+    pub fn new(cols: usize) -> Self {
+        Self::blank(cols, &CellAttributes::default())
+    }
+
+    pub fn blank(cols: usize, attrs: &CellAttributes) -> Self {
+        Self {
+            cells: vec![Cell::blank(attrs); cols],
+            wrapped: false,
+        }
+    }
+
+    /// Text content of the row with trailing blanks removed.
+    pub fn text(&self) -> String {
+        let text: String = self
+            .cells
+            .iter()
+            .filter(|cell| !cell.attrs.flags.contains(CellFlags::WIDE_SPACER))
+            .map(|cell| cell.c)
+            .collect();
+        text.trim_end().to_string()
+    }
+
+    /// Reset every cell in `start..end` to a blank with the given attributes.
+    pub fn erase(&mut self, start: usize, end: usize, attrs: &CellAttributes) {
+        let end = end.min(self.cells.len());
+        for cell in self.cells.iter_mut().take(end).skip(start) {
+            *cell = Cell::blank(attrs);
+        }
+    }
+
+    fn resize(&mut self, cols: usize) {
+        self.cells.resize(cols, Cell::default());
+    }
+}
+
+/// A fixed-size screen of rows.
+#[derive(Debug, Clone)]
+pub struct Grid {
+    rows: Vec<Row>,
+    cols: usize,
+}
+
+impl Grid {
+    pub fn new(cols: usize, rows: usize) -> Self {
+        let cols = cols.max(1);
+        let rows = rows.max(1);
+        Self {
+            rows: (0..rows).map(|_| Row::new(cols)).collect(),
+            cols,
+        }
+    }
+
+    pub fn cols(&self) -> usize {
+        self.cols
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows.len()
+    }
+
+    pub fn row(&self, index: usize) -> &Row {
+        &self.rows[index]
+    }
+
+    pub fn row_mut(&mut self, index: usize) -> &mut Row {
+        &mut self.rows[index]
+    }
+
+    pub fn iter_rows(&self) -> impl Iterator<Item = &Row> {
+        self.rows.iter()
+    }
+
+    pub fn cell(&self, row: usize, col: usize) -> &Cell {
+        &self.rows[row].cells[col]
+    }
+
+    pub fn cell_mut(&mut self, row: usize, col: usize) -> &mut Cell {
+        &mut self.rows[row].cells[col]
+    }
+
+    /// Scroll the region `top..=bottom` up by `count` lines, filling the
+    /// bottom with blank rows. Returns the rows that left the region.
+    pub fn scroll_up(&mut self, top: usize, bottom: usize, count: usize, attrs: &CellAttributes) -> Vec<Row> {
+        let count = count.min(bottom + 1 - top);
+        let removed: Vec<Row> = self.rows.drain(top..top + count).collect();
+        for _ in 0..count {
+            self.rows.insert(bottom + 1 - count, Row::blank(self.cols, attrs));
+        }
+        removed
+    }
+
+    /// Scroll the region `top..=bottom` down by `count` lines, filling the top
+    /// with blank rows.
+    pub fn scroll_down(&mut self, top: usize, bottom: usize, count: usize, attrs: &CellAttributes) {
+        let count = count.min(bottom + 1 - top);
+        self.rows.drain(bottom + 1 - count..=bottom);
+        for _ in 0..count {
+            self.rows.insert(top, Row::blank(self.cols, attrs));
+        }
+    }
+
+    /// Insert `count` blank cells at `col`, shifting the rest of the line right.
+    pub fn insert_cells(&mut self, row: usize, col: usize, count: usize, attrs: &CellAttributes) {
+        let cols = self.cols;
+        let cells = &mut self.rows[row].cells;
+        let count = count.min(cols - col);
+        for _ in 0..count {
+            cells.insert(col, Cell::blank(attrs));
+        }
+        cells.truncate(cols);
+    }
+
+    /// Delete `count` cells at `col`, shifting the rest of the line left.
+    pub fn delete_cells(&mut self, row: usize, col: usize, count: usize, attrs: &CellAttributes) {
+        let cols = self.cols;
+        let cells = &mut self.rows[row].cells;
+        let count = count.min(cols - col);
+        cells.drain(col..col + count);
+        cells.resize(cols, Cell::blank(attrs));
+    }
+
+    /// Clear the whole grid.
+    pub fn clear(&mut self, attrs: &CellAttributes) {
+        for row in &mut self.rows {
+            *row = Row::blank(self.cols, attrs);
+        }
+    }
+
+    /// Resize by truncating or padding rows and columns. Rows are removed
+    /// from or added at the bottom.
+    pub fn resize(&mut self, cols: usize, rows: usize) {
+        let cols = cols.max(1);
+        let rows = rows.max(1);
+        for row in &mut self.rows {
+            row.resize(cols);
+        }
+        self.rows.resize_with(rows, || Row::new(cols));
+        self.cols = cols;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grid_with_lines(lines: &[&str]) -> Grid {
+        let mut grid = Grid::new(5, lines.len());
+        for (y, line) in lines.iter().enumerate() {
+            for (x, c) in line.chars().enumerate() {
+                grid.cell_mut(y, x).c = c;
+            }
+        }
+        grid
+    }
+
+    #[test]
+    fn test_palette() {
+        assert_eq!(Color::Indexed(1).to_rgb(), Some((205, 0, 0)));
+        assert_eq!(Color::Indexed(16).to_rgb(), Some((0, 0, 0)));
+        assert_eq!(Color::Indexed(231).to_rgb(), Some((255, 255, 255)));
+        assert_eq!(Color::Indexed(232).to_rgb(), Some((8, 8, 8)));
+        assert_eq!(Color::Default.to_rgb(), None);
+    }
+
+    #[test]
+    fn test_scroll_region() {
+        let mut grid = grid_with_lines(&["a", "b", "c", "d"]);
+        let removed = grid.scroll_up(1, 2, 1, &CellAttributes::default());
+        assert_eq!(removed[0].text(), "b");
+        let text: Vec<String> = grid.iter_rows().map(Row::text).collect();
+        assert_eq!(text, vec!["a", "c", "", "d"]);
+
+        grid.scroll_down(0, 3, 2, &CellAttributes::default());
+        let text: Vec<String> = grid.iter_rows().map(Row::text).collect();
+        assert_eq!(text, vec!["", "", "a", "c"]);
+    }
+
+    #[test]
+    fn test_insert_and_delete_cells() {
+        let mut grid = grid_with_lines(&["abcde"]);
+        grid.insert_cells(0, 1, 2, &CellAttributes::default());
+        assert_eq!(grid.row(0).text(), "a  bc");
+        grid.delete_cells(0, 0, 3, &CellAttributes::default());
+        assert_eq!(grid.row(0).text(), "bc");
+    }
+
+    #[test]
+    fn test_flags() {
+        let mut flags = CellFlags::BOLD | CellFlags::UNDERLINE;
+        assert!(flags.contains(CellFlags::BOLD));
+        flags.remove(CellFlags::BOLD);
+        assert!(!flags.contains(CellFlags::BOLD));
+        assert!(flags.contains(CellFlags::UNDERLINE));
+    }
+}
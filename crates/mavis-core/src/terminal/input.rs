@@ -0,0 +1,433 @@
+// Keyboard and mouse input encoding
+//
+// Maps key presses to the bytes an xterm sends for them, honoring the modes
+// that change those bytes: application cursor keys (DECCKM), application
+// keypad (DECKPAM), line feed/new line (LNM) and bracketed paste. Mouse
+// events are reported in the X10 or SGR (1006) encoding when the program
+// has enabled mouse tracking. The GUI translates its window events into
+// these types; nothing here needs a window.
+
+use super::{MouseTracking, TerminalModes};
+use std::ops::{BitOr, BitOrAssign};
+
+/// Modifier keys held during a key press.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Hash)]
+pub struct Modifiers(u8);
+
+impl Modifiers {
+    pub const SHIFT: Modifiers = Modifiers(1 << 0);
+    pub const ALT: Modifiers = Modifiers(1 << 1);
+    pub const CTRL: Modifiers = Modifiers(1 << 2);
+
+    pub const fn empty() -> Self {
+        Modifiers(0)
+    }
+
+    pub fn contains(self, other: Modifiers) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn insert(&mut self, other: Modifiers) {
+        self.0 |= other.0;
+    }
+
+    pub fn remove(&mut self, other: Modifiers) {
+        self.0 &= !other.0;
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// The xterm modifier parameter: 1 + shift(1) + alt(2) + ctrl(4).
+    fn parameter(self) -> u8 {
+        1 + self.0
+    }
+}
+
+impl BitOr for Modifiers {
+    type Output = Modifiers;
+
+    fn bitor(self, rhs: Modifiers) -> Modifiers {
+        Modifiers(self.0 | rhs.0)
+    }
+}
+
+impl BitOrAssign for Modifiers {
+    fn bitor_assign(&mut self, rhs: Modifiers) {
+        self.0 |= rhs.0;
+    }
+}
+
+/// A key press, independent of the windowing library.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Key {
+    /// A printable character, already shifted by the keyboard layout.
+    Char(char),
+    Enter,
+    Tab,
+    Backspace,
+    Escape,
+    Up,
+    Down,
+    Left,
+    Right,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+    Insert,
+    Delete,
+    /// Function keys F1 to F12.
+    F(u8),
+    /// Numeric keypad: `'0'..='9'`, `.`, `+`, `-`, `*`, `/`, `=` and `'\r'`
+    /// for its Enter key.
+    Keypad(char),
+}
+
+/// A mouse button, with the wheel counted as two buttons as xterm does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MouseButton {
+    Left,
+    Middle,
+    Right,
+    WheelUp,
+    WheelDown,
+}
+
+/// What happened to the mouse.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MouseAction {
+    Press,
+    Release,
+    /// The pointer moved to another cell.
+    Motion,
+}
+
+/// A mouse event over the grid, in viewport cells.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MouseEvent {
+    pub action: MouseAction,
+    /// The button pressed or released, or held during motion.
+    pub button: Option<MouseButton>,
+    pub col: usize,
+    pub row: usize,
+    pub mods: Modifiers,
+}
+
+/// Bytes to send to the child for a key press, or `None` for keys that send
+/// nothing (e.g. F13).
+pub fn encode_key(key: Key, mods: Modifiers, modes: &TerminalModes) -> Option<Vec<u8>> {
+    let bytes = match key {
+        Key::Char(c) => return Some(encode_char(c, mods)),
+        Key::Enter => with_alt(if modes.linefeed_newline { b"\r\n" } else { b"\r" }, mods),
+        Key::Tab if mods.contains(Modifiers::SHIFT) => b"\x1b[Z".to_vec(),
+        Key::Tab => with_alt(b"\t", mods),
+        // DEL, or BS with Ctrl as most terminals do
+        Key::Backspace if mods.contains(Modifiers::CTRL) => with_alt(b"\x08", mods),
+        Key::Backspace => with_alt(b"\x7f", mods),
+        Key::Escape => with_alt(b"\x1b", mods),
+        Key::Up => cursor_key(b'A', mods, modes),
+        Key::Down => cursor_key(b'B', mods, modes),
+        Key::Right => cursor_key(b'C', mods, modes),
+        Key::Left => cursor_key(b'D', mods, modes),
+        Key::Home => cursor_key(b'H', mods, modes),
+        Key::End => cursor_key(b'F', mods, modes),
+        Key::Insert => tilde_key(2, mods),
+        Key::Delete => tilde_key(3, mods),
+        Key::PageUp => tilde_key(5, mods),
+        Key::PageDown => tilde_key(6, mods),
+        Key::F(n @ 1..=4) => ss3_key(b'P' + (n - 1), mods),
+        Key::F(n @ 5..=12) => {
+            // F5..F12 skip 16 and 22, a leftover of the VT220 layout
+            const CODES: [u8; 8] = [15, 17, 18, 19, 20, 21, 23, 24];
+            tilde_key(CODES[n as usize - 5], mods)
+        }
+        Key::F(_) => return None,
+        Key::Keypad(c) => return keypad_key(c, mods, modes),
+    };
+    Some(bytes)
+}
+
+/// Bytes to send for pasted text. Line breaks become carriage returns, as
+/// if typed; in bracketed paste mode the text is wrapped in `ESC [200~` and
+/// `ESC [201~` so the program can tell it from typing.
+pub fn encode_paste(text: &str, modes: &TerminalModes) -> Vec<u8> {
+    let text = text.replace("\r\n", "\r").replace('\n', "\r");
+    if !modes.bracketed_paste {
+        return text.into_bytes();
+    }
+
+    // An end marker inside the text would let it escape the bracket
+    let text = text.replace("\x1b[201~", "");
+    let mut bytes = Vec::with_capacity(text.len() + 12);
+    bytes.extend_from_slice(b"\x1b[200~");
+    bytes.extend_from_slice(text.as_bytes());
+    bytes.extend_from_slice(b"\x1b[201~");
+    bytes
+}
+
+/// Bytes reporting a mouse event, or `None` when the current tracking mode
+/// does not report it (or it cannot be encoded).
+pub fn encode_mouse(event: &MouseEvent, modes: &TerminalModes) -> Option<Vec<u8>> {
+    let wheel = matches!(event.button, Some(MouseButton::WheelUp | MouseButton::WheelDown));
+    let reported = match (modes.mouse_tracking, event.action) {
+        (MouseTracking::Off, _) => false,
+        (MouseTracking::X10, action) => action == MouseAction::Press,
+        // The wheel has no release
+        (_, MouseAction::Release) => !wheel,
+        (_, MouseAction::Press) => true,
+        (MouseTracking::ButtonEvent, MouseAction::Motion) => event.button.is_some(),
+        (MouseTracking::AnyEvent, MouseAction::Motion) => true,
+        (MouseTracking::Normal, MouseAction::Motion) => false,
+    };
+    if !reported {
+        return None;
+    }
+
+    let mut code = match event.button {
+        Some(MouseButton::Left) => 0,
+        Some(MouseButton::Middle) => 1,
+        Some(MouseButton::Right) => 2,
+        Some(MouseButton::WheelUp) => 64,
+        Some(MouseButton::WheelDown) => 65,
+        None => 3,
+    };
+    if event.action == MouseAction::Motion {
+        code += 32;
+    }
+    // X10 mode reports no modifiers
+    if modes.mouse_tracking != MouseTracking::X10 {
+        if event.mods.contains(Modifiers::SHIFT) {
+            code += 4;
+        }
+        if event.mods.contains(Modifiers::ALT) {
+            code += 8;
+        }
+        if event.mods.contains(Modifiers::CTRL) {
+            code += 16;
+        }
+    }
+    let (col, row) = (event.col + 1, event.row + 1);
+
+    if modes.sgr_mouse {
+        let final_byte = if event.action == MouseAction::Release { 'm' } else { 'M' };
+        return Some(format!("\x1b[<{};{};{}{}", code, col, row, final_byte).into_bytes());
+    }
+
+    // The legacy encoding has no release button and one byte per coordinate
+    if event.action == MouseAction::Release {
+        code = (code & !0b11) | 3;
+    }
+    if col > 223 || row > 223 {
+        return None;
+    }
+    Some(vec![0x1b, b'[', b'M', 32 + code, 32 + col as u8, 32 + row as u8])
+}
+
+fn encode_char(c: char, mods: Modifiers) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(5);
+    if mods.contains(Modifiers::ALT) {
+        bytes.push(0x1b);
+    }
+    match control_code(c).filter(|_| mods.contains(Modifiers::CTRL)) {
+        Some(code) => bytes.push(code),
+        None => bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes()),
+    }
+    bytes
+}
+
+/// The C0 control a key produces with Ctrl held, following xterm: letters
+/// map to 0x01..0x1a, and `@ [ \ ] ^ _` plus the digits 2..8 cover the rest.
+fn control_code(c: char) -> Option<u8> {
+    match c {
+        'a'..='z' => Some(c as u8 - b'a' + 1),
+        'A'..='Z' => Some(c as u8 - b'A' + 1),
+        '@' | ' ' | '2' => Some(0x00),
+        '[' | '3' => Some(0x1b),
+        '\\' | '4' => Some(0x1c),
+        ']' | '5' => Some(0x1d),
+        '^' | '6' => Some(0x1e),
+        '_' | '-' | '/' | '7' => Some(0x1f),
+        '?' | '8' => Some(0x7f),
+        _ => None,
+    }
+}
+
+fn with_alt(bytes: &[u8], mods: Modifiers) -> Vec<u8> {
+    let mut out = Vec::with_capacity(bytes.len() + 1);
+    if mods.contains(Modifiers::ALT) {
+        out.push(0x1b);
+    }
+    out.extend_from_slice(bytes);
+    out
+}
+
+/// Arrows, Home and End: `CSI x`, or `SS3 x` in application cursor mode.
+/// With modifiers they are always `CSI 1 ; m x`.
+fn cursor_key(final_byte: u8, mods: Modifiers, modes: &TerminalModes) -> Vec<u8> {
+    if !mods.is_empty() {
+        return format!("\x1b[1;{}{}", mods.parameter(), final_byte as char).into_bytes();
+    }
+    let introducer = if modes.application_cursor_keys { b'O' } else { b'[' };
+    vec![0x1b, introducer, final_byte]
+}
+
+/// F1..F4: `SS3 x`, or `CSI 1 ; m x` with modifiers.
+fn ss3_key(final_byte: u8, mods: Modifiers) -> Vec<u8> {
+    if !mods.is_empty() {
+        return format!("\x1b[1;{}{}", mods.parameter(), final_byte as char).into_bytes();
+    }
+    vec![0x1b, b'O', final_byte]
+}
+
+/// Editing and function keys: `CSI n ~`, or `CSI n ; m ~` with modifiers.
+fn tilde_key(code: u8, mods: Modifiers) -> Vec<u8> {
+    if mods.is_empty() {
+        format!("\x1b[{}~", code).into_bytes()
+    } else {
+        format!("\x1b[{};{}~", code, mods.parameter()).into_bytes()
+    }
+}
+
+/// Keypad keys send their character unless application keypad mode asks
+/// for `SS3` sequences.
+fn keypad_key(c: char, mods: Modifiers, modes: &TerminalModes) -> Option<Vec<u8>> {
+    let application = match c {
+        '0'..='9' => c as u8 - b'0' + b'p',
+        '.' => b'n',
+        '+' => b'k',
+        '-' => b'm',
+        '*' => b'j',
+        '/' => b'o',
+        '=' => b'X',
+        '\r' => b'M',
+        _ => return None,
+    };
+    if modes.application_keypad {
+        Some(vec![0x1b, b'O', application])
+    } else if c == '\r' {
+        encode_key(Key::Enter, mods, modes)
+    } else {
+        Some(encode_char(c, mods))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(key: Key, mods: Modifiers) -> Vec<u8> {
+        encode_key(key, mods, &TerminalModes::default()).unwrap()
+    }
+
+    #[test]
+    fn test_characters_and_control_keys() {
+        let none = Modifiers::empty();
+        assert_eq!(encode(Key::Char('a'), none), b"a");
+        assert_eq!(encode(Key::Char('é'), none), "é".as_bytes());
+        assert_eq!(encode(Key::Char('c'), Modifiers::CTRL), b"\x03");
+        assert_eq!(encode(Key::Char('C'), Modifiers::CTRL | Modifiers::SHIFT), b"\x03");
+        assert_eq!(encode(Key::Char(' '), Modifiers::CTRL), b"\x00");
+        assert_eq!(encode(Key::Char('['), Modifiers::CTRL), b"\x1b");
+        assert_eq!(encode(Key::Char('b'), Modifiers::ALT), b"\x1bb");
+        assert_eq!(encode(Key::Char('x'), Modifiers::ALT | Modifiers::CTRL), b"\x1b\x18");
+        assert_eq!(encode(Key::Enter, none), b"\r");
+        assert_eq!(encode(Key::Tab, Modifiers::SHIFT), b"\x1b[Z");
+        assert_eq!(encode(Key::Backspace, none), b"\x7f");
+        assert_eq!(encode(Key::Backspace, Modifiers::CTRL), b"\x08");
+        assert_eq!(encode(Key::Escape, none), b"\x1b");
+    }
+
+    #[test]
+    fn test_cursor_and_editing_keys() {
+        let none = Modifiers::empty();
+        let mut modes = TerminalModes::default();
+        assert_eq!(encode(Key::Up, none), b"\x1b[A");
+        assert_eq!(encode(Key::Home, none), b"\x1b[H");
+        assert_eq!(encode(Key::Right, Modifiers::CTRL), b"\x1b[1;5C");
+        assert_eq!(encode(Key::End, Modifiers::SHIFT | Modifiers::ALT), b"\x1b[1;4F");
+        assert_eq!(encode(Key::PageUp, none), b"\x1b[5~");
+        assert_eq!(encode(Key::Delete, Modifiers::SHIFT), b"\x1b[3;2~");
+
+        modes.application_cursor_keys = true;
+        assert_eq!(encode_key(Key::Down, none, &modes).unwrap(), b"\x1bOB");
+        assert_eq!(encode_key(Key::Left, Modifiers::CTRL, &modes).unwrap(), b"\x1b[1;5D");
+    }
+
+    #[test]
+    fn test_function_and_keypad_keys() {
+        let none = Modifiers::empty();
+        assert_eq!(encode(Key::F(1), none), b"\x1bOP");
+        assert_eq!(encode(Key::F(4), Modifiers::SHIFT), b"\x1b[1;2S");
+        assert_eq!(encode(Key::F(5), none), b"\x1b[15~");
+        assert_eq!(encode(Key::F(6), none), b"\x1b[17~");
+        assert_eq!(encode(Key::F(12), Modifiers::CTRL), b"\x1b[24;5~");
+        assert!(encode_key(Key::F(13), none, &TerminalModes::default()).is_none());
+
+        let mut modes = TerminalModes::default();
+        assert_eq!(encode_key(Key::Keypad('7'), none, &modes).unwrap(), b"7");
+        assert_eq!(encode_key(Key::Keypad('\r'), none, &modes).unwrap(), b"\r");
+        modes.application_keypad = true;
+        assert_eq!(encode_key(Key::Keypad('7'), none, &modes).unwrap(), b"\x1bOw");
+        assert_eq!(encode_key(Key::Keypad('\r'), none, &modes).unwrap(), b"\x1bOM");
+    }
+
+    #[test]
+    fn test_mouse_reports() {
+        let press = MouseEvent {
+            action: MouseAction::Press,
+            button: Some(MouseButton::Left),
+            col: 2,
+            row: 4,
+            mods: Modifiers::CTRL,
+        };
+        let release = MouseEvent {
+            action: MouseAction::Release,
+            ..press
+        };
+        let drag = MouseEvent {
+            action: MouseAction::Motion,
+            ..press
+        };
+        let hover = MouseEvent { button: None, ..drag };
+
+        let mut modes = TerminalModes::default();
+        assert!(encode_mouse(&press, &modes).is_none());
+
+        modes.mouse_tracking = MouseTracking::X10;
+        assert_eq!(encode_mouse(&press, &modes).unwrap(), b"\x1b[M\x20\x23\x25");
+        assert!(encode_mouse(&release, &modes).is_none());
+
+        modes.mouse_tracking = MouseTracking::Normal;
+        assert_eq!(encode_mouse(&press, &modes).unwrap(), b"\x1b[M\x30\x23\x25");
+        assert_eq!(encode_mouse(&release, &modes).unwrap(), b"\x1b[M\x33\x23\x25");
+        assert!(encode_mouse(&drag, &modes).is_none());
+
+        modes.mouse_tracking = MouseTracking::ButtonEvent;
+        modes.sgr_mouse = true;
+        assert_eq!(encode_mouse(&press, &modes).unwrap(), b"\x1b[<16;3;5M");
+        assert_eq!(encode_mouse(&release, &modes).unwrap(), b"\x1b[<16;3;5m");
+        assert_eq!(encode_mouse(&drag, &modes).unwrap(), b"\x1b[<48;3;5M");
+        assert!(encode_mouse(&hover, &modes).is_none());
+
+        modes.mouse_tracking = MouseTracking::AnyEvent;
+        assert_eq!(encode_mouse(&hover, &modes).unwrap(), b"\x1b[<51;3;5M");
+        let wheel = MouseEvent {
+            button: Some(MouseButton::WheelDown),
+            mods: Modifiers::empty(),
+            ..press
+        };
+        assert_eq!(encode_mouse(&wheel, &modes).unwrap(), b"\x1b[<65;3;5M");
+    }
+
+    #[test]
+    fn test_paste() {
+        let mut modes = TerminalModes::default();
+        assert_eq!(encode_paste("ls\r\necho\n", &modes), b"ls\recho\r");
+
+        modes.bracketed_paste = true;
+        assert_eq!(encode_paste("a\nb", &modes), b"\x1b[200~a\rb\x1b[201~");
+        assert_eq!(encode_paste("x\x1b[201~rm -rf /", &modes), b"\x1b[200~xrm -rf /\x1b[201~");
+    }
+}
@@ -0,0 +1,352 @@
+// Hyperlinks in terminal output
+//
+// Links come from two places: OSC 8 sequences, stored on the cells they
+// were printed under, and rules matching text such as URLs or compiler
+// style `path:line:col` locations. Rules run on the logical line under the
+// pointer, so a link that soft-wraps is still found whole.
+
+use super::grid::CellFlags;
+use super::screen::Screen;
+use super::search::LogicalLine;
+use super::selection::Point;
+use regex::Regex;
+
+/// Web addresses, stopping before trailing punctuation.
+const URL_PATTERN: &str = r#"(?:https?|ftp|file)://[^\s<>"'`]*[^\s<>"'`.,;:!?)\]]"#;
+
+/// `path:line` or `path:line:col`, where the path has a file extension.
+const FILE_PATTERN: &str =
+    r"(?P<path>(?:[A-Za-z]:)?[\w.\-~/\\]*[\w\-]\.[A-Za-z0-9]+):(?P<line>\d+)(?::(?P<col>\d+))?";
+
+/// What a rule's matches point to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkKind {
+    Url,
+    /// A file location. The `path`, `line` and `col` capture groups give
+    /// its parts; without a `path` group the whole match is the path.
+    File,
+}
+
+impl LinkKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LinkKind::Url => "url",
+            LinkKind::File => "file",
+        }
+    }
+
+    pub fn parse(kind: &str) -> Option<Self> {
+        match kind {
+            "url" => Some(LinkKind::Url),
+            "file" => Some(LinkKind::File),
+            _ => None,
+        }
+    }
+}
+
+/// Where activating a link leads.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LinkTarget {
+    Url(String),
+    File {
+        path: String,
+        line: Option<u32>,
+        column: Option<u32>,
+    },
+}
+
+/// A link found in the output, from its first to its last cell (inclusive).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Link {
+    pub start: Point,
+    pub end: Point,
+    pub text: String,
+    pub target: LinkTarget,
+}
+
+impl Link {
+    /// Whether the cell at `line`, `col` is part of the link.
+    pub fn contains(&self, line: usize, col: usize) -> bool {
+        let point = Point::new(line, col);
+        point >= self.start && point <= self.end
+    }
+}
+
+/// A named pattern recognising links in plain text.
+#[derive(Debug, Clone)]
+pub struct LinkRule {
+    name: String,
+    kind: LinkKind,
+    pattern: Regex,
+}
+
+impl LinkRule {
+    /// Compile `pattern`. Fails for an invalid regular expression.
+    pub fn new(name: &str, kind: LinkKind, pattern: &str) -> Result<Self, regex::Error> {
+        Ok(Self {
+            name: name.to_string(),
+            kind,
+            pattern: Regex::new(pattern)?,
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn kind(&self) -> LinkKind {
+        self.kind
+    }
+
+    pub fn pattern(&self) -> &str {
+        self.pattern.as_str()
+    }
+
+    fn target(&self, captures: &regex::Captures) -> LinkTarget {
+        let whole = captures.get(0).map_or("", |m| m.as_str());
+        match self.kind {
+            LinkKind::Url => LinkTarget::Url(whole.to_string()),
+            LinkKind::File => {
+                let number = |group: &str| captures.name(group).and_then(|m| m.as_str().parse().ok());
+                LinkTarget::File {
+                    path: captures.name("path").map_or(whole, |m| m.as_str()).to_string(),
+                    line: number("line"),
+                    column: number("col"),
+                }
+            }
+        }
+    }
+}
+
+/// Finds the link under a cell, from OSC 8 attributes first and then the
+/// rules, in order.
+#[derive(Debug, Clone)]
+pub struct LinkDetector {
+    rules: Vec<LinkRule>,
+}
+
+impl Default for LinkDetector {
+    fn default() -> Self {
+        Self {
+            rules: vec![
+                LinkRule::new("url", LinkKind::Url, URL_PATTERN).expect("valid URL pattern"),
+                LinkRule::new("file", LinkKind::File, FILE_PATTERN).expect("valid file pattern"),
+            ],
+        }
+    }
+}
+
+impl LinkDetector {
+    /// A detector that only follows OSC 8 links.
+    pub fn empty() -> Self {
+        Self { rules: Vec::new() }
+    }
+
+    pub fn rules(&self) -> &[LinkRule] {
+        &self.rules
+    }
+
+    /// Add `rule`, replacing the rule of the same name if there is one.
+    pub fn add_rule(&mut self, rule: LinkRule) {
+        match self.rules.iter_mut().find(|r| r.name == rule.name) {
+            Some(existing) => *existing = rule,
+            None => self.rules.push(rule),
+        }
+    }
+
+    /// Remove the rule called `name`. Returns false if there was none.
+    pub fn remove_rule(&mut self, name: &str) -> bool {
+        let before = self.rules.len();
+        self.rules.retain(|r| r.name != name);
+        self.rules.len() != before
+    }
+
+    /// The link covering `point`, a cell addressed as in `Screen::line`.
+    pub fn link_at(&self, screen: &Screen, point: Point) -> Option<Link> {
+        let cell = screen.line(point.line)?.cells.get(point.col)?;
+        // The right half of a wide glyph belongs to the glyph
+        let point = if cell.attrs.flags.contains(CellFlags::WIDE_SPACER) && point.col > 0 {
+            Point::new(point.line, point.col - 1)
+        } else {
+            point
+        };
+
+        let logical = LogicalLine::containing(screen, point.line);
+        if let Some(link) = hyperlink_at(screen, &logical, point) {
+            return Some(link);
+        }
+
+        let offset = logical.offset(point)?;
+        self.rules.iter().find_map(|rule| {
+            rule.pattern.captures_iter(&logical.text).find_map(|captures| {
+                let found = captures.get(0)?;
+                if found.is_empty() || offset < found.start() || offset >= found.end() {
+                    return None;
+                }
+                let (start, end) = logical.span(screen, found.start(), found.end());
+                Some(Link {
+                    start,
+                    end,
+                    text: found.as_str().to_string(),
+                    target: rule.target(&captures),
+                })
+            })
+        })
+    }
+}
+
+/// The OSC 8 link at `point`, covering the neighbouring cells printed
+/// under the same link.
+fn hyperlink_at(screen: &Screen, logical: &LogicalLine, point: Point) -> Option<Link> {
+    let link_of = |p: Point| screen.line(p.line)?.cells.get(p.col)?.attrs.link;
+    let id = link_of(point)?;
+    let hyperlink = screen.hyperlink(id)?;
+
+    let cells = logical.cells();
+    let index = cells.iter().position(|&(_, p)| p == point)?;
+    let first = cells[..index]
+        .iter()
+        .rposition(|&(_, p)| link_of(p) != Some(id))
+        .map_or(0, |i| i + 1);
+    let last = cells[index..]
+        .iter()
+        .position(|&(_, p)| link_of(p) != Some(id))
+        .map_or(cells.len(), |i| index + i)
+        - 1;
+    let end = if last + 1 < cells.len() {
+        cells[last + 1].0
+    } else {
+        logical.text.len()
+    };
+    let (start, end_point) = logical.span(screen, cells[first].0, end);
+
+    Some(Link {
+        start,
+        end: end_point,
+        text: logical.text[cells[first].0..end].to_string(),
+        target: uri_target(&hyperlink.uri),
+    })
+}
+
+/// `file://` URIs open as files, anything else as a URL.
+fn uri_target(uri: &str) -> LinkTarget {
+    match file_uri_path(uri) {
+        Some(path) => LinkTarget::File {
+            path,
+            line: None,
+            column: None,
+        },
+        None => LinkTarget::Url(uri.to_string()),
+    }
+}
+
+/// The local path of a `file://host/path` URI, percent-decoded. Used for
+/// OSC 8 links and OSC 7 directory reports.
+pub(super) fn file_uri_path(uri: &str) -> Option<String> {
+    let rest = uri.strip_prefix("file://")?;
+    // Drop the host part
+    let path = rest.find('/').map_or(rest, |slash| &rest[slash..]);
+
+    let mut bytes = Vec::with_capacity(path.len());
+    let mut input = path.bytes();
+    while let Some(byte) = input.next() {
+        let hex = |b: Option<u8>| b.and_then(|b| (b as char).to_digit(16));
+        if byte == b'%'
+            && let Some(high) = hex(input.clone().next())
+            && let Some(low) = hex(input.clone().nth(1))
+        {
+            bytes.push((high * 16 + low) as u8);
+            input.nth(1);
+        } else {
+            bytes.push(byte);
+        }
+    }
+    let path = String::from_utf8_lossy(&bytes).into_owned();
+
+    // file:///C:/dir is the Windows path C:/dir
+    let drive = path.as_bytes();
+    if drive.len() >= 3 && drive[0] == b'/' && drive[1].is_ascii_alphabetic() && drive[2] == b':' {
+        return Some(path[1..].to_string());
+    }
+    Some(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terminal::terminal_with;
+
+    #[test]
+    fn test_osc8_links() {
+        let terminal = terminal_with(
+            30,
+            2,
+            b"see \x1b]8;id=a;https://example.com/docs\x1b\\the docs\x1b]8;;\x1b\\ now\r\n\x1b]8;;file:///C:/src/main.rs\x07main\x1b]8;;\x07",
+        );
+        let detector = LinkDetector::empty();
+        let screen = terminal.screen();
+
+        let link = detector.link_at(screen, Point::new(0, 6)).unwrap();
+        assert_eq!(link.text, "the docs");
+        assert_eq!((link.start, link.end), (Point::new(0, 4), Point::new(0, 11)));
+        assert_eq!(link.target, LinkTarget::Url("https://example.com/docs".into()));
+        assert!(detector.link_at(screen, Point::new(0, 13)).is_none());
+
+        let file = detector.link_at(screen, Point::new(1, 0)).unwrap();
+        assert_eq!(
+            file.target,
+            LinkTarget::File {
+                path: "C:/src/main.rs".into(),
+                line: None,
+                column: None
+            }
+        );
+    }
+
+    #[test]
+    fn test_detected_urls_and_locations() {
+        let terminal = terminal_with(12, 5, b"(https://a.io/x?q=1).\r\nat src/lib.rs:12:5");
+        let detector = LinkDetector::default();
+        let screen = terminal.screen();
+
+        // The URL wraps onto the second row and excludes the closing paren
+        let url = detector.link_at(screen, Point::new(1, 2)).unwrap();
+        assert_eq!(url.target, LinkTarget::Url("https://a.io/x?q=1".into()));
+        assert_eq!((url.start, url.end), (Point::new(0, 1), Point::new(1, 6)));
+        assert!(detector.link_at(screen, Point::new(1, 7)).is_none());
+
+        let location = detector.link_at(screen, Point::new(3, 0)).unwrap();
+        assert_eq!(location.text, "src/lib.rs:12:5");
+        assert_eq!(
+            location.target,
+            LinkTarget::File {
+                path: "src/lib.rs".into(),
+                line: Some(12),
+                column: Some(5)
+            }
+        );
+    }
+
+    #[test]
+    fn test_file_uri_paths() {
+        assert_eq!(file_uri_path("file://host/home/me/My%20Docs").unwrap(), "/home/me/My Docs");
+        assert_eq!(file_uri_path("file:///C:/Users/me").unwrap(), "C:/Users/me");
+        assert_eq!(file_uri_path("file:///tmp/100%").unwrap(), "/tmp/100%");
+        assert!(file_uri_path("https://example.com").is_none());
+    }
+
+    #[test]
+    fn test_rules_can_be_replaced() {
+        let terminal = terminal_with(20, 1, b"see JIRA-42 now");
+        let mut detector = LinkDetector::default();
+        assert!(detector.link_at(terminal.screen(), Point::new(0, 5)).is_none());
+
+        detector.add_rule(LinkRule::new("ticket", LinkKind::Url, r"JIRA-\d+").unwrap());
+        let link = detector.link_at(terminal.screen(), Point::new(0, 5)).unwrap();
+        assert_eq!(link.text, "JIRA-42");
+
+        assert!(detector.remove_rule("ticket"));
+        assert!(!detector.remove_rule("ticket"));
+        assert_eq!(detector.rules().len(), 2);
+    }
+}
@@ -0,0 +1,615 @@
+// Terminal emulation for MAVIS
+//
+// A headless VT100/xterm emulator: a byte stream from the PTY is tokenized by
+// `Parser` and applied to a `Screen` made of cell grids. Nothing here depends on
+// the GUI, so the emulator can be driven from tests on any platform.
+// `session` ties an emulator to a PTY child for each open terminal tab,
+// `input` encodes key presses and mouse reports for it, `selection` tracks
+// the text the user has selected and `search` finds text in the scrollback.
+// `recording` saves a session's output as an asciicast and plays it back.
+
+pub mod commands;
+pub mod grid;
+pub mod input;
+pub mod links;
+pub mod parser;
+pub mod recording;
+pub mod screen;
+pub mod scrollback;
+pub mod search;
+pub mod selection;
+pub mod session;
+pub mod utf8;
+
+pub use commands::{Command, CommandHistory};
+pub use grid::{Cell, CellAttributes, CellFlags, Color, Grid, HyperlinkId, Row};
+pub use input::{Key, Modifiers, MouseAction, MouseButton, MouseEvent};
+pub use links::{Link, LinkDetector, LinkKind, LinkRule, LinkTarget};
+pub use parser::{Params, Parser, Perform};
+pub use recording::{Player, Recorder, Recording};
+pub use screen::{Cursor, CursorShape, Hyperlink, MouseTracking, Screen, ShellEvent, ShellMark, TerminalModes};
+pub use scrollback::Scrollback;
+pub use search::{Search, SearchMatch, SearchOptions};
+pub use selection::{Point, Selection, SelectionMode, SelectionRange};
+pub use session::{ExitEvent, SessionId, SessionManager, SharedSessionManager, TerminalSession};
+pub use utf8::Utf8Decoder;
+
+use log::trace;
+
+/// A terminal emulator instance: parser plus screen state.
+#[derive(Debug)]
+pub struct Terminal {
+    decoder: Utf8Decoder,
+    parser: Parser,
+    screen: Screen,
+    selection: Option<Selection>,
+    search: Option<Search>,
+    // Output arrived since the search was last run, and how many lines the
+    // scrollback dropped meanwhile
+    search_dirty: bool,
+    search_evicted: usize,
+    commands: CommandHistory,
+}
+
+impl Terminal {
+    /// Create a terminal with the given dimensions in cells, keeping up to
+    /// `scrollback_lines` lines of history.
+    pub fn new(cols: usize, rows: usize, scrollback_lines: usize) -> Self {
+        Self {
+            decoder: Utf8Decoder::new(),
+            parser: Parser::new(),
+            screen: Screen::new(cols, rows, scrollback_lines),
+            selection: None,
+            search: None,
+            search_dirty: false,
+            search_evicted: 0,
+            commands: CommandHistory::new(),
+        }
+    }
+
+    /// Feed raw output from the child process. Reads may split multi-byte
+    /// characters; the incomplete tail is kept until the next call.
+    pub fn process(&mut self, data: &[u8]) {
+        let alternate = self.screen.modes().alternate_screen;
+        let evicted = self.screen.scrollback().evicted();
+
+        let (parser, screen) = (&mut self.parser, &mut self.screen);
+        self.decoder.decode(data, |c| parser.advance(screen, c));
+        trace!("Processed {} bytes into terminal", data.len());
+
+        // Keep the selection on the same text as lines leave the scrollback,
+        // whether evicted from a full one or cleared; drop it if its own
+        // lines went.
+        let shifted = self.screen.scrollback().evicted().wrapping_sub(evicted);
+        if let Some(selection) = &mut self.selection
+            && (alternate != self.screen.modes().alternate_screen || !selection.scroll_up(shifted))
+        {
+            self.selection = None;
+        }
+        // Searching is deferred until the matches are needed
+        if self.search.is_some() {
+            self.search_dirty = true;
+            self.search_evicted += shifted;
+        }
+
+        // Commands move up with the lines they are on, then take new marks
+        self.commands.scroll_up(shifted);
+        let evicted = self.screen.scrollback().evicted();
+        for event in self.screen.take_shell_events() {
+            let dropped = evicted.wrapping_sub(event.evicted);
+            if let Some(line) = event.point.line.checked_sub(dropped) {
+                let point = Point::new(line, event.point.col);
+                let cwd = self.screen.working_directory();
+                self.commands.apply(event.mark, point, &self.screen, cwd);
+            }
+        }
+    }
+
+    /// Resize the emulated screen, reflowing wrapped lines. Recorded
+    /// commands move with their text; the selection is cleared.
+    pub fn resize(&mut self, cols: usize, rows: usize) {
+        let mut points: Vec<&mut Point> = self.commands.points_mut().collect();
+        let dropped = self.screen.resize_tracking(cols, rows, &mut points);
+        self.commands.scroll_up(dropped);
+        self.selection = None;
+    }
+
+    pub fn screen(&self) -> &Screen {
+        &self.screen
+    }
+
+    pub fn screen_mut(&mut self) -> &mut Screen {
+        &mut self.screen
+    }
+
+    /// The visible grid.
+    pub fn grid(&self) -> &Grid {
+        self.screen.grid()
+    }
+
+    pub fn cursor(&self) -> &Cursor {
+        self.screen.cursor()
+    }
+
+    pub fn modes(&self) -> &TerminalModes {
+        self.screen.modes()
+    }
+
+    pub fn title(&self) -> &str {
+        self.screen.title()
+    }
+
+    pub fn cols(&self) -> usize {
+        self.screen.cols()
+    }
+
+    pub fn rows(&self) -> usize {
+        self.screen.rows()
+    }
+
+    /// Number of lines the view is scrolled back into history.
+    pub fn display_offset(&self) -> usize {
+        self.screen.display_offset()
+    }
+
+    /// Number of lines held in scrollback.
+    pub fn history_len(&self) -> usize {
+        self.screen.scrollback().len()
+    }
+
+    /// Scroll the view by `lines`; positive values move back into history.
+    pub fn scroll_lines(&mut self, lines: isize) {
+        self.screen.scroll_display(lines);
+    }
+
+    /// Scroll the view by whole pages; positive values move back into history.
+    pub fn scroll_pages(&mut self, pages: isize) {
+        self.screen.scroll_page(pages);
+    }
+
+    pub fn scroll_to_bottom(&mut self) {
+        self.screen.scroll_to_bottom();
+    }
+
+    /// Line index (as in `Screen::line`) shown at viewport row `row`.
+    pub fn viewport_line(&self, row: usize) -> usize {
+        self.screen.viewport_line(row)
+    }
+
+    /// Start a new selection at viewport cell `col`, `row`.
+    pub fn start_selection(&mut self, mode: SelectionMode, col: usize, row: usize) {
+        let point = Point::new(self.viewport_line(row), col);
+        self.selection = Some(Selection::new(mode, point));
+    }
+
+    /// Extend the selection to viewport cell `col`, `row`.
+    pub fn update_selection(&mut self, col: usize, row: usize) {
+        let point = Point::new(self.viewport_line(row), col);
+        if let Some(selection) = &mut self.selection {
+            selection.update(point);
+        }
+    }
+
+    pub fn clear_selection(&mut self) {
+        self.selection = None;
+    }
+
+    pub fn selection(&self) -> Option<&Selection> {
+        self.selection.as_ref()
+    }
+
+    /// The selected cells, for highlighting.
+    pub fn selection_range(&self) -> Option<SelectionRange> {
+        self.selection.as_ref()?.range(&self.screen)
+    }
+
+    /// The selected text, for copying to the clipboard.
+    pub fn selection_text(&self) -> Option<String> {
+        self.selection.as_ref()?.text(&self.screen)
+    }
+
+    /// The link at viewport cell `col`, `row`, if any.
+    pub fn link_at(&self, detector: &LinkDetector, col: usize, row: usize) -> Option<Link> {
+        detector.link_at(&self.screen, Point::new(self.viewport_line(row), col))
+    }
+
+    /// Commands recorded from shell integration marks.
+    pub fn commands(&self) -> &CommandHistory {
+        &self.commands
+    }
+
+    /// Current directory of the shell, if it reports it (OSC 7).
+    pub fn working_directory(&self) -> Option<&str> {
+        self.screen.working_directory()
+    }
+
+    /// Scroll the prompt above the top of the view to the top. Returns
+    /// false if there is none, or the alternate screen is shown.
+    pub fn scroll_to_previous_prompt(&mut self) -> bool {
+        if self.modes().alternate_screen {
+            return false;
+        }
+        let Some(line) = self.commands.previous_prompt(self.viewport_line(0)).map(|c| c.prompt.line) else {
+            return false;
+        };
+        self.screen.scroll_to_line(line);
+        true
+    }
+
+    /// Scroll the prompt below the top of the view to the top, or as far
+    /// as the live screen allows. Returns false if there is none.
+    pub fn scroll_to_next_prompt(&mut self) -> bool {
+        if self.modes().alternate_screen {
+            return false;
+        }
+        let Some(line) = self.commands.next_prompt(self.viewport_line(0)).map(|c| c.prompt.line) else {
+            return false;
+        };
+        self.screen.scroll_to_line(line);
+        true
+    }
+
+    /// Output of the last finished command, for copying.
+    pub fn last_output_text(&self) -> Option<String> {
+        if self.modes().alternate_screen {
+            return None;
+        }
+        self.commands.last_finished()?.output_text(&self.screen)
+    }
+
+    /// Search the scrollback and screen for `query`, highlighting every
+    /// match. Returns the number of matches.
+    pub fn search(&mut self, query: &str, options: SearchOptions) -> Result<usize, regex::Error> {
+        let mut search = Search::new(query, options)?;
+        search.update(&self.screen, 0);
+        let count = search.matches().len();
+        self.search = Some(search);
+        self.search_dirty = false;
+        self.search_evicted = 0;
+        Ok(count)
+    }
+
+    pub fn clear_search(&mut self) {
+        self.search = None;
+    }
+
+    /// The active search, as of the last `update_search`.
+    pub fn active_search(&self) -> Option<&Search> {
+        self.search.as_ref()
+    }
+
+    /// Run the active search again if output arrived since it last ran.
+    pub fn update_search(&mut self) {
+        if !self.search_dirty {
+            return;
+        }
+        if let Some(search) = &mut self.search {
+            search.update(&self.screen, self.search_evicted);
+        }
+        self.search_dirty = false;
+        self.search_evicted = 0;
+    }
+
+    /// Go to the next match towards newer output, scrolling it into view.
+    pub fn search_next(&mut self) -> Option<SearchMatch> {
+        self.update_search();
+        let found = self.search.as_mut()?.next_match()?;
+        self.reveal_line(found.start.line);
+        Some(found)
+    }
+
+    /// Go to the previous match towards older output, scrolling it into view.
+    pub fn search_previous(&mut self) -> Option<SearchMatch> {
+        self.update_search();
+        let found = self.search.as_mut()?.previous_match()?;
+        self.reveal_line(found.start.line);
+        Some(found)
+    }
+
+    /// Scroll so `line` is visible, centring it if it was not.
+    fn reveal_line(&mut self, line: usize) {
+        let top = self.viewport_line(0);
+        if line < top || line >= top + self.rows() {
+            self.screen.scroll_to_line(line.saturating_sub(self.rows() / 2));
+        }
+    }
+
+    /// Drain replies to device queries that must be written back to the PTY.
+    pub fn take_responses(&mut self) -> Vec<u8> {
+        self.screen.take_responses()
+    }
+
+    /// Bytes the child expects for a key press in the current modes.
+    pub fn encode_key(&self, key: Key, mods: Modifiers) -> Option<Vec<u8>> {
+        input::encode_key(key, mods, self.modes())
+    }
+
+    /// Bytes reporting a mouse event, if the program asked for it.
+    pub fn encode_mouse(&self, event: &MouseEvent) -> Option<Vec<u8>> {
+        input::encode_mouse(event, self.modes())
+    }
+
+    /// Whether the program wants mouse events instead of local selection.
+    pub fn mouse_reporting(&self) -> bool {
+        self.modes().mouse_tracking != MouseTracking::Off
+    }
+
+    /// Bytes the child expects for pasted text in the current modes.
+    pub fn encode_paste(&self, text: &str) -> Vec<u8> {
+        input::encode_paste(text, self.modes())
+    }
+}
+
+/// A terminal with room for 100 lines of scrollback that has processed
+/// `input`.
+#[cfg(test)]
+pub(crate) fn terminal_with(cols: usize, rows: usize, input: &[u8]) -> Terminal {
+    let mut terminal = Terminal::new(cols, rows, 100);
+    terminal.process(input);
+    terminal
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(terminal: &Terminal) -> Vec<String> {
+        terminal.grid().iter_rows().map(Row::text).collect()
+    }
+
+    #[test]
+    fn test_plain_output_and_newlines() {
+        let terminal = terminal_with(10, 3, b"hello\r\nworld");
+        assert_eq!(lines(&terminal), vec!["hello", "world", ""]);
+        assert_eq!((terminal.cursor().row, terminal.cursor().col), (1, 5));
+    }
+
+    #[test]
+    fn test_autowrap_and_scroll() {
+        let terminal = terminal_with(4, 2, b"abcdefghij");
+        assert_eq!(lines(&terminal), vec!["efgh", "ij"]);
+        assert!(terminal.grid().row(0).wrapped);
+    }
+
+    #[test]
+    fn test_cursor_movement_and_erase() {
+        let terminal = terminal_with(10, 3, b"1234567890\x1b[1;4H\x1b[K\x1b[3;2HX\x1b[2A\x1b[DY");
+        assert_eq!(lines(&terminal), vec!["1Y3", "", " X"]);
+    }
+
+    #[test]
+    fn test_erase_display() {
+        let terminal = terminal_with(5, 3, b"aaaaabbbbbccccc\x1b[2;3H\x1b[J");
+        assert_eq!(lines(&terminal), vec!["aaaaa", "bb", ""]);
+        let terminal = terminal_with(5, 3, b"aaaaabbbbbccccc\x1b[2;3H\x1b[1J");
+        assert_eq!(lines(&terminal), vec!["", "   bb", "ccccc"]);
+    }
+
+    #[test]
+    fn test_sgr_colors() {
+        let terminal = terminal_with(10, 1, b"\x1b[1;31mA\x1b[38;5;200mB\x1b[38;2;1;2;3;48:2::4:5:6mC\x1b[0mD");
+        let grid = terminal.grid();
+        assert_eq!(grid.cell(0, 0).attrs.fg, Color::Indexed(1));
+        assert!(grid.cell(0, 0).attrs.flags.contains(CellFlags::BOLD));
+        assert_eq!(grid.cell(0, 1).attrs.fg, Color::Indexed(200));
+        assert_eq!(grid.cell(0, 2).attrs.fg, Color::Rgb(1, 2, 3));
+        assert_eq!(grid.cell(0, 2).attrs.bg, Color::Rgb(4, 5, 6));
+        assert_eq!(grid.cell(0, 3).attrs, CellAttributes::default());
+    }
+
+    #[test]
+    fn test_scroll_region() {
+        let mut terminal = terminal_with(5, 4, b"one\r\ntwo\r\nthree\r\nfour");
+        terminal.process(b"\x1b[2;3r\x1b[3;1H\nnew");
+        assert_eq!(lines(&terminal), vec!["one", "three", "new", "four"]);
+    }
+
+    #[test]
+    fn test_alternate_screen_restores_primary() {
+        let mut terminal = terminal_with(10, 2, b"shell$ ");
+        terminal.process(b"\x1b[?1049h\x1b[Hfullscreen");
+        assert!(terminal.modes().alternate_screen);
+        assert_eq!(lines(&terminal), vec!["fullscreen", ""]);
+        terminal.process(b"\x1b[?1049l");
+        assert!(!terminal.modes().alternate_screen);
+        assert_eq!(lines(&terminal), vec!["shell$", ""]);
+        assert_eq!(terminal.cursor().col, 7);
+    }
+
+    #[test]
+    fn test_decset_modes() {
+        let terminal = terminal_with(10, 2, b"\x1b[?1h\x1b[?2004h\x1b[?25l\x1b[?1002h\x1b[?1006h");
+        let modes = terminal.modes();
+        assert!(modes.application_cursor_keys);
+        assert!(modes.bracketed_paste);
+        assert!(!modes.show_cursor);
+        assert_eq!(modes.mouse_tracking, MouseTracking::ButtonEvent);
+        assert!(modes.sgr_mouse);
+    }
+
+    #[test]
+    fn test_insert_delete_lines_and_chars() {
+        let mut terminal = terminal_with(5, 3, b"aaa\r\nbbb\r\nccc");
+        terminal.process(b"\x1b[2;1H\x1b[L");
+        assert_eq!(lines(&terminal), vec!["aaa", "", "bbb"]);
+        terminal.process(b"\x1b[1;1H\x1b[2P");
+        assert_eq!(lines(&terminal), vec!["a", "", "bbb"]);
+        terminal.process(b"\x1b[3;1H\x1b[M");
+        assert_eq!(lines(&terminal), vec!["a", "", ""]);
+    }
+
+    #[test]
+    fn test_device_status_report() {
+        let mut terminal = terminal_with(10, 5, b"\x1b[3;4H\x1b[6n\x1b[c");
+        assert_eq!(terminal.take_responses(), b"\x1b[3;4R\x1b[?62;22c".to_vec());
+        assert!(terminal.take_responses().is_empty());
+    }
+
+    #[test]
+    fn test_device_status_report_above_restored_origin() {
+        // DECSC in origin mode, then a scroll region below the saved row
+        let mut terminal = terminal_with(10, 5, b"\x1b[?6h\x1b7\x1b[3;5r\x1b8\x1b[6n");
+        assert_eq!(terminal.take_responses(), b"\x1b[1;1R".to_vec());
+    }
+
+    #[test]
+    fn test_title_and_line_drawing() {
+        let terminal = terminal_with(5, 1, b"\x1b]0;my title\x07\x1b(0lqk\x1b(B");
+        assert_eq!(terminal.title(), "my title");
+        assert_eq!(lines(&terminal), vec!["┌─┐"]);
+    }
+
+    #[test]
+    fn test_resize_clamps_cursor() {
+        let mut terminal = terminal_with(10, 5, b"\x1b[5;10H");
+        terminal.resize(4, 2);
+        assert_eq!((terminal.cursor().row, terminal.cursor().col), (1, 3));
+        assert_eq!((terminal.cols(), terminal.rows()), (4, 2));
+    }
+
+    #[test]
+    fn test_scrollback_is_bounded() {
+        let mut terminal = Terminal::new(10, 2, 3);
+        terminal.process(b"1\r\n2\r\n3\r\n4\r\n5\r\n6");
+        assert_eq!(terminal.history_len(), 3);
+        let history: Vec<String> = terminal.screen().scrollback().iter().map(Row::text).collect();
+        assert_eq!(history, vec!["2", "3", "4"]);
+        assert_eq!(lines(&terminal), vec!["5", "6"]);
+    }
+
+    #[test]
+    fn test_alternate_screen_does_not_fill_scrollback() {
+        let mut terminal = terminal_with(10, 2, b"\x1b[?1049h1\r\n2\r\n3\r\n4");
+        assert_eq!(terminal.history_len(), 0);
+        terminal.process(b"\x1b[?1049l\x1b[3J");
+        assert_eq!(terminal.history_len(), 0);
+    }
+
+    #[test]
+    fn test_viewport_scrolling() {
+        let mut terminal = terminal_with(10, 2, b"a\r\nb\r\nc\r\nd\r\ne");
+        terminal.scroll_lines(1);
+        let visible: Vec<String> = (0..2).map(|i| terminal.screen().visible_row(i).text()).collect();
+        assert_eq!(visible, vec!["c", "d"]);
+
+        // New output keeps the scrolled view on the same lines
+        terminal.process(b"\r\nf");
+        assert_eq!(terminal.display_offset(), 2);
+        assert_eq!(terminal.screen().visible_row(0).text(), "c");
+
+        terminal.scroll_pages(10);
+        assert_eq!(terminal.display_offset(), terminal.history_len());
+        assert_eq!(terminal.screen().visible_row(0).text(), "a");
+        terminal.scroll_to_bottom();
+        assert_eq!(terminal.screen().visible_row(1).text(), "f");
+    }
+
+    #[test]
+    fn test_resize_reflows_wrapped_lines() {
+        let mut terminal = terminal_with(4, 3, b"abcdefghij\r\nxy");
+        assert_eq!(lines(&terminal), vec!["efgh", "ij", "xy"]);
+        assert_eq!(terminal.history_len(), 1);
+
+        terminal.resize(10, 3);
+        assert_eq!(lines(&terminal), vec!["abcdefghij", "xy", ""]);
+        assert_eq!(terminal.history_len(), 0);
+        assert_eq!((terminal.cursor().row, terminal.cursor().col), (1, 2));
+
+        terminal.resize(3, 3);
+        assert_eq!(lines(&terminal), vec!["ghi", "j", "xy"]);
+        let history: Vec<String> = terminal.screen().scrollback().iter().map(Row::text).collect();
+        assert_eq!(history, vec!["abc", "def"]);
+        assert_eq!((terminal.cursor().row, terminal.cursor().col), (2, 2));
+    }
+
+    #[test]
+    fn test_utf8_split_across_reads() {
+        let mut terminal = Terminal::new(20, 2, 0);
+        let bytes = "caf\u{e9} \u{2192} ok".as_bytes();
+        terminal.process(&bytes[..4]);
+        terminal.process(&bytes[4..8]);
+        terminal.process(&bytes[8..]);
+        assert_eq!(lines(&terminal), vec!["caf\u{e9} \u{2192} ok", ""]);
+    }
+
+    #[test]
+    fn test_wide_glyphs_take_two_cells() {
+        let mut terminal = terminal_with(4, 2, "a\u{4f60}\u{597d}".as_bytes());
+        let grid = terminal.grid();
+        assert!(grid.cell(0, 1).attrs.flags.contains(CellFlags::WIDE));
+        assert!(grid.cell(0, 2).attrs.flags.contains(CellFlags::WIDE_SPACER));
+        // The second glyph does not fit in the last column and wraps
+        assert_eq!(lines(&terminal), vec!["a\u{4f60}", "\u{597d}"]);
+        assert_eq!((terminal.cursor().row, terminal.cursor().col), (1, 2));
+
+        // Overwriting the right half of a wide glyph blanks the left half too
+        terminal.process(b"\x1b[1;3Hx");
+        assert_eq!(lines(&terminal), vec!["a x", "\u{597d}"]);
+        assert!(terminal.grid().row(0).cells.iter().all(|cell| cell.attrs.flags.is_empty()));
+    }
+
+    #[test]
+    fn test_selection_follows_scrolled_text() {
+        let mut terminal = Terminal::new(10, 2, 2);
+        terminal.process(b"one\r\ntwo\r\nthree");
+        terminal.start_selection(SelectionMode::Line, 0, 1);
+        assert_eq!(terminal.selection_text().as_deref(), Some("three"));
+
+        // "one" is evicted from the full scrollback; the selection moves up
+        terminal.process(b"\r\nfour\r\nfive");
+        assert_eq!(terminal.selection_text().as_deref(), Some("three"));
+        let line = terminal.selection_range().unwrap().start.line;
+        assert_eq!(terminal.screen().line(line).unwrap().text(), "three");
+
+        // Until its own line is evicted
+        terminal.process(b"\r\nsix\r\nseven");
+        assert!(terminal.selection().is_none());
+
+        terminal.start_selection(SelectionMode::Simple, 0, 0);
+        terminal.process(b"\x1b[?1049h");
+        assert!(terminal.selection().is_none());
+    }
+
+    #[test]
+    fn test_selection_follows_text_past_dropped_history() {
+        // Without scrollback every line scrolled off is dropped at once
+        let mut terminal = Terminal::new(10, 2, 0);
+        terminal.process(b"one\r\ntwo");
+        terminal.start_selection(SelectionMode::Line, 0, 1);
+        terminal.process(b"\r\nthree");
+        assert_eq!(terminal.selection_text().as_deref(), Some("two"));
+
+        // Clearing the scrollback keeps a selection on the screen
+        let mut terminal = terminal_with(10, 2, b"a\r\nb\r\nc");
+        terminal.start_selection(SelectionMode::Line, 0, 1);
+        terminal.process(b"\x1b[3J");
+        assert_eq!(terminal.history_len(), 0);
+        assert_eq!(terminal.selection_text().as_deref(), Some("c"));
+    }
+
+    #[test]
+    fn test_search_scrolls_to_matches() {
+        let mut terminal = terminal_with(10, 2, b"match 1\r\nx\r\ny\r\nz\r\nmatch 2");
+        assert_eq!(terminal.search("MATCH", SearchOptions::default()).unwrap(), 2);
+
+        // The newest match is on screen; the older one is in scrollback
+        assert_eq!(terminal.search_previous().unwrap().start.line, 4);
+        assert_eq!(terminal.display_offset(), 0);
+        assert_eq!(terminal.search_previous().unwrap().start.line, 0);
+        assert_eq!(terminal.screen().visible_row(0).text(), "match 1");
+
+        // New output is searched lazily
+        terminal.process(b"\r\nmatch 3");
+        terminal.update_search();
+        assert_eq!(terminal.active_search().unwrap().matches().len(), 3);
+        assert_eq!(terminal.active_search().unwrap().current().unwrap().start.line, 0);
+    }
+
+    #[test]
+    fn test_reflow_keeps_wide_glyphs_whole() {
+        let mut terminal = terminal_with(6, 2, "ab\u{1f600}\u{1f600}".as_bytes());
+        terminal.resize(3, 3);
+        assert_eq!(lines(&terminal), vec!["ab", "\u{1f600}", "\u{1f600}"]);
+        assert!(terminal.grid().cell(1, 0).attrs.flags.contains(CellFlags::WIDE));
+    }
+}
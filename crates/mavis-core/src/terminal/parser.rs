@@ -0,0 +1,492 @@
+// VT100/xterm escape sequence parser
+//
+// State machine modelled on the DEC ANSI parser described by Paul Williams
+// (https://vt100.net/emu/dec_ansi_parser). The parser only tokenizes; the
+// effect of each sequence is applied by a `Perform` implementation.
+
+/// Maximum number of CSI parameters kept; extra parameters are ignored.
+const MAX_PARAMS: usize = 32;
+/// Maximum number of intermediate characters kept.
+const MAX_INTERMEDIATES: usize = 2;
+/// Upper bound on the size of an OSC payload, to guard against runaway input.
+const MAX_OSC_LEN: usize = 4096;
+
+/// Receives the tokens produced by the `Parser`.
+pub trait Perform {
+    /// A printable character in the ground state.
+    fn print(&mut self, c: char);
+
+    /// A C0 or C1 control character.
+    fn execute(&mut self, byte: u8);
+
+    /// A complete CSI sequence (`ESC [ ... final`).
+    fn csi_dispatch(&mut self, params: &Params, intermediates: &[char], ignore: bool, action: char);
+
+    /// A complete escape sequence that is not CSI, OSC or DCS.
+    fn esc_dispatch(&mut self, intermediates: &[char], ignore: bool, byte: char);
+
+    /// A complete OSC string, split on `;`.
+    fn osc_dispatch(&mut self, params: &[&str], bell_terminated: bool);
+}
+
+/// CSI parameters. Each parameter may carry colon separated sub-parameters
+/// (e.g. `38:2::255:0:0`), so every entry is a group of values.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Params {
+    groups: Vec<Vec<u16>>,
+}
+
+impl Params {
+    /// Number of top-level parameters.
+    pub fn len(&self) -> usize {
+        self.groups.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.groups.is_empty()
+    }
+
+    /// Iterates over parameter groups (a parameter followed by its sub-parameters).
+    pub fn iter(&self) -> impl Iterator<Item = &[u16]> {
+        self.groups.iter().map(|g| g.as_slice())
+    }
+
+    /// Returns the first value of parameter `index`, or `default` when it is
+    /// missing or zero (the VT convention for "use the default").
+    pub fn get_or(&self, index: usize, default: u16) -> u16 {
+        match self.groups.get(index).and_then(|g| g.first()) {
+            Some(&0) | None => default,
+            Some(&v) => v,
+        }
+    }
+
+    /// Returns the raw first value of parameter `index`, treating a missing
+    /// parameter as zero.
+    pub fn raw(&self, index: usize) -> u16 {
+        self.groups.get(index).and_then(|g| g.first()).copied().unwrap_or(0)
+    }
+
+    fn clear(&mut self) {
+        self.groups.clear();
+    }
+
+    fn push(&mut self, value: u16) {
+        if self.groups.len() < MAX_PARAMS {
+            self.groups.push(vec![value]);
+        }
+    }
+
+    fn extend_last(&mut self, value: u16) {
+        if let Some(group) = self.groups.last_mut() {
+            group.push(value);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Ground,
+    Escape,
+    EscapeIntermediate,
+    CsiEntry,
+    CsiParam,
+    CsiIntermediate,
+    CsiIgnore,
+    DcsPassthrough,
+    OscString,
+    SosPmApcString,
+}
+
+/// Incremental VT parser. Feed it characters with `advance`; state is kept
+/// between calls so sequences may be split across reads.
+#[derive(Debug)]
+pub struct Parser {
+    state: State,
+    params: Params,
+    /// Value of the parameter currently being accumulated.
+    current_param: Option<u16>,
+    /// Whether the current parameter is a colon sub-parameter.
+    in_subparam: bool,
+    intermediates: Vec<char>,
+    ignoring: bool,
+    osc_buffer: String,
+    /// Set when an ESC was seen inside a string state (possible ST).
+    string_escape: bool,
+}
+
+impl Default for Parser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Parser {
+    /// Create a parser in the ground state.
+    pub fn new() -> Self {
+        Self {
+            state: State::Ground,
+            params: Params::default(),
+            current_param: None,
+            in_subparam: false,
+            intermediates: Vec::with_capacity(MAX_INTERMEDIATES),
+            ignoring: false,
+            osc_buffer: String::new(),
+            string_escape: false,
+        }
+    }
+
+    /// Feed a single character to the parser.
+    pub fn advance<P: Perform>(&mut self, performer: &mut P, c: char) {
+        // String states consume everything up to their terminator.
+        match self.state {
+            State::OscString => return self.advance_osc(performer, c),
+            State::DcsPassthrough | State::SosPmApcString => return self.advance_ignored_string(c),
+            _ => {}
+        }
+
+        // "Anywhere" transitions.
+        match c {
+            '\u{18}' | '\u{1a}' => {
+                // CAN / SUB abort the current sequence.
+                performer.execute(c as u8);
+                self.state = State::Ground;
+                return;
+            }
+            '\u{1b}' => {
+                self.enter_escape();
+                return;
+            }
+            '\u{90}' => return self.enter_string(State::DcsPassthrough),
+            '\u{9d}' => return self.enter_string(State::OscString),
+            '\u{98}' | '\u{9e}' | '\u{9f}' => return self.enter_string(State::SosPmApcString),
+            '\u{9b}' => {
+                self.enter_csi();
+                return;
+            }
+            '\u{80}'..='\u{8f}' | '\u{91}'..='\u{97}' | '\u{99}' | '\u{9a}' | '\u{9c}' => {
+                performer.execute(c as u8);
+                self.state = State::Ground;
+                return;
+            }
+            _ => {}
+        }
+
+        match self.state {
+            State::Ground => self.advance_ground(performer, c),
+            State::Escape => self.advance_escape(performer, c),
+            State::EscapeIntermediate => self.advance_escape_intermediate(performer, c),
+            State::CsiEntry | State::CsiParam => self.advance_csi_param(performer, c),
+            State::CsiIntermediate => self.advance_csi_intermediate(performer, c),
+            State::CsiIgnore => self.advance_csi_ignore(performer, c),
+            State::OscString | State::DcsPassthrough | State::SosPmApcString => unreachable!(),
+        }
+    }
+
+    fn advance_ground<P: Perform>(&mut self, performer: &mut P, c: char) {
+        match c {
+            '\u{00}'..='\u{1f}' => performer.execute(c as u8),
+            '\u{7f}' => {}
+            _ => performer.print(c),
+        }
+    }
+
+    fn advance_escape<P: Perform>(&mut self, performer: &mut P, c: char) {
+        match c {
+            '\u{00}'..='\u{1f}' => performer.execute(c as u8),
+            '\u{20}'..='\u{2f}' => {
+                self.collect_intermediate(c);
+                self.state = State::EscapeIntermediate;
+            }
+            '[' => self.enter_csi(),
+            ']' => self.enter_string(State::OscString),
+            'P' => self.enter_string(State::DcsPassthrough),
+            'X' | '^' | '_' => self.enter_string(State::SosPmApcString),
+            '\u{30}'..='\u{7e}' => {
+                performer.esc_dispatch(&self.intermediates, self.ignoring, c);
+                self.state = State::Ground;
+            }
+            _ => {}
+        }
+    }
+
+    fn advance_escape_intermediate<P: Perform>(&mut self, performer: &mut P, c: char) {
+        match c {
+            '\u{00}'..='\u{1f}' => performer.execute(c as u8),
+            '\u{20}'..='\u{2f}' => self.collect_intermediate(c),
+            '\u{30}'..='\u{7e}' => {
+                performer.esc_dispatch(&self.intermediates, self.ignoring, c);
+                self.state = State::Ground;
+            }
+            _ => {}
+        }
+    }
+
+    fn advance_csi_param<P: Perform>(&mut self, performer: &mut P, c: char) {
+        match c {
+            '\u{00}'..='\u{1f}' => performer.execute(c as u8),
+            '0'..='9' => {
+                let digit = c as u16 - '0' as u16;
+                let value = self.current_param.unwrap_or(0);
+                self.current_param = Some(value.saturating_mul(10).saturating_add(digit));
+                self.state = State::CsiParam;
+            }
+            ';' => {
+                self.finish_param();
+                self.in_subparam = false;
+                self.state = State::CsiParam;
+            }
+            ':' => {
+                self.finish_param();
+                self.in_subparam = true;
+                self.state = State::CsiParam;
+            }
+            '<' | '=' | '>' | '?' => {
+                // Private markers are only valid before any parameter digits.
+                if self.state == State::CsiEntry {
+                    self.collect_intermediate(c);
+                    self.state = State::CsiParam;
+                } else {
+                    self.state = State::CsiIgnore;
+                }
+            }
+            '\u{20}'..='\u{2f}' => {
+                self.finish_trailing_param();
+                self.collect_intermediate(c);
+                self.state = State::CsiIntermediate;
+            }
+            '\u{40}'..='\u{7e}' => {
+                self.finish_trailing_param();
+                performer.csi_dispatch(&self.params, &self.intermediates, self.ignoring, c);
+                self.state = State::Ground;
+            }
+            _ => {}
+        }
+    }
+
+    fn advance_csi_intermediate<P: Perform>(&mut self, performer: &mut P, c: char) {
+        match c {
+            '\u{00}'..='\u{1f}' => performer.execute(c as u8),
+            '\u{20}'..='\u{2f}' => self.collect_intermediate(c),
+            '\u{30}'..='\u{3f}' => self.state = State::CsiIgnore,
+            '\u{40}'..='\u{7e}' => {
+                performer.csi_dispatch(&self.params, &self.intermediates, self.ignoring, c);
+                self.state = State::Ground;
+            }
+            _ => {}
+        }
+    }
+
+    fn advance_csi_ignore<P: Perform>(&mut self, performer: &mut P, c: char) {
+        match c {
+            '\u{00}'..='\u{1f}' => performer.execute(c as u8),
+            '\u{40}'..='\u{7e}' => self.state = State::Ground,
+            _ => {}
+        }
+    }
+
+    fn advance_osc<P: Perform>(&mut self, performer: &mut P, c: char) {
+        if self.string_escape {
+            self.string_escape = false;
+            if c == '\\' {
+                self.dispatch_osc(performer, false);
+                self.state = State::Ground;
+                return;
+            }
+            // Any other character after ESC aborts the string and starts a new sequence.
+            self.dispatch_osc(performer, false);
+            self.enter_escape();
+            return self.advance(performer, c);
+        }
+
+        match c {
+            '\u{07}' => {
+                self.dispatch_osc(performer, true);
+                self.state = State::Ground;
+            }
+            '\u{9c}' => {
+                self.dispatch_osc(performer, false);
+                self.state = State::Ground;
+            }
+            '\u{1b}' => self.string_escape = true,
+            '\u{18}' | '\u{1a}' => self.state = State::Ground,
+            '\u{00}'..='\u{1f}' => {}
+            _ => {
+                if self.osc_buffer.len() < MAX_OSC_LEN {
+                    self.osc_buffer.push(c);
+                }
+            }
+        }
+    }
+
+    fn advance_ignored_string(&mut self, c: char) {
+        if self.string_escape {
+            self.string_escape = false;
+            if c == '\\' {
+                self.state = State::Ground;
+                return;
+            }
+        }
+        match c {
+            '\u{1b}' => self.string_escape = true,
+            '\u{07}' | '\u{9c}' | '\u{18}' | '\u{1a}' => self.state = State::Ground,
+            _ => {}
+        }
+    }
+
+    fn dispatch_osc<P: Perform>(&mut self, performer: &mut P, bell_terminated: bool) {
+        let params: Vec<&str> = self.osc_buffer.split(';').collect();
+        performer.osc_dispatch(&params, bell_terminated);
+    }
+
+    fn enter_escape(&mut self) {
+        self.state = State::Escape;
+        self.intermediates.clear();
+        self.ignoring = false;
+    }
+
+    fn enter_csi(&mut self) {
+        self.state = State::CsiEntry;
+        self.params.clear();
+        self.current_param = None;
+        self.in_subparam = false;
+        self.intermediates.clear();
+        self.ignoring = false;
+    }
+
+    fn enter_string(&mut self, state: State) {
+        self.state = state;
+        self.osc_buffer.clear();
+        self.string_escape = false;
+    }
+
+    fn collect_intermediate(&mut self, c: char) {
+        if self.intermediates.len() < MAX_INTERMEDIATES {
+            self.intermediates.push(c);
+        } else {
+            self.ignoring = true;
+        }
+    }
+
+    /// Store the parameter being accumulated when a separator is seen.
+    fn finish_param(&mut self) {
+        let value = self.current_param.take().unwrap_or(0);
+        if self.in_subparam {
+            self.params.extend_last(value);
+        } else {
+            self.params.push(value);
+        }
+    }
+
+    /// Store the final parameter before dispatch. `CSI m` has no parameters,
+    /// but `CSI ;m` has two empty ones.
+    fn finish_trailing_param(&mut self) {
+        if self.current_param.is_some() || !self.params.is_empty() || self.in_subparam {
+            self.finish_param();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct Recorder {
+        printed: String,
+        executed: Vec<u8>,
+        csi: Vec<(Vec<Vec<u16>>, Vec<char>, char)>,
+        esc: Vec<(Vec<char>, char)>,
+        osc: Vec<Vec<String>>,
+    }
+
+    impl Perform for Recorder {
+        fn print(&mut self, c: char) {
+            self.printed.push(c);
+        }
+
+        fn execute(&mut self, byte: u8) {
+            self.executed.push(byte);
+        }
+
+        fn csi_dispatch(&mut self, params: &Params, intermediates: &[char], _ignore: bool, action: char) {
+            let groups = params.iter().map(|g| g.to_vec()).collect();
+            self.csi.push((groups, intermediates.to_vec(), action));
+        }
+
+        fn esc_dispatch(&mut self, intermediates: &[char], _ignore: bool, byte: char) {
+            self.esc.push((intermediates.to_vec(), byte));
+        }
+
+        fn osc_dispatch(&mut self, params: &[&str], _bell_terminated: bool) {
+            self.osc.push(params.iter().map(|s| s.to_string()).collect());
+        }
+    }
+
+    fn parse(input: &str) -> Recorder {
+        let mut parser = Parser::new();
+        let mut recorder = Recorder::default();
+        for c in input.chars() {
+            parser.advance(&mut recorder, c);
+        }
+        recorder
+    }
+
+    #[test]
+    fn test_plain_text_and_controls() {
+        let r = parse("ab\r\nc");
+        assert_eq!(r.printed, "abc");
+        assert_eq!(r.executed, vec![b'\r', b'\n']);
+    }
+
+    #[test]
+    fn test_csi_params() {
+        let r = parse("\x1b[12;34H\x1b[m\x1b[;5H");
+        assert_eq!(r.csi[0], (vec![vec![12], vec![34]], vec![], 'H'));
+        assert_eq!(r.csi[1], (vec![], vec![], 'm'));
+        assert_eq!(r.csi[2], (vec![vec![0], vec![5]], vec![], 'H'));
+    }
+
+    #[test]
+    fn test_csi_private_marker_and_subparams() {
+        let r = parse("\x1b[?1049h\x1b[38:2::255:128:0m");
+        assert_eq!(r.csi[0], (vec![vec![1049]], vec!['?'], 'h'));
+        assert_eq!(r.csi[1], (vec![vec![38, 2, 0, 255, 128, 0]], vec![], 'm'));
+    }
+
+    #[test]
+    fn test_sequence_split_across_calls() {
+        let mut parser = Parser::new();
+        let mut recorder = Recorder::default();
+        for c in "\x1b[3".chars() {
+            parser.advance(&mut recorder, c);
+        }
+        assert!(recorder.csi.is_empty());
+        for c in "1mX".chars() {
+            parser.advance(&mut recorder, c);
+        }
+        assert_eq!(recorder.csi[0], (vec![vec![31]], vec![], 'm'));
+        assert_eq!(recorder.printed, "X");
+    }
+
+    #[test]
+    fn test_osc_terminators() {
+        let r = parse("\x1b]0;hello\x07\x1b]2;world\x1b\\after");
+        assert_eq!(r.osc[0], vec!["0", "hello"]);
+        assert_eq!(r.osc[1], vec!["2", "world"]);
+        assert_eq!(r.printed, "after");
+    }
+
+    #[test]
+    fn test_esc_dispatch_with_intermediate() {
+        let r = parse("\x1b(0\x1b7");
+        assert_eq!(r.esc[0], (vec!['('], '0'));
+        assert_eq!(r.esc[1], (vec![], '7'));
+    }
+
+    #[test]
+    fn test_dcs_is_ignored() {
+        let r = parse("\x1bPq#0;2;0;0;0\x1b\\ok");
+        assert!(r.csi.is_empty());
+        assert_eq!(r.printed, "ok");
+    }
+}
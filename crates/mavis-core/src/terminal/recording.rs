@@ -0,0 +1,424 @@
+// Session recording and replay in the asciicast v2 format
+//
+// An asciicast v2 file is a JSON header line followed by one JSON array per
+// event, `[seconds, code, data]`: code "o" is output, "i" input, "r" a
+// resize to "COLSxROWS" and "m" a marker. `Recorder` writes the output of a
+// session as it is fed to the emulator. `Recording` reads a file back and
+// `Player` feeds its events to a terminal on a clock that runs at any
+// speed, or that tests advance by hand.
+
+use super::Terminal;
+use super::utf8::Utf8Decoder;
+use crate::error::{CoreError, CoreResult};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Largest width or height a recording may ask for. Anything bigger comes
+/// from a broken or malicious file, and would take gigabytes of cells.
+const MAX_SIZE: usize = 1000;
+
+/// The first line of an asciicast v2 file.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Header {
+    pub version: u32,
+    pub width: usize,
+    pub height: usize,
+    /// Start of the recording, in seconds since the Unix epoch.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<u64>,
+    /// Longest pause kept on replay, in seconds.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub idle_time_limit: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+}
+
+impl Header {
+    /// Header for a `width` x `height` recording starting now.
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            version: 2,
+            width,
+            height,
+            timestamp: SystemTime::now().duration_since(UNIX_EPOCH).ok().map(|t| t.as_secs()),
+            idle_time_limit: None,
+            title: None,
+            env: BTreeMap::new(),
+        }
+    }
+}
+
+/// What happened at one point of a recording.
+#[derive(Debug, Clone, PartialEq)]
+pub enum EventData {
+    Output(String),
+    Input(String),
+    Resize(usize, usize),
+    Marker(String),
+}
+
+impl EventData {
+    /// Feed the event to `terminal`. Input and markers change nothing.
+    pub fn apply(&self, terminal: &mut Terminal) {
+        match self {
+            EventData::Output(text) => terminal.process(text.as_bytes()),
+            EventData::Resize(cols, rows) => terminal.resize(*cols, *rows),
+            EventData::Input(_) | EventData::Marker(_) => {}
+        }
+    }
+
+    fn encode(&self) -> (&'static str, String) {
+        match self {
+            EventData::Output(text) => ("o", text.clone()),
+            EventData::Input(text) => ("i", text.clone()),
+            EventData::Resize(cols, rows) => ("r", format!("{}x{}", cols, rows)),
+            EventData::Marker(label) => ("m", label.clone()),
+        }
+    }
+
+    /// `None` for event codes this version does not know.
+    fn decode(code: &str, data: String) -> CoreResult<Option<Self>> {
+        Ok(Some(match code {
+            "o" => EventData::Output(data),
+            "i" => EventData::Input(data),
+            "m" => EventData::Marker(data),
+            "r" => {
+                let size = data
+                    .split_once('x')
+                    .and_then(|(cols, rows)| Some((cols.parse().ok()?, rows.parse().ok()?)))
+                    .filter(|&(cols, rows)| (1..=MAX_SIZE).contains(&cols) && (1..=MAX_SIZE).contains(&rows));
+                match size {
+                    Some((cols, rows)) => EventData::Resize(cols, rows),
+                    None => return Err(CoreError::RecordingError(format!("Invalid resize '{}'", data))),
+                }
+            }
+            _ => return Ok(None),
+        }))
+    }
+}
+
+/// An event and when it happened.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Event {
+    /// Seconds since the start of the recording.
+    pub time: f64,
+    pub data: EventData,
+}
+
+/// Writes events to an asciicast v2 stream as they happen. Each event is
+/// flushed, so the file stays readable while recording.
+#[derive(Debug)]
+pub struct Recorder<W: Write = BufWriter<File>> {
+    writer: W,
+    started: Instant,
+    // Output can split a character between reads, but events hold text
+    decoder: Utf8Decoder,
+}
+
+impl Recorder {
+    /// Create `path` and write `header` to it.
+    pub fn create(path: impl AsRef<Path>, header: &Header) -> CoreResult<Self> {
+        Self::new(BufWriter::new(File::create(path)?), header)
+    }
+}
+
+impl<W: Write> Recorder<W> {
+    pub fn new(mut writer: W, header: &Header) -> CoreResult<Self> {
+        serde_json::to_writer(&mut writer, header)?;
+        writeln!(writer)?;
+        writer.flush()?;
+        Ok(Self {
+            writer,
+            started: Instant::now(),
+            decoder: Utf8Decoder::new(),
+        })
+    }
+
+    /// Record raw output from the child.
+    pub fn output(&mut self, data: &[u8]) -> CoreResult<()> {
+        let text = self.decoder.decode_to_string(data);
+        if text.is_empty() {
+            return Ok(());
+        }
+        self.record(EventData::Output(text))
+    }
+
+    pub fn resize(&mut self, cols: usize, rows: usize) -> CoreResult<()> {
+        self.record(EventData::Resize(cols, rows))
+    }
+
+    /// Record `data` as happening now.
+    pub fn record(&mut self, data: EventData) -> CoreResult<()> {
+        self.record_at(self.started.elapsed(), &data)
+    }
+
+    /// Record `data` as happening `time` after the start.
+    pub fn record_at(&mut self, time: Duration, data: &EventData) -> CoreResult<()> {
+        // Microseconds are as precise as the format is written elsewhere
+        let seconds = (time.as_secs_f64() * 1e6).round() / 1e6;
+        let (code, text) = data.encode();
+        serde_json::to_writer(&mut self.writer, &(seconds, code, text))?;
+        writeln!(self.writer)?;
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    /// Stop recording and return the writer.
+    pub fn finish(mut self) -> CoreResult<W> {
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// A whole asciicast v2 recording.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Recording {
+    pub header: Header,
+    pub events: Vec<Event>,
+}
+
+impl Recording {
+    pub fn open(path: impl AsRef<Path>) -> CoreResult<Self> {
+        let path = path.as_ref();
+        let file = File::open(path).map_err(|_| CoreError::FileNotFound(path.display().to_string()))?;
+        Self::read(BufReader::new(file))
+    }
+
+    pub fn parse(text: &str) -> CoreResult<Self> {
+        Self::read(text.as_bytes())
+    }
+
+    /// Read a recording. Events with unknown codes are skipped.
+    pub fn read(reader: impl BufRead) -> CoreResult<Self> {
+        let mut lines = reader.lines().enumerate().filter(|(_, line)| line.as_ref().map_or(true, |l| !l.trim().is_empty()));
+        let header_line = match lines.next() {
+            Some((_, line)) => line?,
+            None => return Err(CoreError::RecordingError("Empty recording".to_string())),
+        };
+        let header: Header = serde_json::from_str(&header_line)
+            .map_err(|e| CoreError::RecordingError(format!("Invalid header: {}", e)))?;
+        if header.version != 2 {
+            return Err(CoreError::RecordingError(format!(
+                "Unsupported asciicast version {}",
+                header.version
+            )));
+        }
+        if header.width > MAX_SIZE || header.height > MAX_SIZE {
+            return Err(CoreError::RecordingError(format!(
+                "Terminal size {}x{} is larger than {}x{}",
+                header.width, header.height, MAX_SIZE, MAX_SIZE
+            )));
+        }
+
+        let mut events = Vec::new();
+        for (index, line) in lines {
+            let invalid = |message: String| CoreError::RecordingError(format!("Line {}: {}", index + 1, message));
+            let (time, code, data): (f64, String, String) =
+                serde_json::from_str(&line?).map_err(|e| invalid(e.to_string()))?;
+            if let Some(data) = EventData::decode(&code, data).map_err(|e| invalid(e.to_string()))? {
+                events.push(Event { time, data });
+            }
+        }
+        Ok(Self { header, events })
+    }
+
+    /// Time of the last event.
+    pub fn duration(&self) -> Duration {
+        let last = self.events.last().map_or(0.0, |event| event.time);
+        Duration::from_secs_f64(last.max(0.0))
+    }
+
+    /// A terminal of the recorded size to play the recording into.
+    pub fn terminal(&self, scrollback_lines: usize) -> Terminal {
+        Terminal::new(self.header.width.max(1), self.header.height.max(1), scrollback_lines)
+    }
+}
+
+/// Plays a recording back on a clock scaled by the speed. The clock only
+/// moves when `advance` is called, so tests can step through a recording
+/// without waiting.
+#[derive(Debug, Clone)]
+pub struct Player {
+    recording: Recording,
+    // Event times with pauses cut to the header's idle time limit
+    times: Vec<f64>,
+    next: usize,
+    clock: f64,
+    speed: f64,
+}
+
+impl Player {
+    pub fn new(recording: Recording) -> Self {
+        let limit = recording.header.idle_time_limit.filter(|limit| *limit > 0.0);
+        let mut times = Vec::with_capacity(recording.events.len());
+        let (mut previous, mut shifted) = (0.0, 0.0);
+        for event in &recording.events {
+            let gap = (event.time - previous).max(0.0);
+            previous = event.time.max(previous);
+            shifted += limit.map_or(gap, |limit| gap.min(limit));
+            times.push(shifted);
+        }
+
+        Self {
+            recording,
+            times,
+            next: 0,
+            clock: 0.0,
+            speed: 1.0,
+        }
+    }
+
+    pub fn recording(&self) -> &Recording {
+        &self.recording
+    }
+
+    pub fn speed(&self) -> f64 {
+        self.speed
+    }
+
+    /// Play `speed` times faster than recorded. Non-positive speeds are
+    /// ignored.
+    pub fn set_speed(&mut self, speed: f64) {
+        if speed > 0.0 && speed.is_finite() {
+            self.speed = speed;
+        }
+    }
+
+    /// How far into the recording playback is, in recorded time.
+    pub fn position(&self) -> Duration {
+        Duration::from_secs_f64(self.clock)
+    }
+
+    /// Length of the playback in recorded time, with pauses cut.
+    pub fn duration(&self) -> Duration {
+        Duration::from_secs_f64(self.times.last().copied().unwrap_or(0.0))
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.next == self.times.len()
+    }
+
+    /// Real time until the next event is due, at the current speed.
+    pub fn time_to_next(&self) -> Option<Duration> {
+        let due = *self.times.get(self.next)?;
+        Some(Duration::from_secs_f64((due - self.clock).max(0.0) / self.speed))
+    }
+
+    /// Move the clock on by `elapsed` real time and pass each event that
+    /// became due to `emit`. Returns how many there were.
+    pub fn advance(&mut self, elapsed: Duration, mut emit: impl FnMut(&EventData)) -> usize {
+        self.clock += elapsed.as_secs_f64() * self.speed;
+        let start = self.next;
+        while self.next < self.times.len() && self.times[self.next] <= self.clock {
+            emit(&self.recording.events[self.next].data);
+            self.next += 1;
+        }
+        self.next - start
+    }
+
+    /// Advance by `elapsed` and apply the due events to `terminal`.
+    pub fn play(&mut self, terminal: &mut Terminal, elapsed: Duration) -> usize {
+        self.advance(elapsed, |data| data.apply(terminal))
+    }
+
+    /// Apply every remaining event to `terminal` at once.
+    pub fn play_to_end(&mut self, terminal: &mut Terminal) {
+        for event in &self.recording.events[self.next..] {
+            event.data.apply(terminal);
+        }
+        self.next = self.times.len();
+        self.clock = self.clock.max(self.times.last().copied().unwrap_or(0.0));
+    }
+
+    /// Start again from the beginning.
+    pub fn rewind(&mut self) {
+        self.next = 0;
+        self.clock = 0.0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CAST: &str = r#"{"version": 2, "width": 10, "height": 3, "idle_time_limit": 1.0}
+[0.5, "o", "hello"]
+[0.75, "x", "unknown events are skipped"]
+[1.0, "r", "20x3"]
+
+[6.0, "o", "\r\n\u001b[1mworld\u001b[0m"]
+"#;
+
+    #[test]
+    fn test_recorder_round_trip() {
+        let mut header = Header::new(10, 3);
+        header.title = Some("demo".to_string());
+        let mut recorder = Recorder::new(Vec::new(), &header).unwrap();
+        // A character split between two reads is written whole
+        recorder.output(b"caf\xc3").unwrap();
+        recorder.output(b"\xa9").unwrap();
+        recorder.resize(20, 5).unwrap();
+        recorder.record_at(Duration::from_millis(1500), &EventData::Marker("done".to_string())).unwrap();
+        let written = String::from_utf8(recorder.finish().unwrap()).unwrap();
+
+        let recording = Recording::parse(&written).unwrap();
+        assert_eq!(recording.header, header);
+        let data: Vec<_> = recording.events.iter().map(|event| event.data.clone()).collect();
+        assert_eq!(
+            data,
+            vec![
+                EventData::Output("caf".to_string()),
+                EventData::Output("é".to_string()),
+                EventData::Resize(20, 5),
+                EventData::Marker("done".to_string()),
+            ]
+        );
+        assert_eq!(recording.duration(), Duration::from_millis(1500));
+    }
+
+    #[test]
+    fn test_player_drives_terminal() {
+        let recording = Recording::parse(CAST).unwrap();
+        assert_eq!(recording.events.len(), 3);
+        let mut terminal = recording.terminal(100);
+        let mut player = Player::new(recording);
+        // The 5 second pause is cut to the idle limit
+        assert_eq!(player.duration(), Duration::from_secs(2));
+
+        player.set_speed(2.0);
+        assert_eq!(player.time_to_next(), Some(Duration::from_millis(250)));
+        assert_eq!(player.play(&mut terminal, Duration::from_millis(100)), 0);
+        assert_eq!(player.play(&mut terminal, Duration::from_millis(400)), 2);
+        assert_eq!(terminal.screen().cols(), 20);
+        assert_eq!(terminal.grid().row(0).text(), "hello");
+
+        player.play_to_end(&mut terminal);
+        assert!(player.is_finished() && player.time_to_next().is_none());
+        assert_eq!(terminal.grid().row(1).text(), "world");
+        assert!(terminal.grid().row(1).cells[0].attrs.flags.contains(crate::terminal::CellFlags::BOLD));
+
+        player.rewind();
+        assert_eq!(player.position(), Duration::ZERO);
+        assert!(!player.is_finished());
+    }
+
+    #[test]
+    fn test_invalid_recordings() {
+        assert!(Recording::parse("").is_err());
+        assert!(Recording::parse(r#"{"version": 1, "width": 80, "height": 24}"#).is_err());
+        let bad_event = "{\"version\": 2, \"width\": 80, \"height\": 24}\n[0.1, \"r\", \"80\"]";
+        let error = Recording::parse(bad_event).unwrap_err().to_string();
+        assert!(error.contains("Line 2"), "{}", error);
+
+        // Sizes that would allocate billions of cells
+        assert!(Recording::parse(r#"{"version": 2, "width": 100000, "height": 100000}"#).is_err());
+        let huge_resize = "{\"version\": 2, \"width\": 80, \"height\": 24}\n[0.1, \"r\", \"80x100000\"]";
+        assert!(Recording::parse(huge_resize).is_err());
+    }
+}
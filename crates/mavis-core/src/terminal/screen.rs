@@ -0,0 +1,1355 @@
+// Screen state driven by the VT parser: cursor, modes, scroll region and
+// the primary/alternate grids.
+
+use super::grid::{Cell, CellAttributes, CellFlags, Color, Grid, HyperlinkId, Row};
+use super::links::file_uri_path;
+use super::parser::{Params, Perform};
+use super::scrollback::Scrollback;
+use super::selection::Point;
+use log::{debug, trace};
+use std::collections::HashMap;
+use unicode_width::UnicodeWidthChar;
+
+/// Mouse reporting requested by the running program (DECSET 9/1000/1002/1003).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MouseTracking {
+    #[default]
+    Off,
+    /// Button presses only (mode 9).
+    X10,
+    /// Presses and releases (mode 1000).
+    Normal,
+    /// Presses, releases and drags (mode 1002).
+    ButtonEvent,
+    /// All motion (mode 1003).
+    AnyEvent,
+}
+
+/// Cursor style requested with DECSCUSR.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CursorShape {
+    #[default]
+    Block,
+    Underline,
+    Bar,
+}
+
+/// Terminal modes toggled by SM/RM and DECSET/DECRST.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TerminalModes {
+    /// DECCKM: cursor keys send SS3 sequences.
+    pub application_cursor_keys: bool,
+    /// DECKPAM: keypad sends application sequences.
+    pub application_keypad: bool,
+    /// DECAWM: wrap to the next line at the right margin.
+    pub auto_wrap: bool,
+    /// DECOM: cursor addressing is relative to the scroll region.
+    pub origin: bool,
+    /// IRM: printed characters shift the rest of the line right.
+    pub insert: bool,
+    /// LNM: line feed also performs a carriage return.
+    pub linefeed_newline: bool,
+    /// DECTCEM: cursor is visible.
+    pub show_cursor: bool,
+    /// Mode 2004: pasted text is wrapped in bracket sequences.
+    pub bracketed_paste: bool,
+    /// Mouse reporting mode.
+    pub mouse_tracking: MouseTracking,
+    /// Mode 1006: mouse reports use the SGR encoding.
+    pub sgr_mouse: bool,
+    /// Mode 1004: focus in/out is reported.
+    pub focus_events: bool,
+    /// Modes 47/1047/1049: the alternate screen is active.
+    pub alternate_screen: bool,
+}
+
+impl Default for TerminalModes {
+    fn default() -> Self {
+        Self {
+            application_cursor_keys: false,
+            application_keypad: false,
+            auto_wrap: true,
+            origin: false,
+            insert: false,
+            linefeed_newline: false,
+            show_cursor: true,
+            bracketed_paste: false,
+            mouse_tracking: MouseTracking::Off,
+            sgr_mouse: false,
+            focus_events: false,
+            alternate_screen: false,
+        }
+    }
+}
+
+/// Cursor position and the rendition applied to newly printed characters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Cursor {
+    pub row: usize,
+    pub col: usize,
+    pub attrs: CellAttributes,
+    /// The last column was written; the next printable character wraps first.
+    pub pending_wrap: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Charset {
+    Ascii,
+    DecSpecialGraphics,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct SavedCursor {
+    cursor: Cursor,
+    origin: bool,
+    charsets: [Charset; 2],
+    active_charset: usize,
+}
+
+/// A hyperlink opened with OSC 8.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Hyperlink {
+    /// The `id=` parameter, which ties together cells of one link printed
+    /// in separate runs.
+    pub id: Option<String>,
+    pub uri: String,
+}
+
+/// Shell integration marks (OSC 133), sent by shells around each prompt
+/// and command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShellMark {
+    /// `A`: the prompt starts.
+    PromptStart,
+    /// `B`: the prompt ends and the command line starts.
+    CommandStart,
+    /// `C`: the command was entered and its output starts.
+    OutputStart,
+    /// `D`: the command finished, with its exit status if reported.
+    CommandEnd(Option<i32>),
+}
+
+/// A shell mark and where the cursor was when it arrived.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ShellEvent {
+    pub mark: ShellMark,
+    /// Line as in `Screen::line`.
+    pub point: Point,
+    /// `Scrollback::evicted()` when the mark arrived, to account for lines
+    /// dropped since.
+    pub evicted: usize,
+}
+
+/// Emulated screen. Implements `Perform` so the parser can drive it directly.
+#[derive(Debug)]
+pub struct Screen {
+    primary: Grid,
+    alternate: Grid,
+    /// Lines that scrolled off the top of the primary screen.
+    scrollback: Scrollback,
+    /// How many lines the view is scrolled back into history (0 = live).
+    display_offset: usize,
+    cursor: Cursor,
+    saved_primary: Option<SavedCursor>,
+    saved_alternate: Option<SavedCursor>,
+    /// Scroll region, inclusive on both ends.
+    scroll_top: usize,
+    scroll_bottom: usize,
+    modes: TerminalModes,
+    tab_stops: Vec<bool>,
+    charsets: [Charset; 2],
+    active_charset: usize,
+    cursor_shape: CursorShape,
+    title: String,
+    /// Replies to device queries (DA, DSR) waiting to be written to the PTY.
+    responses: Vec<u8>,
+    bell: bool,
+    last_printed: Option<char>,
+    /// OSC 8 hyperlinks by id, and the one applied to printed characters.
+    hyperlinks: Vec<Hyperlink>,
+    hyperlink_ids: HashMap<Hyperlink, HyperlinkId>,
+    hyperlink: Option<HyperlinkId>,
+    /// Shell integration marks not yet taken, and the directory last
+    /// reported with OSC 7.
+    shell_events: Vec<ShellEvent>,
+    working_directory: Option<String>,
+}
+
+impl Screen {
+    pub fn new(cols: usize, rows: usize, scrollback_lines: usize) -> Self {
+        let primary = Grid::new(cols, rows);
+        let cols = primary.cols();
+        let rows = primary.rows();
+        Self {
+            alternate: Grid::new(cols, rows),
+            primary,
+            scrollback: Scrollback::new(scrollback_lines),
+            display_offset: 0,
+            cursor: Cursor::default(),
+            saved_primary: None,
+            saved_alternate: None,
+            scroll_top: 0,
+            scroll_bottom: rows - 1,
+            modes: TerminalModes::default(),
+            tab_stops: default_tab_stops(cols),
+            charsets: [Charset::Ascii; 2],
+            active_charset: 0,
+            cursor_shape: CursorShape::default(),
+            title: String::new(),
+            responses: Vec::new(),
+            bell: false,
+            last_printed: None,
+            hyperlinks: Vec::new(),
+            hyperlink_ids: HashMap::new(),
+            hyperlink: None,
+            shell_events: Vec::new(),
+            working_directory: None,
+        }
+    }
+
+    /// The grid currently shown (primary or alternate).
+    pub fn grid(&self) -> &Grid {
+        if self.modes.alternate_screen {
+            &self.alternate
+        } else {
+            &self.primary
+        }
+    }
+
+    fn grid_mut(&mut self) -> &mut Grid {
+        if self.modes.alternate_screen {
+            &mut self.alternate
+        } else {
+            &mut self.primary
+        }
+    }
+
+    pub fn cols(&self) -> usize {
+        self.primary.cols()
+    }
+
+    pub fn rows(&self) -> usize {
+        self.primary.rows()
+    }
+
+    pub fn cursor(&self) -> &Cursor {
+        &self.cursor
+    }
+
+    pub fn cursor_shape(&self) -> CursorShape {
+        self.cursor_shape
+    }
+
+    pub fn modes(&self) -> &TerminalModes {
+        &self.modes
+    }
+
+    /// Window title set through OSC 0/2.
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+
+    /// Scroll region as an inclusive `(top, bottom)` row range.
+    pub fn scroll_region(&self) -> (usize, usize) {
+        (self.scroll_top, self.scroll_bottom)
+    }
+
+    /// Drain replies that must be written back to the child process.
+    pub fn take_responses(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.responses)
+    }
+
+    /// Returns whether BEL was received since the last call.
+    pub fn take_bell(&mut self) -> bool {
+        std::mem::replace(&mut self.bell, false)
+    }
+
+    /// Resize both grids. The primary screen is reflowed so wrapped lines
+    /// are rewrapped at the new width, with rows moving to or from the
+    /// scrollback as the height changes. The alternate screen is truncated.
+    /// The scroll region is reset to the full screen.
+    pub fn resize(&mut self, cols: usize, rows: usize) {
+        self.resize_tracking(cols, rows, &mut []);
+    }
+
+    /// Resize like `resize`, moving `points` (lines as in `line()`) with
+    /// the text they are on when the primary screen is reflowed. Returns
+    /// how many rows were dropped from the top because the scrollback was
+    /// full; the points' lines still count those rows.
+    pub fn resize_tracking(&mut self, cols: usize, rows: usize, points: &mut [&mut Point]) -> usize {
+        let cols = cols.max(1);
+        let rows = rows.max(1);
+        let dropped = if self.modes.alternate_screen {
+            self.primary.resize(cols, rows);
+            0
+        } else {
+            self.reflow_primary(cols, rows, points)
+        };
+        self.alternate.resize(cols, rows);
+        self.scroll_top = 0;
+        self.scroll_bottom = rows - 1;
+        self.tab_stops = default_tab_stops(cols);
+        self.cursor.row = self.cursor.row.min(rows - 1);
+        self.cursor.col = self.cursor.col.min(cols - 1);
+        self.cursor.pending_wrap = false;
+        self.display_offset = 0;
+        dropped
+    }
+
+    /// Full reset (RIS). Scrollback, the hyperlinks it may refer to, and
+    /// the shell's working directory are kept.
+    pub fn reset(&mut self) {
+        let title = std::mem::take(&mut self.title);
+        let working_directory = self.working_directory.take();
+        let scrollback = std::mem::replace(&mut self.scrollback, Scrollback::new(0));
+        let hyperlinks = std::mem::take(&mut self.hyperlinks);
+        let hyperlink_ids = std::mem::take(&mut self.hyperlink_ids);
+        *self = Screen::new(self.cols(), self.rows(), 0);
+        self.title = title;
+        self.scrollback = scrollback;
+        self.hyperlinks = hyperlinks;
+        self.hyperlink_ids = hyperlink_ids;
+        self.working_directory = working_directory;
+    }
+
+    /// Drain the shell integration marks received since the last call.
+    pub fn take_shell_events(&mut self) -> Vec<ShellEvent> {
+        std::mem::take(&mut self.shell_events)
+    }
+
+    /// Current directory of the shell, as last reported with OSC 7.
+    pub fn working_directory(&self) -> Option<&str> {
+        self.working_directory.as_deref()
+    }
+
+    /// The OSC 8 hyperlink registered as `id`.
+    pub fn hyperlink(&self, id: HyperlinkId) -> Option<&Hyperlink> {
+        self.hyperlinks.get(id.0 as usize)
+    }
+
+    // --- Scrollback and viewport ---
+
+    pub fn scrollback(&self) -> &Scrollback {
+        &self.scrollback
+    }
+
+    /// Number of lines scrolled back from the live screen.
+    pub fn display_offset(&self) -> usize {
+        self.display_offset
+    }
+
+    /// Total number of lines reachable through `line()`: the scrollback
+    /// (primary screen only) followed by the visible grid.
+    pub fn total_lines(&self) -> usize {
+        self.history_len() + self.rows()
+    }
+
+    /// Line by index into scrollback followed by the visible grid, where 0
+    /// is the oldest scrollback line.
+    pub fn line(&self, index: usize) -> Option<&Row> {
+        let history = self.history_len();
+        if index < history {
+            self.scrollback.get(index)
+        } else if index - history < self.rows() {
+            Some(self.grid().row(index - history))
+        } else {
+            None
+        }
+    }
+
+    /// Row `index` of the viewport, taking the display offset into account.
+    pub fn visible_row(&self, index: usize) -> &Row {
+        let history = self.history_len();
+        let line = history - self.display_offset + index;
+        if line < history {
+            &self.scrollback[line]
+        } else {
+            self.grid().row(line - history)
+        }
+    }
+
+    /// Line index (as in `line()`) shown at viewport row `row`.
+    pub fn viewport_line(&self, row: usize) -> usize {
+        self.history_len() - self.display_offset + row
+    }
+
+    /// Scroll the view by `delta` lines; positive values move back into history.
+    pub fn scroll_display(&mut self, delta: isize) {
+        let offset = self.display_offset as isize + delta;
+        self.display_offset = offset.clamp(0, self.history_len() as isize) as usize;
+    }
+
+    /// Scroll the view by whole pages; positive values move back into history.
+    pub fn scroll_page(&mut self, pages: isize) {
+        self.scroll_display(pages * self.rows() as isize);
+    }
+
+    /// Scroll so that `line` (as in `line()`) is at the top of the view.
+    pub fn scroll_to_line(&mut self, line: usize) {
+        self.display_offset = self.history_len().saturating_sub(line);
+    }
+
+    /// Return the view to the live screen.
+    pub fn scroll_to_bottom(&mut self) {
+        self.display_offset = 0;
+    }
+
+    /// Scrollback is only reachable from the primary screen.
+    fn history_len(&self) -> usize {
+        if self.modes.alternate_screen {
+            0
+        } else {
+            self.scrollback.len()
+        }
+    }
+
+    /// Rewrap the primary screen and scrollback for a new size.
+    fn reflow_primary(&mut self, cols: usize, rows: usize, points: &mut [&mut Point]) -> usize {
+        // Only rows up to the cursor or the last non-blank row take part; blank
+        // rows below them are just padding.
+        let cursor_row = self.cursor.row;
+        let last_used = (0..self.primary.rows())
+            .rev()
+            .find(|&r| r <= cursor_row || !is_blank_row(self.primary.row(r)))
+            .unwrap_or(0);
+        let cursor_index = self.scrollback.len() + cursor_row;
+
+        let mut old_rows: Vec<Row> = self.scrollback.drain().collect();
+        old_rows.extend((0..=last_used).map(|r| self.primary.row(r).clone()));
+
+        // Tracked points by row; those in the padding move to the last row
+        let mut points_by_row: HashMap<usize, Vec<usize>> = HashMap::new();
+        for (index, point) in points.iter().enumerate() {
+            points_by_row.entry(point.line.min(old_rows.len() - 1)).or_default().push(index);
+        }
+
+        // Join wrapped rows into logical lines, remembering where the cursor
+        // and the tracked points sit: (logical line, offset in it).
+        let mut lines: Vec<Vec<Cell>> = Vec::new();
+        let mut current: Vec<Cell> = Vec::new();
+        let mut cursor_line = (0, 0);
+        let mut point_lines = vec![(0, 0); points.len()];
+        for (index, row) in old_rows.into_iter().enumerate() {
+            if index == cursor_index {
+                cursor_line = (lines.len(), current.len() + self.cursor.col);
+            }
+            for &point in points_by_row.get(&index).into_iter().flatten() {
+                point_lines[point] = (lines.len(), current.len() + points[point].col);
+            }
+            let wrapped = row.wrapped;
+            let mut cells = row.cells;
+            if !wrapped {
+                while cells.last().is_some_and(|cell| *cell == Cell::default()) {
+                    cells.pop();
+                }
+            }
+            current.extend(cells);
+            if !wrapped {
+                lines.push(std::mem::take(&mut current));
+            }
+        }
+        if !current.is_empty() {
+            lines.push(current);
+        }
+
+        let mut points_by_line: HashMap<usize, Vec<usize>> = HashMap::new();
+        for (index, &(line, _)) in point_lines.iter().enumerate() {
+            points_by_line.entry(line).or_default().push(index);
+        }
+
+        // Rewrap every logical line at the new width. The cursor's offset
+        // comes first among the offsets mapped for a line.
+        let mut new_rows: Vec<Row> = Vec::new();
+        let mut cursor_position = (0, 0);
+        let mut point_positions = vec![(0, 0); points.len()];
+        for (index, cells) in lines.into_iter().enumerate() {
+            let tracked = points_by_line.get(&index).map(Vec::as_slice).unwrap_or_default();
+            let has_cursor = index == cursor_line.0;
+            let mut offsets: Vec<usize> = tracked.iter().map(|&point| point_lines[point].1).collect();
+            if has_cursor {
+                offsets.insert(0, cursor_line.1);
+            }
+            let (rows, positions) = wrap_line(cells, cols, &offsets);
+            let mut positions = positions.into_iter().map(|(row, col)| (new_rows.len() + row, col));
+            if has_cursor {
+                cursor_position = positions.next().unwrap_or_default();
+            }
+            for (&point, position) in tracked.iter().zip(positions) {
+                point_positions[point] = position;
+            }
+            new_rows.extend(rows);
+        }
+
+        // The bottom of the content becomes the bottom of the screen; rows
+        // above it go to scrollback.
+        let first_visible = new_rows.len().saturating_sub(rows).min(cursor_position.0);
+        let last_kept = (first_visible + rows).min(new_rows.len()) - 1;
+        let mut grid = Grid::new(cols, rows);
+        for (index, row) in new_rows.into_iter().enumerate() {
+            if index < first_visible {
+                self.scrollback.push(row);
+            } else if index - first_visible < rows {
+                *grid.row_mut(index - first_visible) = row;
+            }
+        }
+        self.primary = grid;
+        self.cursor.row = cursor_position.0 - first_visible;
+        self.cursor.col = cursor_position.1;
+
+        for (point, (line, col)) in points.iter_mut().zip(point_positions) {
+            **point = Point::new(line.min(last_kept), col);
+        }
+        first_visible - self.scrollback.len()
+    }
+
+    /// Continue on the next line after the right margin (auto-wrap).
+    fn wrap_to_next_line(&mut self) {
+        let row = self.cursor.row;
+        self.grid_mut().row_mut(row).wrapped = true;
+        self.carriage_return();
+        self.linefeed();
+    }
+
+    /// Blank both halves of a wide glyph if `col` is part of one, so
+    /// overwriting half of it never leaves the other half behind.
+    fn clear_wide_glyph(&mut self, row: usize, col: usize) {
+        let cols = self.cols();
+        let flags = self.grid().cell(row, col).attrs.flags;
+        let other = if flags.contains(CellFlags::WIDE) && col + 1 < cols {
+            col + 1
+        } else if flags.contains(CellFlags::WIDE_SPACER) && col > 0 {
+            col - 1
+        } else {
+            return;
+        };
+        let grid = self.grid_mut();
+        for index in [col, other] {
+            let cell = grid.cell_mut(row, index);
+            let attrs = cell.attrs;
+            *cell = Cell::blank(&attrs);
+        }
+    }
+
+    // --- Cursor movement ---
+
+    /// Move to an absolute position, honouring origin mode.
+    fn goto(&mut self, row: usize, col: usize) {
+        let (min_row, max_row) = if self.modes.origin {
+            (self.scroll_top, self.scroll_bottom)
+        } else {
+            (0, self.rows() - 1)
+        };
+        self.cursor.row = (min_row + row).min(max_row);
+        self.cursor.col = col.min(self.cols() - 1);
+        self.cursor.pending_wrap = false;
+    }
+
+    fn move_up(&mut self, count: usize) {
+        let limit = if self.cursor.row >= self.scroll_top { self.scroll_top } else { 0 };
+        self.cursor.row = self.cursor.row.saturating_sub(count).max(limit);
+        self.cursor.pending_wrap = false;
+    }
+
+    fn move_down(&mut self, count: usize) {
+        let limit = if self.cursor.row <= self.scroll_bottom {
+            self.scroll_bottom
+        } else {
+            self.rows() - 1
+        };
+        self.cursor.row = (self.cursor.row + count).min(limit);
+        self.cursor.pending_wrap = false;
+    }
+
+    fn move_forward(&mut self, count: usize) {
+        self.cursor.col = (self.cursor.col + count).min(self.cols() - 1);
+        self.cursor.pending_wrap = false;
+    }
+
+    fn move_backward(&mut self, count: usize) {
+        self.cursor.col = self.cursor.col.saturating_sub(count);
+        self.cursor.pending_wrap = false;
+    }
+
+    fn carriage_return(&mut self) {
+        self.cursor.col = 0;
+        self.cursor.pending_wrap = false;
+    }
+
+    fn linefeed(&mut self) {
+        if self.cursor.row == self.scroll_bottom {
+            self.scroll_up(1);
+        } else if self.cursor.row < self.rows() - 1 {
+            self.cursor.row += 1;
+        }
+        self.cursor.pending_wrap = false;
+    }
+
+    fn reverse_index(&mut self) {
+        if self.cursor.row == self.scroll_top {
+            self.scroll_down(1);
+        } else if self.cursor.row > 0 {
+            self.cursor.row -= 1;
+        }
+        self.cursor.pending_wrap = false;
+    }
+
+    fn tab_forward(&mut self, count: usize) {
+        for _ in 0..count {
+            let next = (self.cursor.col + 1..self.cols()).find(|&col| self.tab_stops[col]);
+            self.cursor.col = next.unwrap_or(self.cols() - 1);
+        }
+        self.cursor.pending_wrap = false;
+    }
+
+    fn tab_backward(&mut self, count: usize) {
+        for _ in 0..count {
+            let prev = (0..self.cursor.col).rev().find(|&col| self.tab_stops[col]);
+            self.cursor.col = prev.unwrap_or(0);
+        }
+        self.cursor.pending_wrap = false;
+    }
+
+    // --- Scrolling and erasing ---
+
+    fn scroll_up(&mut self, count: usize) {
+        let (top, bottom) = (self.scroll_top, self.scroll_bottom);
+        let attrs = self.cursor.attrs;
+        let removed = self.grid_mut().scroll_up(top, bottom, count, &attrs);
+
+        // Lines leaving the top of the primary screen are kept as history.
+        if top == 0 && !self.modes.alternate_screen {
+            for row in removed {
+                self.scrollback.push(row);
+                // Keep a scrolled-back view anchored on the same content.
+                if self.display_offset > 0 {
+                    self.display_offset = (self.display_offset + 1).min(self.scrollback.len());
+                }
+            }
+        }
+    }
+
+    fn scroll_down(&mut self, count: usize) {
+        let (top, bottom) = (self.scroll_top, self.scroll_bottom);
+        let attrs = self.cursor.attrs;
+        self.grid_mut().scroll_down(top, bottom, count, &attrs);
+    }
+
+    fn insert_lines(&mut self, count: usize) {
+        if self.cursor.row < self.scroll_top || self.cursor.row > self.scroll_bottom {
+            return;
+        }
+        let (row, bottom) = (self.cursor.row, self.scroll_bottom);
+        let attrs = self.cursor.attrs;
+        self.grid_mut().scroll_down(row, bottom, count, &attrs);
+        self.carriage_return();
+    }
+
+    fn delete_lines(&mut self, count: usize) {
+        if self.cursor.row < self.scroll_top || self.cursor.row > self.scroll_bottom {
+            return;
+        }
+        let (row, bottom) = (self.cursor.row, self.scroll_bottom);
+        let attrs = self.cursor.attrs;
+        self.grid_mut().scroll_up(row, bottom, count, &attrs);
+        self.carriage_return();
+    }
+
+    fn erase_in_display(&mut self, mode: u16) {
+        let attrs = self.cursor.attrs;
+        let (row, col) = (self.cursor.row, self.cursor.col);
+        let cols = self.cols();
+        let rows = self.rows();
+        let grid = self.grid_mut();
+        match mode {
+            0 => {
+                grid.row_mut(row).erase(col, cols, &attrs);
+                grid.row_mut(row).wrapped = false;
+                for r in row + 1..rows {
+                    *grid.row_mut(r) = Row::blank(cols, &attrs);
+                }
+            }
+            1 => {
+                for r in 0..row {
+                    *grid.row_mut(r) = Row::blank(cols, &attrs);
+                }
+                grid.row_mut(row).erase(0, col + 1, &attrs);
+            }
+            2 => grid.clear(&attrs),
+            3 => {
+                self.scrollback.clear();
+                self.display_offset = 0;
+            }
+            _ => debug!("Unhandled ED mode {}", mode),
+        }
+    }
+
+    fn erase_in_line(&mut self, mode: u16) {
+        let attrs = self.cursor.attrs;
+        let (row, col) = (self.cursor.row, self.cursor.col);
+        let cols = self.cols();
+        let line = self.grid_mut().row_mut(row);
+        match mode {
+            0 => {
+                line.erase(col, cols, &attrs);
+                line.wrapped = false;
+            }
+            1 => line.erase(0, col + 1, &attrs),
+            2 => {
+                line.erase(0, cols, &attrs);
+                line.wrapped = false;
+            }
+            _ => debug!("Unhandled EL mode {}", mode),
+        }
+    }
+
+    // --- Screen switching and cursor save/restore ---
+
+    fn save_cursor(&mut self) {
+        let saved = SavedCursor {
+            cursor: self.cursor,
+            origin: self.modes.origin,
+            charsets: self.charsets,
+            active_charset: self.active_charset,
+        };
+        if self.modes.alternate_screen {
+            self.saved_alternate = Some(saved);
+        } else {
+            self.saved_primary = Some(saved);
+        }
+    }
+
+    fn restore_cursor(&mut self) {
+        let saved = if self.modes.alternate_screen {
+            self.saved_alternate
+        } else {
+            self.saved_primary
+        };
+        match saved {
+            Some(saved) => {
+                self.cursor = saved.cursor;
+                self.cursor.row = self.cursor.row.min(self.rows() - 1);
+                self.cursor.col = self.cursor.col.min(self.cols() - 1);
+                self.modes.origin = saved.origin;
+                self.charsets = saved.charsets;
+                self.active_charset = saved.active_charset;
+            }
+            None => {
+                self.cursor = Cursor::default();
+                self.modes.origin = false;
+            }
+        }
+    }
+
+    fn enter_alternate_screen(&mut self, clear: bool) {
+        if self.modes.alternate_screen {
+            return;
+        }
+        self.modes.alternate_screen = true;
+        self.display_offset = 0;
+        if clear {
+            let attrs = self.cursor.attrs;
+            self.alternate.clear(&attrs);
+        }
+    }
+
+    fn exit_alternate_screen(&mut self) {
+        self.modes.alternate_screen = false;
+    }
+
+    fn set_mode(&mut self, mode: u16, enable: bool) {
+        match mode {
+            4 => self.modes.insert = enable,
+            20 => self.modes.linefeed_newline = enable,
+            _ => debug!("Unhandled ANSI mode {} ({})", mode, enable),
+        }
+    }
+
+    fn set_private_mode(&mut self, mode: u16, enable: bool) {
+        match mode {
+            1 => self.modes.application_cursor_keys = enable,
+            6 => {
+                self.modes.origin = enable;
+                self.goto(0, 0);
+            }
+            7 => self.modes.auto_wrap = enable,
+            9 => self.set_mouse_tracking(MouseTracking::X10, enable),
+            12 => {} // Cursor blink, rendering choice left to the GUI.
+            25 => self.modes.show_cursor = enable,
+            47 => {
+                if enable {
+                    self.enter_alternate_screen(false);
+                } else {
+                    self.exit_alternate_screen();
+                }
+            }
+            1000 => self.set_mouse_tracking(MouseTracking::Normal, enable),
+            1002 => self.set_mouse_tracking(MouseTracking::ButtonEvent, enable),
+            1003 => self.set_mouse_tracking(MouseTracking::AnyEvent, enable),
+            1004 => self.modes.focus_events = enable,
+            1006 => self.modes.sgr_mouse = enable,
+            1047 => {
+                if enable {
+                    self.enter_alternate_screen(false);
+                } else {
+                    let attrs = self.cursor.attrs;
+                    self.alternate.clear(&attrs);
+                    self.exit_alternate_screen();
+                }
+            }
+            1048 => {
+                if enable {
+                    self.save_cursor();
+                } else {
+                    self.restore_cursor();
+                }
+            }
+            1049 => {
+                if enable {
+                    self.save_cursor();
+                    self.enter_alternate_screen(true);
+                } else {
+                    self.exit_alternate_screen();
+                    self.restore_cursor();
+                }
+            }
+            2004 => self.modes.bracketed_paste = enable,
+            _ => debug!("Unhandled DEC private mode {} ({})", mode, enable),
+        }
+    }
+
+    fn set_mouse_tracking(&mut self, tracking: MouseTracking, enable: bool) {
+        if enable {
+            self.modes.mouse_tracking = tracking;
+        } else if self.modes.mouse_tracking == tracking {
+            self.modes.mouse_tracking = MouseTracking::Off;
+        }
+    }
+
+    /// Soft terminal reset (DECSTR).
+    fn soft_reset(&mut self) {
+        self.modes.insert = false;
+        self.modes.origin = false;
+        self.modes.auto_wrap = true;
+        self.modes.show_cursor = true;
+        self.modes.application_cursor_keys = false;
+        self.modes.application_keypad = false;
+        self.scroll_top = 0;
+        self.scroll_bottom = self.rows() - 1;
+        self.cursor.attrs = CellAttributes::default();
+        self.cursor.pending_wrap = false;
+        self.charsets = [Charset::Ascii; 2];
+        self.active_charset = 0;
+        self.saved_primary = None;
+        self.saved_alternate = None;
+    }
+
+    fn set_scroll_region(&mut self, top: usize, bottom: usize) {
+        let bottom = bottom.min(self.rows() - 1);
+        if top < bottom {
+            self.scroll_top = top;
+            self.scroll_bottom = bottom;
+            self.goto(0, 0);
+        }
+    }
+
+    // --- Graphic rendition ---
+
+    fn set_graphics_rendition(&mut self, params: &Params) {
+        if params.is_empty() {
+            self.cursor.attrs = CellAttributes::default();
+            return;
+        }
+
+        let groups: Vec<&[u16]> = params.iter().collect();
+        let attrs = &mut self.cursor.attrs;
+        let mut i = 0;
+        while i < groups.len() {
+            let group = groups[i];
+            match group[0] {
+                0 => *attrs = CellAttributes::default(),
+                1 => attrs.flags.insert(CellFlags::BOLD),
+                2 => attrs.flags.insert(CellFlags::DIM),
+                3 => attrs.flags.insert(CellFlags::ITALIC),
+                4 => {
+                    // `4:0` turns underline off; other sub-parameters select styles.
+                    if group.get(1) == Some(&0) {
+                        attrs.flags.remove(CellFlags::UNDERLINE);
+                    } else {
+                        attrs.flags.insert(CellFlags::UNDERLINE);
+                    }
+                }
+                5 | 6 => attrs.flags.insert(CellFlags::BLINK),
+                7 => attrs.flags.insert(CellFlags::INVERSE),
+                8 => attrs.flags.insert(CellFlags::HIDDEN),
+                9 => attrs.flags.insert(CellFlags::STRIKETHROUGH),
+                21 => attrs.flags.insert(CellFlags::UNDERLINE),
+                22 => attrs.flags.remove(CellFlags::BOLD | CellFlags::DIM),
+                23 => attrs.flags.remove(CellFlags::ITALIC),
+                24 => attrs.flags.remove(CellFlags::UNDERLINE),
+                25 => attrs.flags.remove(CellFlags::BLINK),
+                27 => attrs.flags.remove(CellFlags::INVERSE),
+                28 => attrs.flags.remove(CellFlags::HIDDEN),
+                29 => attrs.flags.remove(CellFlags::STRIKETHROUGH),
+                code @ 30..=37 => attrs.fg = Color::Indexed((code - 30) as u8),
+                38 => {
+                    let (color, consumed) = parse_extended_color(group, &groups[i + 1..]);
+                    if let Some(color) = color {
+                        attrs.fg = color;
+                    }
+                    i += consumed;
+                }
+                39 => attrs.fg = Color::Default,
+                code @ 40..=47 => attrs.bg = Color::Indexed((code - 40) as u8),
+                48 => {
+                    let (color, consumed) = parse_extended_color(group, &groups[i + 1..]);
+                    if let Some(color) = color {
+                        attrs.bg = color;
+                    }
+                    i += consumed;
+                }
+                49 => attrs.bg = Color::Default,
+                code @ 90..=97 => attrs.fg = Color::Indexed((code - 90 + 8) as u8),
+                code @ 100..=107 => attrs.bg = Color::Indexed((code - 100 + 8) as u8),
+                code => trace!("Unhandled SGR parameter {}", code),
+            }
+            i += 1;
+        }
+    }
+
+    /// OSC 8: start printing under the link to `uri`, or end the current
+    /// link when `uri` is empty. `params` is a `:`-separated list of
+    /// `key=value` pairs, of which only `id` is defined.
+    fn set_hyperlink(&mut self, params: &str, uri: &str) {
+        if uri.is_empty() {
+            self.hyperlink = None;
+            return;
+        }
+        let id = params
+            .split(':')
+            .find_map(|pair| pair.strip_prefix("id="))
+            .filter(|id| !id.is_empty())
+            .map(str::to_string);
+        let link = Hyperlink {
+            id,
+            uri: uri.to_string(),
+        };
+        let next = HyperlinkId(self.hyperlinks.len() as u32);
+        let id = *self.hyperlink_ids.entry(link.clone()).or_insert(next);
+        if id == next {
+            self.hyperlinks.push(link);
+        }
+        self.hyperlink = Some(id);
+    }
+
+    /// OSC 133 ; A|B|C|D [; exit status] [; options]. Marks are queued with
+    /// the cursor position; those sent while the alternate screen is shown
+    /// have no place in the history and are dropped.
+    fn shell_mark(&mut self, params: &[&str]) {
+        let mark = match params[1] {
+            "A" => ShellMark::PromptStart,
+            "B" => ShellMark::CommandStart,
+            "C" => ShellMark::OutputStart,
+            "D" => ShellMark::CommandEnd(params.get(2).and_then(|code| code.parse().ok())),
+            _ => {
+                debug!("Unhandled OSC 133 mark: {:?}", params);
+                return;
+            }
+        };
+        if self.modes.alternate_screen {
+            return;
+        }
+        self.shell_events.push(ShellEvent {
+            mark,
+            point: Point::new(self.history_len() + self.cursor.row, self.cursor.col),
+            evicted: self.scrollback.evicted(),
+        });
+    }
+
+    fn report_device_status(&mut self, request: u16) {
+        match request {
+            5 => self.responses.extend_from_slice(b"\x1b[0n"),
+            6 => {
+                let row = if self.modes.origin {
+                    self.cursor.row.saturating_sub(self.scroll_top)
+                } else {
+                    self.cursor.row
+                };
+                let reply = format!("\x1b[{};{}R", row + 1, self.cursor.col + 1);
+                self.responses.extend_from_slice(reply.as_bytes());
+            }
+            _ => debug!("Unhandled DSR request {}", request),
+        }
+    }
+
+    fn map_charset(&self, c: char) -> char {
+        match self.charsets[self.active_charset] {
+            Charset::Ascii => c,
+            Charset::DecSpecialGraphics => dec_special_graphics(c),
+        }
+    }
+}
+
+impl Perform for Screen {
+    fn print(&mut self, c: char) {
+        let c = self.map_charset(c);
+        let width = c.width().unwrap_or(0);
+        if width == 0 {
+            // Combining marks have no cell of their own.
+            trace!("Dropping zero-width character {:?}", c);
+            return;
+        }
+        let cols = self.cols();
+
+        if self.cursor.pending_wrap && self.modes.auto_wrap {
+            self.wrap_to_next_line();
+        }
+
+        // A wide glyph that does not fit in the last column moves to the next line.
+        if width == 2 && self.cursor.col + 1 >= cols && cols > 1 {
+            if self.modes.auto_wrap {
+                let (row, col) = (self.cursor.row, self.cursor.col);
+                self.clear_wide_glyph(row, col);
+                *self.grid_mut().cell_mut(row, col) = Cell::default();
+                self.wrap_to_next_line();
+            } else {
+                self.cursor.col = cols - 2;
+            }
+        }
+
+        let (row, col) = (self.cursor.row, self.cursor.col);
+        let attrs = CellAttributes {
+            link: self.hyperlink,
+            ..self.cursor.attrs
+        };
+        if self.modes.insert {
+            self.grid_mut().insert_cells(row, col, width, &attrs);
+        }
+        for covered in col..(col + width).min(cols) {
+            self.clear_wide_glyph(row, covered);
+        }
+
+        let cell = self.grid_mut().cell_mut(row, col);
+        cell.c = c;
+        cell.attrs = attrs;
+        if width == 2 && col + 1 < cols {
+            cell.attrs.flags.insert(CellFlags::WIDE);
+            let spacer = self.grid_mut().cell_mut(row, col + 1);
+            spacer.c = ' ';
+            spacer.attrs = attrs;
+            spacer.attrs.flags.insert(CellFlags::WIDE_SPACER);
+        }
+        self.last_printed = Some(c);
+
+        if col + width < cols {
+            self.cursor.col += width;
+        } else {
+            self.cursor.col = cols - 1;
+            self.cursor.pending_wrap = self.modes.auto_wrap;
+        }
+    }
+
+    fn execute(&mut self, byte: u8) {
+        match byte {
+            0x07 => self.bell = true,
+            0x08 => self.move_backward(1),
+            0x09 => self.tab_forward(1),
+            0x0a..=0x0c => {
+                self.linefeed();
+                if self.modes.linefeed_newline {
+                    self.carriage_return();
+                }
+            }
+            0x0d => self.carriage_return(),
+            0x0e => self.active_charset = 1,
+            0x0f => self.active_charset = 0,
+            0x84 => self.linefeed(),
+            0x85 => {
+                self.carriage_return();
+                self.linefeed();
+            }
+            0x88 => {
+                let col = self.cursor.col;
+                self.tab_stops[col] = true;
+            }
+            0x8d => self.reverse_index(),
+            _ => trace!("Ignored control character {:#04x}", byte),
+        }
+    }
+
+    fn csi_dispatch(&mut self, params: &Params, intermediates: &[char], ignore: bool, action: char) {
+        if ignore {
+            return;
+        }
+        let count = |index: usize| params.get_or(index, 1) as usize;
+
+        match (intermediates, action) {
+            ([], '@') => {
+                let (row, col) = (self.cursor.row, self.cursor.col);
+                let attrs = self.cursor.attrs;
+                self.grid_mut().insert_cells(row, col, count(0), &attrs);
+            }
+            ([], 'A') => self.move_up(count(0)),
+            ([], 'B') | ([], 'e') => self.move_down(count(0)),
+            ([], 'C') | ([], 'a') => self.move_forward(count(0)),
+            ([], 'D') => self.move_backward(count(0)),
+            ([], 'E') => {
+                self.move_down(count(0));
+                self.carriage_return();
+            }
+            ([], 'F') => {
+                self.move_up(count(0));
+                self.carriage_return();
+            }
+            ([], 'G') | ([], '`') => {
+                self.cursor.col = (count(0) - 1).min(self.cols() - 1);
+                self.cursor.pending_wrap = false;
+            }
+            ([], 'H') | ([], 'f') => self.goto(count(0) - 1, count(1) - 1),
+            ([], 'I') => self.tab_forward(count(0)),
+            ([], 'J') | (['?'], 'J') => self.erase_in_display(params.raw(0)),
+            ([], 'K') | (['?'], 'K') => self.erase_in_line(params.raw(0)),
+            ([], 'L') => self.insert_lines(count(0)),
+            ([], 'M') => self.delete_lines(count(0)),
+            ([], 'P') => {
+                let (row, col) = (self.cursor.row, self.cursor.col);
+                let attrs = self.cursor.attrs;
+                self.grid_mut().delete_cells(row, col, count(0), &attrs);
+            }
+            ([], 'S') => self.scroll_up(count(0)),
+            ([], 'T') => self.scroll_down(count(0)),
+            ([], 'X') => {
+                let (row, col) = (self.cursor.row, self.cursor.col);
+                let attrs = self.cursor.attrs;
+                self.grid_mut().row_mut(row).erase(col, col + count(0), &attrs);
+            }
+            ([], 'Z') => self.tab_backward(count(0)),
+            ([], 'b') => {
+                if let Some(c) = self.last_printed {
+                    for _ in 0..count(0) {
+                        self.print(c);
+                    }
+                }
+            }
+            ([], 'c') => {
+                if params.raw(0) == 0 {
+                    // VT220 with ANSI colour.
+                    self.responses.extend_from_slice(b"\x1b[?62;22c");
+                }
+            }
+            (['>'], 'c') => self.responses.extend_from_slice(b"\x1b[>0;10;1c"),
+            ([], 'd') => {
+                let row = count(0) - 1;
+                let col = self.cursor.col;
+                self.goto(row, col);
+            }
+            ([], 'g') => match params.raw(0) {
+                0 => {
+                    let col = self.cursor.col;
+                    self.tab_stops[col] = false;
+                }
+                3 => self.tab_stops.iter_mut().for_each(|stop| *stop = false),
+                _ => {}
+            },
+            ([], 'h') => params.iter().for_each(|g| self.set_mode(g[0], true)),
+            ([], 'l') => params.iter().for_each(|g| self.set_mode(g[0], false)),
+            (['?'], 'h') => params.iter().for_each(|g| self.set_private_mode(g[0], true)),
+            (['?'], 'l') => params.iter().for_each(|g| self.set_private_mode(g[0], false)),
+            ([], 'm') => self.set_graphics_rendition(params),
+            ([], 'n') => self.report_device_status(params.raw(0)),
+            ([], 'r') => {
+                let top = count(0) - 1;
+                let bottom = params.get_or(1, self.rows() as u16) as usize - 1;
+                self.set_scroll_region(top, bottom);
+            }
+            ([], 's') => self.save_cursor(),
+            ([], 'u') => self.restore_cursor(),
+            ([], 't') => {} // Window manipulation is not supported.
+            ([' '], 'q') => {
+                self.cursor_shape = match params.raw(0) {
+                    3 | 4 => CursorShape::Underline,
+                    5 | 6 => CursorShape::Bar,
+                    _ => CursorShape::Block,
+                };
+            }
+            (['!'], 'p') => self.soft_reset(),
+            _ => debug!(
+                "Unhandled CSI sequence: params={:?} intermediates={:?} action={}",
+                params, intermediates, action
+            ),
+        }
+    }
+
+    fn esc_dispatch(&mut self, intermediates: &[char], ignore: bool, byte: char) {
+        if ignore {
+            return;
+        }
+        match (intermediates, byte) {
+            ([], '7') => self.save_cursor(),
+            ([], '8') => self.restore_cursor(),
+            ([], 'D') => self.linefeed(),
+            ([], 'E') => {
+                self.carriage_return();
+                self.linefeed();
+            }
+            ([], 'H') => {
+                let col = self.cursor.col;
+                self.tab_stops[col] = true;
+            }
+            ([], 'M') => self.reverse_index(),
+            ([], 'c') => self.reset(),
+            ([], '=') => self.modes.application_keypad = true,
+            ([], '>') => self.modes.application_keypad = false,
+            (['('], designator) | ([')'], designator) => {
+                let slot = if intermediates[0] == '(' { 0 } else { 1 };
+                self.charsets[slot] = match designator {
+                    '0' => Charset::DecSpecialGraphics,
+                    _ => Charset::Ascii,
+                };
+            }
+            (['#'], '8') => {
+                // DECALN: fill the screen with 'E'.
+                let (cols, rows) = (self.cols(), self.rows());
+                let grid = self.grid_mut();
+                for row in 0..rows {
+                    for col in 0..cols {
+                        grid.cell_mut(row, col).c = 'E';
+                    }
+                }
+            }
+            _ => debug!("Unhandled ESC sequence: intermediates={:?} byte={}", intermediates, byte),
+        }
+    }
+
+    fn osc_dispatch(&mut self, params: &[&str], _bell_terminated: bool) {
+        match params.first().copied() {
+            Some("0") | Some("2") => self.title = params[1..].join(";"),
+            Some("1") => {} // Icon name.
+            // OSC 8 ; params ; URI: the URI may itself contain semicolons
+            Some("8") if params.len() >= 3 => self.set_hyperlink(params[1], &params[2..].join(";")),
+            // OSC 7 ; file://host/path
+            Some("7") if params.len() >= 2 => match file_uri_path(&params[1..].join(";")) {
+                Some(path) => self.working_directory = Some(path),
+                None => debug!("Unhandled OSC 7 directory: {:?}", params),
+            },
+            Some("133") if params.len() >= 2 => self.shell_mark(params),
+            _ => debug!("Unhandled OSC sequence: {:?}", params),
+        }
+    }
+}
+
+/// Parse the colour following SGR 38/48. Supports both the colon form
+/// (`38:2::r:g:b`, `38:5:n`) and the legacy semicolon form (`38;2;r;g;b`).
+/// Returns the colour and the number of following parameter groups consumed.
+fn parse_extended_color(group: &[u16], rest: &[&[u16]]) -> (Option<Color>, usize) {
+    let channel = |v: u16| v.min(255) as u8;
+
+    if group.len() > 1 {
+        let color = match group[1] {
+            5 => group.get(2).map(|&n| Color::Indexed(channel(n))),
+            2 => {
+                // The colour space id between the selector and the channels is optional.
+                let rgb = match group.len() {
+                    5 => Some(&group[2..5]),
+                    n if n >= 6 => Some(&group[3..6]),
+                    _ => None,
+                };
+                rgb.map(|c| Color::Rgb(channel(c[0]), channel(c[1]), channel(c[2])))
+            }
+            _ => None,
+        };
+        return (color, 0);
+    }
+
+    match rest.first().map(|g| g[0]) {
+        Some(5) => (rest.get(1).map(|g| Color::Indexed(channel(g[0]))), 2.min(rest.len())),
+        Some(2) if rest.len() >= 4 => (
+            Some(Color::Rgb(channel(rest[1][0]), channel(rest[2][0]), channel(rest[3][0]))),
+            4,
+        ),
+        Some(2) => (None, rest.len()),
+        _ => (None, 0),
+    }
+}
+
+/// Line drawing characters selected with `ESC ( 0`.
+fn dec_special_graphics(c: char) -> char {
+    match c {
+        '`' => '◆',
+        'a' => '▒',
+        'b' => '␉',
+        'c' => '␌',
+        'd' => '␍',
+        'e' => '␊',
+        'f' => '°',
+        'g' => '±',
+        'h' => '␤',
+        'i' => '␋',
+        'j' => '┘',
+        'k' => '┐',
+        'l' => '┌',
+        'm' => '└',
+        'n' => '┼',
+        'o' => '⎺',
+        'p' => '⎻',
+        'q' => '─',
+        'r' => '⎼',
+        's' => '⎽',
+        't' => '├',
+        'u' => '┤',
+        'v' => '┴',
+        'w' => '┬',
+        'x' => '│',
+        'y' => '≤',
+        'z' => '≥',
+        '{' => 'π',
+        '|' => '≠',
+        '}' => '£',
+        '~' => '·',
+        _ => c,
+    }
+}
+
+/// Split a logical line into rows of `cols` cells, never separating the two
+/// halves of a wide glyph. Also returns the `(row, col)` each of `offsets`
+/// moves to; an offset past the end of the text stays on the last row.
+fn wrap_line(cells: Vec<Cell>, cols: usize, offsets: &[usize]) -> (Vec<Row>, Vec<(usize, usize)>) {
+    let len = cells.len();
+    let mut rows = Vec::new();
+    let mut current: Vec<Cell> = Vec::with_capacity(cols);
+    let mut positions = vec![(0, 0); offsets.len()];
+
+    for (index, cell) in cells.into_iter().enumerate() {
+        let wide = cell.attrs.flags.contains(CellFlags::WIDE);
+        if current.len() == cols || (wide && cols > 1 && current.len() + 1 == cols) {
+            current.resize(cols, Cell::default());
+            rows.push(Row {
+                cells: std::mem::replace(&mut current, Vec::with_capacity(cols)),
+                wrapped: true,
+            });
+        }
+        for (position, _) in positions.iter_mut().zip(offsets).filter(|&(_, &offset)| offset == index) {
+            *position = (rows.len(), current.len());
+        }
+        current.push(cell);
+    }
+
+    for (position, &offset) in positions.iter_mut().zip(offsets) {
+        if offset >= len {
+            *position = (rows.len(), (current.len() + offset - len).min(cols - 1));
+        }
+    }
+    current.resize(cols, Cell::default());
+    rows.push(Row {
+        cells: current,
+        wrapped: false,
+    });
+    (rows, positions)
+}
+
+fn is_blank_row(row: &Row) -> bool {
+    row.cells.iter().all(|cell| *cell == Cell::default())
+}
+
+fn default_tab_stops(cols: usize) -> Vec<bool> {
+    (0..cols).map(|col| col % 8 == 0 && col != 0).collect()
+}
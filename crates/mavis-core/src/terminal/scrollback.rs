@@ -0,0 +1,156 @@
+// Bounded scrollback storage for lines that scrolled off the top of the screen
+
+use super::grid::Row;
+use std::collections::VecDeque;
+use std::ops::Index;
+
+/// Ring buffer of rows, oldest first. Once `capacity` rows are stored, pushing
+/// a new row evicts the oldest one.
+#[derive(Debug, Clone)]
+pub struct Scrollback {
+    lines: VecDeque<Row>,
+    capacity: usize,
+    /// Rows evicted so far, so callers holding line indices can
+    /// tell how far the buffer has shifted.
+    evicted: usize,
+}
+
+impl Scrollback {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            // Grow lazily; most sessions never fill a large scrollback.
+            lines: VecDeque::with_capacity(capacity.min(1024)),
+            capacity,
+            evicted: 0,
+        }
+    }
+
+    /// Append a row, evicting the oldest row when full.
+    pub fn push(&mut self, row: Row) {
+        if self.capacity == 0 {
+            // The row is evicted as soon as it arrives
+            self.evicted = self.evicted.wrapping_add(1);
+            return;
+        }
+        if self.lines.len() == self.capacity {
+            self.lines.pop_front();
+            self.evicted = self.evicted.wrapping_add(1);
+        }
+        self.lines.push_back(row);
+    }
+
+    /// Remove and return the most recent row.
+    pub fn pop(&mut self) -> Option<Row> {
+        self.lines.pop_back()
+    }
+
+    /// Row at `index`, where 0 is the oldest stored row.
+    pub fn get(&self, index: usize) -> Option<&Row> {
+        self.lines.get(index)
+    }
+
+    /// Number of rows evicted by `push`, `set_capacity` or `clear` since
+    /// the buffer was created.
+    pub fn evicted(&self) -> usize {
+        self.evicted
+    }
+
+    pub fn len(&self) -> usize {
+        self.lines.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Change the maximum number of rows, dropping the oldest rows if needed.
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        while self.lines.len() > capacity {
+            self.lines.pop_front();
+            self.evicted = self.evicted.wrapping_add(1);
+        }
+    }
+
+    /// Remove every row, counting them as evicted.
+    pub fn clear(&mut self) {
+        self.evicted = self.evicted.wrapping_add(self.lines.len());
+        self.lines.clear();
+    }
+
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &Row> + ExactSizeIterator {
+        self.lines.iter()
+    }
+
+    /// Remove all rows, oldest first.
+    pub fn drain(&mut self) -> impl Iterator<Item = Row> + '_ {
+        self.lines.drain(..)
+    }
+}
+
+impl Index<usize> for Scrollback {
+    type Output = Row;
+
+    fn index(&self, index: usize) -> &Row {
+        &self.lines[index]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(text: &str) -> Row {
+        let mut row = Row::new(text.len().max(1));
+        for (cell, c) in row.cells.iter_mut().zip(text.chars()) {
+            cell.c = c;
+        }
+        row
+    }
+
+    #[test]
+    fn test_evicts_oldest_when_full() {
+        let mut scrollback = Scrollback::new(2);
+        scrollback.push(row("a"));
+        scrollback.push(row("b"));
+        scrollback.push(row("c"));
+        assert_eq!(scrollback.len(), 2);
+        assert_eq!(scrollback.get(0).unwrap().text(), "b");
+        assert_eq!(scrollback.get(1).unwrap().text(), "c");
+    }
+
+    #[test]
+    fn test_shrinking_capacity() {
+        let mut scrollback = Scrollback::new(5);
+        for text in ["a", "b", "c", "d"] {
+            scrollback.push(row(text));
+        }
+        scrollback.set_capacity(1);
+        assert_eq!(scrollback.len(), 1);
+        assert_eq!(scrollback.evicted(), 3);
+        assert_eq!(scrollback.pop().unwrap().text(), "d");
+    }
+
+    #[test]
+    fn test_zero_capacity_keeps_nothing() {
+        let mut scrollback = Scrollback::new(0);
+        scrollback.push(row("a"));
+        assert!(scrollback.is_empty());
+        assert_eq!(scrollback.evicted(), 1);
+    }
+
+    #[test]
+    fn test_clear_counts_evicted_rows() {
+        let mut scrollback = Scrollback::new(5);
+        for text in ["a", "b"] {
+            scrollback.push(row(text));
+        }
+        scrollback.clear();
+        assert!(scrollback.is_empty());
+        assert_eq!(scrollback.evicted(), 2);
+    }
+}
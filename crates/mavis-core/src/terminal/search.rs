@@ -0,0 +1,285 @@
+// Search through the scrollback and screen
+//
+// Queries are plain text or regular expressions, matched against logical
+// lines: rows joined across soft wraps, so a match can continue onto the
+// next row. Matches are addressed like selections, by line index as in
+// `Screen::line`.
+
+use super::grid::CellFlags;
+use super::screen::Screen;
+use super::selection::{Point, Selection, SelectionMode};
+use regex::{Regex, RegexBuilder};
+
+/// How a query is interpreted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SearchOptions {
+    /// Treat the query as a regular expression rather than literal text.
+    pub regex: bool,
+    pub case_sensitive: bool,
+}
+
+/// A match, from its first to its last cell (inclusive).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SearchMatch {
+    pub start: Point,
+    pub end: Point,
+}
+
+impl SearchMatch {
+    /// Whether the cell at `line`, `col` is part of the match.
+    pub fn contains(&self, line: usize, col: usize) -> bool {
+        let point = Point::new(line, col);
+        point >= self.start && point <= self.end
+    }
+
+    /// The matched text, with soft wraps joined.
+    pub fn text(&self, screen: &Screen) -> String {
+        let mut selection = Selection::new(SelectionMode::Simple, self.start);
+        selection.update(self.end);
+        selection.text(screen).unwrap_or_default()
+    }
+}
+
+/// An active search: the compiled query, its matches, and the match the
+/// user navigated to.
+#[derive(Debug, Clone)]
+pub struct Search {
+    query: String,
+    options: SearchOptions,
+    pattern: Regex,
+    matches: Vec<SearchMatch>,
+    current: Option<usize>,
+}
+
+impl Search {
+    /// Compile `query`. Fails for an invalid regular expression.
+    pub fn new(query: &str, options: SearchOptions) -> Result<Self, regex::Error> {
+        Ok(Self {
+            query: query.to_string(),
+            options,
+            pattern: compile(query, options)?,
+            matches: Vec::new(),
+            current: None,
+        })
+    }
+
+    pub fn query(&self) -> &str {
+        &self.query
+    }
+
+    pub fn options(&self) -> SearchOptions {
+        self.options
+    }
+
+    /// All matches, oldest first.
+    pub fn matches(&self) -> &[SearchMatch] {
+        &self.matches
+    }
+
+    /// The match navigated to, if any.
+    pub fn current(&self) -> Option<&SearchMatch> {
+        self.matches.get(self.current?)
+    }
+
+    /// Index of the current match in `matches()`.
+    pub fn current_index(&self) -> Option<usize> {
+        self.current
+    }
+
+    /// Find the matches in `screen` again. The current match stays current
+    /// if it is still there after moving up by `evicted` lines, the number
+    /// of lines dropped from the scrollback since the last update.
+    pub fn update(&mut self, screen: &Screen, evicted: usize) {
+        let current = self.current().and_then(|m| {
+            let line = m.start.line.checked_sub(evicted)?;
+            Some(Point::new(line, m.start.col))
+        });
+        self.matches = find_matches(screen, &self.pattern);
+        self.current = current.and_then(|start| self.matches.iter().position(|m| m.start == start));
+    }
+
+    /// Move to the next match towards newer output, wrapping around.
+    pub fn next_match(&mut self) -> Option<SearchMatch> {
+        let len = self.matches.len();
+        if len == 0 {
+            return None;
+        }
+        let index = self.current.map_or(0, |i| (i + 1) % len);
+        self.current = Some(index);
+        Some(self.matches[index])
+    }
+
+    /// Move to the previous match towards older output, wrapping around.
+    /// Starts from the newest match.
+    pub fn previous_match(&mut self) -> Option<SearchMatch> {
+        let len = self.matches.len();
+        if len == 0 {
+            return None;
+        }
+        let index = self.current.map_or(len - 1, |i| (i + len - 1) % len);
+        self.current = Some(index);
+        Some(self.matches[index])
+    }
+}
+
+/// Find every match of `query` in `screen` without keeping search state,
+/// e.g. for scripts scanning output.
+pub fn find(screen: &Screen, query: &str, options: SearchOptions) -> Result<Vec<SearchMatch>, regex::Error> {
+    Ok(find_matches(screen, &compile(query, options)?))
+}
+
+fn compile(query: &str, options: SearchOptions) -> Result<Regex, regex::Error> {
+    let pattern = if options.regex {
+        query.to_string()
+    } else {
+        regex::escape(query)
+    };
+    RegexBuilder::new(&pattern)
+        .case_insensitive(!options.case_sensitive)
+        .build()
+}
+
+fn find_matches(screen: &Screen, pattern: &Regex) -> Vec<SearchMatch> {
+    let mut matches = Vec::new();
+    let total = screen.total_lines();
+    let mut line = 0;
+    while line < total {
+        let (logical, next) = LogicalLine::read(screen, line);
+        line = next;
+        for found in pattern.find_iter(&logical.text) {
+            if !found.is_empty() {
+                let (start, end) = logical.span(screen, found.start(), found.end());
+                matches.push(SearchMatch { start, end });
+            }
+        }
+    }
+    matches
+}
+
+/// One logical line: rows joined across soft wraps, with the cell each
+/// character came from.
+pub(super) struct LogicalLine {
+    pub text: String,
+    // Byte offset in `text` of each character, and the cell it came from
+    cells: Vec<(usize, Point)>,
+}
+
+impl LogicalLine {
+    /// Gather the logical line starting at `line`. Also returns the line
+    /// after it.
+    pub fn read(screen: &Screen, mut line: usize) -> (Self, usize) {
+        let mut logical = LogicalLine {
+            text: String::new(),
+            cells: Vec::new(),
+        };
+        while let Some(row) = screen.line(line) {
+            for (col, cell) in row.cells.iter().enumerate() {
+                if !cell.attrs.flags.contains(CellFlags::WIDE_SPACER) {
+                    logical.cells.push((logical.text.len(), Point::new(line, col)));
+                    logical.text.push(cell.c);
+                }
+            }
+            line += 1;
+            if !row.wrapped {
+                break;
+            }
+        }
+        (logical, line)
+    }
+
+    /// Gather the logical line that `line` is part of.
+    pub fn containing(screen: &Screen, mut line: usize) -> Self {
+        while line > 0 && screen.line(line - 1).is_some_and(|row| row.wrapped) {
+            line -= 1;
+        }
+        Self::read(screen, line).0
+    }
+
+    /// Byte offset in `text` of each character, and the cell it came from.
+    pub fn cells(&self) -> &[(usize, Point)] {
+        &self.cells
+    }
+
+    /// Byte offset in `text` of the character at `point`.
+    pub fn offset(&self, point: Point) -> Option<usize> {
+        let index = self.cells.binary_search_by_key(&point, |&(_, p)| p).ok()?;
+        Some(self.cells[index].0)
+    }
+
+    /// First and last cell of the non-empty byte range `start..end`.
+    pub fn span(&self, screen: &Screen, start: usize, end: usize) -> (Point, Point) {
+        let first = self.cells.partition_point(|&(offset, _)| offset < start);
+        let last = self.cells.partition_point(|&(offset, _)| offset < end) - 1;
+        let end = self.cells[last].1;
+        // A wide glyph covers its spacer cell too
+        let wide = screen
+            .line(end.line)
+            .and_then(|row| row.cells.get(end.col))
+            .is_some_and(|cell| cell.attrs.flags.contains(CellFlags::WIDE));
+        let end = if wide { Point::new(end.line, end.col + 1) } else { end };
+        (self.cells[first].1, end)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terminal::terminal_with;
+
+    #[test]
+    fn test_plain_and_regex_queries() {
+        let terminal = terminal_with(20, 3, b"Error: disk\r\nok\r\nerror 42");
+        let screen = terminal.screen();
+
+        let plain = find(screen, "error", SearchOptions::default()).unwrap();
+        assert_eq!(plain.len(), 2);
+        assert_eq!(plain[0].start, Point::new(0, 0));
+        assert_eq!(plain[1].end, Point::new(2, 4));
+
+        let sensitive = SearchOptions {
+            case_sensitive: true,
+            ..Default::default()
+        };
+        assert_eq!(find(screen, "error", sensitive).unwrap().len(), 1);
+        // Regex metacharacters are literal in plain queries
+        assert!(find(screen, "r.", SearchOptions::default()).unwrap().is_empty());
+
+        let regex = SearchOptions {
+            regex: true,
+            ..Default::default()
+        };
+        let numbers = find(screen, r"\d+", regex).unwrap();
+        assert_eq!(numbers.len(), 1);
+        assert_eq!(numbers[0].text(screen), "42");
+        assert!(find(screen, "(", regex).is_err());
+    }
+
+    #[test]
+    fn test_matches_span_soft_wraps() {
+        let terminal = terminal_with(5, 3, b"xxhello world");
+        let matches = find(terminal.screen(), "hello", SearchOptions::default()).unwrap();
+        assert_eq!(matches.len(), 1);
+        assert_eq!((matches[0].start, matches[0].end), (Point::new(0, 2), Point::new(1, 1)));
+        assert_eq!(matches[0].text(terminal.screen()), "hello");
+        assert!(matches[0].contains(0, 4) && matches[0].contains(1, 0) && !matches[0].contains(1, 2));
+    }
+
+    #[test]
+    fn test_navigation_wraps_around() {
+        let terminal = terminal_with(10, 3, b"a1\r\na2\r\na3");
+        let mut search = Search::new("a", SearchOptions::default()).unwrap();
+        search.update(terminal.screen(), 0);
+        assert_eq!(search.matches().len(), 3);
+
+        // Previous starts from the newest match
+        assert_eq!(search.previous_match().unwrap().start.line, 2);
+        assert_eq!(search.previous_match().unwrap().start.line, 1);
+        assert_eq!(search.next_match().unwrap().start.line, 2);
+        assert_eq!(search.next_match().unwrap().start.line, 0);
+        assert_eq!(search.current_index(), Some(0));
+
+        // The current match survives an update
+        search.update(terminal.screen(), 0);
+        assert_eq!(search.current().unwrap().start.line, 0);
+    }
+}
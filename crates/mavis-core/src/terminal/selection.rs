@@ -0,0 +1,339 @@
+// Text selection over the scrollback and screen
+//
+// Points are addressed by line index as in `Screen::line` (0 is the oldest
+// scrollback line) so a selection stays on the same text while the view
+// scrolls. Word and line selections snap to whole words and logical lines,
+// following soft wraps; block selections cover a rectangle of columns.
+
+use super::grid::{CellFlags, Row};
+use super::screen::Screen;
+
+/// A cell position: line index as in `Screen::line`, and column.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Point {
+    pub line: usize,
+    pub col: usize,
+}
+
+impl Point {
+    pub fn new(line: usize, col: usize) -> Self {
+        Self { line, col }
+    }
+}
+
+/// How a selection grows from its anchor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SelectionMode {
+    /// Character by character (click and drag).
+    #[default]
+    Simple,
+    /// Whole words (double click).
+    Word,
+    /// Whole logical lines (triple click).
+    Line,
+    /// A rectangle of columns.
+    Block,
+}
+
+/// A selection being made or kept, from where it started to where the
+/// pointer is now.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Selection {
+    mode: SelectionMode,
+    anchor: Point,
+    head: Point,
+}
+
+/// The cells a selection covers, resolved against the screen contents.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SelectionRange {
+    pub start: Point,
+    pub end: Point,
+    /// Only columns `start.col..=end.col` are covered on each line.
+    pub block: bool,
+}
+
+impl Selection {
+    pub fn new(mode: SelectionMode, point: Point) -> Self {
+        Self {
+            mode,
+            anchor: point,
+            head: point,
+        }
+    }
+
+    pub fn mode(&self) -> SelectionMode {
+        self.mode
+    }
+
+    /// Move the end that follows the pointer.
+    pub fn update(&mut self, point: Point) {
+        self.head = point;
+    }
+
+    /// Shift the selection up by `lines` after rows were dropped from the
+    /// top of the scrollback. Returns false when part of it was dropped.
+    pub fn scroll_up(&mut self, lines: usize) -> bool {
+        if self.anchor.line < lines || self.head.line < lines {
+            return false;
+        }
+        self.anchor.line -= lines;
+        self.head.line -= lines;
+        true
+    }
+
+    /// Resolve the selected cells, snapping word and line selections to
+    /// their boundaries. Returns `None` if the selection lies outside the
+    /// screen's lines.
+    pub fn range(&self, screen: &Screen) -> Option<SelectionRange> {
+        let last_line = screen.total_lines().checked_sub(1)?;
+        let clamp = |p: Point| Point::new(p.line.min(last_line), p.col.min(screen.cols() - 1));
+        let (anchor, head) = (clamp(self.anchor), clamp(self.head));
+
+        if self.mode == SelectionMode::Block {
+            return Some(SelectionRange {
+                start: Point::new(anchor.line.min(head.line), anchor.col.min(head.col)),
+                end: Point::new(anchor.line.max(head.line), anchor.col.max(head.col)),
+                block: true,
+            });
+        }
+
+        let (start, end) = if anchor <= head { (anchor, head) } else { (head, anchor) };
+        let (start, end) = match self.mode {
+            SelectionMode::Word => (word_start(screen, start), word_end(screen, end)),
+            SelectionMode::Line => (line_start(screen, start), line_end(screen, end)),
+            _ => (start, end),
+        };
+        Some(SelectionRange {
+            start: widen_start(screen, start),
+            end: widen_end(screen, end),
+            block: false,
+        })
+    }
+
+    /// The selected text. Soft-wrapped lines are joined, other line breaks
+    /// become `\n`, and trailing blanks of each line are dropped.
+    pub fn text(&self, screen: &Screen) -> Option<String> {
+        let range = self.range(screen)?;
+        let mut text = String::new();
+        for line in range.start.line..=range.end.line {
+            let Some(row) = screen.line(line) else {
+                break;
+            };
+            let (from, to) = if range.block {
+                (range.start.col, range.end.col)
+            } else {
+                let from = if line == range.start.line { range.start.col } else { 0 };
+                let to = if line == range.end.line { range.end.col } else { row.cells.len() - 1 };
+                (from, to)
+            };
+
+            let joined = !range.block && row.wrapped && line != range.end.line;
+            let part = cells_text(row, from, to);
+            if joined {
+                text.push_str(&part);
+            } else {
+                text.push_str(part.trim_end());
+                if line != range.end.line {
+                    text.push('\n');
+                }
+            }
+        }
+        Some(text)
+    }
+}
+
+impl SelectionRange {
+    /// Whether the cell at `line`, `col` is selected.
+    pub fn contains(&self, line: usize, col: usize) -> bool {
+        if line < self.start.line || line > self.end.line {
+            return false;
+        }
+        if self.block {
+            return col >= self.start.col && col <= self.end.col;
+        }
+        (line > self.start.line || col >= self.start.col) && (line < self.end.line || col <= self.end.col)
+    }
+}
+
+/// Text of the cells `from..=to`, skipping the right halves of wide glyphs.
+fn cells_text(row: &Row, from: usize, to: usize) -> String {
+    row.cells
+        .iter()
+        .take(to + 1)
+        .skip(from)
+        .filter(|cell| !cell.attrs.flags.contains(CellFlags::WIDE_SPACER))
+        .map(|cell| cell.c)
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CharClass {
+    Blank,
+    Word,
+    Other(char),
+}
+
+/// Word characters include the punctuation common in paths and URLs, so a
+/// double click picks up a whole path.
+fn char_class(c: char) -> CharClass {
+    if c.is_whitespace() || c == '\0' {
+        CharClass::Blank
+    } else if c.is_alphanumeric() || "_-./~:@%+#?=&".contains(c) {
+        CharClass::Word
+    } else {
+        CharClass::Other(c)
+    }
+}
+
+fn class_at(screen: &Screen, point: Point) -> Option<CharClass> {
+    let cell = screen.line(point.line)?.cells.get(point.col)?;
+    if cell.attrs.flags.contains(CellFlags::WIDE_SPACER) {
+        // The right half belongs to the glyph to its left
+        return class_at(screen, Point::new(point.line, point.col.checked_sub(1)?));
+    }
+    Some(char_class(cell.c))
+}
+
+/// The cell before `point`, continuing onto the previous line only if it
+/// soft-wraps into this one.
+fn previous_cell(screen: &Screen, point: Point) -> Option<Point> {
+    if point.col > 0 {
+        return Some(Point::new(point.line, point.col - 1));
+    }
+    let line = point.line.checked_sub(1)?;
+    let row = screen.line(line)?;
+    row.wrapped.then(|| Point::new(line, row.cells.len() - 1))
+}
+
+/// The cell after `point`, continuing onto the next line if this one
+/// soft-wraps.
+fn next_cell(screen: &Screen, point: Point) -> Option<Point> {
+    let row = screen.line(point.line)?;
+    if point.col + 1 < row.cells.len() {
+        return Some(Point::new(point.line, point.col + 1));
+    }
+    let next = screen.line(point.line + 1)?;
+    (row.wrapped && !next.cells.is_empty()).then(|| Point::new(point.line + 1, 0))
+}
+
+fn word_start(screen: &Screen, mut point: Point) -> Point {
+    let Some(class) = class_at(screen, point) else {
+        return point;
+    };
+    while let Some(previous) = previous_cell(screen, point)
+        && class_at(screen, previous) == Some(class)
+    {
+        point = previous;
+    }
+    point
+}
+
+fn word_end(screen: &Screen, mut point: Point) -> Point {
+    let Some(class) = class_at(screen, point) else {
+        return point;
+    };
+    while let Some(next) = next_cell(screen, point)
+        && class_at(screen, next) == Some(class)
+    {
+        point = next;
+    }
+    point
+}
+
+fn line_start(screen: &Screen, point: Point) -> Point {
+    let mut line = point.line;
+    while line > 0 && screen.line(line - 1).is_some_and(|row| row.wrapped) {
+        line -= 1;
+    }
+    Point::new(line, 0)
+}
+
+fn line_end(screen: &Screen, point: Point) -> Point {
+    let mut line = point.line;
+    while screen.line(line).is_some_and(|row| row.wrapped) && screen.line(line + 1).is_some() {
+        line += 1;
+    }
+    Point::new(line, screen.cols() - 1)
+}
+
+/// Extend a start point on the right half of a wide glyph to the glyph.
+fn widen_start(screen: &Screen, point: Point) -> Point {
+    let spacer = screen
+        .line(point.line)
+        .and_then(|row| row.cells.get(point.col))
+        .is_some_and(|cell| cell.attrs.flags.contains(CellFlags::WIDE_SPACER));
+    if spacer && point.col > 0 {
+        Point::new(point.line, point.col - 1)
+    } else {
+        point
+    }
+}
+
+/// Extend an end point on the left half of a wide glyph to its spacer.
+fn widen_end(screen: &Screen, point: Point) -> Point {
+    let wide = screen
+        .line(point.line)
+        .and_then(|row| row.cells.get(point.col))
+        .is_some_and(|cell| cell.attrs.flags.contains(CellFlags::WIDE));
+    if wide && point.col + 1 < screen.cols() {
+        Point::new(point.line, point.col + 1)
+    } else {
+        point
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terminal::{Terminal, terminal_with};
+
+    fn selected(terminal: &Terminal, mode: SelectionMode, anchor: (usize, usize), head: (usize, usize)) -> String {
+        let mut selection = Selection::new(mode, Point::new(anchor.0, anchor.1));
+        selection.update(Point::new(head.0, head.1));
+        selection.text(terminal.screen()).unwrap()
+    }
+
+    #[test]
+    fn test_simple_selection_across_lines() {
+        let terminal = terminal_with(10, 3, b"hello   \r\nworld\r\nagain");
+        assert_eq!(selected(&terminal, SelectionMode::Simple, (0, 2), (1, 2)), "llo\nwor");
+        // Dragging backwards selects the same text
+        assert_eq!(selected(&terminal, SelectionMode::Simple, (1, 2), (0, 2)), "llo\nwor");
+
+        let range = Selection::new(SelectionMode::Simple, Point::new(0, 8)).range(terminal.screen()).unwrap();
+        assert!(range.contains(0, 8));
+        assert!(!range.contains(0, 7) && !range.contains(1, 8));
+    }
+
+    #[test]
+    fn test_word_selection_follows_wraps() {
+        let terminal = terminal_with(8, 3, b"cat /usr/local/bin x");
+        // "/usr/local/bin" starts on the first row and wraps onto the second
+        assert_eq!(selected(&terminal, SelectionMode::Word, (0, 6), (0, 6)), "/usr/local/bin");
+        assert_eq!(selected(&terminal, SelectionMode::Word, (0, 1), (0, 1)), "cat");
+        assert_eq!(selected(&terminal, SelectionMode::Word, (0, 1), (1, 7)), "cat /usr/local/bin");
+    }
+
+    #[test]
+    fn test_line_selection_joins_soft_wraps() {
+        let terminal = terminal_with(4, 4, b"abcdefg\r\nhi");
+        assert_eq!(selected(&terminal, SelectionMode::Line, (1, 1), (1, 1)), "abcdefg");
+        assert_eq!(selected(&terminal, SelectionMode::Line, (0, 3), (2, 0)), "abcdefg\nhi");
+    }
+
+    #[test]
+    fn test_block_selection() {
+        let terminal = terminal_with(10, 3, b"0123456789\r\nabcdefghij\r\nABCDEFGHIJ");
+        assert_eq!(selected(&terminal, SelectionMode::Block, (2, 5), (0, 3)), "345\ndef\nDEF");
+        let range = Selection::new(SelectionMode::Block, Point::new(0, 3)).range(terminal.screen()).unwrap();
+        assert!(range.contains(0, 3) && !range.contains(1, 3));
+    }
+
+    #[test]
+    fn test_wide_glyphs_are_selected_whole() {
+        let terminal = terminal_with(10, 1, "a\u{4f60}\u{597d}b".as_bytes());
+        // Starting on the right half of the first glyph still includes it
+        assert_eq!(selected(&terminal, SelectionMode::Simple, (0, 2), (0, 3)), "\u{4f60}\u{597d}");
+    }
+}
@@ -0,0 +1,1093 @@
+// Terminal sessions: a PTY child, its I/O threads and the emulator it feeds
+//
+// Each session splits its PTY: a reader thread forwards output over a
+// channel, a writer thread drains queued input, and resizes go straight to
+// the shared control handle. Nothing here waits on the child, so the GUI
+// never blocks on an idle shell.
+//
+// When the child exits the reader thread reports its exit code, the session
+// prints an exit banner, and the manager applies the exit policy from the
+// settings or profile: close the tab, keep it, or restart with backoff.
+//
+// A session can also replay an asciicast recording instead of running a
+// command: a player thread sends the recorded output over the same channel,
+// so the tab behaves like one showing a program that exits at the end.
+// Any session can record its output to an asciicast as it is polled.
+//
+// `SessionManager` owns every open session in tab order. It is shared between
+// the GUI, which draws and polls the sessions, and the Lua API, which opens
+// and closes them.
+
+use super::recording::{EventData, Header, Player, Recorder, Recording};
+use super::{Key, Link, LinkDetector, Modifiers, MouseEvent, Terminal};
+use crate::config::{Config, ExitPolicy, TerminalConfig, TerminalProfile};
+use crate::error::{CoreError, CoreResult};
+use crate::pty::{self, PtyCommand, PtyControl, PtyReader, PtyWriter, SharedPtyControl};
+use log::{debug, error, info};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+/// How long a requested size must stay unchanged before it is applied.
+pub const RESIZE_DEBOUNCE: Duration = Duration::from_millis(100);
+
+/// Identifies a session for the lifetime of the manager.
+pub type SessionId = u32;
+
+/// The session manager shared between the GUI and Lua.
+pub type SharedSessionManager = Arc<Mutex<SessionManager>>;
+
+/// Reported when the child of a session exits.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ExitEvent {
+    pub session: SessionId,
+    /// Exit code, or `None` if it could not be determined.
+    pub code: Option<u32>,
+    pub exited_at: SystemTime,
+}
+
+/// Messages from a session's reader thread.
+#[derive(Debug)]
+enum PtyEvent {
+    Output(Vec<u8>),
+    /// A recording being replayed changed size.
+    Resize(usize, usize),
+    Exited(ExitEvent),
+}
+
+/// What a session runs.
+#[derive(Debug, Clone)]
+enum Source {
+    Command(PtyCommand),
+    Replay(Recording),
+}
+
+/// One shell running in a PTY, with its own parser state and I/O threads.
+#[derive(Debug)]
+pub struct TerminalSession {
+    id: SessionId,
+    profile: Option<String>,
+    /// Kept to start the shell or replay again after it exits.
+    source: Source,
+    /// Title from the command or profile, used until the program sets one.
+    initial_title: String,
+    font_family: String,
+    font_size: f32,
+    on_exit: ExitPolicy,
+    terminal: Terminal,
+    control: SharedPtyControl,
+    input_tx: Sender<Vec<u8>>,
+    output_rx: Receiver<PtyEvent>,
+    started_at: Instant,
+    exit: Option<ExitEvent>,
+    restart_at: Option<Instant>,
+    restart_attempts: u32,
+    /// Size requested by the view and when it was first requested.
+    pending_resize: Option<(usize, usize, Instant)>,
+    recorder: Option<Recorder>,
+    recording_path: Option<PathBuf>,
+    /// Playback of a replay session, for changing its speed.
+    replay: Option<Arc<ReplayControl>>,
+}
+
+impl TerminalSession {
+    /// Spawn `command` and start the threads that move its input and output.
+    pub fn spawn(id: SessionId, command: &PtyCommand, config: &TerminalConfig) -> CoreResult<Self> {
+        let (cols, rows) = (config.columns as u16, config.rows as u16);
+        let (control, input_tx, output_rx) = start(id, command, cols, rows)?;
+
+        Ok(Self {
+            id,
+            profile: None,
+            source: Source::Command(command.clone()),
+            initial_title: command.get_title().unwrap_or(command.get_program()).to_string(),
+            font_family: config.font_family.clone(),
+            font_size: config.font_size,
+            on_exit: config.on_exit,
+            terminal: Terminal::new(cols as usize, rows as usize, config.scrollback_lines as usize),
+            control,
+            input_tx,
+            output_rx,
+            started_at: Instant::now(),
+            exit: None,
+            restart_at: None,
+            restart_attempts: 0,
+            pending_resize: None,
+            recorder: None,
+            recording_path: None,
+            replay: None,
+        })
+    }
+
+    /// Play `recording` back at `speed` times the recorded pace. The tab
+    /// keeps the recorded size and exits when the recording ends.
+    pub fn replay(id: SessionId, recording: Recording, speed: f64, config: &TerminalConfig) -> CoreResult<Self> {
+        if !(speed > 0.0 && speed.is_finite()) {
+            return Err(CoreError::RecordingError(format!("Invalid replay speed {}", speed)));
+        }
+        let playback = Arc::new(ReplayControl::new(speed));
+        let (control, input_tx, output_rx) = start_replay(id, &recording, playback.clone())?;
+
+        Ok(Self {
+            id,
+            profile: None,
+            initial_title: recording.header.title.clone().unwrap_or_else(|| "replay".to_string()),
+            font_family: config.font_family.clone(),
+            font_size: config.font_size,
+            on_exit: ExitPolicy::Keep,
+            terminal: recording.terminal(config.scrollback_lines as usize),
+            source: Source::Replay(recording),
+            control,
+            input_tx,
+            output_rx,
+            started_at: Instant::now(),
+            exit: None,
+            restart_at: None,
+            restart_attempts: 0,
+            pending_resize: None,
+            recorder: None,
+            recording_path: None,
+            replay: Some(playback),
+        })
+    }
+
+    /// Start the command again in this tab, keeping the screen and
+    /// scrollback. A child that is still running is killed first.
+    pub fn respawn(&mut self) -> CoreResult<()> {
+        if self.exit.is_none() {
+            self.control.terminate()?;
+        }
+        self.restart_at = None;
+        self.started_at = Instant::now();
+
+        let (control, input_tx, output_rx) = match &self.source {
+            Source::Command(command) => {
+                let screen = self.terminal.screen();
+                let (cols, rows) = (screen.cols() as u16, screen.rows() as u16);
+                start(self.id, command, cols, rows)?
+            }
+            Source::Replay(recording) => {
+                // Replays start over on a clean screen of the recorded size
+                let speed = self.replay.as_ref().map_or(1.0, |replay| replay.speed());
+                let playback = Arc::new(ReplayControl::new(speed));
+                let started = start_replay(self.id, recording, playback.clone())?;
+                self.terminal = recording.terminal(self.terminal.screen().scrollback().capacity());
+                self.replay = Some(playback);
+                started
+            }
+        };
+        self.control = control;
+        self.input_tx = input_tx;
+        self.output_rx = output_rx;
+        self.exit = None;
+        info!("Restarted terminal session {} ({})", self.id, self.initial_title);
+        Ok(())
+    }
+
+    pub fn id(&self) -> SessionId {
+        self.id
+    }
+
+    /// Name of the profile this session was opened from, if any.
+    pub fn profile(&self) -> Option<&str> {
+        self.profile.as_deref()
+    }
+
+    /// Title set by the program (OSC 0/2), or the initial title.
+    pub fn title(&self) -> &str {
+        match self.terminal.title() {
+            "" => &self.initial_title,
+            title => title,
+        }
+    }
+
+    /// Font family and size for this session.
+    pub fn font(&self) -> (&str, f32) {
+        (&self.font_family, self.font_size)
+    }
+
+    pub fn terminal(&self) -> &Terminal {
+        &self.terminal
+    }
+
+    pub fn terminal_mut(&mut self) -> &mut Terminal {
+        &mut self.terminal
+    }
+
+    /// Resize and lifecycle control of the PTY.
+    pub fn control(&self) -> &SharedPtyControl {
+        &self.control
+    }
+
+    /// Whether the child has exited (and has not been restarted yet).
+    pub fn has_exited(&self) -> bool {
+        self.exit.is_some()
+    }
+
+    /// How the child exited, while it is not running.
+    pub fn exit(&self) -> Option<&ExitEvent> {
+        self.exit.as_ref()
+    }
+
+    pub fn exit_policy(&self) -> ExitPolicy {
+        self.on_exit
+    }
+
+    /// When an automatic restart is due, if one is scheduled.
+    pub fn restart_at(&self) -> Option<Instant> {
+        self.restart_at
+    }
+
+    /// Queue input for the child. Returns immediately; the writer thread
+    /// delivers it in order.
+    pub fn write(&self, data: &[u8]) -> CoreResult<()> {
+        self.input_tx
+            .send(data.to_vec())
+            .map_err(|_| CoreError::IoError(io::Error::new(io::ErrorKind::BrokenPipe, "PTY writer has exited")))
+    }
+
+    /// Send a key press, encoded for the current terminal modes, and jump
+    /// back to the live screen.
+    pub fn send_key(&mut self, key: Key, mods: Modifiers) -> CoreResult<()> {
+        let Some(bytes) = self.terminal.encode_key(key, mods) else {
+            return Ok(());
+        };
+        self.terminal.scroll_to_bottom();
+        self.write(&bytes)
+    }
+
+    /// Send pasted text, bracketed if the program asked for it.
+    pub fn paste(&mut self, text: &str) -> CoreResult<()> {
+        let bytes = self.terminal.encode_paste(text);
+        self.terminal.scroll_to_bottom();
+        self.write(&bytes)
+    }
+
+    /// Report a mouse event to the program if it enabled mouse tracking.
+    pub fn send_mouse(&mut self, event: &MouseEvent) -> CoreResult<()> {
+        match self.terminal.encode_mouse(event) {
+            Some(bytes) => self.write(&bytes),
+            None => Ok(()),
+        }
+    }
+
+    /// Resize the emulator and the PTY.
+    pub fn resize(&mut self, cols: usize, rows: usize) -> CoreResult<()> {
+        self.pending_resize = None;
+        self.resize_terminal(cols, rows);
+        self.control.resize(cols as u16, rows as u16)
+    }
+
+    /// Ask for a new size, e.g. every frame from the view. `poll` applies it
+    /// once it has not changed for `RESIZE_DEBOUNCE`. Replays keep the size
+    /// they were recorded at.
+    pub fn request_resize(&mut self, cols: usize, rows: usize) {
+        if self.is_replay() {
+            return;
+        }
+        let (cols, rows) = (cols.max(1), rows.max(1));
+        let screen = self.terminal.screen();
+        let current = (screen.cols(), screen.rows());
+        match self.pending_resize {
+            Some((pending_cols, pending_rows, _)) if (pending_cols, pending_rows) == (cols, rows) => {}
+            _ if current == (cols, rows) => self.pending_resize = None,
+            _ => self.pending_resize = Some((cols, rows, Instant::now())),
+        }
+    }
+
+    /// Feed pending output into the emulator and answer device queries.
+    /// Returns the exit event if the child exited since the last poll.
+    pub fn poll(&mut self) -> Option<ExitEvent> {
+        if let Some((cols, rows, requested_at)) = self.pending_resize
+            && requested_at.elapsed() >= RESIZE_DEBOUNCE
+        {
+            debug!("Resizing terminal session {} to {}x{}", self.id, cols, rows);
+            if let Err(e) = self.resize(cols, rows) {
+                error!("Failed to resize terminal session {}: {}", self.id, e);
+            }
+        }
+
+        let mut exited = None;
+        while let Ok(event) = self.output_rx.try_recv() {
+            match event {
+                PtyEvent::Output(data) => {
+                    self.record(|recorder| recorder.output(&data));
+                    self.terminal.process(&data);
+                }
+                PtyEvent::Resize(cols, rows) => self.resize_terminal(cols, rows),
+                PtyEvent::Exited(event) => {
+                    let message = match event.code {
+                        Some(code) => format!("process exited with code {}", code),
+                        None => "process exited".to_string(),
+                    };
+                    info!("Terminal session {}: {}", self.id, message);
+                    self.print_banner(&message);
+                    self.exit = Some(event.clone());
+                    exited = Some(event);
+                }
+            }
+        }
+
+        let responses = self.terminal.take_responses();
+        if !responses.is_empty()
+            && self.exit.is_none()
+            && let Err(e) = self.write(&responses)
+        {
+            error!("Failed to send terminal response to session {}: {}", self.id, e);
+        }
+        exited
+    }
+
+    /// Record the output from now on to `path` as an asciicast v2 file,
+    /// replacing any recording in progress.
+    pub fn start_recording(&mut self, path: impl AsRef<Path>) -> CoreResult<()> {
+        self.stop_recording()?;
+        let screen = self.terminal.screen();
+        let mut header = Header::new(screen.cols(), screen.rows());
+        header.title = Some(self.title().to_string());
+        header.env.insert("TERM".to_string(), "xterm-256color".to_string());
+        if let Source::Command(command) = &self.source {
+            header.env.insert("SHELL".to_string(), command.get_program().to_string());
+        }
+        let path = path.as_ref();
+        self.recorder = Some(Recorder::create(path, &header)?);
+        self.recording_path = Some(path.to_path_buf());
+        info!("Recording terminal session {} to {}", self.id, path.display());
+        Ok(())
+    }
+
+    /// Finish the recording in progress. Returns false if there was none.
+    pub fn stop_recording(&mut self) -> CoreResult<bool> {
+        self.recording_path = None;
+        match self.recorder.take() {
+            Some(recorder) => {
+                recorder.finish()?;
+                info!("Stopped recording terminal session {}", self.id);
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recorder.is_some()
+    }
+
+    /// File the recording in progress is written to.
+    pub fn recording_path(&self) -> Option<&Path> {
+        self.recording_path.as_deref()
+    }
+
+    /// Whether this session replays a recording rather than running a command.
+    pub fn is_replay(&self) -> bool {
+        matches!(self.source, Source::Replay(_))
+    }
+
+    /// Playback speed of a replay session.
+    pub fn replay_speed(&self) -> Option<f64> {
+        self.replay.as_ref().map(|replay| replay.speed())
+    }
+
+    /// Change the playback speed of a replay session. Returns false for
+    /// other sessions or non-positive speeds.
+    pub fn set_replay_speed(&self, speed: f64) -> bool {
+        match &self.replay {
+            Some(replay) if speed > 0.0 && speed.is_finite() => {
+                replay.set_speed(speed);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Resize the emulator, recording the new size.
+    fn resize_terminal(&mut self, cols: usize, rows: usize) {
+        self.terminal.resize(cols, rows);
+        self.record(|recorder| recorder.resize(cols, rows));
+    }
+
+    /// Write to the recording in progress. A failed write ends it.
+    fn record(&mut self, write: impl FnOnce(&mut Recorder) -> CoreResult<()>) {
+        if let Some(recorder) = &mut self.recorder
+            && let Err(e) = write(recorder)
+        {
+            error!("Stopped recording terminal session {}: {}", self.id, e);
+            self.recorder = None;
+            self.recording_path = None;
+        }
+    }
+
+    /// Schedule an automatic restart. The delay starts at `initial` and
+    /// doubles with each attempt up to `max`; a child that stayed up for
+    /// `max` or longer resets the backoff.
+    fn schedule_restart(&mut self, initial: Duration, max: Duration) {
+        if self.started_at.elapsed() >= max {
+            self.restart_attempts = 0;
+        }
+        let delay = initial.saturating_mul(2u32.saturating_pow(self.restart_attempts)).min(max);
+        self.restart_attempts += 1;
+        self.restart_at = Some(Instant::now() + delay);
+        self.print_banner(&format!("restarting in {:.1}s", delay.as_secs_f32()));
+    }
+
+    /// Print a dim `[message]` line below the output.
+    fn print_banner(&mut self, message: &str) {
+        let newline = if self.terminal.cursor().col > 0 { "\r\n" } else { "" };
+        let banner = format!("{}\x1b[0;2m[{}]\x1b[0m\r\n", newline, message);
+        self.terminal.process(banner.as_bytes());
+    }
+}
+
+impl Drop for TerminalSession {
+    fn drop(&mut self) {
+        // Killing the child ends the output, so the reader thread exits; the
+        // writer thread exits once `input_tx` is dropped.
+        if let Err(e) = self.control.terminate() {
+            error!("Failed to terminate session {}: {}", self.id, e);
+        }
+    }
+}
+
+/// Owns all open sessions in tab order and tracks the active one.
+#[derive(Debug)]
+pub struct SessionManager {
+    config: TerminalConfig,
+    sessions: Vec<TerminalSession>,
+    active: Option<SessionId>,
+    next_id: SessionId,
+    exit_subscribers: Vec<Sender<ExitEvent>>,
+    links: LinkDetector,
+}
+
+impl Default for SessionManager {
+    fn default() -> Self {
+        Self::new(Config::default().terminal)
+    }
+}
+
+impl SessionManager {
+    pub fn new(config: TerminalConfig) -> Self {
+        Self {
+            config,
+            sessions: Vec::new(),
+            active: None,
+            next_id: 1,
+            exit_subscribers: Vec::new(),
+            links: LinkDetector::default(),
+        }
+    }
+
+    pub fn shared(config: TerminalConfig) -> SharedSessionManager {
+        Arc::new(Mutex::new(Self::new(config)))
+    }
+
+    pub fn config(&self) -> &TerminalConfig {
+        &self.config
+    }
+
+    /// Replace the terminal settings used for sessions opened from now on.
+    pub fn set_config(&mut self, config: TerminalConfig) {
+        self.config = config;
+    }
+
+    /// Rules for finding links in every session's output.
+    pub fn link_detector(&self) -> &LinkDetector {
+        &self.links
+    }
+
+    pub fn link_detector_mut(&mut self) -> &mut LinkDetector {
+        &mut self.links
+    }
+
+    /// The link at viewport cell `col`, `row` of session `id`.
+    pub fn link_at(&self, id: SessionId, col: usize, row: usize) -> Option<Link> {
+        self.get(id)?.terminal().link_at(&self.links, col, row)
+    }
+
+    pub fn profiles(&self) -> &[TerminalProfile] {
+        &self.config.profiles
+    }
+
+    pub fn profile(&self, name: &str) -> Option<&TerminalProfile> {
+        self.config.profiles.iter().find(|profile| profile.name == name)
+    }
+
+    /// Open the configured default profile, or the plain shell settings when
+    /// there is none.
+    pub fn open_default(&mut self) -> CoreResult<SessionId> {
+        match self.config.default_profile.clone() {
+            Some(name) => self.open_profile(&name),
+            None => {
+                let command = PtyCommand::from_config(&self.config);
+                self.open(&self.inherit_cwd(command))
+            }
+        }
+    }
+
+    /// Open a new tab for the named profile.
+    pub fn open_profile(&mut self, name: &str) -> CoreResult<SessionId> {
+        let profile = self
+            .profile(name)
+            .cloned()
+            .ok_or_else(|| CoreError::ConfigError(format!("Unknown terminal profile '{}'", name)))?;
+        let command = PtyCommand::from_profile(&self.config, &profile);
+        let id = self.open(&self.inherit_cwd(command))?;
+
+        if let Some(session) = self.get_mut(id) {
+            session.profile = Some(profile.name.clone());
+            if let Some(family) = profile.font_family {
+                session.font_family = family;
+            }
+            if let Some(size) = profile.font_size {
+                session.font_size = size;
+            }
+            if let Some(policy) = profile.on_exit {
+                session.on_exit = policy;
+            }
+        }
+        Ok(id)
+    }
+
+    /// Start `command` where the active tab's shell is, if it reports its
+    /// directory and `command` has none of its own.
+    fn inherit_cwd(&self, command: PtyCommand) -> PtyCommand {
+        if !self.config.inherit_working_directory || command.get_cwd().is_some() {
+            return command;
+        }
+        match self.active().and_then(|session| session.terminal().working_directory()) {
+            Some(dir) if Path::new(dir).is_dir() => command.cwd(dir),
+            _ => command,
+        }
+    }
+
+    /// Open a new tab running `command` and make it active.
+    pub fn open(&mut self, command: &PtyCommand) -> CoreResult<SessionId> {
+        let id = self.next_id;
+        let session = TerminalSession::spawn(id, command, &self.config)?;
+        Ok(self.push(session))
+    }
+
+    /// Open a new tab replaying the asciicast recording at `path` at
+    /// `speed` times the recorded pace, and make it active.
+    pub fn open_replay(&mut self, path: impl AsRef<Path>, speed: f64) -> CoreResult<SessionId> {
+        let recording = Recording::open(path)?;
+        let session = TerminalSession::replay(self.next_id, recording, speed, &self.config)?;
+        Ok(self.push(session))
+    }
+
+    fn push(&mut self, session: TerminalSession) -> SessionId {
+        let id = session.id;
+        info!("Opened terminal session {} ({})", id, session.title());
+        self.next_id += 1;
+        self.sessions.push(session);
+        self.active = Some(id);
+        id
+    }
+
+    /// Close a session, terminating its child. Returns false for unknown ids.
+    pub fn close(&mut self, id: SessionId) -> bool {
+        let Some(index) = self.sessions.iter().position(|session| session.id == id) else {
+            return false;
+        };
+        self.sessions.remove(index);
+        info!("Closed terminal session {}", id);
+
+        if self.active == Some(id) {
+            // Activate the neighbouring tab
+            let next = index.min(self.sessions.len().saturating_sub(1));
+            self.active = self.sessions.get(next).map(|session| session.id);
+        }
+        true
+    }
+
+    pub fn get(&self, id: SessionId) -> Option<&TerminalSession> {
+        self.sessions.iter().find(|session| session.id == id)
+    }
+
+    pub fn get_mut(&mut self, id: SessionId) -> Option<&mut TerminalSession> {
+        self.sessions.iter_mut().find(|session| session.id == id)
+    }
+
+    /// Sessions in tab order.
+    pub fn sessions(&self) -> impl Iterator<Item = &TerminalSession> {
+        self.sessions.iter()
+    }
+
+    pub fn sessions_mut(&mut self) -> impl Iterator<Item = &mut TerminalSession> {
+        self.sessions.iter_mut()
+    }
+
+    pub fn ids(&self) -> Vec<SessionId> {
+        self.sessions.iter().map(|session| session.id).collect()
+    }
+
+    pub fn active_id(&self) -> Option<SessionId> {
+        self.active
+    }
+
+    /// Make `id` the active tab. Returns false for unknown ids.
+    pub fn set_active(&mut self, id: SessionId) -> bool {
+        let known = self.get(id).is_some();
+        if known {
+            self.active = Some(id);
+        }
+        known
+    }
+
+    pub fn active(&self) -> Option<&TerminalSession> {
+        self.active.and_then(|id| self.get(id))
+    }
+
+    pub fn active_mut(&mut self) -> Option<&mut TerminalSession> {
+        let id = self.active?;
+        self.get_mut(id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.sessions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sessions.is_empty()
+    }
+
+    /// Restart a session's command now, killing it if it is still running.
+    /// Returns false for unknown ids.
+    pub fn restart(&mut self, id: SessionId) -> CoreResult<bool> {
+        let Some(session) = self.get_mut(id) else {
+            return Ok(false);
+        };
+        session.restart_attempts = 0;
+        session.respawn()?;
+        Ok(true)
+    }
+
+    /// Receive an `ExitEvent` whenever a session's child exits.
+    pub fn subscribe_exits(&mut self) -> Receiver<ExitEvent> {
+        let (tx, rx) = mpsc::channel();
+        self.exit_subscribers.push(tx);
+        rx
+    }
+
+    /// Feed pending output of every session into its emulator, apply the
+    /// exit policy of sessions whose child exited, and run due restarts.
+    /// Returns the exits seen by this poll.
+    pub fn poll(&mut self) -> Vec<ExitEvent> {
+        let exits: Vec<ExitEvent> = self.sessions.iter_mut().filter_map(TerminalSession::poll).collect();
+
+        let (initial, max) = self.restart_backoff();
+        for event in &exits {
+            self.exit_subscribers.retain(|tx| tx.send(event.clone()).is_ok());
+            let Some(session) = self.get_mut(event.session) else {
+                continue;
+            };
+            match session.on_exit {
+                ExitPolicy::Close => {
+                    self.close(event.session);
+                }
+                ExitPolicy::Keep => {}
+                ExitPolicy::Restart => session.schedule_restart(initial, max),
+            }
+        }
+
+        let now = Instant::now();
+        for session in &mut self.sessions {
+            if session.restart_at.is_some_and(|at| at <= now)
+                && let Err(e) = session.respawn()
+            {
+                error!("Failed to restart terminal session {}: {}", session.id, e);
+                session.print_banner(&format!("restart failed: {}", e));
+                session.schedule_restart(initial, max);
+            }
+        }
+        exits
+    }
+
+    /// Initial and maximum delay for automatic restarts.
+    fn restart_backoff(&self) -> (Duration, Duration) {
+        (
+            Duration::from_millis(self.config.restart_delay_ms as u64),
+            Duration::from_millis(self.config.restart_max_delay_ms as u64),
+        )
+    }
+}
+
+/// Spawn `command` in a PTY and start its reader and writer threads.
+fn start(
+    id: SessionId,
+    command: &PtyCommand,
+    cols: u16,
+    rows: u16,
+) -> CoreResult<(SharedPtyControl, Sender<Vec<u8>>, Receiver<PtyEvent>)> {
+    let halves = pty::spawn_split(command, cols, rows)?;
+    let output_rx = spawn_reader(id, halves.reader, halves.control.clone())?;
+    let input_tx = spawn_writer(id, halves.writer)?;
+    Ok((halves.control, input_tx, output_rx))
+}
+
+/// Playback state shared between a replay session and its player thread.
+#[derive(Debug)]
+struct ReplayControl {
+    // f64 bits
+    speed: AtomicU64,
+    stopped: AtomicBool,
+    finished: AtomicBool,
+}
+
+impl ReplayControl {
+    fn new(speed: f64) -> Self {
+        Self {
+            speed: AtomicU64::new(speed.to_bits()),
+            stopped: AtomicBool::new(false),
+            finished: AtomicBool::new(false),
+        }
+    }
+
+    fn speed(&self) -> f64 {
+        f64::from_bits(self.speed.load(Ordering::Relaxed))
+    }
+
+    fn set_speed(&self, speed: f64) {
+        self.speed.store(speed.to_bits(), Ordering::Relaxed);
+    }
+}
+
+impl PtyControl for ReplayControl {
+    // The recording decides the size
+    fn resize(&self, _cols: u16, _rows: u16) -> CoreResult<()> {
+        Ok(())
+    }
+
+    fn exit_status(&self) -> CoreResult<Option<u32>> {
+        Ok(self.finished.load(Ordering::Relaxed).then_some(0))
+    }
+
+    fn terminate(&self) -> CoreResult<()> {
+        self.stopped.store(true, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+/// Start the thread that plays `recording` over a channel like PTY output.
+/// Input sent to the session is accepted and discarded as it arrives.
+fn start_replay(
+    id: SessionId,
+    recording: &Recording,
+    playback: Arc<ReplayControl>,
+) -> CoreResult<(SharedPtyControl, Sender<Vec<u8>>, Receiver<PtyEvent>)> {
+    let (tx, rx) = mpsc::channel::<PtyEvent>();
+    let (input_tx, input_rx) = mpsc::channel::<Vec<u8>>();
+    let mut player = Player::new(recording.clone());
+    let control = playback.clone();
+
+    thread::Builder::new()
+        .name(format!("replay-{}", id))
+        .spawn(move || {
+            info!("Replay thread for session {} started.", id);
+            let mut last = Instant::now();
+            while !control.stopped.load(Ordering::Relaxed) {
+                // Nothing reads keystrokes during a replay
+                while input_rx.try_recv().is_ok() {}
+                player.set_speed(control.speed());
+                let Some(wait) = player.time_to_next() else {
+                    break;
+                };
+                // Wake up now and then to notice speed changes and stops
+                thread::sleep(wait.min(Duration::from_millis(50)));
+                let now = Instant::now();
+                let mut open = true;
+                player.advance(now - last, |data| {
+                    let event = match data {
+                        EventData::Output(text) => PtyEvent::Output(text.as_bytes().to_vec()),
+                        EventData::Resize(cols, rows) => PtyEvent::Resize(*cols, *rows),
+                        EventData::Input(_) | EventData::Marker(_) => return,
+                    };
+                    open &= tx.send(event).is_ok();
+                });
+                last = now;
+                if !open {
+                    info!("Replay channel closed by receiver. Replay thread exiting.");
+                    return;
+                }
+            }
+            drop(input_rx);
+
+            control.finished.store(true, Ordering::Relaxed);
+            let exited_at = SystemTime::now();
+            let _ = tx.send(PtyEvent::Exited(ExitEvent { session: id, code: Some(0), exited_at }));
+        })?;
+
+    Ok((playback, input_tx, rx))
+}
+
+/// Start the thread that reads the PTY and forwards output over a channel.
+/// When the output ends it reports how the child exited and stops.
+fn spawn_reader(id: SessionId, mut reader: PtyReader, control: SharedPtyControl) -> CoreResult<Receiver<PtyEvent>> {
+    let (tx, rx) = mpsc::channel::<PtyEvent>();
+
+    thread::Builder::new()
+        .name(format!("pty-reader-{}", id))
+        .spawn(move || {
+            info!("PTY reader thread for session {} started.", id);
+            let mut buffer = [0u8; 4096];
+            loop {
+                match reader.read(&mut buffer) {
+                    Ok(0) => {
+                        info!("PTY read 0 bytes, assuming process exited. Reader thread exiting.");
+                        break;
+                    }
+                    Ok(bytes_read) => {
+                        if tx.send(PtyEvent::Output(buffer[..bytes_read].to_vec())).is_err() {
+                            info!("PTY output channel closed by receiver. Reader thread exiting.");
+                            return;
+                        }
+                    }
+                    Err(e) => {
+                        error!("Error reading from PTY: {}. Reader thread exiting.", e);
+                        break;
+                    }
+                }
+            }
+
+            let exited_at = SystemTime::now();
+            let code = wait_for_exit_code(&*control);
+            let _ = tx.send(PtyEvent::Exited(ExitEvent { session: id, code, exited_at }));
+        })?;
+
+    Ok(rx)
+}
+
+// The terminal can close a moment before the child is reaped, so give the
+// exit status a second to become available.
+fn wait_for_exit_code(control: &dyn PtyControl) -> Option<u32> {
+    for _ in 0..50 {
+        match control.exit_status() {
+            Ok(Some(code)) => return Some(code),
+            Ok(None) => thread::sleep(Duration::from_millis(20)),
+            Err(e) => {
+                error!("Failed to get PTY exit status: {}", e);
+                return None;
+            }
+        }
+    }
+    None
+}
+
+/// Start the thread that writes queued input to the PTY. It exits when the
+/// session drops its sender or a write fails.
+fn spawn_writer(id: SessionId, mut writer: PtyWriter) -> CoreResult<Sender<Vec<u8>>> {
+    let (tx, rx) = mpsc::channel::<Vec<u8>>();
+
+    thread::Builder::new()
+        .name(format!("pty-writer-{}", id))
+        .spawn(move || {
+            for data in rx {
+                if let Err(e) = writer.write_all(&data).and_then(|()| writer.flush()) {
+                    error!("Error writing to PTY: {}. Writer thread exiting.", e);
+                    break;
+                }
+                debug!("Wrote {} bytes to session {}", data.len(), id);
+            }
+        })?;
+
+    Ok(tx)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    fn config_with_profiles() -> TerminalConfig {
+        serde_json::from_value(serde_json::json!({
+            "shell_executable": "/bin/sh",
+            "columns": 40, "rows": 5, "scrollback_lines": 100,
+            "font_family": "Cascadia Code", "font_size": 12.0,
+            "restart_delay_ms": 10, "restart_max_delay_ms": 40,
+            "profiles": [
+                { "name": "greeter", "command": "/bin/sh -c \"echo hello from $WHO\"",
+                  "environment": { "WHO": "profile" }, "font_size": 16.0 },
+                { "name": "flaky", "command": "/bin/sh -c \"echo run; exit 3\"", "on_exit": "restart" },
+                { "name": "oneshot", "command": "/bin/sh -c true", "on_exit": "close" },
+                { "name": "where", "command": "/bin/sh -c pwd" }
+            ]
+        }))
+        .unwrap()
+    }
+
+    fn poll_until(manager: &mut SessionManager, id: SessionId, done: impl Fn(&TerminalSession) -> bool) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while Instant::now() < deadline {
+            manager.poll();
+            if done(manager.get(id).unwrap()) {
+                return;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        panic!("session {} did not reach the expected state", id);
+    }
+
+    #[test]
+    fn test_open_profile_and_read_output() {
+        let mut manager = SessionManager::new(config_with_profiles());
+        let id = manager.open_profile("greeter").unwrap();
+        assert_eq!(manager.active_id(), Some(id));
+
+        poll_until(&mut manager, id, TerminalSession::has_exited);
+        let session = manager.get(id).unwrap();
+        assert_eq!(session.terminal().grid().row(0).text(), "hello from profile");
+        assert_eq!(session.terminal().grid().row(1).text(), "[process exited with code 0]");
+        assert_eq!(session.exit().unwrap().code, Some(0));
+        assert_eq!(session.profile(), Some("greeter"));
+        assert_eq!(session.title(), "greeter");
+        assert_eq!(session.font(), ("Cascadia Code", 16.0));
+
+        assert!(manager.open_profile("missing").is_err());
+    }
+
+    #[test]
+    fn test_write_to_idle_session() {
+        let mut manager = SessionManager::new(config_with_profiles());
+        let id = manager.open(&PtyCommand::new("/bin/sh").args(["-c", "read line; echo \"got $line\""])).unwrap();
+
+        // The reader thread is blocked on a silent child; input must still arrive
+        thread::sleep(Duration::from_millis(100));
+        let session = manager.get_mut(id).unwrap();
+        session.resize(30, 5).unwrap();
+        for c in "abc".chars() {
+            session.send_key(Key::Char(c), Modifiers::empty()).unwrap();
+        }
+        session.send_key(Key::Enter, Modifiers::empty()).unwrap();
+
+        poll_until(&mut manager, id, TerminalSession::has_exited);
+        let terminal = manager.get(id).unwrap().terminal();
+        assert_eq!(terminal.grid().row(1).text(), "got abc");
+        assert_eq!(terminal.screen().cols(), 30);
+    }
+
+    #[test]
+    fn test_resize_is_debounced() {
+        let mut manager = SessionManager::new(config_with_profiles());
+        let id = manager.open(&PtyCommand::new("/bin/sh").args(["-c", "sleep 0.5; stty size"])).unwrap();
+
+        let session = manager.get_mut(id).unwrap();
+        session.request_resize(30, 6);
+        session.request_resize(50, 8);
+        session.poll();
+        assert_eq!(session.terminal().screen().cols(), 40);
+
+        // The latest size wins once it has settled
+        thread::sleep(RESIZE_DEBOUNCE);
+        session.request_resize(50, 8);
+        session.poll();
+        assert_eq!((session.terminal().screen().cols(), session.terminal().screen().rows()), (50, 8));
+
+        poll_until(&mut manager, id, TerminalSession::has_exited);
+        assert_eq!(manager.get(id).unwrap().terminal().grid().row(0).text(), "8 50");
+    }
+
+    #[test]
+    fn test_new_tabs_inherit_reported_directory() {
+        let mut manager = SessionManager::new(config_with_profiles());
+        let shell = manager
+            .open(&PtyCommand::new("/bin/sh").args(["-c", "printf '\\033]7;file://host/tmp\\007'; sleep 2"]))
+            .unwrap();
+        poll_until(&mut manager, shell, |session| session.terminal().working_directory().is_some());
+        assert_eq!(manager.get(shell).unwrap().terminal().working_directory(), Some("/tmp"));
+
+        let id = manager.open_profile("where").unwrap();
+        poll_until(&mut manager, id, TerminalSession::has_exited);
+        assert_eq!(manager.get(id).unwrap().terminal().grid().row(0).text(), "/tmp");
+    }
+
+    #[test]
+    fn test_record_and_replay() {
+        let path = std::env::temp_dir().join(format!("mavis-session-{}.cast", std::process::id()));
+        let mut manager = SessionManager::new(config_with_profiles());
+        let id = manager
+            .open(&PtyCommand::new("/bin/sh").args(["-c", "printf 'one\\r\\n'; sleep 0.2; printf two"]))
+            .unwrap();
+        // Nothing is lost: output only reaches the emulator when polled
+        manager.get_mut(id).unwrap().start_recording(&path).unwrap();
+        assert_eq!(manager.get(id).unwrap().recording_path(), Some(path.as_path()));
+        poll_until(&mut manager, id, TerminalSession::has_exited);
+        assert!(manager.get_mut(id).unwrap().stop_recording().unwrap());
+        assert!(!manager.get_mut(id).unwrap().is_recording());
+
+        let recording = Recording::open(&path).unwrap();
+        assert_eq!((recording.header.width, recording.header.height), (40, 5));
+        assert!(recording.duration() >= Duration::from_millis(150));
+
+        let replay = manager.open_replay(&path, 10.0).unwrap();
+        let session = manager.get_mut(replay).unwrap();
+        assert!(session.is_replay() && session.replay_speed() == Some(10.0));
+        // The view cannot resize a replay
+        session.request_resize(80, 24);
+        poll_until(&mut manager, replay, TerminalSession::has_exited);
+        let terminal = manager.get(replay).unwrap().terminal();
+        assert_eq!((terminal.cols(), terminal.rows()), (40, 5));
+        assert_eq!(terminal.grid().row(0).text(), "one");
+        assert_eq!(terminal.grid().row(1).text(), "two");
+
+        assert!(manager.open_replay(&path, 0.0).is_err());
+        assert!(manager.open_replay(path.with_extension("missing"), 1.0).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_sessions_are_independent() {
+        let mut manager = SessionManager::new(config_with_profiles());
+        let first = manager.open(&PtyCommand::new("/bin/sh").args(["-c", "echo one"])).unwrap();
+        let second = manager.open(&PtyCommand::new("/bin/sh").args(["-c", "echo two"])).unwrap();
+        assert_eq!(manager.ids(), vec![first, second]);
+
+        poll_until(&mut manager, first, TerminalSession::has_exited);
+        poll_until(&mut manager, second, TerminalSession::has_exited);
+        assert_eq!(manager.get(first).unwrap().terminal().grid().row(0).text(), "one");
+        assert_eq!(manager.get(second).unwrap().terminal().grid().row(0).text(), "two");
+
+        assert!(manager.close(second));
+        assert_eq!(manager.active_id(), Some(first));
+        assert!(!manager.close(second));
+        assert!(manager.close(first));
+        assert_eq!(manager.active_id(), None);
+    }
+
+    #[test]
+    fn test_restart_policy_backs_off() {
+        let mut manager = SessionManager::new(config_with_profiles());
+        let exits = manager.subscribe_exits();
+        let id = manager.open_profile("flaky").unwrap();
+        assert_eq!(manager.get(id).unwrap().exit_policy(), ExitPolicy::Restart);
+
+        let deadline = Instant::now() + Duration::from_secs(10);
+        let mut codes = Vec::new();
+        while codes.len() < 3 && Instant::now() < deadline {
+            manager.poll();
+            codes.extend(exits.try_iter().map(|event| (event.session, event.code)));
+            thread::sleep(Duration::from_millis(5));
+        }
+        assert_eq!(codes, vec![(id, Some(3)); 3]);
+
+        let text: Vec<String> = (0..5).map(|row| manager.get(id).unwrap().terminal().grid().row(row).text()).collect();
+        assert!(text.iter().any(|line| line == "[restarting in 0.0s]"), "{:?}", text);
+        assert!(text.iter().any(|line| line == "[process exited with code 3]"), "{:?}", text);
+    }
+
+    #[test]
+    fn test_close_policy_removes_tab() {
+        let mut manager = SessionManager::new(config_with_profiles());
+        let id = manager.open_profile("oneshot").unwrap();
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while manager.get(id).is_some() && Instant::now() < deadline {
+            manager.poll();
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert!(manager.is_empty());
+        assert_eq!(manager.active_id(), None);
+    }
+}
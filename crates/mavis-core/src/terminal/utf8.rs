@@ -0,0 +1,152 @@
+// Streaming UTF-8 decoder for PTY output
+//
+// PTY reads split the byte stream at arbitrary points, so a multi-byte
+// character can straddle two reads. The decoder keeps the incomplete tail of
+// one chunk and completes it with the start of the next.
+
+/// Incremental UTF-8 decoder. Invalid bytes decode to U+FFFD; a truncated
+/// sequence at the end of a chunk is held back until more data arrives.
+#[derive(Debug, Default, Clone)]
+pub struct Utf8Decoder {
+    pending: Vec<u8>,
+}
+
+impl Utf8Decoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Decode `data`, calling `emit` for each complete character.
+    pub fn decode(&mut self, data: &[u8], mut emit: impl FnMut(char)) {
+        let mut input = data;
+
+        // Finish a sequence left over from the previous chunk first.
+        if !self.pending.is_empty() {
+            let needed = sequence_len(self.pending[0]) - self.pending.len();
+            let take = needed.min(input.len());
+            self.pending.extend_from_slice(&input[..take]);
+            input = &input[take..];
+
+            match std::str::from_utf8(&self.pending) {
+                Ok(text) => {
+                    text.chars().for_each(&mut emit);
+                    self.pending.clear();
+                }
+                Err(error) if error.error_len().is_none() => {
+                    // Still incomplete; the chunk was shorter than the sequence.
+                    return;
+                }
+                Err(error) => {
+                    // The continuation bytes were wrong. Emit U+FFFD for the
+                    // lead byte and decode the rest as ordinary input.
+                    let invalid = error.error_len().unwrap_or(1);
+                    emit(char::REPLACEMENT_CHARACTER);
+                    let rest = self.pending.split_off(invalid);
+                    self.pending.clear();
+                    self.decode_chunk(&rest, &mut emit);
+                }
+            }
+        }
+
+        self.decode_chunk(input, &mut emit);
+    }
+
+    /// Decode `data` into a `String`.
+    pub fn decode_to_string(&mut self, data: &[u8]) -> String {
+        let mut text = String::with_capacity(data.len());
+        self.decode(data, |c| text.push(c));
+        text
+    }
+
+    /// Whether part of a character is waiting for more input.
+    pub fn has_pending(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    /// Flush an incomplete trailing sequence as U+FFFD (e.g. at end of stream).
+    pub fn finish(&mut self, mut emit: impl FnMut(char)) {
+        if !self.pending.is_empty() {
+            self.pending.clear();
+            emit(char::REPLACEMENT_CHARACTER);
+        }
+    }
+
+    fn decode_chunk(&mut self, mut input: &[u8], emit: &mut impl FnMut(char)) {
+        loop {
+            match std::str::from_utf8(input) {
+                Ok(text) => {
+                    text.chars().for_each(&mut *emit);
+                    return;
+                }
+                Err(error) => {
+                    let (valid, rest) = input.split_at(error.valid_up_to());
+                    // `valid_up_to` guarantees this prefix is valid UTF-8.
+                    if let Ok(text) = std::str::from_utf8(valid) {
+                        text.chars().for_each(&mut *emit);
+                    }
+                    match error.error_len() {
+                        Some(len) => {
+                            emit(char::REPLACEMENT_CHARACTER);
+                            input = &rest[len..];
+                        }
+                        None => {
+                            // Truncated sequence at the end of the chunk.
+                            self.pending.extend_from_slice(rest);
+                            return;
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Expected length of a sequence from its lead byte.
+fn sequence_len(lead: u8) -> usize {
+    match lead {
+        0xc0..=0xdf => 2,
+        0xe0..=0xef => 3,
+        0xf0..=0xf7 => 4,
+        _ => 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sequence_split_across_chunks() {
+        let bytes = "a€b😀".as_bytes();
+        for split in 0..bytes.len() {
+            let mut decoder = Utf8Decoder::new();
+            let mut text = decoder.decode_to_string(&bytes[..split]);
+            text.push_str(&decoder.decode_to_string(&bytes[split..]));
+            assert_eq!(text, "a€b😀", "split at {}", split);
+            assert!(!decoder.has_pending());
+        }
+    }
+
+    #[test]
+    fn test_byte_at_a_time() {
+        let mut decoder = Utf8Decoder::new();
+        let mut text = String::new();
+        for byte in "日本語".as_bytes() {
+            text.push_str(&decoder.decode_to_string(&[*byte]));
+        }
+        assert_eq!(text, "日本語");
+    }
+
+    #[test]
+    fn test_invalid_bytes_replaced() {
+        let mut decoder = Utf8Decoder::new();
+        assert_eq!(decoder.decode_to_string(b"a\xffb"), "a\u{fffd}b");
+        // A lead byte followed by a non-continuation byte in the next chunk
+        assert_eq!(decoder.decode_to_string(b"x\xe2"), "x");
+        assert_eq!(decoder.decode_to_string(b"y"), "\u{fffd}y");
+        decoder.decode(b"\xe2\x82", |_| {});
+        let mut flushed = String::new();
+        decoder.finish(|c| flushed.push(c));
+        assert_eq!(flushed, "\u{fffd}");
+    }
+}
@@ -4,6 +4,7 @@ use crate::error::GuiError;
 use imgui::Ui;
 use log::{error, info, trace};
 use std::ffi::CString;
+use std::path::PathBuf;
 use windows::Win32::Foundation::{HWND, LPARAM, WPARAM};
 use windows::Win32::UI::WindowsAndMessaging::{
     CreateWindowExW, SendMessageW, SetWindowPos, ShowWindow, CW_USEDEFAULT,
@@ -120,11 +121,23 @@ impl Drop for EditorView {
     }
 }
 
+/// A position in a file to show, e.g. from a link clicked in the terminal.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileLocation {
+    pub path: PathBuf,
+    pub line: Option<u32>,
+    pub column: Option<u32>,
+}
+
 // Placeholder for overall IDE state management struct
 #[derive(Default, Debug)]
 pub struct IdeState {
     pub editors: Vec<EditorView>,
     pub active_editor_index: Option<usize>,
+    /// Location last asked to be opened; shown until editors can load files
+    pub location: Option<FileLocation>,
+    // Bring the panel to the front on the next frame
+    focus: bool,
     // Add other IDE-specific state
 }
 
@@ -134,11 +147,25 @@ impl IdeState {
         Self {
             editors: Vec::new(),
             active_editor_index: None,
+            location: None,
+            focus: false,
         }
     }
 
+    /// Show `path` at `line` and `column` (1-based) in the IDE panel.
+    pub fn open_location(&mut self, path: PathBuf, line: Option<u32>, column: Option<u32>) {
+        info!("Opening {:?} at line {:?}, column {:?}", path, line, column);
+        // TODO: Load the file into an editor and move the caret once
+        // EditorView supports loading content
+        self.location = Some(FileLocation { path, line, column });
+        self.focus = true;
+    }
+
     /// Renders the IDE panel using ImGui.
     pub fn draw(&mut self, ui: &Ui, parent_hwnd: HWND) {
+        if std::mem::take(&mut self.focus) {
+            ui.set_next_window_focus();
+        }
         // Create a window using the correct imgui-rs API
         let window = imgui::Window::new("IDE Panel");
         window
@@ -174,6 +201,18 @@ impl IdeState {
                         //     }
                         // }
                     }
+                } else if let Some(location) = &self.location {
+                    let mut label = location.path.display().to_string();
+                    if let Some(line) = location.line {
+                        label.push_str(&format!(":{}", line));
+                        if let Some(column) = location.column {
+                            label.push_str(&format!(":{}", column));
+                        }
+                    }
+                    ui.text(label);
+                    if !location.path.exists() {
+                        ui.text_disabled("File not found.");
+                    }
                 } else {
                     ui.text("No file open.");
                 }
@@ -1,13 +1,12 @@
 use crate::ide::IdeState;
+use crate::widgets::notifications::AlertNotifications;
+use crate::widgets::processes::ProcessTableState;
 use crate::widgets::terminal::TerminalWidgetState;
-use mavis_core::{monitor::ResourceUsage, ConPtySession};
-use std::{
-    collections::HashMap,
-    sync::{
-        mpsc::{Receiver, Sender},
-        Arc, Mutex,
-    },
+use mavis_core::{
+    monitor::{MonitorControl, ResourceUsage, SharedHistory, UsageSubscription},
+    terminal::SharedSessionManager,
 };
+use std::collections::HashMap;
 
 /// Holds the current state of the MAVIS GUI.
 #[derive(Debug, Default)]
@@ -18,6 +17,12 @@ pub struct GuiState {
     /// Last known resource usage data.
     pub resource_usage: ResourceUsage,
 
+    /// New samples from the resource monitor, replacing `resource_usage`.
+    pub usage_updates: Option<UsageSubscription>,
+
+    /// Metrics history recorded by the resource monitor, for sparklines.
+    pub metrics_history: SharedHistory,
+
     /// Visibility state of different UI panels or widgets.
     /// Key: Widget/Panel ID (String), Value: visible (bool)
     pub widget_visibility: HashMap<String, bool>,
@@ -35,13 +40,20 @@ pub struct GuiState {
     // State for the terminal widget
     pub terminal_state: TerminalWidgetState,
     pub show_terminal: bool,
-    
-    // Skip complex types that don't implement Serialize/Deserialize
-    pub conpty_session: Option<Arc<Mutex<ConPtySession>>>,
 
-    // Channel for ConPTY output reading thread
-    pub conpty_output_rx: Option<Receiver<Vec<u8>>>,
-    pub conpty_output_tx: Option<Sender<Vec<u8>>>, // Kept here temporarily for setup ease
+    // State for the process table
+    pub process_table: ProcessTableState,
+    pub show_processes: bool,
+    /// Settings of the running resource monitor, which decide whether the
+    /// process table may end processes or change their priority
+    pub monitor_control: Option<MonitorControl>,
+
+    // Alerts raised by the resource monitor
+    pub alert_notifications: AlertNotifications,
+    pub show_alerts: bool,
+
+    // Open terminal tabs, shared with the Lua API
+    pub terminal_sessions: SharedSessionManager,
 
     // State for the IDE component
     pub ide_state: IdeState, // NEW
@@ -53,13 +65,17 @@ impl GuiState {
         Self {
             should_exit: false,
             resource_usage: ResourceUsage::default(),
+            usage_updates: None,
+            metrics_history: SharedHistory::default(),
             widget_visibility: HashMap::new(),
             show_demo_window: true, // Show demo window by default initially
             terminal_state: TerminalWidgetState::new(),
             show_terminal: true,
-            conpty_session: None,
-            conpty_output_rx: None, // Initialize channel ends as None
-            conpty_output_tx: None,
+            process_table: ProcessTableState::new(),
+            show_processes: false,
+            alert_notifications: AlertNotifications::default(),
+            show_alerts: false,
+            terminal_sessions: SharedSessionManager::default(),
             ide_state: IdeState::new(), // NEW: Initialize IdeState
         }
     }
@@ -69,6 +85,18 @@ impl GuiState {
         self.resource_usage = usage;
     }
 
+    /// Takes the newest sample from `usage_updates`, if there is one.
+    /// Returns true if `resource_usage` changed.
+    pub fn poll_resource_usage(&mut self) -> bool {
+        match self.usage_updates.as_mut().and_then(UsageSubscription::try_next) {
+            Some(usage) => {
+                self.update_resource_usage(usage);
+                true
+            }
+            None => false,
+        }
+    }
+
     /// Sets the visibility of a specific widget/panel.
     pub fn set_widget_visibility(&mut self, id: String, visible: bool) {
         self.widget_visibility.insert(id, visible);
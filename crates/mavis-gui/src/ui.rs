@@ -1,13 +1,14 @@
 use crate::state::GuiState;
 use crate::widgets;
 use imgui::{MenuItem, Ui};
-use log::debug;
+use log::{debug, error};
 use mavis_core::config::Config as CoreConfig;
+use mavis_core::monitor::process::ProcessController;
 use windows::Win32::Foundation::HWND;
 
 /// Main function to draw the MAVIS user interface.
-pub fn draw_ui(ui: &Ui, state: &mut GuiState, _core_config: &CoreConfig, parent_hwnd: HWND) {
-    // --- Process Async Updates (e.g., ConPTY output) ---
+pub fn draw_ui(ui: &Ui, state: &mut GuiState, core_config: &CoreConfig, parent_hwnd: HWND) {
+    // --- Process Async Updates (e.g., PTY output) ---
     process_async_updates(state);
 
     // --- Top Menu Bar ---
@@ -17,38 +18,29 @@ pub fn draw_ui(ui: &Ui, state: &mut GuiState, _core_config: &CoreConfig, parent_
     // This is where widgets defined in Lua will eventually be drawn.
     // For now, we can draw some basic info or the demo window.
 
-    // Example: Show resource usage from GuiState
-    imgui::Window::new("System Monitor")
-        .size([300.0, 150.0], imgui::Condition::FirstUseEver)
-        .build(ui, || {
-            ui.text(format!("CPU Usage: {:.1}%", state.resource_usage.cpu_usage));
-            ui.text(format!(
-                "Memory Usage: {:.1}% ({:.1} / {:.1} GB)",
-                state.resource_usage.memory_usage,
-                state.resource_usage.used_memory as f64 / 1_073_741_824.0, // Convert bytes to GB
-                state.resource_usage.total_memory as f64 / 1_073_741_824.0
-            ));
-            ui.separator();
-            ui.text(format!(
-                "Network Down: {:.2} KB/s",
-                state.resource_usage.network_down_bytes as f64 / 1024.0 // Bytes to KB
-            ));
-            ui.text(format!(
-                "Network Up: {:.2} KB/s",
-                state.resource_usage.network_up_bytes as f64 / 1024.0 // Bytes to KB
-            ));
-             ui.separator();
-            ui.text(format!("Disk Usage: {:.1}%", state.resource_usage.disk_usage));
-             ui.text(format!(
-                 "Disk Read: {:.2} MB/s",
-                 state.resource_usage.disk_read_bytes as f64 / 1_048_576.0 // Bytes to MB
-             ));
-             ui.text(format!(
-                 "Disk Write: {:.2} MB/s",
-                 state.resource_usage.disk_write_bytes as f64 / 1_048_576.0 // Bytes to MB
-             ));
-        });
+    // System monitor fed by the core's resource monitor
+    widgets::monitor::draw_monitor_widget(ui, &state.resource_usage, &state.metrics_history);
 
+    // --- Process Table ---
+    if state.show_processes {
+        // Built each frame, so settings changed by scripts apply at once
+        let controller = match &state.monitor_control {
+            Some(control) => control.process_controller(),
+            None => ProcessController::new(&core_config.monitoring),
+        };
+        widgets::processes::draw_process_table(
+            ui,
+            &mut state.process_table,
+            &mut state.show_processes,
+            &state.resource_usage.processes,
+            &controller,
+        );
+    }
+
+    // --- Alerts ---
+    if state.show_alerts {
+        widgets::notifications::draw_notifications(ui, &mut state.alert_notifications, &mut state.show_alerts);
+    }
 
     // --- Demo Window ---
     if state.show_demo_window {
@@ -57,15 +49,16 @@ pub fn draw_ui(ui: &Ui, state: &mut GuiState, _core_config: &CoreConfig, parent_
 
     // --- Terminal Widget ---
     if state.show_terminal {
-        // Clone the Option<Arc> to pass to the widget function
-        let session_ref = state.conpty_session.as_ref();
         widgets::terminal::draw_terminal_widget(
             ui,
             &mut state.terminal_state,
             &mut state.show_terminal,
-            session_ref, // Pass the Option<&Arc<Mutex<ConPtySession>>>
+            &state.terminal_sessions,
         );
     }
+    // Keystrokes the terminal did not take this frame (hidden, collapsed or
+    // unfocused) are dropped rather than replayed later
+    state.terminal_state.discard_input();
 
     // --- IDE Panel ---
     // TODO: Add a toggle for IDE visibility (e.g., state.show_ide)
@@ -108,29 +101,66 @@ fn draw_menu_bar(ui: &Ui, state: &mut GuiState) {
             if ui.checkbox("Show Terminal", &mut show_terminal) {
                 state.show_terminal = show_terminal;
             }
+            draw_terminal_menu(ui, state);
+            ui.separator();
+            // Toggle the process table
+            let mut show_processes = state.show_processes;
+            if ui.checkbox("Show Processes", &mut show_processes) {
+                state.show_processes = show_processes;
+            }
+            // Toggle the alert notifications
+            let mut show_alerts = state.show_alerts;
+            if ui.checkbox("Show Alerts", &mut show_alerts) {
+                state.show_alerts = show_alerts;
+            }
             // TODO: Add menu items to toggle other specific MAVIS widgets based on state.widget_visibility
         });
         // Add other menus (e.g., "Help")
     });
 }
 
-/// Processes updates received from background threads (e.g., ConPTY reader).
-fn process_async_updates(state: &mut GuiState) {
-    // Check for ConPTY output
-    if let Some(rx) = state.conpty_output_rx.as_ref() {
-        // Use try_recv for non-blocking check
-        while let Ok(data) = rx.try_recv() {
-             debug!("Received {} bytes from ConPTY channel", data.len());
-            state.terminal_state.process_output(&data);
-            if data.is_empty() {
-                // Reader thread signaled exit/error, remove receiver?
-                // state.conpty_output_rx = None; // Or handle state differently
-                debug!("Reader thread signaled exit/error.");
+/// Menu items for opening terminal tabs, one per configured profile.
+fn draw_terminal_menu(ui: &Ui, state: &mut GuiState) {
+    let Ok(mut sessions) = state.terminal_sessions.lock() else {
+        return;
+    };
+
+    let mut opened = None;
+    if MenuItem::new("New Terminal").build(ui) {
+        opened = Some(sessions.open_default());
+    }
+    let profiles: Vec<String> = sessions.profiles().iter().map(|profile| profile.name.clone()).collect();
+    if let Some(_menu) = ui.begin_menu_with_enabled("Open Profile", !profiles.is_empty()) {
+        for name in &profiles {
+            if MenuItem::new(name).build(ui) {
+                opened = Some(sessions.open_profile(name));
             }
         }
     }
+
+    match opened {
+        Some(Ok(_)) => state.show_terminal = true,
+        Some(Err(e)) => error!("Failed to open terminal: {}", e),
+        None => {}
+    }
+}
+
+/// Processes updates received from background threads (e.g., PTY reader).
+fn process_async_updates(state: &mut GuiState) {
+    // Feed PTY output of every terminal tab into its emulator; this also
+    // applies each tab's exit policy
+    match state.terminal_sessions.lock() {
+        Ok(mut sessions) => {
+            sessions.poll();
+        }
+        Err(_) => debug!("Terminal session manager mutex poisoned; skipping PTY output."),
+    }
+
+    // Alerts that fired bring the notifications window up
+    if state.alert_notifications.poll() {
+        state.show_alerts = true;
+    }
 }
 
 // TODO: Implement functions to draw specific widgets based on Lua configuration.
-// fn draw_cpu_widget(ui: &Ui, state: &GuiState) { ... }
 // fn draw_memory_widget(ui: &Ui, state: &GuiState) { ... }
\ No newline at end of file
@@ -1,4 +1,7 @@
 // MAVIS GUI Widget Module
 
+pub mod monitor;
+pub mod notifications;
+pub mod processes;
 // Re-export terminal module
 pub mod terminal;
\ No newline at end of file
@@ -0,0 +1,255 @@
+// System monitor widget
+//
+// Shows the latest `ResourceUsage` published by the core's resource monitor,
+// with a sparkline of each metric's recent history and the health of each
+// metric source.
+
+use imgui::{ProgressBar, StyleColor, TreeNodeFlags, Ui};
+use mavis_core::monitor::{Metric, MetricsHistory, ResourceUsage, SharedHistory, SourceHealth, SourceStatus};
+use mavis_core::monitor::cpu::{CoreUsage, CpuSnapshot};
+use mavis_core::monitor::disk::DiskSnapshot;
+use mavis_core::monitor::network::NetworkSnapshot;
+
+/// Per-core bars per row.
+const CORES_PER_ROW: usize = 4;
+
+/// Volumes with less free space than the alert threshold.
+const LOW_SPACE_COLOR: [f32; 4] = [0.9, 0.3, 0.3, 1.0];
+
+/// Sources that failed to start or to sample.
+const FAILING_COLOR: [f32; 4] = [0.9, 0.3, 0.3, 1.0];
+
+/// Height of a sparkline in pixels.
+const SPARKLINE_HEIGHT: f32 = 32.0;
+
+/// Draws the "System Monitor" window.
+pub fn draw_monitor_widget(ui: &Ui, usage: &ResourceUsage, history: &SharedHistory) {
+    // A poisoned history only costs the sparklines
+    let history = history.lock().ok();
+    let sparkline = |metric| {
+        if let Some(history) = &history {
+            draw_sparkline(ui, history, metric);
+        }
+    };
+
+    imgui::Window::new("System Monitor")
+        .size([320.0, 300.0], imgui::Condition::FirstUseEver)
+        .build(ui, || {
+            draw_cpu(ui, &usage.cpu);
+            sparkline(Metric::CpuUsage);
+            ui.separator();
+            ui.text(format!(
+                "Memory Usage: {:.1}% ({:.1} / {:.1} GB)",
+                usage.memory_usage,
+                usage.used_memory as f64 / 1_073_741_824.0, // Convert bytes to GB
+                usage.total_memory as f64 / 1_073_741_824.0
+            ));
+            sparkline(Metric::MemoryUsage);
+            ui.separator();
+            draw_network(ui, &usage.network);
+            sparkline(Metric::NetworkDown);
+            sparkline(Metric::NetworkUp);
+            ui.separator();
+            draw_disk(ui, &usage.disk);
+            sparkline(Metric::DiskRead);
+            sparkline(Metric::DiskWrite);
+            ui.separator();
+            draw_sources(ui, &usage.sources);
+        });
+}
+
+/// One line per metric family, flagging sources that are not working.
+fn draw_sources(ui: &Ui, sources: &[SourceStatus]) {
+    let failing = sources.iter().filter(|status| status.health.error().is_some()).count();
+    let label = match failing {
+        0 => "Sources###sources".to_string(),
+        failing => format!("Sources ({} failing)###sources", failing),
+    };
+    if !ui.collapsing_header(label, TreeNodeFlags::empty()) {
+        return;
+    }
+    for status in sources {
+        let backend = status.backend.unwrap_or("none");
+        match &status.health {
+            SourceHealth::Ok => ui.text(format!("{}: {}", status.family, backend)),
+            SourceHealth::Disabled => ui.text_disabled(format!("{}: disabled", status.family)),
+            SourceHealth::InitFailed(error) => {
+                ui.text_colored(FAILING_COLOR, format!("{}: failed to start: {}", status.family, error))
+            }
+            SourceHealth::Failing(error) => {
+                ui.text_colored(FAILING_COLOR, format!("{}: {}: {}", status.family, backend, error))
+            }
+        }
+    }
+}
+
+/// A graph of `metric` over the full-resolution history, with its range
+/// in a tooltip.
+fn draw_sparkline(ui: &Ui, history: &MetricsHistory, metric: Metric) {
+    let window = history.retention().full;
+    let series = history.series(metric);
+    let values: Vec<f32> = series.values(window).into_iter().map(|value| value as f32).collect();
+
+    let mut plot = ui
+        .plot_lines(format!("##{}", metric.name()), &values)
+        .graph_size([ui.content_region_avail()[0], SPARKLINE_HEIGHT])
+        .scale_min(0.0);
+    if metric.is_percentage() {
+        plot = plot.scale_max(100.0);
+    }
+    if let Some(latest) = series.latest() {
+        plot = plot.overlay_text(format!("{} {}", metric_label(metric), format_value(metric, latest)));
+    }
+    plot.build();
+
+    if ui.is_item_hovered()
+        && let Some(summary) = series.summary(window)
+    {
+        let mut text = format!(
+            "Last {} min: min {}, avg {}, max {}",
+            window.as_secs() / 60,
+            format_value(metric, summary.min),
+            format_value(metric, summary.avg),
+            format_value(metric, summary.max)
+        );
+        if let Some(p95) = series.percentile(window, 95.0) {
+            text.push_str(&format!("\n95th percentile: {}", format_value(metric, p95)));
+        }
+        ui.tooltip_text(text);
+    }
+}
+
+/// Overall usage with its breakdown, then one bar per logical core.
+fn draw_cpu(ui: &Ui, cpu: &CpuSnapshot) {
+    match cpu.frequency_mhz {
+        Some(mhz) => ui.text(format!("CPU Usage: {:.1}% @ {:.2} GHz", cpu.usage, mhz as f32 / 1000.0)),
+        None => ui.text(format!("CPU Usage: {:.1}%", cpu.usage)),
+    }
+    ui.text(format!(
+        "user {:.0}%  system {:.0}%  iowait {:.0}%  idle {:.0}%",
+        cpu.breakdown.user, cpu.breakdown.system, cpu.breakdown.iowait, cpu.breakdown.idle
+    ));
+    ui.text(format!("{} processes, {} threads", cpu.processes, cpu.threads));
+    if let Some([one, five, fifteen]) = cpu.load_average {
+        ui.text(format!("Load: {:.2} {:.2} {:.2}", one, five, fifteen));
+    }
+
+    let spacing = ui.clone_style().item_spacing[0];
+    let width = (ui.content_region_avail()[0] - spacing * (CORES_PER_ROW - 1) as f32) / CORES_PER_ROW as f32;
+    for (index, core) in cpu.cores.iter().enumerate() {
+        if index % CORES_PER_ROW != 0 {
+            ui.same_line();
+        }
+        ProgressBar::new(core.usage / 100.0)
+            .size([width, 0.0])
+            .overlay_text(format!("{} {:.0}%", index, core.usage))
+            .build(ui);
+        if ui.is_item_hovered() {
+            ui.tooltip_text(core_tooltip(index, core));
+        }
+    }
+}
+
+/// Total throughput, then one line per monitored interface.
+fn draw_network(ui: &Ui, network: &NetworkSnapshot) {
+    ui.text(format!(
+        "Network: {:.2} KB/s down, {:.2} KB/s up",
+        network.down_bytes as f64 / 1024.0, // Bytes to KB
+        network.up_bytes as f64 / 1024.0
+    ));
+    for interface in &network.interfaces {
+        let color = if interface.up { [0.6, 0.9, 0.6, 1.0] } else { [0.6, 0.6, 0.6, 1.0] };
+        ui.text_colored(color, if interface.up { "up  " } else { "down" });
+        ui.same_line();
+        ui.text(format!(
+            "{}: {:.1} KB/s down, {:.1} KB/s up",
+            interface.name,
+            interface.rx_bytes as f64 / 1024.0,
+            interface.tx_bytes as f64 / 1024.0
+        ));
+        if ui.is_item_hovered() {
+            let totals = &interface.totals;
+            ui.tooltip_text(format!(
+                "{} packets/s in, {} packets/s out\nSince start: {:.1} MB in, {:.1} MB out\nErrors: {} in, {} out\nDropped: {} in, {} out",
+                interface.rx_packets,
+                interface.tx_packets,
+                totals.rx_bytes as f64 / 1_048_576.0,
+                totals.tx_bytes as f64 / 1_048_576.0,
+                totals.rx_errors,
+                totals.tx_errors,
+                totals.rx_dropped,
+                totals.tx_dropped
+            ));
+        }
+    }
+}
+
+/// Busy time and throughput per physical disk, then a bar per volume.
+fn draw_disk(ui: &Ui, disk: &DiskSnapshot) {
+    ui.text(format!(
+        "Disk: {:.1}% busy, {:.2} MB/s read, {:.2} MB/s write",
+        disk.busy,
+        disk.read_bytes as f64 / 1_048_576.0, // Bytes to MB
+        disk.write_bytes as f64 / 1_048_576.0
+    ));
+    for physical in &disk.disks {
+        ui.text(format!(
+            "{}: {:.0}% busy, {:.2} / {:.2} MB/s, {:.0} / {:.0} IOPS, queue {:.1}",
+            physical.name,
+            physical.busy,
+            physical.read_bytes as f64 / 1_048_576.0,
+            physical.write_bytes as f64 / 1_048_576.0,
+            physical.read_ops,
+            physical.write_ops,
+            physical.queue_length
+        ));
+    }
+
+    for volume in &disk.volumes {
+        let overlay = format!(
+            "{} {:.1} / {:.1} GB free",
+            volume.mount_point,
+            volume.free_bytes as f64 / 1_073_741_824.0, // Bytes to GB
+            volume.total_bytes as f64 / 1_073_741_824.0
+        );
+        let _color = volume
+            .low_space
+            .then(|| ui.push_style_color(StyleColor::PlotHistogram, LOW_SPACE_COLOR));
+        ProgressBar::new(volume.used_percent() / 100.0).overlay_text(overlay).build(ui);
+        if ui.is_item_hovered() {
+            ui.tooltip_text(format!("{} ({}), {:.1}% used", volume.device, volume.filesystem, volume.used_percent()));
+        }
+    }
+}
+
+fn metric_label(metric: Metric) -> &'static str {
+    match metric {
+        Metric::CpuUsage => "CPU",
+        Metric::MemoryUsage => "Memory",
+        Metric::DiskBusy => "Disk busy",
+        Metric::DiskRead => "Read",
+        Metric::DiskWrite => "Write",
+        Metric::NetworkDown => "Down",
+        Metric::NetworkUp => "Up",
+    }
+}
+
+/// A value of `metric` with its unit: percentages, or rates in KB/s.
+fn format_value(metric: Metric, value: f64) -> String {
+    if metric.is_percentage() {
+        format!("{:.1}%", value)
+    } else {
+        format!("{:.1} KB/s", value / 1024.0)
+    }
+}
+
+fn core_tooltip(index: usize, core: &CoreUsage) -> String {
+    let mut text = format!(
+        "Core {}: {:.1}%\nuser {:.0}%  system {:.0}%  iowait {:.0}%",
+        index, core.usage, core.breakdown.user, core.breakdown.system, core.breakdown.iowait
+    );
+    if let Some(mhz) = core.frequency_mhz {
+        text.push_str(&format!("\n{} MHz", mhz));
+    }
+    text
+}
@@ -0,0 +1,119 @@
+// Alert notifications widget
+//
+// Collects the events of the core's alert engine as they happen: the alerts
+// firing now are listed until they clear, and recent events stay in a short
+// log until dismissed. Events received this frame are also handed to the
+// script callbacks by the window loop.
+
+use imgui::{Condition, StyleColor, Ui};
+use mavis_core::monitor::alerts::{AlertKind, Severity};
+use mavis_core::monitor::{AlertEvent, AlertSubscription};
+use std::collections::VecDeque;
+use std::time::SystemTime;
+
+/// Events kept in the recent log.
+const RECENT_CAPACITY: usize = 20;
+
+/// Alert notifications widget state
+#[derive(Debug, Default)]
+pub struct AlertNotifications {
+    subscription: Option<AlertSubscription>,
+    /// Alerts firing now, as the events that fired them
+    active: Vec<AlertEvent>,
+    /// Newest last
+    recent: VecDeque<AlertEvent>,
+    /// Received since the last `take_new`
+    new_events: Vec<AlertEvent>,
+}
+
+impl AlertNotifications {
+    pub fn new(subscription: AlertSubscription) -> Self {
+        Self {
+            subscription: Some(subscription),
+            ..Default::default()
+        }
+    }
+
+    /// Receive the events that happened since the last call. Returns true
+    /// if an alert fired.
+    pub fn poll(&mut self) -> bool {
+        let Some(subscription) = &mut self.subscription else {
+            return false;
+        };
+        let mut fired = false;
+        while let Some(event) = subscription.try_next() {
+            self.active
+                .retain(|active| (&active.rule, &active.subject) != (&event.rule, &event.subject));
+            if event.kind == AlertKind::Fired {
+                fired = true;
+                self.active.push(event.clone());
+            }
+            if self.recent.len() == RECENT_CAPACITY {
+                self.recent.pop_front();
+            }
+            self.recent.push_back(event.clone());
+            self.new_events.push(event);
+        }
+        fired
+    }
+
+    /// The events received since the last call, oldest first.
+    pub fn take_new(&mut self) -> Vec<AlertEvent> {
+        std::mem::take(&mut self.new_events)
+    }
+
+    pub fn active(&self) -> &[AlertEvent] {
+        &self.active
+    }
+}
+
+/// Draws the "Alerts" window.
+pub fn draw_notifications(ui: &Ui, notifications: &mut AlertNotifications, opened: &mut bool) {
+    imgui::Window::new("Alerts")
+        .size([360.0, 220.0], Condition::FirstUseEver)
+        .opened(opened)
+        .build(ui, || {
+            if notifications.active.is_empty() {
+                ui.text_disabled("No active alerts");
+            }
+            for event in &notifications.active {
+                let _color = ui.push_style_color(StyleColor::Text, severity_color(event.severity));
+                ui.text_wrapped(&event.message);
+            }
+
+            ui.separator();
+            ui.text("Recent");
+            if !notifications.recent.is_empty() {
+                ui.same_line();
+                if ui.small_button("Dismiss") {
+                    notifications.recent.clear();
+                }
+            }
+            let now = SystemTime::now();
+            for event in notifications.recent.iter().rev() {
+                let ago = now.duration_since(event.at).unwrap_or_default().as_secs();
+                let text = format!("{} ago: {}", format_ago(ago), event.message);
+                match event.kind {
+                    AlertKind::Fired => ui.text_colored(severity_color(event.severity), text),
+                    AlertKind::Cleared => ui.text_disabled(text),
+                }
+            }
+        });
+}
+
+fn severity_color(severity: Severity) -> [f32; 4] {
+    match severity {
+        Severity::Info => [0.6, 0.8, 1.0, 1.0],
+        Severity::Warning => [1.0, 0.8, 0.3, 1.0],
+        Severity::Critical => [0.9, 0.3, 0.3, 1.0],
+    }
+}
+
+/// A short age, e.g. "45s" or "3m".
+fn format_ago(seconds: u64) -> String {
+    match seconds {
+        0..60 => format!("{}s", seconds),
+        60..3600 => format!("{}m", seconds / 60),
+        _ => format!("{}h", seconds / 3600),
+    }
+}
@@ -0,0 +1,206 @@
+// Process table widget
+//
+// A sortable, filterable list of the processes in the latest
+// `ResourceUsage`, with actions to end the selected process or change its
+// priority when the core configuration allows it.
+
+use imgui::{
+    Condition, MenuItem, Selectable, SelectableFlags, TableColumnFlags, TableColumnSetup, TableFlags, TableSortDirection, Ui,
+};
+use log::{error, info};
+use mavis_core::monitor::process::{ProcessColumn, ProcessController, ProcessInfo, ProcessPriority, ProcessSnapshot};
+use std::time::SystemTime;
+
+/// Table columns in display order; the command line is not sortable.
+const COLUMNS: [(&str, Option<ProcessColumn>); 7] = [
+    ("PID", Some(ProcessColumn::Pid)),
+    ("Name", Some(ProcessColumn::Name)),
+    ("CPU", Some(ProcessColumn::Cpu)),
+    ("Memory", Some(ProcessColumn::Memory)),
+    ("I/O", Some(ProcessColumn::Io)),
+    ("Running", Some(ProcessColumn::StartTime)),
+    ("Command line", None),
+];
+
+/// Process table widget state
+#[derive(Debug, Default)]
+pub struct ProcessTableState {
+    filter: String,
+    sort: ProcessColumn,
+    descending: bool,
+    selected: Option<u32>,
+    // Outcome of the last action, shown next to the buttons
+    status: Option<String>,
+}
+
+impl ProcessTableState {
+    pub fn new() -> Self {
+        Self {
+            descending: true,
+            ..Default::default()
+        }
+    }
+}
+
+/// Draws the "Processes" window.
+pub fn draw_process_table(
+    ui: &Ui,
+    state: &mut ProcessTableState,
+    opened: &mut bool,
+    snapshot: &ProcessSnapshot,
+    controller: &ProcessController,
+) {
+    imgui::Window::new("Processes")
+        .size([640.0, 420.0], Condition::FirstUseEver)
+        .opened(opened)
+        .build(ui, || {
+            ui.set_next_item_width(ui.content_region_avail()[0] * 0.4);
+            ui.input_text("##process_filter", &mut state.filter)
+                .hint("Filter by name, command line or PID")
+                .build();
+            ui.same_line();
+            ui.text(format!("{} processes", snapshot.processes.len()));
+
+            draw_actions(ui, state, snapshot, controller);
+            ui.separator();
+            draw_table(ui, state, snapshot);
+        });
+}
+
+/// End and priority buttons for the selected process.
+fn draw_actions(ui: &Ui, state: &mut ProcessTableState, snapshot: &ProcessSnapshot, controller: &ProcessController) {
+    let selected = state.selected.and_then(|pid| snapshot.get(pid));
+    let mut result = None;
+
+    ui.disabled(!controller.is_allowed() || selected.is_none(), || {
+        if ui.button("End process")
+            && let Some(process) = selected
+        {
+            result = Some((format!("Asked {} ({}) to end", process.name, process.pid), controller.terminate(process.pid)));
+        }
+        ui.same_line();
+        if ui.button("Priority") {
+            ui.open_popup("process_priority");
+        }
+    });
+    ui.popup("process_priority", || {
+        for priority in ProcessPriority::ALL {
+            if MenuItem::new(priority.label()).build(ui)
+                && let Some(process) = selected
+            {
+                let message = format!("Set {} ({}) to {}", process.name, process.pid, priority.label());
+                result = Some((message, controller.set_priority(process.pid, priority)));
+            }
+        }
+    });
+    if !controller.is_allowed() {
+        ui.same_line();
+        ui.text_disabled("Process control is turned off in the monitoring settings");
+    }
+
+    match result {
+        Some((message, Ok(()))) => {
+            info!("{}", message);
+            state.status = Some(message);
+        }
+        Some((_, Err(e))) => {
+            error!("{}", e);
+            state.status = Some(e.to_string());
+        }
+        None => {}
+    }
+    if let Some(status) = &state.status {
+        ui.same_line();
+        ui.text_disabled(status);
+    }
+}
+
+fn draw_table(ui: &Ui, state: &mut ProcessTableState, snapshot: &ProcessSnapshot) {
+    let flags = TableFlags::SORTABLE
+        | TableFlags::RESIZABLE
+        | TableFlags::ROW_BG
+        | TableFlags::BORDERS_V
+        | TableFlags::SCROLL_Y;
+    let Some(_table) = ui.begin_table_with_flags("##processes", COLUMNS.len(), flags) else {
+        return;
+    };
+
+    ui.table_setup_scroll_freeze(0, 1);
+    for (name, column) in COLUMNS {
+        let mut setup = TableColumnSetup::new(name);
+        setup.flags = match column {
+            None => TableColumnFlags::NO_SORT | TableColumnFlags::WIDTH_STRETCH,
+            Some(column) if column == state.sort => TableColumnFlags::DEFAULT_SORT | TableColumnFlags::PREFER_SORT_DESCENDING,
+            Some(_) => TableColumnFlags::PREFER_SORT_DESCENDING,
+        };
+        ui.table_setup_column_with(setup);
+    }
+    ui.table_headers_row();
+
+    if let Some(mut specs) = ui.table_sort_specs_mut() {
+        specs.conditional_sort(|specs| {
+            if let Some(spec) = specs.iter().next()
+                && let Some((_, Some(column))) = COLUMNS.get(spec.column_idx())
+            {
+                state.sort = *column;
+                state.descending = spec.sort_direction() == Some(TableSortDirection::Descending);
+            }
+        });
+    }
+
+    let mut processes = ProcessSnapshot::new(snapshot.filter(&state.filter).cloned().collect());
+    processes.sort(state.sort, state.descending);
+    let now = SystemTime::now();
+    for process in &processes.processes {
+        ui.table_next_row();
+        ui.table_next_column();
+        let selected = state.selected == Some(process.pid);
+        if Selectable::new(process.pid.to_string())
+            .selected(selected)
+            .flags(SelectableFlags::SPAN_ALL_COLUMNS)
+            .build(ui)
+        {
+            state.selected = Some(process.pid);
+            state.status = None;
+        }
+        if ui.is_item_hovered() {
+            ui.tooltip_text(process_tooltip(process));
+        }
+        ui.table_next_column();
+        ui.text(&process.name);
+        ui.table_next_column();
+        ui.text(format!("{:.1}%", process.cpu_usage));
+        ui.table_next_column();
+        ui.text(format!("{:.1} MB", process.memory_bytes as f64 / 1_048_576.0));
+        ui.table_next_column();
+        ui.text(format!("{:.1} KB/s", (process.read_bytes + process.write_bytes) as f64 / 1024.0));
+        ui.table_next_column();
+        ui.text(process.start_time.map(|start| running_for(start, now)).unwrap_or_default());
+        ui.table_next_column();
+        ui.text(&process.command_line);
+    }
+}
+
+fn process_tooltip(process: &ProcessInfo) -> String {
+    let mut text = format!(
+        "{} ({})\nRead {:.1} KB/s, write {:.1} KB/s",
+        process.name,
+        process.pid,
+        process.read_bytes as f64 / 1024.0,
+        process.write_bytes as f64 / 1024.0
+    );
+    if let Some(parent) = process.parent {
+        text.push_str(&format!("\nParent: {}", parent));
+    }
+    text
+}
+
+/// How long a process started at `start` has been running, e.g. "2h 05m".
+fn running_for(start: SystemTime, now: SystemTime) -> String {
+    let seconds = now.duration_since(start).unwrap_or_default().as_secs();
+    match (seconds / 86_400, seconds / 3600 % 24, seconds / 60 % 60) {
+        (0, 0, minutes) => format!("{}m {:02}s", minutes, seconds % 60),
+        (0, hours, minutes) => format!("{}h {:02}m", hours, minutes),
+        (days, hours, _) => format!("{}d {:02}h", days, hours),
+    }
+}
@@ -0,0 +1,246 @@
+// Translation of winit keyboard and mouse events into terminal input
+
+use mavis_core::terminal::{Key, Modifiers, MouseButton};
+use winit::event::{
+    ElementState, KeyboardInput, ModifiersState, MouseButton as WinitButton, MouseScrollDelta, VirtualKeyCode,
+    WindowEvent,
+};
+
+/// Input for the focused terminal, queued between frames. Pointer
+/// positions are in logical pixels, the coordinates ImGui draws in.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TerminalInput {
+    Key(Key, Modifiers),
+    /// Paste the clipboard (Shift+Insert or Ctrl+Shift+V).
+    Paste,
+    /// Copy the selection (Ctrl+Insert or Ctrl+Shift+C).
+    Copy,
+    /// Open the scrollback search bar (Ctrl+Shift+F).
+    Find,
+    /// Scroll to the previous or next shell prompt (Ctrl+Shift+Up/Down).
+    PreviousPrompt,
+    NextPrompt,
+    /// Copy the last command's output (Ctrl+Shift+O).
+    CopyLastOutput,
+    /// Start or stop recording the session (Ctrl+Shift+R).
+    ToggleRecording,
+    PointerMoved([f32; 2], Modifiers),
+    MouseButton {
+        button: MouseButton,
+        pressed: bool,
+        position: [f32; 2],
+        mods: Modifiers,
+    },
+    Wheel {
+        delta: MouseScrollDelta,
+        position: [f32; 2],
+        mods: Modifiers,
+    },
+}
+
+/// Turns window events into terminal input. Text comes from
+/// `ReceivedCharacter`, so the keyboard layout is respected; named keys and
+/// Ctrl/Alt chords come from `KeyboardInput`.
+#[derive(Debug, Default)]
+pub struct KeyTranslator {
+    modifiers: ModifiersState,
+    // The keypad reports both a key and a character; keep only the key
+    suppress_next_char: bool,
+    // Last pointer position, which button and wheel events do not carry
+    pointer: [f32; 2],
+}
+
+impl KeyTranslator {
+    /// Translate `event`; `scale_factor` converts physical pixels to logical.
+    pub fn translate(&mut self, event: &WindowEvent, scale_factor: f64) -> Option<TerminalInput> {
+        match event {
+            WindowEvent::ModifiersChanged(state) => {
+                self.modifiers = *state;
+                None
+            }
+            WindowEvent::CursorMoved { position, .. } => {
+                let position = position.to_logical::<f32>(scale_factor);
+                self.pointer = [position.x, position.y];
+                Some(TerminalInput::PointerMoved(self.pointer, self.modifiers()))
+            }
+            WindowEvent::MouseInput { state, button, .. } => {
+                let button = match button {
+                    WinitButton::Left => MouseButton::Left,
+                    WinitButton::Middle => MouseButton::Middle,
+                    WinitButton::Right => MouseButton::Right,
+                    WinitButton::Other(_) => return None,
+                };
+                Some(TerminalInput::MouseButton {
+                    button,
+                    pressed: *state == ElementState::Pressed,
+                    position: self.pointer,
+                    mods: self.modifiers(),
+                })
+            }
+            WindowEvent::MouseWheel { delta, .. } => Some(TerminalInput::Wheel {
+                delta: *delta,
+                position: self.pointer,
+                mods: self.modifiers(),
+            }),
+            WindowEvent::ReceivedCharacter(c) => {
+                if std::mem::take(&mut self.suppress_next_char) || c.is_control() || self.is_chord() {
+                    return None;
+                }
+                Some(TerminalInput::Key(Key::Char(*c), Modifiers::empty()))
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(keycode),
+                        ..
+                    },
+                ..
+            } => self.translate_key(*keycode),
+            _ => None,
+        }
+    }
+
+    fn translate_key(&mut self, keycode: VirtualKeyCode) -> Option<TerminalInput> {
+        let mods = self.modifiers();
+        let shift = self.modifiers.shift();
+        let ctrl = self.modifiers.ctrl();
+
+        if (keycode == VirtualKeyCode::Insert && shift && !ctrl)
+            || (keycode == VirtualKeyCode::V && shift && ctrl)
+        {
+            return Some(TerminalInput::Paste);
+        }
+        if (keycode == VirtualKeyCode::Insert && ctrl && !shift)
+            || (keycode == VirtualKeyCode::C && shift && ctrl)
+        {
+            return Some(TerminalInput::Copy);
+        }
+        if keycode == VirtualKeyCode::F && shift && ctrl {
+            return Some(TerminalInput::Find);
+        }
+        if shift && ctrl {
+            match keycode {
+                VirtualKeyCode::Up => return Some(TerminalInput::PreviousPrompt),
+                VirtualKeyCode::Down => return Some(TerminalInput::NextPrompt),
+                VirtualKeyCode::O => return Some(TerminalInput::CopyLastOutput),
+                VirtualKeyCode::R => return Some(TerminalInput::ToggleRecording),
+                _ => {}
+            }
+        }
+        if let Some(key) = named_key(keycode) {
+            self.suppress_next_char = matches!(key, Key::Keypad(c) if c != '\r');
+            return Some(TerminalInput::Key(key, mods));
+        }
+        // Characters typed with Ctrl or Alt arrive here rather than as text
+        if self.is_chord() {
+            let c = chord_char(keycode)?;
+            let c = if shift { c.to_ascii_uppercase() } else { c };
+            return Some(TerminalInput::Key(Key::Char(c), mods));
+        }
+        None
+    }
+
+    fn modifiers(&self) -> Modifiers {
+        let mut mods = Modifiers::empty();
+        if self.modifiers.shift() {
+            mods |= Modifiers::SHIFT;
+        }
+        if self.modifiers.alt() {
+            mods |= Modifiers::ALT;
+        }
+        if self.modifiers.ctrl() {
+            mods |= Modifiers::CTRL;
+        }
+        mods
+    }
+
+    // Ctrl+Alt together is AltGr on Windows, which types ordinary text
+    fn is_chord(&self) -> bool {
+        self.modifiers.ctrl() != self.modifiers.alt()
+    }
+}
+
+fn named_key(keycode: VirtualKeyCode) -> Option<Key> {
+    use VirtualKeyCode as K;
+    let key = match keycode {
+        K::Return => Key::Enter,
+        K::Tab => Key::Tab,
+        K::Back => Key::Backspace,
+        K::Escape => Key::Escape,
+        K::Up => Key::Up,
+        K::Down => Key::Down,
+        K::Left => Key::Left,
+        K::Right => Key::Right,
+        K::Home => Key::Home,
+        K::End => Key::End,
+        K::PageUp => Key::PageUp,
+        K::PageDown => Key::PageDown,
+        K::Insert => Key::Insert,
+        K::Delete => Key::Delete,
+        K::F1 => Key::F(1),
+        K::F2 => Key::F(2),
+        K::F3 => Key::F(3),
+        K::F4 => Key::F(4),
+        K::F5 => Key::F(5),
+        K::F6 => Key::F(6),
+        K::F7 => Key::F(7),
+        K::F8 => Key::F(8),
+        K::F9 => Key::F(9),
+        K::F10 => Key::F(10),
+        K::F11 => Key::F(11),
+        K::F12 => Key::F(12),
+        K::Numpad0 => Key::Keypad('0'),
+        K::Numpad1 => Key::Keypad('1'),
+        K::Numpad2 => Key::Keypad('2'),
+        K::Numpad3 => Key::Keypad('3'),
+        K::Numpad4 => Key::Keypad('4'),
+        K::Numpad5 => Key::Keypad('5'),
+        K::Numpad6 => Key::Keypad('6'),
+        K::Numpad7 => Key::Keypad('7'),
+        K::Numpad8 => Key::Keypad('8'),
+        K::Numpad9 => Key::Keypad('9'),
+        K::NumpadDecimal => Key::Keypad('.'),
+        K::NumpadAdd => Key::Keypad('+'),
+        K::NumpadSubtract => Key::Keypad('-'),
+        K::NumpadMultiply => Key::Keypad('*'),
+        K::NumpadDivide => Key::Keypad('/'),
+        K::NumpadEquals => Key::Keypad('='),
+        K::NumpadEnter => Key::Keypad('\r'),
+        _ => return None,
+    };
+    Some(key)
+}
+
+/// The unshifted character of a key on a US layout, for Ctrl/Alt chords.
+fn chord_char(keycode: VirtualKeyCode) -> Option<char> {
+    use VirtualKeyCode as K;
+    let letters = [
+        K::A, K::B, K::C, K::D, K::E, K::F, K::G, K::H, K::I, K::J, K::K, K::L, K::M,
+        K::N, K::O, K::P, K::Q, K::R, K::S, K::T, K::U, K::V, K::W, K::X, K::Y, K::Z,
+    ];
+    let digits = [K::Key0, K::Key1, K::Key2, K::Key3, K::Key4, K::Key5, K::Key6, K::Key7, K::Key8, K::Key9];
+
+    if let Some(index) = letters.iter().position(|&k| k == keycode) {
+        return Some((b'a' + index as u8) as char);
+    }
+    if let Some(index) = digits.iter().position(|&k| k == keycode) {
+        return Some((b'0' + index as u8) as char);
+    }
+    let c = match keycode {
+        K::Space => ' ',
+        K::LBracket => '[',
+        K::RBracket => ']',
+        K::Backslash => '\\',
+        K::Minus => '-',
+        K::Equals => '=',
+        K::Slash => '/',
+        K::Period => '.',
+        K::Comma => ',',
+        K::Semicolon => ';',
+        K::Apostrophe => '\'',
+        K::Grave => '`',
+        _ => return None,
+    };
+    Some(c)
+}
@@ -0,0 +1,34 @@
+// Opening links clicked in the terminal
+//
+// Ctrl+click on a link queues it; once the frame is drawn the script
+// handler set with `MAVIS.terminal.on_link` gets the first chance to open
+// it, and `open_link` is the fallback.
+
+use crate::ide::IdeState;
+use log::{info, warn};
+use mavis_core::terminal::{Link, LinkTarget};
+use std::path::PathBuf;
+use std::process::Command;
+
+/// Open `link` the default way: URLs in the system browser, file locations
+/// in the IDE panel.
+pub fn open_link(link: &Link, ide: &mut IdeState) {
+    match &link.target {
+        LinkTarget::Url(url) => {
+            info!("Opening {}", url);
+            if let Err(e) = Command::new("explorer").arg(url).spawn() {
+                warn!("Failed to open {}: {}", url, e);
+            }
+        }
+        LinkTarget::File { path, line, column } => ide.open_location(PathBuf::from(path), *line, *column),
+    }
+}
+
+/// Text shown while hovering a link.
+pub fn describe(link: &Link) -> String {
+    match &link.target {
+        LinkTarget::Url(url) => format!("Ctrl+click to open {}", url),
+        LinkTarget::File { path, line: Some(line), .. } => format!("Ctrl+click to open {} at line {}", path, line),
+        LinkTarget::File { path, .. } => format!("Ctrl+click to open {}", path),
+    }
+}
@@ -1,144 +1,546 @@
 // Terminal widget for MAVIS GUI
 
-use imgui::Ui;
-use log::{debug, info};
-use std::sync::{Arc, Mutex};
-use mavis_core::ConPtySession;
+mod keys;
+mod links;
+mod mouse;
+mod search;
+
+use imgui::{StyleColor, TabBar, TabItem, TabItemFlags, Ui};
+use log::info;
+use mavis_core::config::{Config, TerminalConfig};
+use mavis_core::terminal::{
+    CellAttributes, CellFlags, Color, Key, Link, LinkDetector, Modifiers, MouseButton, SessionId, SharedSessionManager,
+    Terminal, TerminalSession,
+};
+use mouse::MouseState;
+use search::{CURRENT_MATCH_BG, MATCH_BG, SearchBar};
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+use winit::event::{MouseScrollDelta, WindowEvent};
+
+/// Gutter marker beside the prompt of a command that failed.
+const FAILED_COMMAND_MARK: [f32; 4] = [0.85, 0.2, 0.2, 1.0];
+/// Recording indicator.
+const RECORDING_COLOR: [f32; 4] = [0.95, 0.3, 0.3, 1.0];
+
+pub use keys::TerminalInput;
+pub use links::open_link;
 
 /// Terminal widget state
-#[derive(Debug, Default)]
+#[derive(Debug)]
 pub struct TerminalWidgetState {
-    // Terminal buffer storage
-    buffer: Vec<String>,
-    // Terminal cursor position
-    cursor_x: usize,
-    cursor_y: usize,
-    // Input buffer for commands
-    input_buffer: String,
-    // Terminal size
+    // Keyboard input waiting for the next frame, and the translator state
+    pending_input: Vec<TerminalInput>,
+    translator: keys::KeyTranslator,
+    mouse: MouseState,
+    search: SearchBar,
+    // Link under the pointer while Ctrl is held, and links Ctrl+clicked
+    // this frame, waiting to be opened
+    hovered_link: Option<Link>,
+    activated_links: Vec<Link>,
+    // Screen position of the grid's top-left cell, as last drawn
+    grid_origin: [f32; 2],
+    // Tab shown last frame, to notice when the active session changes elsewhere
+    shown_tab: Option<SessionId>,
+    // Terminal size in cells, and the cell size in pixels it was measured with
     width: usize,
     height: usize,
+    char_size: [f32; 2],
+    // Font size the ImGui font was loaded at; profiles scale relative to it
+    base_font_size: f32,
+}
+
+impl Default for TerminalWidgetState {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl TerminalWidgetState {
     /// Create a new terminal widget state
     pub fn new() -> Self {
+        Self::from_config(&Config::default().terminal)
+    }
+
+    /// Create a terminal widget state sized from the terminal configuration
+    pub fn from_config(config: &TerminalConfig) -> Self {
         Self {
-            buffer: vec![String::new()],
-            cursor_x: 0,
-            cursor_y: 0,
-            input_buffer: String::with_capacity(256),
-            width: 80,
-            height: 25,
+            pending_input: Vec::new(),
+            translator: keys::KeyTranslator::default(),
+            mouse: MouseState::default(),
+            search: SearchBar::default(),
+            hovered_link: None,
+            activated_links: Vec::new(),
+            grid_origin: [0.0, 0.0],
+            shown_tab: None,
+            width: config.columns as usize,
+            height: config.rows as usize,
+            // Estimate until the font has been measured
+            char_size: [config.font_size * 0.6, config.font_size * 1.3],
+            base_font_size: config.font_size,
         }
     }
 
-    /// Process output data from ConPTY
-    pub fn process_output(&mut self, data: &[u8]) {
-        if data.is_empty() {
-            return;
+    /// Queue keyboard and mouse input from a window event for the focused
+    /// terminal
+    pub fn handle_window_event(&mut self, event: &WindowEvent, scale_factor: f64) {
+        if let Some(input) = self.translator.translate(event, scale_factor) {
+            self.pending_input.push(input);
         }
+    }
 
-        // Simple implementation: just convert bytes to UTF-8 string and add to buffer
-        if let Ok(text) = String::from_utf8(data.to_vec()) {
-            // Very basic terminal emulation - just append to last line or split on newlines
-            for c in text.chars() {
-                match c {
-                    '\n' => {
-                        self.cursor_y += 1;
-                        self.cursor_x = 0;
-                        if self.cursor_y >= self.buffer.len() {
-                            self.buffer.push(String::new());
-                        }
-                    },
-                    '\r' => self.cursor_x = 0,
-                    '\t' => self.cursor_x = (self.cursor_x + 8) / 8 * 8,
-                    _ => {
-                        // Ensure we have a line to write to
-                        while self.cursor_y >= self.buffer.len() {
-                            self.buffer.push(String::new());
-                        }
-
-                        // Ensure line is long enough
-                        let line = &mut self.buffer[self.cursor_y];
-                        while line.chars().count() < self.cursor_x {
-                            line.push(' ');
-                        }
-
-                        // Insert character at cursor position
-                        line.push(c);
-                        self.cursor_x += 1;
-                    }
+    /// Drop input no session took this frame
+    pub fn discard_input(&mut self) {
+        self.pending_input.clear();
+    }
+
+    /// Links the user activated since the last call
+    pub fn take_activated_links(&mut self) -> Vec<Link> {
+        std::mem::take(&mut self.activated_links)
+    }
+
+    /// Viewport cell under `position`, clamped to the grid, and whether the
+    /// position is inside the grid at all.
+    fn cell_at(&self, position: [f32; 2]) -> ((usize, usize), bool) {
+        let col = (position[0] - self.grid_origin[0]) / self.char_size[0];
+        let row = (position[1] - self.grid_origin[1]) / self.char_size[1];
+        let inside = col >= 0.0 && row >= 0.0 && col < self.width as f32 && row < self.height as f32;
+        let clamp = |v: f32, len: usize| (v.max(0.0) as usize).min(len.saturating_sub(1));
+        ((clamp(col, self.width), clamp(row, self.height)), inside)
+    }
+}
+
+/// Resolve a cell colour to an ImGui RGBA value.
+fn color_to_rgba(color: Color, fallback: [f32; 4]) -> [f32; 4] {
+    match color.to_rgb() {
+        Some((r, g, b)) => [r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0, 1.0],
+        None => fallback,
+    }
+}
+
+/// Foreground and background colours for a cell, applying bold-as-bright,
+/// dim, inverse and hidden renditions.
+fn cell_colors(attrs: &CellAttributes, default_fg: [f32; 4], default_bg: [f32; 4]) -> ([f32; 4], [f32; 4]) {
+    let fg_color = match attrs.fg {
+        Color::Indexed(index) if index < 8 && attrs.flags.contains(CellFlags::BOLD) => Color::Indexed(index + 8),
+        other => other,
+    };
+    let mut fg = color_to_rgba(fg_color, default_fg);
+    let mut bg = color_to_rgba(attrs.bg, default_bg);
+
+    if attrs.flags.contains(CellFlags::DIM) {
+        fg = [fg[0] * 0.66, fg[1] * 0.66, fg[2] * 0.66, fg[3]];
+    }
+    if attrs.flags.contains(CellFlags::INVERSE) {
+        std::mem::swap(&mut fg, &mut bg);
+    }
+    if attrs.flags.contains(CellFlags::HIDDEN) {
+        fg = bg;
+    }
+    (fg, bg)
+}
+
+/// Draws the emulator grid cell by cell, batching runs of equal attributes.
+fn draw_grid(ui: &Ui, terminal: &Terminal, char_size: [f32; 2], hovered_link: Option<&Link>) {
+    let draw_list = ui.get_window_draw_list();
+    let origin = ui.cursor_screen_pos();
+    let default_fg = ui.style_color(StyleColor::Text);
+    let default_bg = ui.style_color(StyleColor::ChildBg);
+    let selection_bg = ui.style_color(StyleColor::TextSelectedBg);
+    let selection = terminal.selection_range();
+    let screen = terminal.screen();
+
+    // Search matches that touch the viewport
+    let (top_line, bottom_line) = (terminal.viewport_line(0), terminal.viewport_line(screen.rows() - 1));
+    let search = terminal.active_search();
+    let current = search.and_then(|search| search.current()).copied();
+    let matches: Vec<_> = search
+        .map(|search| search.matches())
+        .unwrap_or_default()
+        .iter()
+        .filter(|m| m.end.line >= top_line && m.start.line <= bottom_line)
+        .copied()
+        .collect();
+
+    for y in 0..screen.rows() {
+        let row = screen.visible_row(y);
+        let line = terminal.viewport_line(y);
+        // Selection wins over the current match, which wins over other matches
+        let highlight = |x: usize| {
+            if selection.is_some_and(|range| range.contains(line, x)) {
+                Some(selection_bg)
+            } else if current.is_some_and(|m| m.contains(line, x)) {
+                Some(CURRENT_MATCH_BG)
+            } else if matches.iter().any(|m| m.contains(line, x)) {
+                Some(MATCH_BG)
+            } else {
+                None
+            }
+        };
+        let top = origin[1] + y as f32 * char_size[1];
+        let mut x = 0;
+        while x < row.cells.len() {
+            // Collect a run of cells sharing the same attributes and highlight
+            let attrs = row.cells[x].attrs;
+            let run_highlight = highlight(x);
+            let start = x;
+            let mut text = String::new();
+            // Wide glyphs differ in flags from their neighbours, so each one
+            // is drawn as its own run at its first cell; spacer halves add nothing.
+            while x < row.cells.len() && row.cells[x].attrs == attrs && highlight(x) == run_highlight {
+                if !attrs.flags.contains(CellFlags::WIDE_SPACER) {
+                    text.push(row.cells[x].c);
                 }
+                x += 1;
+            }
+
+            let left = origin[0] + start as f32 * char_size[0];
+            let right = origin[0] + x as f32 * char_size[0];
+            let (fg, bg) = cell_colors(&attrs, default_fg, default_bg);
+            let bg = run_highlight.unwrap_or(bg);
+
+            if run_highlight.is_some() || attrs.bg != Color::Default || attrs.flags.contains(CellFlags::INVERSE) {
+                draw_list
+                    .add_rect([left, top], [right, top + char_size[1]], bg)
+                    .filled(true)
+                    .build();
+            }
+            if !text.trim_end().is_empty() {
+                draw_list.add_text([left, top], fg, text.trim_end());
+            }
+            if attrs.flags.contains(CellFlags::UNDERLINE) {
+                let baseline = top + char_size[1] - 1.0;
+                draw_list.add_line([left, baseline], [right, baseline], fg).build();
+            }
+            if attrs.flags.contains(CellFlags::STRIKETHROUGH) {
+                let middle = top + char_size[1] / 2.0;
+                draw_list.add_line([left, middle], [right, middle], fg).build();
+            }
+        }
+    }
+
+    // Underline the link under the pointer across every row it covers
+    if let Some(link) = hovered_link {
+        for y in 0..screen.rows() {
+            let line = terminal.viewport_line(y);
+            if line < link.start.line || line > link.end.line {
+                continue;
             }
-            debug!("Processed {} bytes into terminal", data.len());
+            let from = if line == link.start.line { link.start.col } else { 0 };
+            let to = if line == link.end.line { link.end.col } else { screen.cols() - 1 };
+            let baseline = origin[1] + (y + 1) as f32 * char_size[1] - 1.0;
+            draw_list
+                .add_line(
+                    [origin[0] + from as f32 * char_size[0], baseline],
+                    [origin[0] + (to + 1) as f32 * char_size[0], baseline],
+                    default_fg,
+                )
+                .build();
         }
     }
+
+    // Mark the prompts of failed commands in the padding left of the grid.
+    // Full-screen programs on the alternate screen have no prompts.
+    if !terminal.modes().alternate_screen {
+        let commands = terminal.commands();
+        for y in 0..screen.rows() {
+            if commands.at_prompt_line(terminal.viewport_line(y)).is_some_and(|c| c.failed()) {
+                let top = origin[1] + y as f32 * char_size[1];
+                draw_list
+                    .add_rect([origin[0] - 4.0, top], [origin[0] - 1.0, top + char_size[1]], FAILED_COMMAND_MARK)
+                    .filled(true)
+                    .build();
+            }
+        }
+    }
+
+    // Cursor, hidden while looking at scrollback
+    if terminal.modes().show_cursor && terminal.display_offset() == 0 {
+        let cursor = terminal.cursor();
+        let left = origin[0] + cursor.col as f32 * char_size[0];
+        let top = origin[1] + cursor.row as f32 * char_size[1];
+        draw_list
+            .add_rect([left, top], [left + char_size[0], top + char_size[1]], default_fg)
+            .build();
+    }
+
+    // Reserve the space taken by the grid so the scroll region knows its size
+    ui.dummy([
+        screen.cols() as f32 * char_size[0],
+        screen.rows() as f32 * char_size[1],
+    ]);
 }
 
-/// Draws the terminal widget using ImGui
+/// Draws the terminal widget using ImGui: one tab per session
 pub fn draw_terminal_widget(
     ui: &Ui,
     state: &mut TerminalWidgetState,
     p_open: &mut bool,
-    session: Option<&Arc<Mutex<ConPtySession>>>,
+    sessions: &SharedSessionManager,
 ) {
     let window = imgui::Window::new("Terminal")
-        .size([state.width as f32 * 8.0, state.height as f32 * 16.0], imgui::Condition::FirstUseEver)
+        .size(
+            [state.width as f32 * state.char_size[0], state.height as f32 * state.char_size[1]],
+            imgui::Condition::FirstUseEver,
+        )
         .opened(p_open);
 
     window.build(ui, || {
-        // Update terminal dimensions based on available space
-        let available_size = ui.content_region_avail();
-        let child_size = [available_size[0], available_size[1] - 30.0];
-        
-        // Calculate approximate character dimensions
-        state.width = (available_size[0] / 8.0) as usize;  // Assuming 8px char width
-        state.height = (child_size[1] / 16.0) as usize;    // Assuming 16px char height
-        
-        // Use imgui::ChildWindow for scrolling area in imgui 0.8.0
-        imgui::ChildWindow::new("terminal_output")
-            .size(child_size)
-            .build(ui, || {
-                // Display terminal content from buffer
-                for line in &state.buffer {
-                    ui.text(line);
-                }
+        let Ok(mut manager) = sessions.lock() else {
+            ui.text_disabled("Terminal sessions are unavailable.");
+            return;
+        };
+        if manager.is_empty() {
+            ui.text_disabled("No terminal sessions. Open one from the View menu.");
+            return;
+        }
+
+        // Select the active tab when it was changed outside the tab bar (e.g. from Lua)
+        let active = manager.active_id();
+        let force_select = active != state.shown_tab;
+        let mut closed = Vec::new();
+        let mut restarts = Vec::new();
+        // Rules are shared by all sessions; cloning shares the compiled patterns
+        let detector = manager.link_detector().clone();
 
-                // Auto-scroll to bottom when at bottom
-                if ui.scroll_y() >= ui.scroll_max_y() {
-                    ui.set_scroll_here_y();
+        TabBar::new("terminal_tabs").reorderable(true).build(ui, || {
+            for id in manager.ids() {
+                let Some(session) = manager.get_mut(id) else {
+                    continue;
+                };
+                let label = format!("{}###terminal_tab_{}", session.title(), id);
+                let flags = if force_select && active == Some(id) {
+                    TabItemFlags::SET_SELECTED
+                } else {
+                    TabItemFlags::empty()
+                };
+
+                let mut open = true;
+                let shown = TabItem::new(&label).opened(&mut open).flags(flags).build(ui, || {
+                    if draw_session(ui, state, session, &detector) {
+                        restarts.push(id);
+                    }
+                });
+                if shown.is_some() {
+                    state.shown_tab = Some(id);
                 }
-            });
-
-        // Input field
-        let mut input_changed = false;
-        let input_width = ui.content_region_avail()[0] - 60.0;
-        ui.set_next_item_width(input_width);
-        
-        ui.input_text("##terminal_input", &mut state.input_buffer)
-            .enter_returns_true(true)
-            .build();
-        
-        ui.same_line();
-        if ui.button("Send") || ui.is_item_active() && ui.is_key_pressed(imgui::Key::Enter) {
-            input_changed = true;
+                if !open {
+                    closed.push(id);
+                }
+            }
+        });
+
+        // Clicking a tab makes it the active session
+        if let Some(id) = state.shown_tab
+            && !force_select
+        {
+            manager.set_active(id);
         }
+        for id in restarts {
+            if let Err(e) = manager.restart(id) {
+                info!("Failed to restart terminal session {}: {}", id, e);
+            }
+        }
+        for id in closed {
+            manager.close(id);
+        }
+    });
+}
+
+/// Sends queued keyboard and mouse input to the session. Shift+PageUp,
+/// Shift+PageDown and the wheel (unless the program tracks the mouse) move
+/// the view through scrollback instead, keys typed into the search bar
+/// stay there, and Ctrl+click opens links.
+fn forward_input(ui: &Ui, state: &mut TerminalWidgetState, session: &mut TerminalSession, detector: &LinkDetector) {
+    let pending = std::mem::take(&mut state.pending_input);
+    for input in pending {
+        let result = match input {
+            TerminalInput::Key(Key::Escape, _) if state.search.is_open() => {
+                state.search.close(session);
+                Ok(())
+            }
+            // The search field is being typed into
+            TerminalInput::Key(..) | TerminalInput::Paste if state.search.has_focus() => Ok(()),
+            TerminalInput::Find => {
+                state.search.open();
+                Ok(())
+            }
+            TerminalInput::PreviousPrompt => {
+                session.terminal_mut().scroll_to_previous_prompt();
+                Ok(())
+            }
+            TerminalInput::NextPrompt => {
+                session.terminal_mut().scroll_to_next_prompt();
+                Ok(())
+            }
+            TerminalInput::CopyLastOutput => {
+                if let Some(text) = session.terminal().last_output_text() {
+                    ui.set_clipboard_text(text);
+                }
+                Ok(())
+            }
+            TerminalInput::ToggleRecording if session.is_recording() => session.stop_recording().map(|_| ()),
+            TerminalInput::ToggleRecording => session.start_recording(recording_path(session.id())),
+            TerminalInput::Key(Key::PageUp, Modifiers::SHIFT) => {
+                session.terminal_mut().scroll_pages(1);
+                Ok(())
+            }
+            TerminalInput::Key(Key::PageDown, Modifiers::SHIFT) => {
+                session.terminal_mut().scroll_pages(-1);
+                Ok(())
+            }
+            TerminalInput::Key(key, mods) => session.send_key(key, mods),
+            TerminalInput::Paste => match ui.clipboard_text() {
+                Some(text) => session.paste(&text),
+                None => Ok(()),
+            },
+            TerminalInput::Copy => {
+                if let Some(text) = session.terminal().selection_text() {
+                    ui.set_clipboard_text(text);
+                }
+                Ok(())
+            }
+            TerminalInput::PointerMoved(position, mods) => {
+                let (cell, inside) = state.cell_at(position);
+                state.hovered_link = if inside && mods.contains(Modifiers::CTRL) {
+                    session.terminal().link_at(detector, cell.0, cell.1)
+                } else {
+                    None
+                };
+                state.mouse.moved(session, cell, mods)
+            }
 
-        // Process input when Enter is pressed
-        if input_changed && !state.input_buffer.is_empty() {
-            if let Some(session_ref) = session {
-                if let Ok(mut session) = session_ref.lock() {
-                    // Send input to ConPTY
-                    let input_with_newline = format!("{}\r\n", state.input_buffer);
-                    if let Err(e) = session.write(input_with_newline.as_bytes()) {
-                        info!("Failed to send input to ConPTY: {}", e);
+            TerminalInput::MouseButton {
+                button,
+                pressed,
+                position,
+                mods,
+            } => {
+                let (cell, inside) = state.cell_at(position);
+                let link = if button == MouseButton::Left && pressed && inside && mods.contains(Modifiers::CTRL) {
+                    session.terminal().link_at(detector, cell.0, cell.1)
+                } else {
+                    None
+                };
+                match link {
+                    Some(link) => {
+                        state.activated_links.push(link);
+                        Ok(())
                     }
+                    None => state.mouse.button(session, button, pressed, cell, inside, mods),
+                }
+            }
+            TerminalInput::Wheel { delta, position, mods } => {
+                let (cell, inside) = state.cell_at(position);
+                let lines = match delta {
+                    MouseScrollDelta::LineDelta(_, y) => y.round() as isize,
+                    MouseScrollDelta::PixelDelta(pixels) => (pixels.y as f32 / state.char_size[1]).round() as isize,
+                };
+                if inside && lines != 0 {
+                    state.mouse.wheel(session, lines, cell, mods)
+                } else {
+                    Ok(())
                 }
             }
-            
-            // Clear input buffer after sending
-            state.input_buffer.clear();
+        };
+        if let Err(e) = result {
+            info!("Failed to send input to PTY: {}", e);
         }
-    });
-}
\ No newline at end of file
+    }
+}
+
+/// Replay speeds offered for replay sessions.
+const REPLAY_SPEEDS: [f64; 5] = [0.5, 1.0, 2.0, 4.0, 8.0];
+
+/// Where Ctrl+Shift+R records session `id` to.
+fn recording_path(id: SessionId) -> PathBuf {
+    let started = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |t| t.as_secs());
+    std::env::temp_dir().join(format!("mavis-terminal-{}-{}.cast", id, started))
+}
+
+/// A line above the output while the session is being recorded, or with
+/// the speed controls of a replay.
+fn draw_status(ui: &Ui, session: &TerminalSession) {
+    if let Some(path) = session.recording_path() {
+        ui.text_colored(RECORDING_COLOR, "REC");
+        ui.same_line();
+        ui.text_disabled(format!("{} (Ctrl+Shift+R to stop)", path.display()));
+    }
+    if let Some(current) = session.replay_speed() {
+        ui.text_disabled("Replay speed:");
+        for speed in REPLAY_SPEEDS {
+            ui.same_line();
+            let label = format!("{}x", speed);
+            if speed == current {
+                ui.text(&label);
+            } else if ui.small_button(&label) {
+                session.set_replay_speed(speed);
+            }
+        }
+    }
+}
+
+/// Draws one session and forwards keystrokes to it while the terminal
+/// window has focus. Returns true when the user asked to restart an exited
+/// session.
+fn draw_session(ui: &Ui, state: &mut TerminalWidgetState, session: &mut TerminalSession, detector: &LinkDetector) -> bool {
+    if !session.has_exited() && ui.is_window_focused_with_flags(imgui::WindowFocusedFlags::ROOT_AND_CHILD_WINDOWS) {
+        forward_input(ui, state, session, detector);
+    }
+    if state.search.is_open() {
+        state.search.draw(ui, session);
+    }
+    draw_status(ui, session);
+
+    // Exited sessions keep a line below the output for the restart button
+    let available_size = ui.content_region_avail();
+    let child_size = if session.has_exited() {
+        [available_size[0], available_size[1] - ui.frame_height_with_spacing()]
+    } else {
+        available_size
+    };
+
+    // Use imgui::ChildWindow for scrolling area in imgui 0.8.0
+    // The grid always fits the child window and scrollback is handled by
+    // the emulator, so ImGui scrolling stays off
+    imgui::ChildWindow::new("terminal_output")
+        .size(child_size)
+        .scroll_bar(false)
+        .scrollable(false)
+        .build(ui, || {
+            // Profiles can ask for a different font size; the family needs a
+            // font atlas entry per family, which is not loaded yet.
+            let (_, font_size) = session.font();
+            if state.base_font_size > 0.0 {
+                ui.set_window_font_scale(font_size / state.base_font_size);
+            }
+
+            // Fit the grid to the space the child window has, measured in
+            // cells of the current font. The session debounces the resize
+            // so dragging the window does not flood the shell with SIGWINCH.
+            let char_size = [ui.calc_text_size("M")[0], ui.text_line_height()];
+            let region = ui.content_region_avail();
+            state.char_size = char_size;
+            state.width = ((region[0] / char_size[0]) as usize).max(1);
+            state.height = ((region[1] / char_size[1]) as usize).max(1);
+            session.request_resize(state.width, state.height);
+
+            state.grid_origin = ui.cursor_screen_pos();
+            draw_grid(ui, session.terminal(), char_size, state.hovered_link.as_ref());
+            if let Some(link) = &state.hovered_link
+                && ui.is_window_hovered()
+            {
+                ui.tooltip_text(links::describe(link));
+            }
+        });
+
+    // Once the shell has exited there is nothing to send input to
+    if session.has_exited() {
+        if session.restart_at().is_some() {
+            ui.text_disabled("Restarting...");
+            return false;
+        }
+        return ui.button("Restart");
+    }
+    false
+}
@@ -0,0 +1,138 @@
+// Mouse handling for the terminal widget
+//
+// While the program has enabled mouse tracking, presses, releases, motion
+// and the wheel are reported to it (Shift bypasses this, as in xterm).
+// Otherwise the mouse selects text locally: drag for characters, double
+// click for words, triple click for lines, Alt+drag for a block.
+
+use mavis_core::error::CoreResult;
+use mavis_core::terminal::{Modifiers, MouseAction, MouseButton, MouseEvent, SelectionMode, TerminalSession};
+use std::time::{Duration, Instant};
+
+/// Clicks closer together than this on the same cell count as one
+/// double or triple click.
+const MULTI_CLICK_INTERVAL: Duration = Duration::from_millis(400);
+
+/// Pointer state carried between events.
+#[derive(Debug, Default)]
+pub struct MouseState {
+    // Button held down since a press over the grid
+    held: Option<MouseButton>,
+    // Cell the pointer was last seen over, to report motion once per cell
+    cell: Option<(usize, usize)>,
+    // Local selection in progress, and whether the pointer moved since the press
+    selecting: bool,
+    dragged: bool,
+    last_click: Option<(Instant, (usize, usize))>,
+    clicks: u8,
+}
+
+impl MouseState {
+    /// A button was pressed (`pressed`) or released over viewport cell
+    /// `cell`; `inside` is false when the pointer is outside the grid.
+    pub fn button(
+        &mut self,
+        session: &mut TerminalSession,
+        button: MouseButton,
+        pressed: bool,
+        cell: (usize, usize),
+        inside: bool,
+        mods: Modifiers,
+    ) -> CoreResult<()> {
+        let report = reports(session, mods);
+        if pressed {
+            if !inside {
+                return Ok(());
+            }
+            self.held = Some(button);
+            self.cell = Some(cell);
+            if report {
+                return session.send_mouse(&event(MouseAction::Press, Some(button), cell, mods));
+            }
+            if button == MouseButton::Left {
+                self.start_selection(session, cell, mods);
+            }
+            return Ok(());
+        }
+
+        if self.held != Some(button) {
+            return Ok(());
+        }
+        self.held = None;
+        if report {
+            return session.send_mouse(&event(MouseAction::Release, Some(button), cell, mods));
+        }
+        if button == MouseButton::Left && self.selecting {
+            self.selecting = false;
+            // A plain click selects nothing
+            let simple = session.terminal().selection().is_some_and(|s| s.mode() == SelectionMode::Simple);
+            if simple && !self.dragged {
+                session.terminal_mut().clear_selection();
+            }
+        }
+        Ok(())
+    }
+
+    /// The pointer moved over viewport cell `cell`.
+    pub fn moved(&mut self, session: &mut TerminalSession, cell: (usize, usize), mods: Modifiers) -> CoreResult<()> {
+        if self.cell == Some(cell) {
+            return Ok(());
+        }
+        self.cell = Some(cell);
+        if reports(session, mods) {
+            return session.send_mouse(&event(MouseAction::Motion, self.held, cell, mods));
+        }
+        if self.selecting {
+            self.dragged = true;
+            session.terminal_mut().update_selection(cell.0, cell.1);
+        }
+        Ok(())
+    }
+
+    /// The wheel turned by `lines`; positive values scroll up.
+    pub fn wheel(&mut self, session: &mut TerminalSession, lines: isize, cell: (usize, usize), mods: Modifiers) -> CoreResult<()> {
+        if !reports(session, mods) {
+            session.terminal_mut().scroll_lines(lines * 3);
+            return Ok(());
+        }
+        let button = if lines > 0 { MouseButton::WheelUp } else { MouseButton::WheelDown };
+        for _ in 0..lines.unsigned_abs() {
+            session.send_mouse(&event(MouseAction::Press, Some(button), cell, mods))?;
+        }
+        Ok(())
+    }
+
+    fn start_selection(&mut self, session: &mut TerminalSession, cell: (usize, usize), mods: Modifiers) {
+        let now = Instant::now();
+        let repeated = self
+            .last_click
+            .is_some_and(|(at, last)| last == cell && now.duration_since(at) < MULTI_CLICK_INTERVAL);
+        self.clicks = if repeated { self.clicks % 3 + 1 } else { 1 };
+        self.last_click = Some((now, cell));
+
+        let mode = match self.clicks {
+            2 => SelectionMode::Word,
+            3 => SelectionMode::Line,
+            _ if mods.contains(Modifiers::ALT) => SelectionMode::Block,
+            _ => SelectionMode::Simple,
+        };
+        session.terminal_mut().start_selection(mode, cell.0, cell.1);
+        self.selecting = true;
+        self.dragged = false;
+    }
+}
+
+/// Whether mouse input goes to the program rather than to local selection.
+fn reports(session: &TerminalSession, mods: Modifiers) -> bool {
+    session.terminal().mouse_reporting() && !mods.contains(Modifiers::SHIFT)
+}
+
+fn event(action: MouseAction, button: Option<MouseButton>, cell: (usize, usize), mods: Modifiers) -> MouseEvent {
+    MouseEvent {
+        action,
+        button,
+        col: cell.0,
+        row: cell.1,
+        mods,
+    }
+}
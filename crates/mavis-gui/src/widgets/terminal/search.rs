@@ -0,0 +1,117 @@
+// Scrollback search bar for the terminal widget
+
+use imgui::Ui;
+use mavis_core::terminal::{SearchOptions, SessionId, TerminalSession};
+
+/// Background of every match, and of the match navigated to.
+pub const MATCH_BG: [f32; 4] = [0.55, 0.45, 0.1, 1.0];
+pub const CURRENT_MATCH_BG: [f32; 4] = [0.9, 0.55, 0.1, 1.0];
+
+/// State of the search bar shown above the grid (Ctrl+Shift+F).
+#[derive(Debug, Default)]
+pub struct SearchBar {
+    open: bool,
+    query: String,
+    options: SearchOptions,
+    error: Option<String>,
+    // Whether the query field had keyboard focus last frame
+    focused: bool,
+    // Query, options and session the terminal was last searched with
+    applied: Option<(String, SearchOptions, SessionId)>,
+}
+
+impl SearchBar {
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    /// Whether keystrokes belong to the query field rather than the shell.
+    pub fn has_focus(&self) -> bool {
+        self.open && self.focused
+    }
+
+    pub fn open(&mut self) {
+        self.open = true;
+    }
+
+    /// Hide the bar and remove the highlights from `session`.
+    pub fn close(&mut self, session: &mut TerminalSession) {
+        self.open = false;
+        self.focused = false;
+        self.applied = None;
+        session.terminal_mut().clear_search();
+    }
+
+    /// Draws the bar and keeps the session's search in step with it.
+    pub fn draw(&mut self, ui: &Ui, session: &mut TerminalSession) {
+        let mut navigate = None;
+
+        ui.set_next_item_width(ui.content_region_avail()[0] * 0.4);
+        if ui
+            .input_text("##terminal_search", &mut self.query)
+            .hint("Search")
+            .enter_returns_true(true)
+            .build()
+        {
+            // Enter searches back through history, Shift+Enter forward
+            navigate = Some(!ui.io().key_shift);
+        }
+        self.focused = ui.is_item_active();
+        ui.same_line();
+        ui.checkbox("Regex", &mut self.options.regex);
+        ui.same_line();
+        ui.checkbox("Match case", &mut self.options.case_sensitive);
+        ui.same_line();
+        if ui.button("Prev") {
+            navigate = Some(true);
+        }
+        ui.same_line();
+        if ui.button("Next") {
+            navigate = Some(false);
+        }
+
+        self.apply(session);
+        let terminal = session.terminal_mut();
+        terminal.update_search();
+        match navigate {
+            Some(true) => {
+                terminal.search_previous();
+            }
+            Some(false) => {
+                terminal.search_next();
+            }
+            None => {}
+        }
+
+        ui.same_line();
+        if let Some(error) = &self.error {
+            ui.text_colored([1.0, 0.4, 0.4, 1.0], error);
+        } else if let Some(search) = terminal.active_search() {
+            let count = search.matches().len();
+            match search.current_index() {
+                Some(index) => ui.text(format!("{}/{}", index + 1, count)),
+                None => ui.text(format!("{} matches", count)),
+            }
+        }
+        ui.same_line();
+        if ui.small_button("x") {
+            self.close(session);
+        }
+    }
+
+    /// Search again when the query, its options or the shown session changed.
+    fn apply(&mut self, session: &mut TerminalSession) {
+        let key = (self.query.clone(), self.options, session.id());
+        if self.applied.as_ref() == Some(&key) {
+            return;
+        }
+        self.error = None;
+        if self.query.is_empty() {
+            session.terminal_mut().clear_search();
+        } else if let Err(e) = session.terminal_mut().search(&self.query, self.options) {
+            session.terminal_mut().clear_search();
+            self.error = Some(format!("Invalid pattern: {}", e));
+        }
+        self.applied = Some(key);
+    }
+}
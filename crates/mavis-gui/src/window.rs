@@ -1,10 +1,13 @@
 use crate::error::GuiResult;
 use crate::state::GuiState; 
 use crate::ui;
+use crate::widgets;
 
 use imgui::Context;
 use log::{info, warn};
 use mavis_core::config::Config as CoreConfig;
+use mavis_core::monitor::{AlertEvent, ResourceUsage};
+use mavis_core::terminal::Link;
 use std::sync::{Arc, Mutex};
 use std::time::Instant;
 use winit::{
@@ -16,7 +19,21 @@ use winit::{
 use windows::Win32::Foundation::HWND;
 
 /// Main entry point to run the MAVIS GUI.
-pub fn run_gui(core_config: &CoreConfig, gui_state: Arc<Mutex<GuiState>>) -> GuiResult<()> {
+///
+/// `on_link` is offered every link activated in the terminal and returns
+/// true if it opened the link; otherwise the default action applies.
+/// `on_alert` gets every alert event the notifications widget receives,
+/// and `on_usage` every new resource usage sample the GUI shows.
+/// `on_frame` runs at the start of every frame, for work queued by other
+/// threads that has to happen on this one.
+pub fn run_gui(
+    core_config: &CoreConfig,
+    gui_state: Arc<Mutex<GuiState>>,
+    mut on_link: impl FnMut(&Link) -> bool + 'static,
+    mut on_alert: impl FnMut(&AlertEvent) + 'static,
+    mut on_usage: impl FnMut(&ResourceUsage) + 'static,
+    mut on_frame: impl FnMut() + 'static,
+) -> GuiResult<()> {
     info!("Starting MAVIS GUI...");
     
     // TEMPORARY: Display warning that this is a placeholder implementation
@@ -60,16 +77,38 @@ pub fn run_gui(core_config: &CoreConfig, gui_state: Arc<Mutex<GuiState>>) -> Gui
                 info!("Close requested, exiting...");
                 *control_flow = ControlFlow::Exit;
             }
+            Event::WindowEvent { ref event, .. } => {
+                // There is no platform integration yet, so keyboard and mouse
+                // input for the terminal is taken straight from winit
+                if let Ok(mut state_guard) = gui_state.lock() {
+                    state_guard.terminal_state.handle_window_event(event, window.scale_factor());
+                }
+            }
             _ => {}
         }
         
         // Only draw UI on RedrawRequested
         if let Event::RedrawRequested(_) = event {
+            on_frame();
             let ui = imgui_context.frame();
             
             // Lock state and draw UI
             if let Ok(mut state_guard) = gui_state.lock() {
+                if state_guard.poll_resource_usage() {
+                    on_usage(&state_guard.resource_usage);
+                }
+
                 ui::draw_ui(&ui, &mut state_guard, &core_config, hwnd);
+
+                for link in state_guard.terminal_state.take_activated_links() {
+                    if !on_link(&link) {
+                        widgets::terminal::open_link(&link, &mut state_guard.ide_state);
+                    }
+                }
+
+                for event in state_guard.alert_notifications.take_new() {
+                    on_alert(&event);
+                }
                 
                 if state_guard.should_exit {
                     info!("UI requested exit.");
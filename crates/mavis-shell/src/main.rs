@@ -1,19 +1,22 @@
 use anyhow::{Context, Result}; // Using anyhow for easy error handling in main
-use log::{debug, error, info, LevelFilter};
+use log::{error, info, LevelFilter};
 use mavis_core::{
     config::{ConfigLoader, ConfigWatcher},
     lua::ScriptEngine,
     monitor::ResourceMonitor,
-    conpty::ConPtySession,
+    terminal::SessionManager,
 };
-use mavis_gui::{self, state::GuiState};
-use std::{
-    sync::{
-        mpsc,
-        Arc, Mutex,
-    },
-    thread,
+use mavis_gui::{
+    self,
+    state::GuiState,
+    widgets::{notifications::AlertNotifications, processes::ProcessTableState, terminal::TerminalWidgetState},
 };
+use std::rc::Rc;
+use std::sync::{
+    mpsc,
+    Arc, Mutex,
+};
+use std::time::Duration;
 
 // Shared state or communication channel between threads might be needed later
 // For example, using Arc<Mutex<...>> or channels (mpsc, tokio::sync::mpsc)
@@ -41,6 +44,21 @@ fn main() -> Result<()> {
     let script_engine = 
         ScriptEngine::new(&core_config).context("Failed to initialize Lua script engine")?;
 
+    // Terminal tabs are shared between the GUI and MAVIS.terminal in Lua,
+    // so the API must exist before init.lua runs.
+    let terminal_sessions = SessionManager::shared(core_config.terminal.clone());
+    script_engine
+        .register_terminal_api(terminal_sessions.clone())
+        .context("Failed to register terminal Lua API")?;
+
+    // MAVIS.monitor reads the metrics history the monitor records, and
+    // init.lua may change its settings before it starts
+    let mut resource_monitor = ResourceMonitor::new(&core_config)
+        .context("Failed to initialize resource monitor")?;
+    script_engine
+        .register_monitor_api(&resource_monitor)
+        .context("Failed to register monitor Lua API")?;
+
     // Execute initial configuration scripts (e.g., init.lua)
     // TODO: Determine the exact script(s) to run initially. init.lua seems logical.
     let init_script_path = config_dir.join("init.lua");
@@ -58,15 +76,34 @@ fn main() -> Result<()> {
         );
     }
 
-    // 5. Initialize Resource Monitor (if enabled in config)
-    // TODO: Check config before starting monitor
-    let mut resource_monitor = ResourceMonitor::new(&core_config)
-        .context("Failed to initialize resource monitor")?;
+    // 5. Start the Resource Monitor with the settings init.lua left it;
+    // reloads of init.lua, handled in the GUI loop, reconfigure it while it
+    // runs
     resource_monitor
-        .start(&core_config)
+        .start()
         .context("Failed to start resource monitor")?;    
     info!("Resource monitor started.");
 
+    // Serve and write metrics for Prometheus if configured; stops when
+    // dropped at the end of main. The shell runs on without it, e.g. when
+    // the port is taken by another instance
+    #[cfg(feature = "prometheus")]
+    let _metrics_exporter = mavis_core::monitor::exporter::MetricsExporter::start(
+        &resource_monitor.settings(),
+        resource_monitor.subscribe(Duration::ZERO),
+    )
+    .unwrap_or_else(|e| {
+        error!("Failed to start metrics exporter: {}", e);
+        None
+    });
+    #[cfg(not(feature = "prometheus"))]
+    {
+        let settings = resource_monitor.settings();
+        if settings.exporter_port.is_some() || settings.exporter_textfile.is_some() {
+            log::warn!("A metrics exporter is configured, but MAVIS was built without the prometheus feature");
+        }
+    }
+
     // Define message types for config reload channel
     #[derive(Debug)]
     enum ConfigReloadRequest {
@@ -127,145 +164,91 @@ fn main() -> Result<()> {
         .context("Failed to start config watcher")?;
     info!("Configuration watcher started.");
 
-    // 7. Initialize ConPTY Session (Attempting to launch LF)
-    // TODO: Make command configurable and handle lf.exe not found gracefully.
-    let conpty_command = "lf.exe"; // Target LF
-    let conpty_session_arc = match ConPtySession::new(conpty_command, 80, 25) {
-        Ok(session) => {
-            info!("ConPTY session created successfully for '{}'.", conpty_command);
-            Some(Arc::new(Mutex::new(session)))
-        }
-        Err(e) => {
-            error!("Failed to create ConPTY session: {}", e);
-            None // Continue without terminal functionality
-        }
-    };
-
-    // 8. Setup ConPTY Output Reading Thread
-    let (conpty_output_tx, conpty_output_rx) = mpsc::channel::<Vec<u8>>(); // Create channel
-    let mut conpty_reader_thread: Option<thread::JoinHandle<()>> = None;
-
-    if let Some(session_arc) = conpty_session_arc.clone() { // Clone Arc for the thread
-        let tx_clone = conpty_output_tx.clone(); // Clone Sender for the thread
-
-        conpty_reader_thread = Some(thread::spawn(move || {
-            info!("ConPTY reader thread started.");
-            let mut buffer = [0u8; 4096]; // Read buffer
-
-            loop {
-                // Lock the session mutex to access the read method
-                let read_result: Result<Vec<u8>, std::io::Error> = {
-                    let mut session_guard = match session_arc.lock() {
-                        Ok(guard) => guard,
-                        Err(_) => {
-                            error!("ConPTY session mutex poisoned! Reader thread exiting.");
-                            // Send an empty vec to signal error/exit? Or just break.
-                            let _ = tx_clone.send(Vec::new()); // Signal exit/error
-                            break;
-                        }
-                    };
-                    // Use read method directly (blocking)
-                     match session_guard.read(&mut buffer) {
-                         Ok(0) => {
-                             info!("ConPTY read 0 bytes, assuming process exited. Reader thread exiting.");
-                             let _ = tx_clone.send(Vec::new()); // Signal exit
-                             break; // EOF or process closed pipe
-                         }
-                         Ok(bytes_read) => {
-                             debug!("ConPTY read {} bytes.", bytes_read);
-                             Ok(buffer[..bytes_read].to_vec()) // Convert slice to Vec
-                         }
-                         Err(e) => {
-                             error!("Error reading from ConPTY: {}. Reader thread exiting.", e);
-                             let _ = tx_clone.send(Vec::new()); // Signal exit/error
-                             break; // Propagate error or handle specific cases
-                         }
-                     }
-                };
-
-                // Send data if read was successful
-                if let Ok(data) = read_result {
-                    if data.is_empty() { // Check if we broke loop due to EOF/Error signal
-                        break;
-                    }
-                    if tx_clone.send(data).is_err() {
-                        info!("ConPTY output channel closed by receiver. Reader thread exiting.");
-                        break; // Receiver likely dropped (GUI closed)
-                    }
-                } else {
-                    // Error already logged, break loop
-                    break;
-                }
-
-                // Optional: Add a small sleep to prevent tight loop if needed,
-                // but blocking read should handle this.
+    // 7. Open the first terminal tab unless init.lua already opened some
+    match terminal_sessions.lock() {
+        Ok(mut sessions) if sessions.is_empty() => {
+            if let Err(e) = sessions.open_default() {
+                error!("Failed to create PTY session: {}", e); // Continue without terminal functionality
             }
-        }));
+        }
+        Ok(_) => {}
+        Err(_) => error!("Terminal session manager mutex poisoned."),
     }
-    
+
     // 9. Initialize and Run the GUI
     // The GUI thread will own the ScriptEngine and handle reload requests
     let gui_state = Arc::new(Mutex::new(GuiState {
-        conpty_session: conpty_session_arc, // Use the original Arc here
-        conpty_output_rx: Some(conpty_output_rx), // Store the receiver end
-        conpty_output_tx: Some(conpty_output_tx), // Store sender for potential input later
         should_exit: false,
-        resource_usage: Default::default(),
+        resource_usage: resource_monitor.get_usage(),
+        usage_updates: Some(resource_monitor.subscribe(Duration::ZERO)),
+        metrics_history: resource_monitor.history(),
         widget_visibility: Default::default(),
         show_demo_window: false,
-        terminal_state: Default::default(),
+        terminal_state: TerminalWidgetState::from_config(&core_config.terminal),
         show_terminal: true,
+        process_table: ProcessTableState::new(),
+        show_processes: false,
+        monitor_control: Some(resource_monitor.control()),
+        alert_notifications: AlertNotifications::new(resource_monitor.subscribe_alerts()),
+        show_alerts: false,
+        terminal_sessions,
         ide_state: Default::default(),
     }));
     // Pass the shared state to the GUI function.
-    // The GUI loop should now poll `reload_rx` and handle messages.
     
-    // Create a simple GUI loop to handle the Lua operations in the main thread
-    let script_engine = script_engine;
-    mavis_gui::run_gui(&core_config, gui_state.clone())?;
-
-    // Process any config reload requests in a separate loop
-    let mut running = true;
-    while running {
-        if let Ok(request) = reload_rx.try_recv() {
-            match request {
-                ConfigReloadRequest::ReloadInitScript(script_path) => {
-                    info!("Reloading script: {:?}", script_path);
-                    if let Err(e) = script_engine.load_script(&script_path) {
-                        error!("Failed to reload script: {}", e);
+    // The GUI loop runs on the main thread, which owns the Lua state.
+    // Links clicked in the terminal go to the Lua `on_link` handler first,
+    // alert events to the Lua alert callbacks and new samples to `on_update`;
+    // each frame starts by handling the queued config reload requests
+    let script_engine = Rc::new(script_engine);
+    let link_engine = Rc::clone(&script_engine);
+    let alert_engine = Rc::clone(&script_engine);
+    let usage_engine = Rc::clone(&script_engine);
+    let reload_engine = Rc::clone(&script_engine);
+    mavis_gui::run_gui(
+        &core_config,
+        gui_state.clone(),
+        move |link| {
+            link_engine.handle_terminal_link(link).unwrap_or_else(|e| {
+                error!("Terminal link handler failed: {}", e);
+                false
+            })
+        },
+        move |event| {
+            if let Err(e) = alert_engine.handle_alert(event) {
+                error!("Alert handler failed: {}", e);
+            }
+        },
+        move |usage| {
+            if let Err(e) = usage_engine.handle_usage(usage) {
+                error!("Update handler failed: {}", e);
+            }
+        },
+        move || {
+            while let Ok(request) = reload_rx.try_recv() {
+                match request {
+                    ConfigReloadRequest::ReloadInitScript(script_path) => {
+                        info!("Reloading script: {:?}", script_path);
+                        if let Err(e) = reload_engine.load_script(&script_path) {
+                            error!("Failed to reload script: {}", e);
+                        }
+                    },
+                    ConfigReloadRequest::ReloadTheme(theme_name) => {
+                        info!("Applying theme: {}", theme_name);
+                        // Here you would apply the theme using script_engine
+                        // For example:
+                        // if let Err(e) = script_engine.call_function("apply_theme", theme_name) {
+                        //     error!("Failed to apply theme: {}", e);
+                        // }
                     }
-                },
-                ConfigReloadRequest::ReloadTheme(theme_name) => {
-                    info!("Applying theme: {}", theme_name);
-                    // Here you would apply the theme using script_engine
-                    // For example:
-                    // if let Err(e) = script_engine.call_function("apply_theme", theme_name) {
-                    //     error!("Failed to apply theme: {}", e);
-                    // }
                 }
             }
-        }
-        
-        // Check if we should exit
-        let should_exit = {
-            let state = gui_state.lock().unwrap();
-            state.should_exit
-        };
-        
-        running = !should_exit;
-        std::thread::sleep(std::time::Duration::from_millis(100)); // Avoid busy-waiting
-    }
-    
-    info!("MAVIS Shell shutting down.");
-    // The GUI loop should now poll `config_reload_rx` and handle messages.
+        },
+    )?;
+
     info!("MAVIS Shell shutting down.");
-    // ConPtySession Drop handles termination.
-    // Wait for reader thread to finish if it was started.
-    if let Some(handle) = conpty_reader_thread {
-        info!("Waiting for ConPTY reader thread to join...");
-        let _ = handle.join().map_err(|e| error!("ConPTY reader thread panicked: {:?}", e));
-        info!("ConPTY reader thread joined.");
-    }
+    // Dropping the sessions terminates their children; each reader thread
+    // exits once its PTY closes.
 
     Ok(())
 }
\ No newline at end of file